@@ -5,3 +5,5 @@ pub const BIN_ACTION_SYNC_PARA_HEADER: u8 = BIN_ACTION_START + 0;
 pub const BIN_ACTION_DISPATCH_BLOCK: u8 = BIN_ACTION_START + 1;
 pub const BIN_ACTION_SYNC_HEADER: u8 = BIN_ACTION_START + 2;
 pub const BIN_ACTION_SYNC_COMBINED_HEADERS: u8 = BIN_ACTION_START + 3;
+pub const BIN_ACTION_EXPORT_CLUSTER_STATE: u8 = BIN_ACTION_START + 4;
+pub const BIN_ACTION_IMPORT_CLUSTER_STATE: u8 = BIN_ACTION_START + 5;
@@ -40,6 +40,14 @@ pub struct InitArgs {
 
     /// The public rpc port with acl enabled
     pub public_port: Option<u16>,
+
+    /// The address of the TLS ingress shared by the sidevm instances
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sidevm_ingress: Option<String>,
+
+    /// Attest with DCAP quotes instead of IAS reports when registering the worker
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dcap_attestation: bool,
 }
 
 pub fn git_revision() -> String {
@@ -311,11 +311,26 @@ impl<Validator: BlockValidator> ParachainSynchronizer<Validator> {
         validator: Validator,
         main_bridge: u64,
         headernum_next: chain::BlockNumber,
+    ) -> Self {
+        Self::new_at(validator, main_bridge, headernum_next, 1)
+    }
+
+    /// Start syncing the parachain from `para_headernum_next` instead of its genesis.
+    pub fn new_at(
+        validator: Validator,
+        main_bridge: u64,
+        headernum_next: chain::BlockNumber,
+        para_headernum_next: chain::BlockNumber,
     ) -> Self {
         Self {
-            sync_state: BlockSyncState::new(validator, main_bridge, headernum_next, 1),
+            sync_state: BlockSyncState::new(
+                validator,
+                main_bridge,
+                headernum_next,
+                para_headernum_next,
+            ),
             last_relaychain_state_root: None,
-            para_header_number_next: 1,
+            para_header_number_next: para_headernum_next,
             para_state_roots: Default::default(),
         }
     }
@@ -432,6 +447,22 @@ impl<Validator: BlockValidator> Synchronizer<Validator> {
         Self::Para(ParachainSynchronizer::new(validator, main_bridge, headernum_next))
     }
 
+    /// A parachain synchronizer starting at the parachain block `para_headernum_next`, e.g. to
+    /// verify the blocks cached from a later block than the genesis.
+    pub fn new_parachain_at(
+        validator: Validator,
+        main_bridge: u64,
+        headernum_next: chain::BlockNumber,
+        para_headernum_next: chain::BlockNumber,
+    ) -> Self {
+        Self::Para(ParachainSynchronizer::new_at(
+            validator,
+            main_bridge,
+            headernum_next,
+            para_headernum_next,
+        ))
+    }
+
     pub fn new_solochain(validator: Validator, main_bridge: u64) -> Self {
         Self::Solo(SolochainSynchronizer::new(validator, main_bridge))
     }
@@ -481,3 +512,87 @@ impl<Validator: BlockValidator> StorageSynchronizer for Synchronizer<Validator>
         self.as_dyn_mut().feed_block(block, storage)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts any header and proof, to test the bookkeeping of the synchronizer.
+    struct AcceptAll;
+
+    impl BlockValidator for AcceptAll {
+        fn submit_finalized_headers(
+            &mut self,
+            _bridge_id: u64,
+            _header: chain::Header,
+            _ancestry_proof: Vec<chain::Header>,
+            _grandpa_proof: Vec<u8>,
+            _auhtority_set_change: Option<AuthoritySetChange>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn validate_storage_proof(
+            &self,
+            _state_root: Hash,
+            _proof: StorageProof,
+            _items: &[(&[u8], &[u8])],
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn header(number: chain::BlockNumber, parent_hash: Hash) -> chain::Header {
+        chain::Header {
+            parent_hash,
+            number,
+            state_root: Default::default(),
+            extrinsics_root: Default::default(),
+            digest: Default::default(),
+        }
+    }
+
+    fn justified_relay_header(number: chain::BlockNumber) -> HeaderToSync {
+        HeaderToSync {
+            header: header(number, Default::default()),
+            justification: Some(vec![]),
+        }
+    }
+
+    #[test]
+    fn parachain_sync_can_start_after_genesis() {
+        let mut sync = Synchronizer::new_parachain_at(AcceptAll, 0, 1000, 100);
+        let counters = sync.counters();
+        assert_eq!(counters.next_header_number, 1000);
+        assert_eq!(counters.next_para_header_number, 100);
+        assert_eq!(counters.next_block_number, 100);
+
+        sync.sync_header(vec![justified_relay_header(1000)], None)
+            .ok()
+            .unwrap();
+        let first = header(100, Default::default());
+        let second = header(101, first.hash());
+        // The headers below the start are ignored
+        let headers = vec![header(99, Default::default()), first, second];
+        assert_eq!(
+            sync.sync_parachain_header(headers, vec![], b"key").ok(),
+            Some(101)
+        );
+        assert_eq!(sync.counters().next_para_header_number, 102);
+    }
+
+    #[test]
+    fn parachain_sync_starts_at_genesis_by_default() {
+        let mut sync = Synchronizer::new_parachain(AcceptAll, 0, 1000);
+        assert_eq!(sync.counters().next_para_header_number, 1);
+
+        sync.sync_header(vec![justified_relay_header(1000)], None)
+            .ok()
+            .unwrap();
+        let headers = vec![header(100, Default::default())];
+        assert!(matches!(
+            sync.sync_parachain_header(headers, vec![], b"key"),
+            Err(Error::BlockNumberMismatch)
+        ));
+    }
+}
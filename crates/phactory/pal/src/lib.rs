@@ -14,10 +14,26 @@ pub trait Sealing {
     fn unseal_data(&self, path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, Self::UnsealError>;
 }
 
+/// The collateral to verify a DCAP quote, as served by Intel PCS or a PCCS.
+pub struct DcapCollateral {
+    pub root_ca_crl: Vec<u8>,
+    pub pck_crl: Vec<u8>,
+    pub tcb_info_issuer_chain: Vec<u8>,
+    pub tcb_info: Vec<u8>,
+    pub tcb_info_signature: Vec<u8>,
+    pub qe_identity_issuer_chain: Vec<u8>,
+    pub qe_identity: Vec<u8>,
+    pub qe_identity_signature: Vec<u8>,
+}
+
 pub trait RA {
     type Error: ErrorType;
     fn create_attestation_report(&self, data: &[u8]) -> Result<(String, String, String), Self::Error>;
+    /// Creates a DCAP quote of `data` along with the collateral to verify it.
+    fn create_dcap_quote(&self, data: &[u8]) -> Result<(Vec<u8>, DcapCollateral), Self::Error>;
     fn quote_test(&self) -> Result<(), Self::Error>;
+    /// The measurement of the running enclave, as registered in the on-chain pRuntime allowlist.
+    fn measurement(&self) -> Option<Vec<u8>>;
 }
 
 pub struct MemoryUsage {
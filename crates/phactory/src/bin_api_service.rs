@@ -1,3 +1,4 @@
+use phala_mq::ContractClusterId;
 use phala_types::{wrap_content_to_sign, SignedContentType};
 
 use super::*;
@@ -13,7 +14,12 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
     }
 
     fn get_info_json(&self) -> Result<Value, Value> {
-        Ok(json!(self.get_info()))
+        let mut info = json!(self.get_info());
+        let schedule = self.system.as_ref().and_then(|s| s.pruntime_schedule());
+        if let Some(schedule) = schedule {
+            info["pruntime_schedule"] = json!(schedule);
+        }
+        Ok(info)
     }
 
     fn bin_sync_header(&mut self, input: blocks::SyncHeaderReq) -> Result<Value, Value> {
@@ -50,6 +56,39 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         Ok(json!({ "dispatched_to": resp.synced_to }))
     }
 
+    fn bin_export_cluster_state(&mut self, cluster: ContractClusterId) -> Result<Value, Value> {
+        let send_mq = &self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| error_msg("Runtime not initialized"))?
+            .send_mq;
+        let system = self
+            .system
+            .as_mut()
+            .ok_or_else(|| error_msg("Runtime not initialized"))?;
+        let state = system
+            .export_cluster_state(&cluster, send_mq)
+            .map_err(display)?;
+        Ok(json!({
+            "block_number": system.block_number,
+            "state": hex::encode(state),
+        }))
+    }
+
+    fn bin_import_cluster_state(
+        &mut self,
+        (cluster, state): (ContractClusterId, Vec<u8>),
+    ) -> Result<Value, Value> {
+        let system = self
+            .system
+            .as_mut()
+            .ok_or_else(|| error_msg("Runtime not initialized"))?;
+        let apply_at = system
+            .import_cluster_state(&cluster, &state)
+            .map_err(display)?;
+        Ok(json!({ "apply_at": apply_at }))
+    }
+
     fn try_handle_scale_api(&mut self, action: u8, input: &[u8]) -> Result<Value, Value> {
         use phactory_api::actions::*;
 
@@ -63,6 +102,8 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             BIN_ACTION_SYNC_PARA_HEADER => self.bin_sync_para_header(load_scale(input)?),
             BIN_ACTION_SYNC_COMBINED_HEADERS => self.bin_sync_combined_headers(load_scale(input)?),
             BIN_ACTION_DISPATCH_BLOCK => self.bin_dispatch_block(load_scale(input)?),
+            BIN_ACTION_EXPORT_CLUSTER_STATE => self.bin_export_cluster_state(load_scale(input)?),
+            BIN_ACTION_IMPORT_CLUSTER_STATE => self.bin_import_cluster_state(load_scale(input)?),
             _ => Err(error_msg("Action not found")),
         }
     }
@@ -82,7 +123,10 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             "payload": str_payload,
             "signature": signature,
         });
-        info!("{}", output_json.to_string());
+        // The exported cluster state is too large to be logged
+        if action != phactory_api::actions::BIN_ACTION_EXPORT_CLUSTER_STATE {
+            info!("{}", output_json.to_string());
+        }
         serde_json::to_vec(&output_json).unwrap()
     }
 }
@@ -269,9 +269,12 @@ fn cluster_storage<'a>(
 pub mod cluster {
     use super::Pink;
 
-    use anyhow::{Context, Result};
-    use phala_crypto::sr25519::{Persistence, Sr25519SecretKey, KDF};
-    use phala_mq::{ContractClusterId, ContractId};
+    use anyhow::{anyhow, bail, Context, Result};
+    use phala_crypto::{
+        aead,
+        sr25519::{Persistence, Sr25519SecretKey, KDF},
+    };
+    use phala_mq::{ContractClusterId, ContractId, MessageOrigin};
     use phala_serde_more as more;
     use phala_types::contract::messaging::ResourceType;
     use pink::{
@@ -280,7 +283,7 @@ pub mod cluster {
     };
     use runtime::BlockNumber;
     use serde::{Deserialize, Serialize};
-    use sp_core::sr25519;
+    use sp_core::{sr25519, Pair as _};
     use sp_runtime::{AccountId32, DispatchError};
     use std::collections::{BTreeMap, BTreeSet};
 
@@ -362,6 +365,27 @@ pub mod cluster {
             self.clusters.remove(cluster_id)
         }
 
+        /// Deploys a cluster from the state exported by another worker of the cluster.
+        pub fn restore_cluster(
+            &mut self,
+            cluster_id: &ContractClusterId,
+            state: ClusterState,
+        ) -> Result<&mut Cluster> {
+            if &state.cluster_id != cluster_id {
+                bail!("The state belongs to cluster {}", state.cluster_id);
+            }
+            if self.clusters.contains_key(cluster_id) {
+                bail!("Cluster {} is already deployed", cluster_id);
+            }
+            if state.cluster.storage.root() != state.state_root {
+                bail!("The state root of cluster {} mismatch", cluster_id);
+            }
+            Ok(self
+                .clusters
+                .entry(cluster_id.clone())
+                .or_insert(state.cluster))
+        }
+
         pub fn iter(&self) -> impl Iterator<Item = (&ContractClusterId, &Cluster)> {
             self.clusters.iter()
         }
@@ -372,6 +396,9 @@ pub mod cluster {
         pub log_handler: Option<ContractId>,
         // Version used to control the contract API availability.
         pub version: (u16, u16),
+        /// The gas cost table used to meter sidevm instances deployed in the cluster.
+        #[serde(default)]
+        pub sidevm_cost_table: sidevm::CostTable,
     }
 
     #[derive(Serialize, Deserialize)]
@@ -435,6 +462,160 @@ pub mod cluster {
         pub fn iter_contracts(&self) -> impl Iterator<Item = &ContractId> {
             self.contracts.iter()
         }
+
+        /// Exports the state of the cluster after `block_number`, encrypted with a key derived
+        /// from the cluster key, so that it can be handed over to a worker joining the cluster.
+        pub fn export_state(
+            &self,
+            cluster_id: &ContractClusterId,
+            block_number: BlockNumber,
+            egress_sequences: BTreeMap<MessageOrigin, u64>,
+        ) -> Result<Vec<u8>> {
+            let state = ClusterState {
+                block_number,
+                cluster_id: cluster_id.clone(),
+                state_root: self.storage.root(),
+                cluster: self,
+                egress_sequences,
+            };
+            let mut data = serde_cbor::to_vec(&state).context("Failed to encode cluster state")?;
+            let iv: aead::IV = rand::random();
+            aead::encrypt(&iv, &state_transfer_key(&self.key), &mut data)
+                .map_err(|err| anyhow!("Failed to encrypt cluster state: {:?}", err))?;
+            Ok([&iv[..], &data[..]].concat())
+        }
+    }
+
+    /// The state of a cluster exported by one of its workers, see [`Cluster::export_state`].
+    #[derive(Serialize, Deserialize)]
+    pub struct ClusterState<C = Cluster> {
+        /// The last block processed before the state was exported.
+        pub block_number: BlockNumber,
+        cluster_id: ContractClusterId,
+        state_root: Hash,
+        cluster: C,
+        /// The next egress sequence of the cluster and each of its contracts.
+        pub egress_sequences: BTreeMap<MessageOrigin, u64>,
+    }
+
+    impl ClusterState {
+        /// Decrypts the state exported by another worker of the cluster.
+        pub fn decrypt(cluster_key: &sr25519::Pair, encrypted: &[u8]) -> Result<Self> {
+            if encrypted.len() < aead::IV_BYTES {
+                bail!("Cluster state too short");
+            }
+            let (iv, data) = encrypted.split_at(aead::IV_BYTES);
+            let mut data = data.to_vec();
+            let data = aead::decrypt(iv, &state_transfer_key(cluster_key), &mut data)
+                .map_err(|err| anyhow!("Failed to decrypt cluster state: {:?}", err))?;
+            let state: Self = serde_cbor::from_slice(data).context("Invalid cluster state")?;
+            if state.cluster.key.public() != cluster_key.public() {
+                bail!("The cluster key of the state mismatch");
+            }
+            Ok(state)
+        }
+    }
+
+    fn state_transfer_key(cluster_key: &sr25519::Pair) -> Vec<u8> {
+        let key = cluster_key
+            .derive_sr25519_pair(&[b"cluster state transfer"])
+            .expect("Derive state transfer key should always success!");
+        key.dump_secret_key()[..32].to_vec()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use sp_core::Pair;
+
+        fn cluster_with_contracts(
+            keeper: &mut ClusterKeeper,
+            id: &ContractClusterId,
+            key: &sr25519::Pair,
+        ) -> Vec<ContractId> {
+            let contracts = vec![
+                ContractId::from_low_u64_be(100),
+                ContractId::from_low_u64_be(101),
+            ];
+            let cluster = keeper.get_cluster_or_default_mut(id, key);
+            for contract in &contracts {
+                cluster.add_contract(*contract);
+            }
+            contracts
+        }
+
+        #[test]
+        fn joining_worker_restores_cluster_with_contracts() {
+            let id = ContractClusterId::from_low_u64_be(1);
+            let (key, _) = sr25519::Pair::generate();
+            let mut member = ClusterKeeper::default();
+            let contracts = cluster_with_contracts(&mut member, &id, &key);
+            let cluster = member.get_cluster_mut(&id).unwrap();
+            let sequences = BTreeMap::from([(MessageOrigin::Contract(contracts[1]), 3)]);
+            let exported = cluster.export_state(&id, 10, sequences.clone()).unwrap();
+
+            let mut joining = ClusterKeeper::default();
+            let state = ClusterState::decrypt(&key, &exported).unwrap();
+            assert_eq!(state.block_number, 10);
+            assert_eq!(state.egress_sequences, sequences);
+            let restored = joining.restore_cluster(&id, state).unwrap();
+            assert_eq!(restored.key().public(), key.public());
+            assert_eq!(
+                restored.iter_contracts().collect::<Vec<_>>(),
+                contracts.iter().collect::<Vec<_>>()
+            );
+            let root = restored.storage.root();
+            assert_eq!(root, member.get_cluster_mut(&id).unwrap().storage.root());
+        }
+
+        #[test]
+        fn joining_worker_rejects_bad_cluster_state() {
+            let id = ContractClusterId::from_low_u64_be(1);
+            let (key, _) = sr25519::Pair::generate();
+            let mut member = ClusterKeeper::default();
+            cluster_with_contracts(&mut member, &id, &key);
+            let cluster = member.get_cluster_mut(&id).unwrap();
+            let exported = cluster.export_state(&id, 10, Default::default()).unwrap();
+
+            let (other_key, _) = sr25519::Pair::generate();
+            assert!(ClusterState::decrypt(&other_key, &exported).is_err());
+            let mut tampered = exported.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(ClusterState::decrypt(&key, &tampered).is_err());
+            assert!(ClusterState::decrypt(&key, &exported[..4]).is_err());
+
+            let mut joining = ClusterKeeper::default();
+            let state = ClusterState::decrypt(&key, &exported).unwrap();
+            let other_id = ContractClusterId::from_low_u64_be(2);
+            assert!(joining.restore_cluster(&other_id, state).is_err());
+            let mut state = ClusterState::decrypt(&key, &exported).unwrap();
+            state.state_root = Default::default();
+            assert!(joining.restore_cluster(&id, state).is_err());
+            assert!(joining.is_empty());
+
+            let state = ClusterState::decrypt(&key, &exported).unwrap();
+            assert!(member.restore_cluster(&id, state).is_err());
+        }
+
+        #[test]
+        fn removed_worker_drops_the_cluster() {
+            let mut keeper = ClusterKeeper::default();
+            let id = ContractClusterId::from_low_u64_be(1);
+            let (key, _) = sr25519::Pair::generate();
+            let contract = ContractId::from_low_u64_be(100);
+            keeper
+                .get_cluster_or_default_mut(&id, &key)
+                .add_contract(contract);
+            assert_eq!(keeper.len(), 1);
+
+            let cluster = keeper.remove_cluster(&id).expect("The cluster was added");
+            assert_eq!(
+                cluster.iter_contracts().collect::<Vec<_>>(),
+                vec![&contract]
+            );
+            assert!(keeper.is_empty());
+            assert!(keeper.remove_cluster(&id).is_none());
+        }
     }
 }
 
@@ -9,7 +9,7 @@ use phala_scheduler::RequestScheduler;
 use runtime::BlockNumber;
 use sidevm::{
     service::{Command as SidevmCommand, CommandSender, ExitReason},
-    OcallAborted, VmId,
+    CostTable, FileSystem, OcallAborted, PersistedState, VmId,
 };
 
 use super::pink::cluster::ClusterKeeper;
@@ -110,6 +110,17 @@ struct SidevmInfo {
     start_time: String,
     auto_restart: bool,
     handle: Arc<Mutex<SidevmHandle>>,
+    /// The state blob persisted by the guest program, which is handed over to the restarted
+    /// instance after the worker is restored from a checkpoint.
+    #[serde(default)]
+    persisted_state: PersistedState,
+    /// The gas cost table of the cluster at the time the sidevm was deployed.
+    #[serde(default)]
+    cost_table: CostTable,
+    /// The virtual filesystem of the guest program. It is kept in the encrypted checkpoint, so
+    /// the files survive the restarts of the program and the worker.
+    #[serde(default)]
+    fs: FileSystem,
 }
 
 pub(crate) enum SidevmCode {
@@ -239,6 +250,7 @@ impl FatContract {
         spawner: &sidevm::service::Spawner,
         code: SidevmCode,
         ensure_waiting_code: bool,
+        cost_table: CostTable,
     ) -> Result<()> {
         let handle = self.sidevm_handle();
         if let Some(SidevmHandle::Running(_)) = &handle {
@@ -271,12 +283,25 @@ impl FatContract {
             }
         };
 
+        // Carry the state and files over when new code is pushed to an existing sidevm
+        let (persisted_state, fs) = match &self.sidevm_info {
+            Some(info) => (info.persisted_state.clone(), info.fs.clone()),
+            None => Default::default(),
+        };
         let handle = if code.is_empty() {
             Arc::new(Mutex::new(SidevmHandle::Stopped(
                 ExitReason::WaitingForCode,
             )))
         } else {
-            do_start_sidevm(spawner, &code, self.contract_id.0, self.weight)?
+            do_start_sidevm(
+                spawner,
+                &code,
+                self.contract_id.0,
+                self.weight,
+                persisted_state.clone(),
+                fs.clone(),
+                cost_table.clone(),
+            )?
         };
 
         let start_time = chrono::Utc::now().to_rfc3339();
@@ -286,6 +311,9 @@ impl FatContract {
             start_time,
             handle,
             auto_restart: true,
+            persisted_state,
+            cost_table,
+            fs,
         });
         Ok(())
     }
@@ -314,7 +342,15 @@ impl FatContract {
                     return Ok(());
                 }
                 sidevm_info.start_time = chrono::Utc::now().to_rfc3339();
-                do_start_sidevm(spawner, &sidevm_info.code, self.contract_id.0, self.weight)?
+                do_start_sidevm(
+                    spawner,
+                    &sidevm_info.code,
+                    self.contract_id.0,
+                    self.weight,
+                    sidevm_info.persisted_state.clone(),
+                    sidevm_info.fs.clone(),
+                    sidevm_info.cost_table.clone(),
+                )?
             } else {
                 return Ok(());
             };
@@ -427,15 +463,21 @@ fn do_start_sidevm(
     code: &[u8],
     id: VmId,
     weight: u32,
+    persisted_state: PersistedState,
+    fs: FileSystem,
+    cost_table: CostTable,
 ) -> Result<Arc<Mutex<SidevmHandle>>> {
     let max_memory_pages: u32 = 1024; // 64MB
     let gas_per_breath = 50_000_000_000_u64; // about 20 ms bench
-    let (sender, join_handle) = spawner.start(
+    let (sender, join_handle, _gas_counter) = spawner.start(
         &code,
         max_memory_pages,
         id,
         gas_per_breath,
         local_cache_ops(),
+        persisted_state,
+        fs,
+        Arc::new(cost_table),
         weight,
     )?;
     let handle = Arc::new(Mutex::new(SidevmHandle::Running(sender)));
@@ -57,15 +57,18 @@ pub use chain::BlockNumber;
 pub use contracts::pink;
 pub use prpc_service::RpcService;
 pub use storage::{Storage, StorageExt};
-pub use system::gk;
+pub use system::{gk, serve_sidevm_ingress};
 pub use types::BlockInfo;
 
+/// The GRANDPA light client validating the relaychain headers.
+pub type RelaychainValidator = LightValidation<chain::Runtime>;
+
 pub mod benchmark;
 
 mod bin_api_service;
 mod contracts;
 mod cryptography;
-mod light_validation;
+pub mod light_validation;
 mod prpc_service;
 mod rpc_types;
 mod secret_channel;
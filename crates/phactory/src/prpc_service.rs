@@ -10,7 +10,9 @@ use crate::system::{chain_state, System};
 use super::*;
 use crate::contracts::ContractClusterId;
 use ::pink::runtime::ExecSideEffects;
-use chain::pallet_registry::{Attestation, AttestationValidator, IasFields, IasValidator};
+use chain::pallet_registry::{
+    Attestation, AttestationValidator, IasFields, IasValidator, SgxQuoteCollateral,
+};
 use parity_scale_codec::Encode;
 use pb::{
     phactory_api_server::{PhactoryApi, PhactoryApiServer},
@@ -459,26 +461,12 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
                 info!("Encoded runtime info");
                 info!("{:?}", hex::encode(&cached_resp.encoded_runtime_info));
 
-                let (attn_report, sig, cert) =
-                    match self.platform.create_attestation_report(&runtime_info_hash) {
-                        Ok(r) => r,
-                        Err(e) => {
-                            let message = format!("Failed to create attestation report: {:?}", e);
-                            error!("{}", message);
-                            return Err(from_display(message));
-                        }
-                    };
-
-                cached_resp.attestation = Some(pb::Attestation {
-                    version: 1,
-                    provider: "SGX".to_string(),
-                    payload: Some(pb::AttestationReport {
-                        report: attn_report,
-                        signature: base64::decode(sig).map_err(from_display)?,
-                        signing_cert: base64::decode(cert).map_err(from_display)?,
-                    }),
-                    timestamp: now(),
-                });
+                let attestation = if self.args.dcap_attestation {
+                    create_dcap_attestation_on(&self.platform, &runtime_info_hash)?
+                } else {
+                    create_attestation_report_on(&self.platform, &runtime_info_hash)?
+                };
+                cached_resp.attestation = Some(attestation);
             }
         }
         Ok(cached_resp.clone())
@@ -889,6 +877,42 @@ fn create_attestation_report_on<Platform: pal::Platform>(
     })
 }
 
+/// Creates a DCAP quote of `data`, carried in the same `pb::Attestation` as IAS reports: the
+/// quote goes to `signature` and the SCALE encoded collateral goes to `signing_cert`.
+fn create_dcap_attestation_on<Platform: pal::Platform>(
+    platform: &Platform,
+    data: &[u8],
+) -> RpcResult<pb::Attestation> {
+    let (quote, collateral) = match platform.create_dcap_quote(data) {
+        Ok(r) => r,
+        Err(e) => {
+            let message = format!("Failed to create DCAP quote: {:?}", e);
+            error!("{}", message);
+            return Err(from_display(message));
+        }
+    };
+    let collateral = SgxQuoteCollateral {
+        root_ca_crl: collateral.root_ca_crl,
+        pck_crl: collateral.pck_crl,
+        tcb_info_issuer_chain: collateral.tcb_info_issuer_chain,
+        tcb_info: collateral.tcb_info,
+        tcb_info_signature: collateral.tcb_info_signature,
+        qe_identity_issuer_chain: collateral.qe_identity_issuer_chain,
+        qe_identity: collateral.qe_identity,
+        qe_identity_signature: collateral.qe_identity_signature,
+    };
+    Ok(pb::Attestation {
+        version: 1,
+        provider: "SGX-DCAP".to_string(),
+        payload: Some(pb::AttestationReport {
+            report: String::new(),
+            signature: quote,
+            signing_cert: collateral.encode(),
+        }),
+        timestamp: now(),
+    })
+}
+
 #[async_trait::async_trait]
 /// A server that process all RPCs.
 impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for RpcService<Platform> {
@@ -1087,8 +1111,15 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
             // The time from attestation report is generated by IAS, thus trusted. By default, it's valid for 10h.
             // By ensuring our system timestamp is within the valid period, we know that this pRuntime is not hold back by
             // malicious workers.
-            IasValidator::validate(&attn_to_validate, &payload_hash, block_sec, false, vec![])
-                .map_err(|_| from_display("Invalid RA report from client"))?;
+            IasValidator::validate(
+                &attn_to_validate,
+                &payload_hash,
+                block_sec,
+                false,
+                vec![],
+                vec![],
+            )
+            .map_err(|_| from_display("Invalid RA report from client"))?;
             Some(attn_to_validate)
         };
         // 2. verify challenge validity to prevent replay attack
@@ -1145,6 +1176,11 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
                         .map_err(|_| from_display("Invalid received RA report"))?;
                     ias_fields.extend_mrenclave()
                 }
+                Attestation::SgxDcap { .. } => {
+                    return Err(from_display(
+                        "DCAP attestation is not supported for handover",
+                    ))
+                }
             };
             let req_runtime_timestamp =
                 chain_state::get_pruntime_added_at(&runtime_state.chain_storage, &mrenclave)
@@ -1267,8 +1303,15 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
                 signature: payload.signature,
                 raw_signing_cert: payload.signing_cert,
             };
-            IasValidator::validate(&attn_to_validate, &worker_key_hash, now(), false, vec![])
-                .map_err(|_| from_display("Invalid RA report from server"))?;
+            IasValidator::validate(
+                &attn_to_validate,
+                &worker_key_hash,
+                now(),
+                false,
+                vec![],
+                vec![],
+            )
+            .map_err(|_| from_display("Invalid RA report from server"))?;
         } else {
             info!("Skip RA report check in dev mode");
         }
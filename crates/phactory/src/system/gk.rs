@@ -15,10 +15,11 @@ use phala_types::{
     messaging::{
         BatchRotateMasterKeyEvent, DispatchMasterKeyHistoryEvent, EncryptedKey, GatekeeperEvent,
         KeyDistribution, MessageOrigin, MiningInfoUpdateEvent, MiningReportEvent, RandomNumber,
-        RandomNumberEvent, RotateMasterKeyEvent, SettleInfo, SystemEvent, WorkerEvent,
-        WorkerEventWithKey,
+        RandomNumberEvent, ReliabilityScore, RotateMasterKeyEvent, SettleInfo, SystemEvent,
+        WorkerEvent, WorkerEventWithKey, WorkerReliabilityEvent,
     },
-    wrap_content_to_sign, EcdhPublicKey, SignedContentType, WorkerPublicKey,
+    wrap_content_to_sign, ClusterPublicKey, EcdhPublicKey, SignedContentType, WorkerIdentity,
+    WorkerPublicKey,
 };
 use serde::{Deserialize, Serialize};
 use sp_core::{hashing, sr25519, Pair};
@@ -33,13 +34,16 @@ use std::{
 use fixed_macro::types::U64F64 as fp;
 use log::{debug, info, trace};
 use phactory_api::prpc as pb;
-pub use tokenomic::{FixedPoint, TokenomicInfo};
+pub use tokenomic::{FixedPoint, TokenomicInfo, TokenomicParamsOverride};
 
 /// Block interval to generate pseudo-random on chain
 ///
 /// WARNING: this interval need to be large enough considering the latency of mq
 const VRF_INTERVAL: u32 = 5;
 
+/// Block interval to publish the worker reliability scores
+const RELIABILITY_REPORT_INTERVAL: u32 = 600;
+
 const MASTER_KEY_SHARING_SALT: &[u8] = b"master_key_sharing";
 
 // pesudo_random_number = blake2_256(last_random_number, block_number, derived_master_key)
@@ -68,6 +72,25 @@ fn get_cluster_key(master_key: &sr25519::Pair, cluster: &ContractClusterId) -> s
         .expect("should not fail with valid info")
 }
 
+/// Finds the cluster key matching the on-chain `pubkey` of a deployed cluster
+///
+/// The master key may have been rotated since the cluster was deployed, so all the historical
+/// master keys are tried, the latest first.
+fn find_cluster_key(
+    master_key_history: &[RotatedMasterKey],
+    cluster: &ContractClusterId,
+    pubkey: &ClusterPublicKey,
+) -> Option<sr25519::Pair> {
+    master_key_history
+        .iter()
+        .rev()
+        .map(|key| {
+            let master_key = sr25519::Pair::restore_from_secret_key(&key.secret);
+            get_cluster_key(&master_key, cluster)
+        })
+        .find(|key| &key.public() == pubkey)
+}
+
 #[cfg(feature = "gk-stat")]
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct WorkerStat {
@@ -77,6 +100,37 @@ struct WorkerStat {
     last_gk_responsive_event_at_block: chain::BlockNumber,
 }
 
+/// Reliability counters of a worker in the current report window.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReliabilityWindow {
+    mining_blocks: u32,
+    online_blocks: u32,
+    heartbeats: u32,
+    heartbeat_latency_sum: u32,
+    unresponsive_blocks: u32,
+}
+
+impl ReliabilityWindow {
+    fn score(&self) -> ReliabilityScore {
+        let uptime_ppm = if self.mining_blocks == 0 {
+            0
+        } else {
+            (self.online_blocks as u64 * 1_000_000 / self.mining_blocks as u64) as u32
+        };
+        let heartbeat_latency = if self.heartbeats == 0 {
+            0
+        } else {
+            self.heartbeat_latency_sum / self.heartbeats
+        };
+        ReliabilityScore {
+            uptime_ppm,
+            heartbeats: self.heartbeats,
+            heartbeat_latency,
+            unresponsive_blocks: self.unresponsive_blocks,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WorkerInfo {
     state: WorkerState,
@@ -86,6 +140,8 @@ pub struct WorkerInfo {
     heartbeat_flag: bool,
     #[cfg(feature = "gk-stat")]
     stat: WorkerStat,
+    #[serde(default)]
+    reliability: ReliabilityWindow,
 }
 
 impl WorkerInfo {
@@ -98,6 +154,7 @@ impl WorkerInfo {
             heartbeat_flag: false,
             #[cfg(feature = "gk-stat")]
             stat: Default::default(),
+            reliability: Default::default(),
         }
     }
 
@@ -433,6 +490,9 @@ where
             GatekeeperEvent::UnrespFix => {
                 // Handled by MiningEconomics
             }
+            GatekeeperEvent::EnableReliabilityReport => {
+                // Handled by MiningEconomics
+            }
         }
     }
 
@@ -489,33 +549,67 @@ where
                     });
                 // then distribute cluster key to all workers in one event
                 // the on-chain deployment state should be updated by assigned workers
-                // TODO.shelven: set up expiration
-                let secret_key = cluster_key.dump_secret_key();
-                let secret_keys: BTreeMap<_, _> = workers
-                    .into_iter()
-                    .map(|worker| {
-                        let encrypted_key = self.encrypt_key_to(
-                            &[b"cluster_key_sharing"],
-                            &worker.ecdh_pubkey,
-                            &secret_key,
-                            block.block_number,
-                        );
-                        (worker.pubkey, encrypted_key)
-                    })
-                    .collect();
-                self.egress.push_message(
-                    &ClusterOperation::<chain::AccountId, _>::batch_distribution(
-                        secret_keys,
-                        cluster,
-                        0,
-                        owner,
-                    ),
-                );
+                self.dispatch_cluster_key(block, &cluster_key, cluster, owner, workers);
+                Ok(())
+            }
+            ClusterEvent::AddWorkers {
+                owner,
+                cluster,
+                pubkey,
+                workers,
+            } => {
+                if !origin.is_pallet() {
+                    error!("Attempt to add cluster workers from bad origin");
+                    return Err(TransactionError::BadOrigin);
+                }
+
+                let cluster_key =
+                    match find_cluster_key(&self.master_key_history, &cluster, &pubkey) {
+                        Some(key) => key,
+                        None => {
+                            error!("No master key derives the cluster pubkey of {:?}", cluster);
+                            return Err(TransactionError::UnknownClusterKey);
+                        }
+                    };
+                self.dispatch_cluster_key(block, &cluster_key, cluster, owner, workers);
                 Ok(())
             }
         }
     }
 
+    /// Distributes the cluster key to the workers in one event
+    fn dispatch_cluster_key(
+        &mut self,
+        block: &BlockInfo<'_>,
+        cluster_key: &sr25519::Pair,
+        cluster: ContractClusterId,
+        owner: chain::AccountId,
+        workers: Vec<WorkerIdentity>,
+    ) {
+        // TODO.shelven: set up expiration
+        let secret_key = cluster_key.dump_secret_key();
+        let secret_keys: BTreeMap<_, _> = workers
+            .into_iter()
+            .map(|worker| {
+                let encrypted_key = self.encrypt_key_to(
+                    &[b"cluster_key_sharing"],
+                    &worker.ecdh_pubkey,
+                    &secret_key,
+                    block.block_number,
+                );
+                (worker.pubkey, encrypted_key)
+            })
+            .collect();
+        self.egress.push_message(
+            &ClusterOperation::<chain::AccountId, _>::batch_distribution(
+                secret_keys,
+                cluster,
+                0,
+                owner,
+            ),
+        );
+    }
+
     /// Verify on-chain random number
     fn process_random_number_event(&mut self, origin: MessageOrigin, event: RandomNumberEvent) {
         if !origin.is_gatekeeper() {
@@ -636,6 +730,12 @@ pub struct MiningEconomics<MsgChan> {
     /// Indicates if the payout duration problem in unresponsive state if fixed
     #[serde(default)]
     unresp_fix: bool,
+    /// Indicates if the worker reliability scores are collected and published
+    #[serde(default)]
+    reliability_report: bool,
+    /// Parameters that take precedence over the on-chain ones, used by the what-if simulations
+    #[serde(skip, default)]
+    tokenomic_params_override: Option<TokenomicParamsOverride>,
     #[serde(skip, default)]
     eco_cache: EconomicCalcCache,
 }
@@ -721,6 +821,8 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
             tokenomic_params: tokenomic::test_params(),
             phala_launched: false,
             unresp_fix: false,
+            reliability_report: false,
+            tokenomic_params_override: None,
             eco_cache: Default::default(),
         }
     }
@@ -736,6 +838,24 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
         self.workers.get(pubkey).map(Into::into)
     }
 
+    pub fn workers(&self) -> impl Iterator<Item = &WorkerInfo> {
+        self.workers.values()
+    }
+
+    /// Override some of the tokenomic parameters.
+    ///
+    /// The overrides stay in effect when the parameters are updated on-chain later. This is not
+    /// used in pRuntime, but to simulate a tokenomic parameter change in the replay tool.
+    pub fn override_tokenomic_params(&mut self, overrides: TokenomicParamsOverride) {
+        overrides.apply(&mut self.tokenomic_params);
+        info!(
+            target: "mining",
+            "Tokenomic parameter overridden: {:#?}",
+            &self.tokenomic_params
+        );
+        self.tokenomic_params_override = Some(overrides);
+    }
+
     pub fn will_process_block(&mut self, block: &BlockInfo<'_>) {
         let sum_share = self.sum_share();
         let report = MiningInfoUpdateEvent::new(block.block_number, block.now_ms);
@@ -810,6 +930,17 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
             }
 
             let params = &self.tokenomic_params;
+            if self.reliability_report {
+                let reliability = &mut worker_info.reliability;
+                reliability.mining_blocks = reliability.mining_blocks.saturating_add(1);
+                if worker_info.unresponsive {
+                    reliability.unresponsive_blocks =
+                        reliability.unresponsive_blocks.saturating_add(1);
+                } else {
+                    reliability.online_blocks = reliability.online_blocks.saturating_add(1);
+                }
+            }
+
             if worker_info.unresponsive {
                 trace!(
                     target: "mining",
@@ -835,6 +966,34 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
             debug!(target: "mining", "Report: {:?}", report);
             self.egress.push_message(report);
         }
+
+        if self.reliability_report && block.block_number % RELIABILITY_REPORT_INTERVAL == 0 {
+            self.report_reliability(block);
+        }
+    }
+
+    /// Publishes the reliability scores of the workers mining in the last window and starts a new
+    /// window.
+    fn report_reliability(&mut self, block: &BlockInfo<'_>) {
+        let scores: Vec<_> = self
+            .workers
+            .values_mut()
+            .filter(|info| info.reliability.mining_blocks > 0)
+            .map(|info| {
+                let score = info.reliability.score();
+                info.reliability = Default::default();
+                (info.state.pubkey, score)
+            })
+            .collect();
+        if scores.is_empty() {
+            return;
+        }
+        debug!(target: "mining", "Reliability report for {} workers", scores.len());
+        self.egress.push_message(&WorkerReliabilityEvent {
+            block_number: block.block_number,
+            window: RELIABILITY_REPORT_INTERVAL,
+            scores,
+        });
     }
 
     pub fn process_messages(
@@ -971,6 +1130,14 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
 
                 worker_info.heartbeat_flag = true;
 
+                if self.reliability_report {
+                    let reliability = &mut worker_info.reliability;
+                    reliability.heartbeats = reliability.heartbeats.saturating_add(1);
+                    reliability.heartbeat_latency_sum = reliability
+                        .heartbeat_latency_sum
+                        .saturating_add(block.block_number.saturating_sub(challenge_block));
+                }
+
                 let tokenomic = &mut worker_info.tokenomic;
                 tokenomic.update_p_instant(block.now_ms, iterations, contract_running);
                 tokenomic.challenge_time_last = challenge_time;
@@ -1133,6 +1300,9 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
             GatekeeperEvent::TokenomicParametersChanged(params) => {
                 if origin.is_pallet() {
                     self.tokenomic_params = params.into();
+                    if let Some(overrides) = &self.tokenomic_params_override {
+                        overrides.apply(&mut self.tokenomic_params);
+                    }
                     info!(
                         target: "mining",
                         "Tokenomic parameter updated: {:#?}",
@@ -1172,6 +1342,11 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
                     self.unresp_fix = true;
                 }
             }
+            GatekeeperEvent::EnableReliabilityReport => {
+                if origin.is_pallet() {
+                    self.reliability_report = true;
+                }
+            }
         }
     }
 
@@ -1356,6 +1531,40 @@ mod tokenomic {
         }
     }
 
+    /// The parameters to replace in [`Params`]. `None` keeps the current value.
+    #[derive(Debug, Default, Clone)]
+    pub struct TokenomicParamsOverride {
+        pub rho: Option<FixedPoint>,
+        pub slash_rate: Option<FixedPoint>,
+        pub budget_per_block: Option<FixedPoint>,
+        pub v_max: Option<FixedPoint>,
+        pub cost_k: Option<FixedPoint>,
+        pub cost_b: Option<FixedPoint>,
+        pub treasury_ratio: Option<FixedPoint>,
+        pub heartbeat_window: Option<u32>,
+    }
+
+    impl TokenomicParamsOverride {
+        pub fn apply(&self, params: &mut Params) {
+            fn set<T: Copy>(value: &Option<T>, target: &mut T) {
+                if let Some(value) = value {
+                    *target = *value;
+                }
+            }
+            set(&self.rho, &mut params.rho);
+            set(&self.slash_rate, &mut params.slash_rate);
+            set(&self.budget_per_block, &mut params.budget_per_block);
+            set(&self.v_max, &mut params.v_max);
+            set(&self.cost_k, &mut params.cost_k);
+            set(&self.cost_b, &mut params.cost_b);
+            set(&self.heartbeat_window, &mut params.heartbeat_window);
+            if let Some(treasury_ratio) = self.treasury_ratio {
+                params.treasury_ration = treasury_ratio;
+                params.payout_ration = fp!(1) - treasury_ratio;
+            }
+        }
+    }
+
     pub fn test_params() -> Params {
         Params {
             rho: fp!(1.000000666600231),
@@ -1636,6 +1845,11 @@ pub mod tests {
         fn get_worker_mut(&mut self, n: usize) -> &mut super::WorkerInfo {
             self.gk.workers.get_mut(&self.workers[n]).unwrap()
         }
+
+        fn pallet_say(&mut self, event: msg::GatekeeperEvent) {
+            let sender = MessageOrigin::Pallet(b"Pallet".to_vec());
+            self.mq.dispatch_bound(&sender, event);
+        }
     }
 
     struct ForWorker<'a> {
@@ -2029,6 +2243,173 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn gk_should_report_worker_reliability() {
+        let mut r = Roles::test_roles();
+        let mut block_number = 1;
+
+        // Enable the report & register worker
+        with_block(block_number, |block| {
+            r.pallet_say(msg::GatekeeperEvent::EnableReliabilityReport);
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::Registered(msg::WorkerInfo {
+                confidence_level: 2,
+            }));
+            r.gk.test_process_messages(block);
+        });
+
+        // Start mining & send heartbeat challenge
+        block_number += 1;
+        let challenge_block = block_number;
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::MiningStart {
+                session_id: 1,
+                init_v: fp!(1).to_bits(),
+                init_p: 100,
+            });
+            worker0.challenge();
+            r.gk.test_process_messages(block);
+        });
+
+        // Heartbeat arrives two blocks later
+        block_number += 1;
+        with_block(block_number, |block| {
+            r.gk.test_process_messages(block);
+        });
+        r.for_worker(0).heartbeat(1, challenge_block, 1000);
+        block_number += 1;
+        with_block(block_number, |block| {
+            r.gk.test_process_messages(block);
+        });
+
+        while block_number < super::RELIABILITY_REPORT_INTERVAL {
+            block_number += 1;
+            with_block(block_number, |block| {
+                r.gk.test_process_messages(block);
+            });
+        }
+
+        let messages: Vec<msg::WorkerReliabilityEvent<chain::BlockNumber>> =
+            r.gk.egress.drain_decode();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].block_number, block_number);
+        assert_eq!(messages[0].window, super::RELIABILITY_REPORT_INTERVAL);
+        assert_eq!(
+            messages[0].scores,
+            vec![(
+                r.workers[0],
+                msg::ReliabilityScore {
+                    uptime_ppm: 1_000_000,
+                    heartbeats: 1,
+                    heartbeat_latency: 2,
+                    unresponsive_blocks: 0,
+                }
+            )]
+        );
+        // A new window is started after the report
+        assert_eq!(r.get_worker(0).reliability.mining_blocks, 0);
+    }
+
+    #[test]
+    fn gk_should_find_the_key_of_a_cluster_deployed_before_rotation() {
+        use super::{find_cluster_key, get_cluster_key, RotatedMasterKey};
+        use phala_crypto::sr25519::Persistence;
+        use sp_core::Pair;
+
+        let cluster = phala_types::contract::ContractClusterId::from_low_u64_be(1);
+        let rotated = |rotation_id, seed| {
+            let key = sp_core::sr25519::Pair::from_seed(&[seed; 32]);
+            RotatedMasterKey {
+                rotation_id,
+                block_height: 0,
+                secret: key.dump_secret_key(),
+            }
+        };
+        let history = vec![rotated(0, 1), rotated(1, 2)];
+        let key_at = |n: usize| {
+            let master_key = sp_core::sr25519::Pair::restore_from_secret_key(&history[n].secret);
+            get_cluster_key(&master_key, &cluster)
+        };
+
+        for n in 0..history.len() {
+            let found = find_cluster_key(&history, &cluster, &key_at(n).public())
+                .expect("The cluster key should be found");
+            assert_eq!(found.public(), key_at(n).public());
+        }
+        let unknown = sp_core::sr25519::Pair::from_seed(&[3; 32]).public();
+        assert!(find_cluster_key(&history, &cluster, &unknown).is_none());
+    }
+
+    #[test]
+    fn gk_should_not_report_worker_reliability_until_enabled() {
+        let mut r = Roles::test_roles();
+        let mut block_number = 1;
+
+        // Register worker & start mining
+        with_block(block_number, |block| {
+            let mut worker0 = r.for_worker(0);
+            worker0.pallet_say(msg::WorkerEvent::Registered(msg::WorkerInfo {
+                confidence_level: 2,
+            }));
+            worker0.pallet_say(msg::WorkerEvent::MiningStart {
+                session_id: 1,
+                init_v: fp!(1).to_bits(),
+                init_p: 100,
+            });
+            r.gk.test_process_messages(block);
+        });
+
+        while block_number < super::RELIABILITY_REPORT_INTERVAL {
+            block_number += 1;
+            with_block(block_number, |block| {
+                r.gk.test_process_messages(block);
+            });
+        }
+        let messages: Vec<msg::WorkerReliabilityEvent<chain::BlockNumber>> =
+            r.gk.egress.drain_decode();
+        assert!(messages.is_empty());
+        assert_eq!(r.get_worker(0).reliability.mining_blocks, 0);
+
+        // Only the pallet can enable it
+        block_number += 1;
+        with_block(block_number, |block| {
+            r.for_worker(0)
+                .say(msg::GatekeeperEvent::EnableReliabilityReport);
+            r.gk.test_process_messages(block);
+        });
+        assert!(!r.gk.reliability_report);
+        block_number += 1;
+        with_block(block_number, |block| {
+            r.pallet_say(msg::GatekeeperEvent::EnableReliabilityReport);
+            r.gk.test_process_messages(block);
+        });
+        assert!(r.gk.reliability_report);
+
+        while block_number < 2 * super::RELIABILITY_REPORT_INTERVAL {
+            block_number += 1;
+            with_block(block_number, |block| {
+                r.gk.test_process_messages(block);
+            });
+        }
+        let messages: Vec<msg::WorkerReliabilityEvent<chain::BlockNumber>> =
+            r.gk.egress.drain_decode();
+        assert_eq!(messages.len(), 1);
+        // Only the blocks since the activation are counted
+        assert_eq!(
+            messages[0].scores,
+            vec![(
+                r.workers[0],
+                msg::ReliabilityScore {
+                    uptime_ppm: 1_000_000,
+                    heartbeats: 0,
+                    heartbeat_latency: 0,
+                    unresponsive_blocks: 0,
+                }
+            )]
+        );
+    }
+
     #[test]
     fn gk_should_slash_offline_workers_sliently_case4() {
         let mut r = Roles::test_roles();
@@ -2386,6 +2767,44 @@ pub mod tests {
         assert_eq!(r.get_worker(1).tokenomic.v, fp!(200.00021447729505831407));
     }
 
+    #[test]
+    fn test_tokenomic_params_override() {
+        let mut r = Roles::test_roles();
+
+        r.gk.override_tokenomic_params(super::TokenomicParamsOverride {
+            heartbeat_window: Some(20),
+            ..Default::default()
+        });
+        assert_eq!(r.gk.tokenomic_params.heartbeat_window, 20);
+
+        // The overrides should survive the on-chain updates
+        with_block(1, |block| {
+            let sender = MessageOrigin::Pallet(b"Pallet".to_vec());
+            let params = msg::TokenomicParameters {
+                pha_rate: fp!(1).to_bits(),
+                rho: fp!(1).to_bits(),
+                budget_per_block: fp!(100).to_bits(),
+                v_max: fp!(20000).to_bits(),
+                cost_k: 0,
+                cost_b: 0,
+                slash_rate: 0,
+                treasury_ratio: fp!(0.2).to_bits(),
+                heartbeat_window: 30,
+                rig_k: 0,
+                rig_b: 0,
+                re: 0,
+                k: 0,
+                kappa: 0,
+            };
+            r.mq.dispatch_bound(
+                &sender,
+                msg::GatekeeperEvent::TokenomicParametersChanged(params),
+            );
+            r.gk.test_process_messages(block);
+        });
+        assert_eq!(r.gk.tokenomic_params.heartbeat_window, 20);
+    }
+
     #[test]
     fn serde_fp_works_for_msgpack() {
         use serde::{Deserialize, Serialize};
@@ -4,7 +4,10 @@ mod master_key;
 use crate::{
     benchmark,
     contracts::{pink::cluster::Cluster, AnyContract, ContractsKeeper, ExecuteEnv, SidevmCode},
-    pink::{cluster::ClusterKeeper, ContractEventCallback, Pink},
+    pink::{
+        cluster::{ClusterKeeper, ClusterState},
+        ContractEventCallback, Pink,
+    },
     secret_channel::{ecdh_serde, SecretReceiver},
     types::{BlockInfo, OpaqueError, OpaqueQuery, OpaqueReply},
     StorageExt,
@@ -45,8 +48,8 @@ use phala_types::{
     messaging::{
         AeadIV, BatchRotateMasterKeyEvent, DispatchMasterKeyEvent, DispatchMasterKeyHistoryEvent,
         GatekeeperChange, GatekeeperLaunch, HeartbeatChallenge, KeyDistribution, MiningReportEvent,
-        NewGatekeeperEvent, PRuntimeManagementEvent, RemoveGatekeeperEvent, RetireCondition,
-        RotateMasterKeyEvent, SystemEvent, WorkerEvent,
+        NewGatekeeperEvent, PRuntimeManagementEvent, PRuntimeSchedule, RemoveGatekeeperEvent,
+        RetireCondition, RotateMasterKeyEvent, SystemEvent, WorkerEvent,
     },
     wrap_content_to_sign, EcdhPublicKey, HandoverChallenge, SignedContentType, WorkerPublicKey,
 };
@@ -57,12 +60,47 @@ use sp_io;
 
 use pink::runtime::{HookPoint, PinkEvent};
 use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::future::Future;
+use std::sync::Mutex;
 
 pub type TransactionResult = Result<pink::runtime::ExecSideEffects, TransactionError>;
 
 const MAX_SUPPORTED_CONSENSUS_VERSION: u32 = 1;
+/// How often (in blocks) to warn about a deprecated pRuntime.
+const DEPRECATION_WARNING_INTERVAL: chain::BlockNumber = 600;
+
+/// What the running pRuntime does at a block according to its deprecation schedule.
+#[derive(Debug, PartialEq, Eq)]
+enum ScheduleAction {
+    None,
+    WarnDeprecated,
+    Retire,
+}
+
+fn find_pruntime_schedule(
+    schedules: &BTreeMap<Vec<u8>, PRuntimeSchedule>,
+    pruntime_hash: Option<&[u8]>,
+) -> Option<PRuntimeSchedule> {
+    schedules.get(pruntime_hash?).cloned()
+}
+
+fn pruntime_schedule_action(
+    schedule: &PRuntimeSchedule,
+    block_number: chain::BlockNumber,
+) -> ScheduleAction {
+    if block_number >= schedule.retired_at {
+        return ScheduleAction::Retire;
+    }
+    if block_number == schedule.deprecated_at
+        || (block_number > schedule.deprecated_at
+            && block_number % DEPRECATION_WARNING_INTERVAL == 0)
+    {
+        return ScheduleAction::WarnDeprecated;
+    }
+    ScheduleAction::None
+}
 
 #[derive(Encode, Decode, Debug, Clone, thiserror::Error)]
 #[error("TransactionError: {:?}", self)]
@@ -109,6 +147,7 @@ pub enum TransactionError {
     NoClusterOnGatekeeper,
     NoPinkSystemCode,
     BadPinkSystemVersion,
+    UnknownClusterKey,
 }
 
 impl From<BadOrigin> for TransactionError {
@@ -421,6 +460,15 @@ fn get_contract_key(cluster_key: &sr25519::Pair, contract_id: &ContractId) -> sr
         .expect("should not fail with valid info")
 }
 
+/// A cluster this worker is joining, waiting for its state exported by another worker.
+#[derive(Serialize, Deserialize)]
+struct JoiningCluster {
+    #[serde(with = "more::key_bytes")]
+    key: sr25519::Pair,
+    /// The imported state, applied once the block it was exported at is processed.
+    state: Option<ClusterState>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct System<Platform> {
     platform: Platform,
@@ -451,6 +499,8 @@ pub struct System<Platform> {
 
     pub(crate) contracts: ContractsKeeper,
     pub(crate) contract_clusters: ClusterKeeper,
+    #[serde(default)]
+    joining_clusters: BTreeMap<phala_mq::ContractClusterId, JoiningCluster>,
     #[serde(skip)]
     #[serde(default = "create_sidevm_service_default")]
     sidevm_spawner: Spawner,
@@ -459,6 +509,11 @@ pub struct System<Platform> {
     pub(crate) block_number: BlockNumber,
     pub(crate) now_ms: u64,
     retired_versions: Vec<RetireCondition>,
+    #[serde(default)]
+    pruntime_schedules: BTreeMap<Vec<u8>, PRuntimeSchedule>,
+    // The hash of the running pRuntime, as registered on chain.
+    #[serde(skip)]
+    pruntime_hash: Option<Vec<u8>>,
 
     // The version flag used to coordinate the pruntime's behavior.
     pub(crate) consensus_version: u32,
@@ -477,12 +532,44 @@ fn create_sidevm_service_default() -> Spawner {
     create_sidevm_service(N_WORKERS.with(|n| n.get()))
 }
 
+/// The TLS ingress shared by all the sidevm instances in the worker.
+fn sidevm_ingress() -> sidevm::Ingress {
+    static INGRESS: Mutex<Option<sidevm::Ingress>> = Mutex::new(None);
+    INGRESS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Default::default)
+        .clone()
+}
+
+/// Start serving the sidevm TLS ingress on given address in a background thread.
+pub fn serve_sidevm_ingress(addr: String) {
+    let ingress = sidevm_ingress();
+    std::thread::Builder::new()
+        .name("sidevm-ingress".into())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create tokio runtime for the sidevm ingress");
+            if let Err(err) = runtime.block_on(ingress.serve(&addr)) {
+                error!("Sidevm ingress stopped: {err}");
+            }
+        })
+        .expect("Failed to spawn the sidevm ingress thread");
+}
+
 fn create_sidevm_service(worker_threads: usize) -> Spawner {
     let (service, spawner) = sidevm::service::service(worker_threads);
+    let spawner = spawner.with_ingress(sidevm_ingress());
     spawner.spawn(service.run(|report| match report {
-        Report::VmTerminated { id, reason } => {
+        Report::VmTerminated {
+            id,
+            reason,
+            gas_consumed,
+        } => {
             let id = hex_fmt::HexFmt(&id[..4]);
-            info!("Sidevm {id} terminated with reason: {reason:?}");
+            info!("Sidevm {id} terminated with reason: {reason:?}, gas consumed: {gas_consumed}");
         }
     }));
     spawner
@@ -508,6 +595,7 @@ impl<Platform: pal::Platform> System<Platform> {
         let identity_key = WorkerIdentityKey(identity_key);
         let pubkey = identity_key.public();
         let sender = MessageOrigin::Worker(pubkey);
+        let pruntime_hash = platform.measurement();
 
         System {
             platform,
@@ -530,10 +618,13 @@ impl<Platform: pal::Platform> System<Platform> {
             gatekeeper: None,
             contracts,
             contract_clusters: Default::default(),
+            joining_clusters: Default::default(),
             block_number: 0,
             now_ms: 0,
             sidevm_spawner: create_sidevm_service(worker_threads),
             retired_versions: vec![],
+            pruntime_schedules: Default::default(),
+            pruntime_hash,
             consensus_version: 0,
         }
     }
@@ -685,6 +776,7 @@ impl<Platform: pal::Platform> System<Platform> {
     pub fn will_process_block(&mut self, block: &mut BlockInfo) {
         self.block_number = block.block_number;
         self.now_ms = block.now_ms;
+        self.apply_joining_clusters(block);
 
         if let Some(gatekeeper) = &mut self.gatekeeper {
             gatekeeper.will_process_block(block);
@@ -792,6 +884,8 @@ impl<Platform: pal::Platform> System<Platform> {
 
         let contract_running = !self.contract_clusters.is_empty();
         benchmark::set_flag(benchmark::Flags::CONTRACT_RUNNING, contract_running);
+
+        self.check_pruntime_schedule(block.block_number);
     }
 
     fn process_system_event(&mut self, block: &BlockInfo, event: &SystemEvent) {
@@ -823,6 +917,40 @@ impl<Platform: pal::Platform> System<Platform> {
                 }
                 self.consensus_version = version;
             }
+            PRuntimeManagementEvent::SchedulePRuntimeRetirement {
+                pruntime_hash,
+                schedule,
+            } => {
+                self.pruntime_schedules.insert(pruntime_hash, schedule);
+            }
+            PRuntimeManagementEvent::CancelPRuntimeRetirement { pruntime_hash } => {
+                self.pruntime_schedules.remove(&pruntime_hash);
+            }
+        }
+    }
+
+    /// The deprecation schedule of the running pRuntime, if any.
+    pub(crate) fn pruntime_schedule(&self) -> Option<PRuntimeSchedule> {
+        find_pruntime_schedule(&self.pruntime_schedules, self.pruntime_hash.as_deref())
+    }
+
+    fn check_pruntime_schedule(&self, block_number: chain::BlockNumber) {
+        let schedule = match self.pruntime_schedule() {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        match pruntime_schedule_action(&schedule, block_number) {
+            ScheduleAction::Retire => {
+                error!("This pRuntime has been retired. Please update to the latest version.");
+                std::process::abort();
+            }
+            ScheduleAction::WarnDeprecated => {
+                warn!(
+                    "This pRuntime is deprecated and will retire at block {}. Please update to the latest version.",
+                    schedule.retired_at
+                );
+            }
+            ScheduleAction::None => {}
         }
     }
 
@@ -843,6 +971,7 @@ impl<Platform: pal::Platform> System<Platform> {
                 std::process::abort();
             }
         }
+        self.check_pruntime_schedule(self.block_number);
     }
 
     /// Update local sealed master keys if the received history is longer than existing one.
@@ -1172,17 +1301,7 @@ impl<Platform: pal::Platform> System<Platform> {
                     error!("Invalid origin {:?} sent a {:?}", origin, event);
                     anyhow::bail!("Invalid origin");
                 }
-                let cluster = match self.contract_clusters.remove_cluster(&cluster_id) {
-                    // The cluster is not deployed on this worker, just ignore it.
-                    None => return Ok(()),
-                    Some(cluster) => cluster,
-                };
-                info!("Destroying cluster {}", hex_fmt::HexFmt(&cluster_id));
-                for contract in cluster.iter_contracts() {
-                    if let Some(contract) = self.contracts.remove(&contract) {
-                        contract.destroy(&self.sidevm_spawner);
-                    }
-                }
+                self.purge_cluster(&cluster_id);
             }
             ClusterOperation::UploadResource {
                 origin,
@@ -1203,10 +1322,37 @@ impl<Platform: pal::Platform> System<Platform> {
                     cluster_id, hash
                 );
             }
+            ClusterOperation::RemoveWorker { cluster, worker } => {
+                if !origin.is_pallet() {
+                    error!("Invalid origin {:?} sent a {:?}", origin, event);
+                    anyhow::bail!("Invalid origin");
+                }
+                if worker != self.identity_key.public() {
+                    return Ok(());
+                }
+                info!("Worker: removed from cluster {}", hex_fmt::HexFmt(&cluster));
+                self.purge_cluster(&cluster);
+            }
         }
         Ok(())
     }
 
+    /// Removes the cluster and destroys its contracts on this worker
+    fn purge_cluster(&mut self, cluster_id: &phala_mq::ContractClusterId) {
+        self.joining_clusters.remove(cluster_id);
+        let cluster = match self.contract_clusters.remove_cluster(cluster_id) {
+            // The cluster is not deployed on this worker, just ignore it.
+            None => return,
+            Some(cluster) => cluster,
+        };
+        info!("Destroying cluster {}", hex_fmt::HexFmt(cluster_id));
+        for contract in cluster.iter_contracts() {
+            if let Some(contract) = self.contracts.remove(&contract) {
+                contract.destroy(&self.sidevm_spawner);
+            }
+        }
+    }
+
     fn process_contract_operation_event(
         &mut self,
         block: &mut BlockInfo,
@@ -1459,6 +1605,24 @@ impl<Platform: pal::Platform> System<Platform> {
                 error!("Cluster {:?} is already deployed", &event.cluster);
                 return Err(TransactionError::DuplicatedClusterDeploy.into());
             }
+            // A worker added to a running cluster must not start from an empty state. It waits
+            // for the state exported by another worker of the cluster to be imported instead.
+            if chain_state::cluster_joining_workers(block.storage, &event.cluster)
+                .contains(&my_pubkey)
+            {
+                info!(
+                    "Worker: joining cluster {}, waiting for its state to be imported",
+                    event.cluster
+                );
+                self.joining_clusters.insert(
+                    event.cluster,
+                    JoiningCluster {
+                        key: cluster_key,
+                        state: None,
+                    },
+                );
+                return Ok(());
+            }
             let system_code = block
                 .storage
                 .pink_system_code()
@@ -1539,6 +1703,136 @@ impl<Platform: pal::Platform> System<Platform> {
         Ok(())
     }
 
+    /// Exports the state of a deployed cluster for a worker joining the cluster.
+    pub fn export_cluster_state(
+        &mut self,
+        cluster_id: &phala_mq::ContractClusterId,
+        send_mq: &MessageSendQueue,
+    ) -> anyhow::Result<Vec<u8>> {
+        let cluster = self
+            .contract_clusters
+            .get_cluster_mut(cluster_id)
+            .context("Cluster not deployed")?;
+        let egress_sequences = cluster
+            .iter_contracts()
+            .map(|contract| MessageOrigin::Contract(*contract))
+            .chain([MessageOrigin::Cluster(*cluster_id)])
+            .map(|sender| {
+                let sequence = send_mq.next_sequence(&sender);
+                (sender, sequence)
+            })
+            .collect();
+        cluster.export_state(cluster_id, self.block_number, egress_sequences)
+    }
+
+    /// Imports the state of a cluster this worker is joining.
+    ///
+    /// Returns the block at which the state will be applied.
+    pub fn import_cluster_state(
+        &mut self,
+        cluster_id: &phala_mq::ContractClusterId,
+        encrypted_state: &[u8],
+    ) -> anyhow::Result<chain::BlockNumber> {
+        let joining = self
+            .joining_clusters
+            .get_mut(cluster_id)
+            .context("Not joining the cluster")?;
+        let state = ClusterState::decrypt(&joining.key, encrypted_state)?;
+        if state.block_number < self.block_number {
+            anyhow::bail!(
+                "The cluster state exported at block {} is older than the current block {}",
+                state.block_number,
+                self.block_number
+            );
+        }
+        let apply_at = state.block_number + 1;
+        joining.state = Some(state);
+        Ok(apply_at)
+    }
+
+    /// Deploys the joining clusters whose state was exported at the previous block.
+    fn apply_joining_clusters(&mut self, block: &mut BlockInfo) {
+        let ready: Vec<_> = self
+            .joining_clusters
+            .iter()
+            .filter(|(_, joining)| match &joining.state {
+                Some(state) => state.block_number < block.block_number,
+                None => false,
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for cluster_id in ready {
+            let state = match self
+                .joining_clusters
+                .get_mut(&cluster_id)
+                .and_then(|joining| joining.state.take())
+            {
+                Some(state) => state,
+                None => continue,
+            };
+            if state.block_number + 1 != block.block_number {
+                error!(
+                    "Outdated state of cluster {} exported at block {}, import a newer one",
+                    cluster_id, state.block_number
+                );
+                continue;
+            }
+            let report = match self.join_cluster(block, &cluster_id, state) {
+                Ok(pubkey) => WorkerClusterReport::ClusterDeployed {
+                    id: cluster_id,
+                    pubkey,
+                },
+                Err(err) => {
+                    error!("Failed to join cluster {}: {:?}", cluster_id, err);
+                    self.purge_cluster(&cluster_id);
+                    WorkerClusterReport::ClusterDeploymentFailed { id: cluster_id }
+                }
+            };
+            self.joining_clusters.remove(&cluster_id);
+            self.egress.push_message(&report);
+        }
+    }
+
+    /// Restores the cluster from the exported state and installs its contracts.
+    ///
+    /// Returns the public key of the cluster.
+    fn join_cluster(
+        &mut self,
+        block: &mut BlockInfo,
+        cluster_id: &phala_mq::ContractClusterId,
+        mut state: ClusterState,
+    ) -> anyhow::Result<sr25519::Public> {
+        let egress_sequences = core::mem::take(&mut state.egress_sequences);
+        let cluster = self.contract_clusters.restore_cluster(cluster_id, state)?;
+        for contract_id in cluster.iter_contracts() {
+            let pink = Pink::from_address(AccountId::from(contract_id.0), *cluster_id);
+            let contract_key = get_contract_key(cluster.key(), contract_id);
+            let ecdh_key = contract_key
+                .derive_ecdh_key()
+                .expect("Derive ecdh_key should not fail");
+            let code_hash = pink.instance.code_hash(&cluster.storage);
+            install_contract(
+                &mut self.contracts,
+                *contract_id,
+                pink,
+                code_hash,
+                contract_key,
+                ecdh_key,
+                block,
+                *cluster_id,
+            )?;
+        }
+        for (sender, sequence) in egress_sequences {
+            block.send_mq.set_next_sequence(sender, sequence);
+        }
+        info!(
+            "Worker: joined cluster {} with {} contracts",
+            cluster_id,
+            cluster.iter_contracts().count()
+        );
+        Ok(cluster.key().public())
+    }
+
     pub fn is_registered(&self) -> bool {
         self.worker_state.registered
     }
@@ -1582,6 +1876,7 @@ impl<Platform: pal::Platform> System<Platform> {
 impl<P: pal::Platform> System<P> {
     pub fn on_restored(&mut self) -> Result<()> {
         self.contracts.try_restart_sidevms(&self.sidevm_spawner);
+        self.pruntime_hash = self.platform.measurement();
         self.check_retirement();
         Ok(())
     }
@@ -1619,7 +1914,17 @@ impl<P: pal::Platform> System<P> {
             .contracts
             .get_mut(&contract_id)
             .ok_or_else(|| anyhow!("Contract not found"))?;
-        contract.start_sidevm(&self.sidevm_spawner, SidevmCode::Code(code), true)
+        let cost_table = self
+            .contract_clusters
+            .get_cluster_mut(&contract.cluster_id())
+            .map(|cluster| cluster.config.sidevm_cost_table.clone())
+            .unwrap_or_default();
+        contract.start_sidevm(
+            &self.sidevm_spawner,
+            SidevmCode::Code(code),
+            true,
+            cost_table,
+        )
     }
 }
 
@@ -1808,7 +2113,9 @@ pub(crate) fn apply_pink_events(
                     Some(code) => SidevmCode::Code(code),
                     None => SidevmCode::Hash(code_hash),
                 };
-                if let Err(err) = target_contract.start_sidevm(&spawner, code, false) {
+                let cost_table = cluster.config.sidevm_cost_table.clone();
+                if let Err(err) = target_contract.start_sidevm(&spawner, code, false, cost_table)
+                {
                     error!(target: "sidevm", "[{vmid}] Start sidevm failed: {:?}", err);
                 }
             }
@@ -1851,6 +2158,15 @@ pub(crate) fn apply_pink_events(
                 let contract = get_contract!(&contract);
                 contract.set_weight(weight);
             }
+            PinkEvent::SetSidevmCostTable(table) => {
+                ensure_system!();
+                info!("Set sidevm cost table for {:?} to {:?}", cluster_id, table);
+                cluster.config.sidevm_cost_table = sidevm::CostTable {
+                    instructions: table.instructions.into_iter().collect(),
+                    ocall_base: table.ocall_base,
+                    ocall_per_byte: table.ocall_per_byte,
+                };
+            }
         }
     }
 }
@@ -1958,4 +2274,64 @@ pub mod chain_state {
             storage_map_prefix_twox_64_concat(b"PhalaRegistry", b"PRuntimeAddedAt", runtime_hash);
         chain_storage.get_decoded(&key)
     }
+
+    /// The workers added to the cluster that haven't caught up with it yet
+    pub fn cluster_joining_workers(
+        chain_storage: &Storage,
+        cluster: &phala_mq::ContractClusterId,
+    ) -> Vec<WorkerPublicKey> {
+        let key = storage_map_prefix_twox_64_concat(
+            b"PhalaFatContracts",
+            b"ClusterJoiningWorkers",
+            cluster,
+        );
+        chain_storage.get_decoded(&key).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEDULE: PRuntimeSchedule = PRuntimeSchedule {
+        deprecated_at: 1000,
+        retired_at: 2000,
+    };
+
+    fn action_at(block_number: chain::BlockNumber) -> ScheduleAction {
+        pruntime_schedule_action(&SCHEDULE, block_number)
+    }
+
+    #[test]
+    fn deprecated_pruntime_warns_periodically() {
+        assert_eq!(action_at(999), ScheduleAction::None);
+        assert_eq!(action_at(1000), ScheduleAction::WarnDeprecated);
+        assert_eq!(action_at(1001), ScheduleAction::None);
+        assert_eq!(action_at(1200), ScheduleAction::WarnDeprecated);
+        // Not warned before the deprecation even at the interval
+        assert_eq!(action_at(600), ScheduleAction::None);
+    }
+
+    #[test]
+    fn retired_pruntime_stops() {
+        assert_eq!(action_at(1999), ScheduleAction::None);
+        assert_eq!(action_at(2000), ScheduleAction::Retire);
+        assert_eq!(action_at(2400), ScheduleAction::Retire);
+    }
+
+    #[test]
+    fn unknown_pruntime_has_no_schedule() {
+        let mut schedules = BTreeMap::new();
+        schedules.insert(b"scheduled".to_vec(), SCHEDULE);
+        assert_eq!(
+            find_pruntime_schedule(&schedules, Some(&b"scheduled"[..])),
+            Some(SCHEDULE)
+        );
+        assert_eq!(
+            find_pruntime_schedule(&schedules, Some(&b"other"[..])),
+            None
+        );
+        // The measurement is not available, e.g. in the native build
+        assert_eq!(find_pruntime_schedule(&schedules, None), None);
+    }
 }
@@ -87,6 +87,19 @@ impl MessageSendQueue {
         entry.dummy = dummy;
     }
 
+    /// The sequence of the next message to be sent by the sender.
+    pub fn next_sequence(&self, sender: &SenderId) -> u64 {
+        let inner = self.inner.lock();
+        inner.get(sender).map(|x| x.sequence).unwrap_or_default()
+    }
+
+    /// Continue the sequence of a sender whose earlier messages were sent by another worker.
+    pub fn set_next_sequence(&self, sender: SenderId, sequence: u64) {
+        let mut inner = self.inner.lock();
+        let entry = inner.entry(sender).or_default();
+        entry.sequence = entry.sequence.max(sequence);
+    }
+
     pub fn all_messages(&self) -> Vec<SignedMessage> {
         let inner = self.inner.lock();
         inner
@@ -9,18 +9,22 @@ use jsonrpsee::{
     RpcModule
 };
 use pallet_mq_runtime_api::MqApi;
+use pallet_stakepool_runtime_api::StakePoolApi;
 use sc_client_api::blockchain::{HeaderBackend, HeaderMetadata};
 use sc_client_api::{backend, Backend, BlockBackend, StorageProvider};
 use sc_transaction_pool_api::{InPoolTransaction, TransactionPool};
 use sp_api::{ApiExt, Core, ProvideRuntimeApi, StateBackend};
 use sp_runtime::traits::Header;
-use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use sp_runtime::{generic::BlockId, traits::Block as BlockT, AccountId32};
 use std::fmt::Display;
 use storage_changes::Error as StorageChangesError;
 
+pub use ext_types::{DeadLetter, ReleasingProjection, StakerProjection};
 pub use storage_changes::{GetStorageChangesResponse, MakeInto, StorageChanges};
 
+mod mq_dead_letters;
 mod mq_seq;
+mod stake_pool;
 mod storage_changes;
 
 /// Base code for all errors.
@@ -46,6 +50,37 @@ pub trait NodeRpcExtApi<BlockHash> {
     /// Return the next mq sequence number for given sender which take the ready transactions in count.
     #[method(name = "pha_getMqNextSequence")]
     fn get_mq_seq(&self, sender_hex: String) -> RpcResult<u64>;
+
+    /// Return the messages failed to be handled on chain and kept by the message queue pallet,
+    /// in ascending order of id.
+    /// Returns at most `limit` dead letters with ids from `start`, up to 100 per call.
+    /// Requires the runtime to support `MqApi` v2.
+    #[method(name = "pha_getMqDeadLetters")]
+    fn get_mq_dead_letters(
+        &self,
+        start: Option<u64>,
+        limit: Option<u32>,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Vec<DeadLetter>>;
+
+    /// Return the shares value, the pending rewards and the queued withdrawal of a staker in the
+    /// stake pool `pid`, or null if the user doesn't stake in the pool.
+    #[method(name = "pha_getStakePoolStaker")]
+    fn get_stake_pool_staker(
+        &self,
+        pid: u64,
+        user: AccountId32,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Option<StakerProjection>>;
+
+    /// Return the releasing stake timeline of the stake pool `pid`, or null if the pool doesn't
+    /// exist.
+    #[method(name = "pha_getStakePoolReleasing")]
+    fn get_stake_pool_releasing(
+        &self,
+        pid: u64,
+        at: Option<BlockHash>,
+    ) -> RpcResult<Option<ReleasingProjection>>;
 }
 
 /// Stuffs for custom RPC
@@ -81,7 +116,7 @@ where
         + ProvideRuntimeApi<Block>,
     Client::Api:
         sp_api::Metadata<Block> + ApiExt<Block, StateBackend = backend::StateBackendFor<BE, Block>>,
-    Client::Api: MqApi<Block>,
+    Client::Api: MqApi<Block> + StakePoolApi<Block, AccountId32, u128>,
     Block: BlockT + 'static,
     <<Block as BlockT>::Header as Header>::Number: Into<u64>,
     P: TransactionPool + 'static,
@@ -119,6 +154,38 @@ where
 
         Ok(result?)
     }
+
+    fn get_mq_dead_letters(
+        &self,
+        start: Option<u64>,
+        limit: Option<u32>,
+        at: Option<Block::Hash>,
+    ) -> RpcResult<Vec<DeadLetter>> {
+        let result = mq_dead_letters::get_dead_letters(&*self.client, start, limit, at);
+
+        Ok(result?)
+    }
+
+    fn get_stake_pool_staker(
+        &self,
+        pid: u64,
+        user: AccountId32,
+        at: Option<Block::Hash>,
+    ) -> RpcResult<Option<StakerProjection>> {
+        let result = stake_pool::get_staker(&*self.client, pid, user, at);
+
+        Ok(result?)
+    }
+
+    fn get_stake_pool_releasing(
+        &self,
+        pid: u64,
+        at: Option<Block::Hash>,
+    ) -> RpcResult<Option<ReleasingProjection>> {
+        let result = stake_pool::get_releasing(&*self.client, pid, at);
+
+        Ok(result?)
+    }
 }
 
 pub fn extend_rpc<Client, BE, Block, P>(
@@ -138,7 +205,7 @@ pub fn extend_rpc<Client, BE, Block, P>(
     Block: BlockT + 'static,
     Client::Api:
         sp_api::Metadata<Block> + ApiExt<Block, StateBackend = backend::StateBackendFor<BE, Block>>,
-    Client::Api: MqApi<Block>,
+    Client::Api: MqApi<Block> + StakePoolApi<Block, AccountId32, u128>,
     <<Block as BlockT>::Header as Header>::Number: Into<u64>,
     P: TransactionPool + 'static,
 {
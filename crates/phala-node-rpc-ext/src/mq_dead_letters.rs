@@ -0,0 +1,64 @@
+use super::*;
+use ext_types::DeadLetter;
+use pallet_mq_runtime_api::MqApi;
+use thiserror::Error;
+
+/// The max number of dead letters returned by a single query.
+const MAX_DEAD_LETTERS_PER_QUERY: u32 = 100;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("the runtime doesn't support querying dead letters, requires MqApi v2")]
+    UnsupportedRuntime,
+    #[error("{0}")]
+    ApiError(#[from] sp_api::ApiError),
+}
+
+impl From<Error> for JsonRpseeError {
+    fn from(e: Error) -> Self {
+        JsonRpseeError::Call(
+            CallError::Custom(
+                ErrorObject::owned(
+                    CUSTOM_RPC_ERROR,
+                    e.to_string(),
+                    Option::<()>::None
+                )
+            )
+        )
+    }
+}
+
+pub(super) fn get_dead_letters<Client, Block>(
+    client: &Client,
+    start: Option<u64>,
+    limit: Option<u32>,
+    at: Option<Block::Hash>,
+) -> Result<Vec<DeadLetter>, Error>
+where
+    Client: HeaderBackend<Block> + ProvideRuntimeApi<Block>,
+    Client::Api: MqApi<Block>,
+    Block: BlockT + 'static,
+    <<Block as BlockT>::Header as Header>::Number: Into<u64>,
+{
+    let at = BlockId::hash(at.unwrap_or_else(|| client.info().best_hash));
+    let api = client.runtime_api();
+    if !api.has_api_with::<dyn MqApi<Block>, _>(&at, |v| v >= 2)? {
+        return Err(Error::UnsupportedRuntime);
+    }
+    let limit = limit
+        .unwrap_or(MAX_DEAD_LETTERS_PER_QUERY)
+        .min(MAX_DEAD_LETTERS_PER_QUERY);
+    let dead_letters = api.dead_letters(&at, start.unwrap_or(0), limit)?;
+    Ok(dead_letters
+        .into_iter()
+        .map(|(id, dead_letter)| DeadLetter {
+            id,
+            sender: dead_letter.message.sender.to_string(),
+            destination: String::from_utf8_lossy(dead_letter.message.destination.path())
+                .into_owned(),
+            payload: dead_letter.message.payload,
+            error: format!("{:?}", dead_letter.error),
+            block: dead_letter.block.into(),
+        })
+        .collect())
+}
@@ -75,11 +75,11 @@ where
         );
         // since transactions in `ready()` need to be ordered by sequence
         // it's fine to continue with current iterator.
+        // A batch of messages provides the tags of continuous sequences in order.
         for tg in tx.provides() {
             if tg == &current_tag {
                 current_seq += 1;
                 current_tag = tag(&sender, current_seq);
-                break;
             }
         }
     }
@@ -0,0 +1,84 @@
+use super::*;
+use ext_types::{ReleasingProjection, ReleasingStake, StakerProjection, WithdrawalProjection};
+use pallet_stakepool_runtime_api::{self as api, StakePoolApi};
+use sp_runtime::AccountId32;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    ApiError(#[from] sp_api::ApiError),
+}
+
+impl From<Error> for JsonRpseeError {
+    fn from(e: Error) -> Self {
+        JsonRpseeError::Call(
+            CallError::Custom(
+                ErrorObject::owned(
+                    CUSTOM_RPC_ERROR,
+                    e.to_string(),
+                    Option::<()>::None
+                )
+            )
+        )
+    }
+}
+
+pub(super) fn get_staker<Client, Block>(
+    client: &Client,
+    pid: u64,
+    user: AccountId32,
+    at: Option<Block::Hash>,
+) -> Result<Option<StakerProjection>, Error>
+where
+    Client: HeaderBackend<Block> + ProvideRuntimeApi<Block>,
+    Client::Api: StakePoolApi<Block, AccountId32, u128>,
+    Block: BlockT + 'static,
+{
+    let at = BlockId::hash(at.unwrap_or_else(|| client.info().best_hash));
+    let projection = client.runtime_api().staker_projection(&at, pid, user)?;
+    Ok(projection.map(|staker| StakerProjection {
+        shares: staker.shares,
+        locked: staker.locked,
+        shares_value: staker.shares_value,
+        available_rewards: staker.available_rewards,
+        pending_rewards: staker.pending_rewards,
+        withdrawal: staker.withdrawal.map(withdrawal_projection),
+    }))
+}
+
+pub(super) fn get_releasing<Client, Block>(
+    client: &Client,
+    pid: u64,
+    at: Option<Block::Hash>,
+) -> Result<Option<ReleasingProjection>, Error>
+where
+    Client: HeaderBackend<Block> + ProvideRuntimeApi<Block>,
+    Client::Api: StakePoolApi<Block, AccountId32, u128>,
+    Block: BlockT + 'static,
+{
+    let at = BlockId::hash(at.unwrap_or_else(|| client.info().best_hash));
+    let projection = client.runtime_api().releasing_projection(&at, pid)?;
+    Ok(projection.map(|pool| ReleasingProjection {
+        free_stake: pool.free_stake,
+        releasing_stake: pool.releasing_stake,
+        queued_withdrawals: pool.queued_withdrawals,
+        timeline: pool
+            .timeline
+            .into_iter()
+            .map(|(time, stake)| ReleasingStake { time, stake })
+            .collect(),
+    }))
+}
+
+fn withdrawal_projection(withdrawal: api::WithdrawalProjection<u128>) -> WithdrawalProjection {
+    WithdrawalProjection {
+        position: withdrawal.position,
+        shares: withdrawal.shares,
+        amount: withdrawal.amount,
+        queued_ahead: withdrawal.queued_ahead,
+        start_time: withdrawal.start_time,
+        expires_at: withdrawal.expires_at,
+        fulfilled_at: withdrawal.fulfilled_at,
+    }
+}
@@ -66,3 +66,88 @@ impl<F: MakeInto<T>, T> MakeInto<Vec<T>> for Vec<F> {
         self.into_iter().map(|v| v.into_()).collect()
     }
 }
+
+/// Response for the `pha_getStakePoolStaker` RPC.
+///
+/// The balances are serialized as decimal strings to keep the precision in JavaScript.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StakerProjection {
+    #[serde(with = "balance")]
+    pub shares: u128,
+    #[serde(with = "balance")]
+    pub locked: u128,
+    #[serde(with = "balance")]
+    pub shares_value: u128,
+    #[serde(with = "balance")]
+    pub available_rewards: u128,
+    #[serde(with = "balance")]
+    pub pending_rewards: u128,
+    pub withdrawal: Option<WithdrawalProjection>,
+}
+
+/// A queued withdraw request and its expected fulfillment time, in unix seconds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalProjection {
+    pub position: u32,
+    #[serde(with = "balance")]
+    pub shares: u128,
+    #[serde(with = "balance")]
+    pub amount: u128,
+    #[serde(with = "balance")]
+    pub queued_ahead: u128,
+    pub start_time: u64,
+    pub expires_at: u64,
+    pub fulfilled_at: Option<u64>,
+}
+
+/// Response for the `pha_getStakePoolReleasing` RPC.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleasingProjection {
+    #[serde(with = "balance")]
+    pub free_stake: u128,
+    #[serde(with = "balance")]
+    pub releasing_stake: u128,
+    #[serde(with = "balance")]
+    pub queued_withdrawals: u128,
+    pub timeline: Vec<ReleasingStake>,
+}
+
+/// The stake of a cooling down worker and the unix time it can be reclaimed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleasingStake {
+    pub time: u64,
+    #[serde(with = "balance")]
+    pub stake: u128,
+}
+
+/// A message failed to be handled on chain, in the response of the `pha_getMqDeadLetters` RPC.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    pub id: u64,
+    pub sender: String,
+    /// The destination topic, decoded as utf8 lossily.
+    pub destination: String,
+    #[serde(with = "impl_serde::serialize")]
+    pub payload: Vec<u8>,
+    /// The error returned by the handler, in debug format.
+    pub error: String,
+    /// The block the message was dispatched at.
+    pub block: u64,
+}
+
+mod balance {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(value)
+    }
+
+    pub fn deserialize<'de, De: Deserializer<'de>>(der: De) -> Result<u128, De::Error> {
+        String::deserialize(der)?.parse().map_err(De::Error::custom)
+    }
+}
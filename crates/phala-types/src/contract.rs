@@ -53,12 +53,25 @@ pub mod messaging {
     bind_topic!(ClusterEvent, b"phala/cluster/event");
     #[derive(Encode, Decode, Debug)]
     pub enum ClusterEvent {
-        // TODO.shelven: enable add and remove workers
         DeployCluster {
             owner: AccountId32,
             cluster: ContractClusterId,
             workers: Vec<WorkerIdentity>,
         },
+        /// Distribute the key of a deployed cluster to the newly added workers.
+        ///
+        /// The new workers do not deploy the cluster right away. A new worker waits for the
+        /// cluster state exported by an existing worker to be imported, and reports
+        /// `ClusterDeployed` once the state is applied, or `ClusterDeploymentFailed` if the state
+        /// is rejected.
+        AddWorkers {
+            owner: AccountId32,
+            cluster: ContractClusterId,
+            /// The on-chain cluster pubkey, used to pick the master key the cluster key was
+            /// derived from.
+            pubkey: ClusterPublicKey,
+            workers: Vec<WorkerIdentity>,
+        },
     }
 
     bind_topic!(ContractOperation<CodeHash, AccountId>, b"phala/contract/op");
@@ -121,6 +134,13 @@ pub mod messaging {
             resource_type: ResourceType,
             resource_data: Vec<u8>,
         },
+        /// Remove a worker from the cluster.
+        ///
+        /// The removed worker purges its cluster state and contracts.
+        RemoveWorker {
+            cluster: ContractClusterId,
+            worker: WorkerPublicKey,
+        },
     }
 
     impl<AccountId, BlockNumber> ClusterOperation<AccountId, BlockNumber> {
@@ -144,6 +164,8 @@ pub mod messaging {
 pub enum ClusterPermission<AccountId> {
     Public,
     OnlyOwner(AccountId),
+    /// Only the owner and the deployers allowed by the owner can deploy contracts.
+    AllowList,
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
@@ -111,6 +111,24 @@ pub mod messaging {
     pub enum PRuntimeManagementEvent {
         RetirePRuntime(RetireCondition),
         SetConsensusVersion(u32),
+        /// Schedules the deprecation and retirement of the pRuntime with the given hash.
+        SchedulePRuntimeRetirement {
+            pruntime_hash: Vec<u8>,
+            schedule: PRuntimeSchedule,
+        },
+        /// Cancels the scheduled retirement of the pRuntime with the given hash.
+        CancelPRuntimeRetirement {
+            pruntime_hash: Vec<u8>,
+        },
+    }
+
+    #[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+    #[derive(Encode, Decode, Debug, TypeInfo, Clone, Copy, PartialEq, Eq)]
+    pub struct PRuntimeSchedule {
+        /// From this block on, the operators are warned to upgrade the pRuntime.
+        pub deprecated_at: u32,
+        /// From this block on, the pRuntime stops working and can no longer register.
+        pub retired_at: u32,
     }
 
     #[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
@@ -197,6 +215,32 @@ pub mod messaging {
         pub treasury: U64F64Bits,
     }
 
+    bind_topic!(
+        WorkerReliabilityEvent<BlockNumber>,
+        b"^phala/mining/reliability"
+    );
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct WorkerReliabilityEvent<BlockNumber> {
+        /// The block emiting this message.
+        pub block_number: BlockNumber,
+        /// Number of blocks covered by the scores.
+        pub window: u32,
+        /// The scores of the workers that were mining in the window.
+        pub scores: Vec<(WorkerPublicKey, ReliabilityScore)>,
+    }
+
+    #[derive(Encode, Decode, Clone, Copy, Debug, Default, PartialEq, Eq, TypeInfo)]
+    pub struct ReliabilityScore {
+        /// Ratio of the mining blocks in which the worker was responsive, in parts per million.
+        pub uptime_ppm: u32,
+        /// Number of heartbeats the worker responded to.
+        pub heartbeats: u32,
+        /// Average delay between a heartbeat challenge and its response, in blocks.
+        pub heartbeat_latency: u32,
+        /// Number of mining blocks in which the worker was unresponsive.
+        pub unresponsive_blocks: u32,
+    }
+
     // Messages: Gatekeeper launch
     bind_topic!(GatekeeperLaunch, b"phala/gatekeeper/launch");
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
@@ -393,6 +437,8 @@ pub mod messaging {
         PhalaLaunched,
         /// Fix the payout duration problem in unresponsive state
         UnrespFix,
+        /// Start publishing the worker reliability scores
+        EnableReliabilityReport,
     }
 
     impl GatekeeperEvent {
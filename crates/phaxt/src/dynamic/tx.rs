@@ -40,3 +40,13 @@ pub fn sync_offchain_message(message: SignedMessage) -> StaticTxPayload<SignedMe
     )
     .unvalidated()
 }
+
+pub fn sync_offchain_messages(messages: Vec<SignedMessage>) -> StaticTxPayload<Vec<SignedMessage>> {
+    StaticTxPayload::new(
+        "PhalaMq",
+        "sync_offchain_messages",
+        messages,
+        Default::default(),
+    )
+    .unvalidated()
+}
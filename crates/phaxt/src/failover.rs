@@ -0,0 +1,385 @@
+//! A RPC client backed by several nodes of the same chain.
+//!
+//! The nodes are health-checked periodically. Requests go to the first healthy node in the given
+//! order and fail over to the others if it fails, except for the read-heavy methods which are
+//! spread across all the healthy nodes.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use futures::StreamExt;
+use log::{info, warn};
+use serde_json::{to_value as to_json_value, value::RawValue};
+use subxt::error::RpcError;
+use subxt::rpc::{rpc_params, RpcClient, RpcClientT, RpcFuture, RpcSubscription};
+
+use crate::rpc::SystemHealth;
+use crate::{BlockNumber, Client, Config, Hash, Header};
+
+/// Interval between two health checks of the nodes.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Interval between two attempts to resubscribe a dropped subscription.
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(1);
+/// A node falling behind the best one by more finalized blocks is considered unhealthy.
+const MAX_FINALIZED_LAG: BlockNumber = 5;
+/// A node responding slower than this is considered unhealthy.
+const MAX_LATENCY: Duration = Duration::from_secs(5);
+/// The read-heavy methods to be spread across the healthy nodes.
+const BALANCED_METHODS: &[&str] = &["pha_getStorageChanges", "state_getReadProof"];
+
+#[derive(Default, Clone, Debug)]
+struct Health {
+    healthy: bool,
+    finalized: BlockNumber,
+    peers: u64,
+    latency: Duration,
+}
+
+struct Endpoint {
+    uri: String,
+    client: Mutex<Option<RpcClient>>,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    fn new(uri: String) -> Self {
+        Self {
+            uri,
+            client: Mutex::new(None),
+            health: Default::default(),
+        }
+    }
+
+    fn client(&self) -> Option<RpcClient> {
+        self.client.lock().unwrap().clone()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.health.lock().unwrap().healthy
+    }
+
+    async fn connect(&self) -> anyhow::Result<RpcClient> {
+        if let Some(client) = self.client() {
+            return Ok(client);
+        }
+        let client = Client::<Config>::from_url(&self.uri).await?;
+        let client: RpcClient = (**client.rpc()).clone();
+        *self.client.lock().unwrap() = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Query the health of the node. The health flag is left to be decided by the pool.
+    async fn check(&self) -> anyhow::Result<Health> {
+        let client = self.connect().await?;
+        let start = Instant::now();
+        let system: SystemHealth = client.request("system_health", rpc_params![]).await?;
+        let latency = start.elapsed();
+        let hash: Hash = client
+            .request("chain_getFinalizedHead", rpc_params![])
+            .await?;
+        let header: Option<Header> = client
+            .request("chain_getHeader", rpc_params![to_json_value(hash)?])
+            .await?;
+        let header = header.ok_or_else(|| anyhow!("Finalized header not found"))?;
+        Ok(Health {
+            healthy: !system.is_syncing && (system.peers > 0 || !system.should_have_peers),
+            finalized: header.number,
+            peers: system.peers,
+            latency,
+        })
+    }
+}
+
+pub(crate) struct EndpointPool {
+    endpoints: Vec<Endpoint>,
+    /// Round-robin counter of the balanced requests
+    next: AtomicUsize,
+}
+
+impl EndpointPool {
+    /// Create the pool and do the first health check.
+    pub(crate) async fn connect(uris: &[String]) -> anyhow::Result<Arc<Self>> {
+        let pool = Arc::new(Self {
+            endpoints: uris.iter().cloned().map(Endpoint::new).collect(),
+            next: AtomicUsize::new(0),
+        });
+        pool.check_health().await;
+        if !pool.endpoints.iter().any(|e| e.client().is_some()) {
+            return Err(anyhow!("Failed to connect to any of {uris:?}"));
+        }
+        tokio::spawn(Self::keep_checking(Arc::downgrade(&pool)));
+        Ok(pool)
+    }
+
+    async fn keep_checking(pool: Weak<Self>) {
+        loop {
+            tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+            match pool.upgrade() {
+                Some(pool) => pool.check_health().await,
+                None => break,
+            }
+        }
+    }
+
+    async fn check_health(&self) {
+        let results =
+            futures::future::join_all(self.endpoints.iter().map(|endpoint| endpoint.check())).await;
+        let best_finalized = results
+            .iter()
+            .filter_map(|r| r.as_ref().ok().map(|h| h.finalized))
+            .max()
+            .unwrap_or_default();
+        for (endpoint, result) in self.endpoints.iter().zip(results) {
+            let health = match result {
+                Ok(mut health) => {
+                    health.healthy &= health.finalized + MAX_FINALIZED_LAG >= best_finalized
+                        && health.latency <= MAX_LATENCY;
+                    health
+                }
+                Err(err) => {
+                    warn!("Health check of {} failed: {err:?}", endpoint.uri);
+                    // Reconnect in the next check
+                    *endpoint.client.lock().unwrap() = None;
+                    Health::default()
+                }
+            };
+            let mut current = endpoint.health.lock().unwrap();
+            if current.healthy != health.healthy {
+                let state = if health.healthy {
+                    "healthy"
+                } else {
+                    "unhealthy"
+                };
+                info!(
+                    "Node {} became {state}: finalized={} peers={} latency={:?}",
+                    endpoint.uri, health.finalized, health.peers, health.latency,
+                );
+            }
+            *current = health;
+        }
+    }
+
+    /// The endpoints to try a request on, in order of preference.
+    fn candidates(&self, balanced: bool) -> Vec<&Endpoint> {
+        let mut candidates: Vec<_> = self.endpoints.iter().filter(|e| e.is_healthy()).collect();
+        if balanced && !candidates.is_empty() {
+            let next = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+            candidates.rotate_left(next);
+        }
+        // The unhealthy ones might still be able to serve the request.
+        candidates.extend(self.endpoints.iter().filter(|e| !e.is_healthy()));
+        candidates
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        params: Option<Box<RawValue>>,
+    ) -> Result<Box<RawValue>, RpcError> {
+        // Never resubmit a transaction to another node.
+        let retry = !method.starts_with("author_");
+        let mut last_error = None;
+        for endpoint in self.candidates(BALANCED_METHODS.contains(&method)) {
+            let client = match endpoint.client() {
+                Some(client) => client,
+                None => continue,
+            };
+            match client.request_raw(method, params.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(err) => {
+                    warn!("RPC {method} failed on {}: {err}", endpoint.uri);
+                    last_error = Some(err);
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(no_endpoint))
+    }
+
+    async fn subscribe(
+        &self,
+        sub: &str,
+        params: Option<Box<RawValue>>,
+        unsub: &str,
+    ) -> Result<RpcSubscription, RpcError> {
+        // Never resubmit a watched transaction to another node.
+        let retry = !sub.starts_with("author_");
+        let mut last_error = None;
+        for endpoint in self.candidates(false) {
+            let client = match endpoint.client() {
+                Some(client) => client,
+                None => continue,
+            };
+            match client.subscribe_raw(sub, params.clone(), unsub).await {
+                Ok(subscription) => return Ok(subscription),
+                Err(err) => {
+                    warn!("Subscribing {sub} failed on {}: {err}", endpoint.uri);
+                    last_error = Some(err);
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(no_endpoint))
+    }
+}
+
+fn no_endpoint() -> RpcError {
+    RpcError("No node available".into())
+}
+
+pub(crate) struct FailoverClient(pub Arc<EndpointPool>);
+
+impl RpcClientT for FailoverClient {
+    fn request_raw<'a>(
+        &'a self,
+        method: &'a str,
+        params: Option<Box<RawValue>>,
+    ) -> RpcFuture<'a, Box<RawValue>> {
+        Box::pin(self.0.request(method, params))
+    }
+
+    /// Subscribe on a healthy node. The chain and state subscriptions are resubscribed on another
+    /// node when the node drops them, the others end with the error of the node.
+    fn subscribe_raw<'a>(
+        &'a self,
+        sub: &'a str,
+        params: Option<Box<RawValue>>,
+        unsub: &'a str,
+    ) -> RpcFuture<'a, RpcSubscription> {
+        Box::pin(async move {
+            let subscription = self.0.subscribe(sub, params.clone(), unsub).await?;
+            let pool = self.0.clone();
+            let (sub, unsub) = (sub.to_owned(), unsub.to_owned());
+            Ok(failover_subscription(
+                sub.clone(),
+                subscription,
+                move || {
+                    let pool = pool.clone();
+                    let (sub, params, unsub) = (sub.clone(), params.clone(), unsub.clone());
+                    async move { pool.subscribe(&sub, params, &unsub).await }
+                },
+            ))
+        })
+    }
+}
+
+/// Whether a dropped subscription can be reopened on another node.
+///
+/// Only the chain and state subscriptions are safe to reopen. Reopening an `author_*` one, e.g.
+/// `author_submitAndWatchExtrinsic`, would submit the transaction again.
+fn can_resubscribe(sub: &str) -> bool {
+    sub.starts_with("chain_") || sub.starts_with("state_")
+}
+
+/// Wrap `subscription` to reopen it with `resubscribe` whenever the node drops it, if it is safe
+/// to do so. Otherwise the error or the end of the subscription is passed up to the caller.
+fn failover_subscription<F, Fut>(
+    sub: String,
+    subscription: RpcSubscription,
+    resubscribe: F,
+) -> RpcSubscription
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<RpcSubscription, RpcError>> + Send,
+{
+    if !can_resubscribe(&sub) {
+        return subscription;
+    }
+    let stream = futures::stream::unfold(
+        (subscription, resubscribe),
+        move |(mut subscription, resubscribe)| {
+            let sub = sub.clone();
+            async move {
+                loop {
+                    if let Some(Ok(item)) = subscription.next().await {
+                        return Some((Ok(item), (subscription, resubscribe)));
+                    }
+                    warn!("Subscription {sub} dropped, resubscribing");
+                    loop {
+                        match resubscribe().await {
+                            Ok(new_subscription) => {
+                                subscription = new_subscription;
+                                break;
+                            }
+                            Err(_) => tokio::time::sleep(RESUBSCRIBE_INTERVAL).await,
+                        }
+                    }
+                }
+            }
+        },
+    );
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn items(items: Vec<Result<&str, &str>>) -> RpcSubscription {
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|item| match item {
+                Ok(item) => Ok(RawValue::from_string(item.into()).unwrap()),
+                Err(err) => Err(RpcError(err.into())),
+            })
+            .collect();
+        Box::pin(futures::stream::iter(items))
+    }
+
+    fn collect(subscription: RpcSubscription, n: usize) -> Vec<Result<String, String>> {
+        let items = block_on(subscription.take(n).collect::<Vec<_>>());
+        items
+            .into_iter()
+            .map(|item| item.map(|v| v.get().to_owned()).map_err(|e| e.0))
+            .collect()
+    }
+
+    #[test]
+    fn dropped_submit_and_watch_is_not_resubscribed() {
+        let resubscribed = Arc::new(AtomicUsize::new(0));
+        let counter = resubscribed.clone();
+        let subscription = failover_subscription(
+            "author_submitAndWatchExtrinsic".into(),
+            items(vec![Ok("\"ready\""), Err("connection closed")]),
+            move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async { Ok(items(vec![Ok("\"ready\"")])) }
+            },
+        );
+        assert_eq!(
+            collect(subscription, 3),
+            vec![
+                Ok("\"ready\"".to_owned()),
+                Err("connection closed".to_owned())
+            ]
+        );
+        assert_eq!(resubscribed.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn dropped_chain_subscription_is_resubscribed() {
+        let resubscribed = Arc::new(AtomicUsize::new(0));
+        let counter = resubscribed.clone();
+        let subscription = failover_subscription(
+            "chain_subscribeFinalizedHeads".into(),
+            items(vec![Ok("1"), Err("connection closed")]),
+            move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async { Ok(items(vec![Ok("2")])) }
+            },
+        );
+        assert_eq!(
+            collect(subscription, 2),
+            vec![Ok("1".to_owned()), Ok("2".to_owned())]
+        );
+        assert_eq!(resubscribed.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use std::ops::Deref;
+use std::sync::Arc;
 
 use parity_scale_codec::{Decode, Encode};
 use scale_info::TypeInfo;
@@ -10,6 +11,7 @@ use subxt::{
 
 mod chain_api;
 pub mod dynamic;
+mod failover;
 pub mod rpc;
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, TypeInfo, PartialOrd, Ord, Debug)]
@@ -52,10 +54,30 @@ pub async fn connect(uri: &str) -> Result<ChainApi> {
     let client = Client::from_url(uri)
         .await
         .context("Failed to connect to substrate")?;
+    spawn_runtime_updates(&client);
+    Ok(ChainApi(client))
+}
+
+/// Connect to several nodes of the same chain.
+///
+/// The requests go to the first healthy node and fail over to the others when it fails. The
+/// read-heavy requests are spread across all the healthy nodes.
+pub async fn connect_endpoints(uris: &[String]) -> Result<ChainApi> {
+    if let [uri] = uris {
+        return connect(uri).await;
+    }
+    let pool = failover::EndpointPool::connect(uris).await?;
+    let client = Client::from_rpc_client(Arc::new(failover::FailoverClient(pool)))
+        .await
+        .context("Failed to connect to substrate")?;
+    spawn_runtime_updates(&client);
+    Ok(ChainApi(client))
+}
+
+fn spawn_runtime_updates(client: &RpcClient) {
     let update_client = client.subscribe_to_updates();
     tokio::spawn(async move {
         let result = update_client.perform_runtime_updates().await;
         eprintln!("Runtime update failed with result={:?}", result);
     });
-    Ok(ChainApi(client))
 }
@@ -59,6 +59,11 @@ impl<'a, T: Config> ExtraRpcClient<'a, T> {
             .request("system_syncState", rpc_params![])
             .await?)
     }
+
+    /// Fetch the health status of the node
+    pub async fn system_health(&self) -> Result<SystemHealth, Error> {
+        Ok(self.client.request("system_health", rpc_params![]).await?)
+    }
 }
 
 impl<'a, T: Config> ExtraRpcClient<'a, T>
@@ -88,3 +93,15 @@ pub struct SyncState {
     #[serde(default = "Default::default")]
     pub highest_block: Option<u64>,
 }
+
+/// Health status of a node
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemHealth {
+    /// Number of connected peers
+    pub peers: u64,
+    /// Is the node syncing
+    pub is_syncing: bool,
+    /// Should this node have any peers
+    pub should_have_peers: bool,
+}
@@ -2,6 +2,7 @@
 
 extern crate alloc;
 
+use alloc::string::String;
 use alloc::vec::Vec;
 use ink_env::{emit_event, topics::state::HasRemainingTopics, Environment, Topics};
 
@@ -62,6 +63,19 @@ pub enum HookPoint {
     OnBlockEnd,
 }
 
+/// Tuning parameters of the sidevm gas metering.
+#[derive(Encode, Decode, Debug, Clone, Default)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub struct SidevmCostTable {
+    /// Gas costs of wasm instructions overriding the builtin ones, keyed by the operator name
+    /// such as `I64Add`.
+    pub instructions: Vec<(String, u64)>,
+    /// Base gas charged for each ocall.
+    pub ocall_base: u64,
+    /// Gas charged for each byte copied between the guest memory and the host in an ocall.
+    pub ocall_per_byte: u64,
+}
+
 /// System Event used to communicate between the contract and the runtime.
 #[derive(Encode, Decode, Debug)]
 #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
@@ -101,6 +115,8 @@ pub enum PinkEvent {
     SetLogHandler(AccountId),
     /// Set the weight of contract used to schedule queries and sidevm vruntime
     SetContractWeight { contract: AccountId, weight: u32 },
+    /// Set the gas cost table of sidevm instances started later in current cluster.
+    SetSidevmCostTable(SidevmCostTable),
 }
 
 impl PinkEvent {
@@ -116,6 +132,7 @@ impl PinkEvent {
             PinkEvent::ForceStopSidevm { .. } => true,
             PinkEvent::SetLogHandler(_) => false,
             PinkEvent::SetContractWeight { .. } => false,
+            PinkEvent::SetSidevmCostTable(_) => false,
         }
     }
 
@@ -131,6 +148,7 @@ impl PinkEvent {
             PinkEvent::ForceStopSidevm { .. } => "ForceStopSidevm",
             PinkEvent::SetLogHandler(_) => "SetLogHandler",
             PinkEvent::SetContractWeight { .. } => "SetContractWeight",
+            PinkEvent::SetSidevmCostTable(_) => "SetSidevmCostTable",
         }
     }
 }
@@ -243,6 +261,11 @@ pub fn set_contract_weight(contract: AccountId, weight: u32) {
     emit_event::<PinkEnvironment, _>(PinkEvent::SetContractWeight { contract, weight });
 }
 
+/// Set the gas cost table of sidevm instances started later in current cluster
+pub fn set_sidevm_cost_table(table: SidevmCostTable) {
+    emit_event::<PinkEnvironment, _>(PinkEvent::SetSidevmCostTable(table));
+}
+
 /// Pink defined environment. Used this environment to access the fat contract runtime features.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
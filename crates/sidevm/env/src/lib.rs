@@ -563,6 +563,10 @@ pub trait VmMemory {
     fn copy_to_vm(&self, data: &[u8], ptr: IntPtr) -> Result<()>;
     fn slice_from_vm(&self, ptr: IntPtr, len: IntPtr) -> Result<&[u8]>;
     fn slice_from_vm_mut(&self, ptr: IntPtr, len: IntPtr) -> Result<&mut [u8]>;
+    /// Copy the bytes out of the vm memory. Unlike `slice_from_vm`, the host owns the result.
+    fn copy_from_vm(&self, ptr: IntPtr, len: IntPtr) -> Result<Vec<u8>> {
+        Ok(self.slice_from_vm(ptr, len)?.to_vec())
+    }
 }
 
 extern "C" {
@@ -85,6 +85,14 @@ pub trait OcallFuncs {
     #[ocall(id = 214, encode_input)]
     fn tcp_connect_tls(host: String, port: u16, config: TlsClientConfig) -> Result<i32>;
 
+    /// Register a hostname at the shared TLS ingress of the host.
+    ///
+    /// The connections with given server name (SNI) are accepted by the host and can be obtained
+    /// by invoking tcp_accept on the returned resource_id. The certificate chain in `tls_config`
+    /// must be issued for the hostname by a CA trusted by the host.
+    #[ocall(id = 215, encode_input)]
+    fn tcp_listen_sni(hostname: String, tls_config: TlsServerConfig) -> Result<i32>;
+
     /// Print log message.
     #[ocall(id = 220)]
     fn log(level: log::Level, message: &str) -> Result<()>;
@@ -110,6 +118,17 @@ pub trait OcallFuncs {
     /// Create input channel
     #[ocall(id = 240, encode_output)]
     fn create_input_channel(ch: InputChannel) -> Result<i32>;
+
+    /// Persist a state blob which would be kept across restarts of the instance.
+    ///
+    /// Overwrites the previously persisted blob. The blob is saved into the worker checkpoint
+    /// and can be read back with `persisted_state` after the instance is restarted.
+    #[ocall(id = 250)]
+    fn persist_state(data: &[u8]) -> Result<()>;
+
+    /// Get the state blob persisted by this program, either in the current run or before a restart.
+    #[ocall(id = 251, encode_output)]
+    fn persisted_state() -> Result<Option<Vec<u8>>>;
 }
 
 #[repr(u8)]
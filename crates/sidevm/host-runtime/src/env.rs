@@ -5,7 +5,10 @@ use std::{
     fmt,
     future::Future,
     ops::{Deref, DerefMut},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     task::Poll::{Pending, Ready},
     time::Duration,
 };
@@ -33,8 +36,11 @@ use wasmer_middlewares::metering;
 
 use crate::{
     async_context::{get_task_cx, set_task_env, GuestWaker},
+    ingress::Ingress,
+    metering::CostTable,
     resource::{Resource, ResourceKeeper},
-    tls::{load_tls_config, TlsStream},
+    tls::TlsStream,
+    vfs::{FdTable, FileSystem},
     VmId,
 };
 
@@ -82,8 +88,14 @@ fn _sizeof_i32_must_eq_to_intptr() {
     let _ = core::mem::transmute::<i32, IntPtr>;
 }
 
-pub fn create_env(id: VmId, store: &mut Store, cache_ops: DynCacheOps) -> (Env, Imports) {
-    let raw_env = Env::new(id, cache_ops);
+pub fn create_env(
+    id: VmId,
+    store: &mut Store,
+    cache_ops: DynCacheOps,
+    persisted_state: PersistedState,
+    fs: FileSystem,
+) -> (Env, Imports) {
+    let raw_env = Env::new(id, cache_ops, persisted_state, fs);
     let env = FunctionEnv::new(store, raw_env.clone());
     let wasi_imports = wasi_env::wasi_imports(store, &env);
     (
@@ -151,6 +163,66 @@ pub trait CacheOps {
 
 pub type DynCacheOps = &'static (dyn CacheOps + Send + Sync);
 
+/// Max size in bytes of the state blob a program can persist.
+const MAX_PERSISTED_STATE_SIZE: usize = 1024 * 1024 * 4;
+
+/// A state blob persisted by the guest program.
+///
+/// The host side keeps a clone of it and hands it over to the next instance when the program
+/// is restarted, e.g. after the worker is restored from a checkpoint.
+#[derive(Clone, Default)]
+pub struct PersistedState(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl PersistedState {
+    pub fn new(state: Option<Vec<u8>>) -> Self {
+        Self(Arc::new(Mutex::new(state)))
+    }
+
+    pub fn get(&self) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, state: Option<Vec<u8>>) {
+        *self.0.lock().unwrap() = state;
+    }
+}
+
+impl Serialize for PersistedState {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.lock().unwrap().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PersistedState {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Self::new(Deserialize::deserialize(deserializer)?))
+    }
+}
+
+/// The gas consumed by an instance so far, readable while the instance is running.
+#[derive(Clone, Default)]
+pub struct GasCounter(Arc<AtomicU64>);
+
+impl GasCounter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, gas: u64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_add(gas))
+            });
+    }
+}
+
 struct VmMemory(Option<Memory>);
 
 pub(crate) struct EnvInner {
@@ -168,6 +240,12 @@ pub(crate) struct EnvInner {
     cache_ops: DynCacheOps,
     weight: u32,
     instance: Option<Instance>,
+    persisted_state: PersistedState,
+    cost_table: Arc<CostTable>,
+    gas_consumed: GasCounter,
+    fs: FileSystem,
+    fds: FdTable,
+    ingress: Option<Ingress>,
 }
 
 impl VmMemory {
@@ -176,17 +254,35 @@ impl VmMemory {
     }
 }
 
-struct MemoryView<'a>(wasmer::MemoryView<'a>);
+struct MemoryView<'a> {
+    view: wasmer::MemoryView<'a>,
+    /// Number of bytes copied in or out of the guest memory, used to charge the ocall.
+    ///
+    /// The slices borrowed from the guest memory are not counted.
+    bytes_copied: Cell<usize>,
+}
 
 impl<'a> Deref for MemoryView<'a> {
     type Target = wasmer::MemoryView<'a>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.view
     }
 }
 
 impl<'a> MemoryView<'a> {
+    fn new(view: wasmer::MemoryView<'a>) -> Self {
+        Self {
+            view,
+            bytes_copied: Cell::new(0),
+        }
+    }
+
+    fn add_bytes_copied(&self, len: usize) {
+        self.bytes_copied
+            .set(self.bytes_copied.get().saturating_add(len));
+    }
+
     fn check_addr(&self, offset: usize, len: usize) -> Result<(usize, usize)> {
         let end = offset.checked_add(len).ok_or(OcallError::InvalidAddress)?;
         if end > self.size().bytes().0 {
@@ -203,6 +299,7 @@ impl<'a> env::VmMemory for MemoryView<'a> {
         }
         self.write(ptr as _, data)
             .or(Err(OcallError::InvalidAddress))?;
+        self.add_bytes_copied(data.len());
         Ok(())
     }
 
@@ -217,6 +314,12 @@ impl<'a> env::VmMemory for MemoryView<'a> {
         let slice = unsafe { &mut self.data_unchecked_mut()[offset..end] };
         Ok(slice)
     }
+
+    fn copy_from_vm(&self, ptr: IntPtr, len: IntPtr) -> Result<Vec<u8>> {
+        let data = self.slice_from_vm(ptr, len)?.to_vec();
+        self.add_bytes_copied(data.len());
+        Ok(data)
+    }
 }
 
 #[derive(Clone)]
@@ -225,7 +328,12 @@ pub struct Env {
 }
 
 impl Env {
-    fn new(id: VmId, cache_ops: DynCacheOps) -> Self {
+    fn new(
+        id: VmId,
+        cache_ops: DynCacheOps,
+        persisted_state: PersistedState,
+        fs: FileSystem,
+    ) -> Self {
         Self {
             inner: Arc::new(Mutex::new(EnvInner {
                 memory: VmMemory(None),
@@ -242,6 +350,12 @@ impl Env {
                 cache_ops,
                 weight: 1,
                 instance: None,
+                persisted_state,
+                cost_table: Default::default(),
+                gas_consumed: Default::default(),
+                fs,
+                fds: Default::default(),
+                ingress: None,
             })),
         }
     }
@@ -333,6 +447,11 @@ impl Env {
         log::debug!(target: "sidevm", "[{}] Updated weight to {}", vm_id, weight);
     }
 
+    /// Allow the instance to register hostnames at the shared TLS ingress.
+    pub fn set_ingress(&self, ingress: Ingress) {
+        self.inner.lock().unwrap().ingress = Some(ingress);
+    }
+
     pub fn set_instance(&self, instance: Instance) {
         self.inner.lock().unwrap().instance = Some(instance);
     }
@@ -340,6 +459,30 @@ impl Env {
     pub fn is_stifled(&self, store: &mut impl AsStoreMut) -> bool {
         self.inner.lock().unwrap().is_stifled(store)
     }
+
+    pub fn set_cost_table(&self, cost_table: Arc<CostTable>) {
+        self.inner.lock().unwrap().cost_table = cost_table;
+    }
+
+    /// Add the gas consumed in current breath to the total gas consumed.
+    ///
+    /// Should be called right after the guest returned from a poll.
+    pub fn account_gas_consumed(&self, store: &mut impl AsStoreMut) {
+        let inner = self.inner.lock().unwrap();
+        let remaining = inner.gas_to_breath(store);
+        let consumed = inner.gas_per_breath.saturating_sub(remaining);
+        inner.gas_consumed.add(consumed);
+    }
+
+    /// Total gas consumed by the instance, including the gas charged for ocalls.
+    pub fn gas_consumed(&self) -> u64 {
+        self.inner.lock().unwrap().gas_consumed.get()
+    }
+
+    /// A handle to read the gas consumed while the instance is running.
+    pub fn gas_counter(&self) -> GasCounter {
+        self.inner.lock().unwrap().gas_consumed.clone()
+    }
 }
 
 impl<'a, 'b> env::OcallEnv for FnEnvMut<'a, &'b mut EnvInner> {
@@ -425,6 +568,16 @@ impl<'a, 'b> env::OcallFuncs for FnEnvMut<'a, &'b mut EnvInner> {
                     listener,
                     tls_config,
                 } => (listener, tls_config),
+                Resource::SniListener(rx) => {
+                    return match get_task_cx(waker, |ct| rx.poll_recv(ct)) {
+                        Pending => Err(OcallError::Pending),
+                        Ready(None) => Err(OcallError::EndOfFile),
+                        Ready(Some((stream, addr))) => self
+                            .resources
+                            .push(Resource::TlsStream(stream))
+                            .map(|res_id| (res_id, addr.to_string())),
+                    };
+                }
                 _ => return Err(OcallError::UnsupportedOperation),
             };
             let (stream, addr) = match get_task_cx(waker, |ct| listener.poll_accept(ct)) {
@@ -444,6 +597,15 @@ impl<'a, 'b> env::OcallFuncs for FnEnvMut<'a, &'b mut EnvInner> {
             .map(|res_id| (res_id, remote_addr.to_string()))
     }
 
+    fn tcp_listen_sni(&mut self, hostname: String, tls_config: TlsServerConfig) -> Result<i32> {
+        let ingress = self
+            .ingress
+            .as_ref()
+            .ok_or(OcallError::UnsupportedOperation)?;
+        let rx = ingress.register(self.id, &hostname, tls_config)?;
+        self.resources.push(Resource::SniListener(rx))
+    }
+
     fn tcp_accept_no_addr(&mut self, waker_id: i32, resource_id: i32) -> Result<i32> {
         self.tcp_accept(waker_id, resource_id)
             .map(|(res_id, _)| res_id)
@@ -551,6 +713,18 @@ impl<'a, 'b> env::OcallFuncs for FnEnvMut<'a, &'b mut EnvInner> {
         }
     }
 
+    fn persist_state(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_PERSISTED_STATE_SIZE {
+            return Err(OcallError::ResourceLimited);
+        }
+        self.persisted_state.set(Some(data.to_vec()));
+        Ok(())
+    }
+
+    fn persisted_state(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.persisted_state.get())
+    }
+
     fn gas_remaining(&mut self) -> Result<u8> {
         self.inner.pay(&mut self.store, 1_000_000)?;
         Ok(if self.gas_per_breath == 0 {
@@ -671,12 +845,15 @@ fn do_ocall(
     let env = &mut *guard;
 
     env.current_task = task_id;
-    let result = set_task_env(env.awake_tasks.clone(), task_id, || {
+    let (result, bytes_copied) = set_task_env(env.awake_tasks.clone(), task_id, || {
         let memory = env.memory.unwrap_ref().clone();
-        let vm = MemoryView(memory.view(&func_env));
+        let vm = MemoryView::new(memory.view(&func_env));
         let mut state = env.make_mut(&mut func_env);
-        env::dispatch_ocall(fast_return, &mut state, &vm, func_id, p0, p1, p2, p3)
+        let result = env::dispatch_ocall(fast_return, &mut state, &vm, func_id, p0, p1, p2, p3);
+        (result, vm.bytes_copied.get())
     });
+    let cost = env.cost_table.ocall_cost(bytes_copied);
+    env.pay(&mut func_env, cost)?;
 
     if env.ocall_trace_enabled {
         let func_name = env::ocall_id2name(func_id);
@@ -722,3 +899,73 @@ impl fmt::Display for OcallAborted {
 }
 
 impl std::error::Error for OcallAborted {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use env::OcallFuncs;
+    use wasmer::{BaseTunables, Engine, Pages};
+    use wasmer_compiler_singlepass::Singlepass;
+
+    struct NoCache;
+
+    impl CacheOps for NoCache {
+        fn get(&self, _contract: &[u8], _key: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+        fn set(&self, _contract: &[u8], _key: &[u8], _value: &[u8]) -> Result<()> {
+            Ok(())
+        }
+        fn set_expiration(&self, _contract: &[u8], _key: &[u8], _secs: u64) -> Result<()> {
+            Ok(())
+        }
+        fn remove(&self, _contract: &[u8], _key: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+    }
+
+    fn new_store() -> Store {
+        let engine: Engine = Singlepass::default().into();
+        let tunables = BaseTunables {
+            static_memory_bound: Pages(0),
+            static_memory_offset_guard_size: 0,
+            dynamic_memory_offset_guard_size: 0,
+        };
+        Store::new_with_tunables(&engine, tunables)
+    }
+
+    fn new_env(persisted_state: PersistedState) -> Env {
+        Env::new([0; 32], &NoCache, persisted_state, Default::default())
+    }
+
+    #[test]
+    fn persist_state_works() {
+        let mut store = new_store();
+        let state = PersistedState::default();
+        let env = new_env(state.clone());
+        let mut guard = env.inner.lock().unwrap();
+        let mut env = guard.make_mut(&mut store);
+        assert_eq!(env.persisted_state().unwrap(), None);
+        env.persist_state(b"hello").unwrap();
+        assert_eq!(env.persisted_state().unwrap(), Some(b"hello".to_vec()));
+        assert!(matches!(
+            env.persist_state(&vec![0; MAX_PERSISTED_STATE_SIZE + 1]),
+            Err(OcallError::ResourceLimited)
+        ));
+        // The host side sees the state
+        assert_eq!(state.get(), Some(b"hello".to_vec()));
+    }
+
+    #[test]
+    fn persisted_state_survives_checkpoint() {
+        let state = PersistedState::new(Some(b"hello".to_vec()));
+        let checkpoint = serde_json::to_vec(&state).unwrap();
+        let restored: PersistedState = serde_json::from_slice(&checkpoint).unwrap();
+
+        let mut store = new_store();
+        let env = new_env(restored);
+        let mut guard = env.inner.lock().unwrap();
+        let mut env = guard.make_mut(&mut store);
+        assert_eq!(env.persisted_state().unwrap(), Some(b"hello".to_vec()));
+    }
+}
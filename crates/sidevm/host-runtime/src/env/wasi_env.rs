@@ -1,4 +1,5 @@
-use super::{Env as WasiEnv, Result};
+use super::{Env as WasiEnv, EnvInner, OcallAborted, Result, ShortId};
+use crate::vfs::{join_path, FsError, NodeKind, OpenFile, OpenOptions, Stat, ROOT_FD};
 use libc::{
     clock_getres, clock_gettime, timespec, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID,
     CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID,
@@ -6,7 +7,8 @@ use libc::{
 use sidevm_env::{OcallError, OcallFuncs};
 use thiserror::Error;
 use wasmer::{
-    namespace, AsStoreMut, Exports, Function, FunctionEnv, FunctionEnvMut, Memory32, WasmPtr,
+    namespace, AsStoreMut, Exports, Function, FunctionEnv, FunctionEnvMut, Memory32, MemoryView,
+    WasmPtr,
 };
 use wasmer_wasi_types::*;
 
@@ -192,46 +194,87 @@ pub fn environ_sizes_get(
 }
 
 pub fn fd_advise(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
     _offset: __wasi_filesize_t,
     _len: __wasi_filesize_t,
     _advice: __wasi_advice_t,
 ) -> __wasi_errno_t {
-    __WASI_ESUCCESS
+    with_state(&env, |state, _| {
+        wasi_try!(state.fds.get_mut(fd).ok_or(__WASI_EBADF));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_allocate(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _offset: __wasi_filesize_t,
-    _len: __wasi_filesize_t,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    offset: __wasi_filesize_t,
+    len: __wasi_filesize_t,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, _| {
+        let file = wasi_try!(open_file(state, fd, NodeKind::File));
+        let end = wasi_try!(offset.checked_add(len), __WASI_EFBIG);
+        let path = file.path.clone();
+        let stat = wasi_try!(state.fs.stat(&path).map_err(fs_errno));
+        if end > stat.size {
+            wasi_try!(state.fs.set_len(&path, end).map_err(fs_errno));
+        }
+        __WASI_ESUCCESS
+    })
 }
 
-pub fn fd_close(_env: FunctionEnvMut<WasiEnv>, _fd: __wasi_fd_t) -> __wasi_errno_t {
-    __WASI_ENOSYS
+pub fn fd_close(env: FunctionEnvMut<WasiEnv>, fd: __wasi_fd_t) -> __wasi_errno_t {
+    with_state(&env, |state, _| {
+        if is_stdio(fd) {
+            return __WASI_ESUCCESS;
+        }
+        if fd == ROOT_FD {
+            return __WASI_ENOTSUP;
+        }
+        wasi_try!(state.fds.remove(fd).ok_or(__WASI_EBADF));
+        __WASI_ESUCCESS
+    })
 }
 
-pub fn fd_datasync(_env: FunctionEnvMut<WasiEnv>, _fd: __wasi_fd_t) -> __wasi_errno_t {
-    __WASI_ENOSYS
+pub fn fd_datasync(env: FunctionEnvMut<WasiEnv>, fd: __wasi_fd_t) -> __wasi_errno_t {
+    fd_sync(env, fd)
 }
 
 pub fn fd_fdstat_get(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _buf_ptr: WasmPtr<__wasi_fdstat_t>,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    buf_ptr: WasmPtr<__wasi_fdstat_t>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let (filetype, flags) = if is_stdio(fd) {
+            (__WASI_FILETYPE_CHARACTER_DEVICE, 0)
+        } else {
+            let file = wasi_try!(state.fds.get_mut(fd).ok_or(__WASI_EBADF));
+            let flags = if file.append { __WASI_FDFLAG_APPEND } else { 0 };
+            (filetype(file.kind), flags)
+        };
+        // struct fdstat { u8 filetype; u16 flags; u64 rights_base; u64 rights_inheriting; }
+        let mut buf = [0u8; 24];
+        buf[0] = filetype;
+        buf[2..4].copy_from_slice(&flags.to_le_bytes());
+        buf[8..16].copy_from_slice(&ALL_RIGHTS.to_le_bytes());
+        buf[16..24].copy_from_slice(&ALL_RIGHTS.to_le_bytes());
+        wasi_try!(write_bytes(memory, buf_ptr.offset(), &buf));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_fdstat_set_flags(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _flags: __wasi_fdflags_t,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    flags: __wasi_fdflags_t,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, _| {
+        let file = wasi_try!(open_file(state, fd, NodeKind::File));
+        file.append = flags & __WASI_FDFLAG_APPEND != 0;
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_fdstat_set_rights(
@@ -244,19 +287,32 @@ pub fn fd_fdstat_set_rights(
 }
 
 pub fn fd_filestat_get(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _buf: WasmPtr<__wasi_filestat_t>,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    buf: WasmPtr<__wasi_filestat_t>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let file = wasi_try!(state.fds.get_mut(fd).ok_or(__WASI_EBADF));
+        let stat = wasi_try!(state.fs.stat(&file.path).map_err(fs_errno));
+        wasi_try!(write_bytes(memory, buf.offset(), &encode_filestat(&stat)));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_filestat_set_size(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _st_size: __wasi_filesize_t,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    st_size: __wasi_filesize_t,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, _| {
+        let file = wasi_try!(open_file(state, fd, NodeKind::File));
+        if !file.writable {
+            return __WASI_EBADF;
+        }
+        let path = file.path.clone();
+        wasi_try!(state.fs.set_len(&path, st_size).map_err(fs_errno));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_filestat_set_times(
@@ -266,127 +322,228 @@ pub fn fd_filestat_set_times(
     _st_mtim: __wasi_timestamp_t,
     _fst_flags: __wasi_fstflags_t,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    // Timestamps are not tracked in the virtual filesystem.
+    __WASI_ESUCCESS
 }
 
 pub fn fd_pread(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _iovs: WasmPtr<__wasi_iovec_t<Memory32>>,
-    _iovs_len: u32,
-    _offset: __wasi_filesize_t,
-    _nread: WasmPtr<u32>,
-) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    mut env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_iovec_t<Memory32>>,
+    iovs_len: u32,
+    offset: __wasi_filesize_t,
+    nread: WasmPtr<u32>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    do_fd_read(&mut env, fd, iovs.offset(), iovs_len, Some(offset), nread)
 }
 
 pub fn fd_prestat_get(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _buf: WasmPtr<__wasi_prestat_t>,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    buf: WasmPtr<__wasi_prestat_t>,
 ) -> __wasi_errno_t {
-    __WASI_EBADF
+    if fd != ROOT_FD {
+        return __WASI_EBADF;
+    }
+    with_state(&env, |state, memory| {
+        wasi_try!(state.fds.get_mut(fd).ok_or(__WASI_EBADF));
+        // struct prestat { u8 tag; u32 pr_name_len; }
+        let mut prestat = [0u8; 8];
+        prestat[0] = __WASI_PREOPENTYPE_DIR;
+        prestat[4..8].copy_from_slice(&(ROOT_NAME.len() as u32).to_le_bytes());
+        wasi_try!(write_bytes(memory, buf.offset(), &prestat));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_prestat_dir_name(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _path: WasmPtr<u8>,
-    _path_len: u32,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    path: WasmPtr<u8>,
+    path_len: u32,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    if fd != ROOT_FD {
+        return __WASI_EBADF;
+    }
+    if (path_len as usize) < ROOT_NAME.len() {
+        return __WASI_ENAMETOOLONG;
+    }
+    with_state(&env, |_, memory| {
+        wasi_try!(write_bytes(memory, path.offset(), ROOT_NAME.as_bytes()));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_pwrite(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _iovs: WasmPtr<__wasi_ciovec_t<Memory32>>,
-    _iovs_len: u32,
-    _offset: __wasi_filesize_t,
-    _nwritten: WasmPtr<u32>,
-) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    mut env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_ciovec_t<Memory32>>,
+    iovs_len: u32,
+    offset: __wasi_filesize_t,
+    nwritten: WasmPtr<u32>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    do_fd_write(
+        &mut env,
+        fd,
+        iovs.offset(),
+        iovs_len,
+        Some(offset),
+        nwritten,
+    )
 }
 
 pub fn fd_read(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _iovs: WasmPtr<__wasi_iovec_t<Memory32>>,
-    _iovs_len: u32,
-    _nread: WasmPtr<u32>,
-) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    mut env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_iovec_t<Memory32>>,
+    iovs_len: u32,
+    nread: WasmPtr<u32>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    do_fd_read(&mut env, fd, iovs.offset(), iovs_len, None, nread)
 }
 
 pub fn fd_readdir(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _buf: WasmPtr<u8>,
-    _buf_len: u32,
-    _cookie: __wasi_dircookie_t,
-    _bufused: WasmPtr<u32>,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    buf: WasmPtr<u8>,
+    buf_len: u32,
+    cookie: __wasi_dircookie_t,
+    bufused: WasmPtr<u32>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let path = wasi_try!(open_file(state, fd, NodeKind::Dir)).path.clone();
+        let entries = wasi_try!(state.fs.read_dir(&path).map_err(fs_errno));
+        let mut output = Vec::new();
+        for (i, (name, stat)) in entries.iter().enumerate().skip(cookie as usize) {
+            // struct dirent { u64 d_next; u64 d_ino; u32 d_namlen; u8 d_type; }
+            let mut dirent = [0u8; 24];
+            dirent[0..8].copy_from_slice(&(i as u64 + 1).to_le_bytes());
+            dirent[8..16].copy_from_slice(&stat.ino.to_le_bytes());
+            dirent[16..20].copy_from_slice(&(name.len() as u32).to_le_bytes());
+            dirent[20] = filetype(stat.kind);
+            output.extend_from_slice(&dirent);
+            output.extend_from_slice(name.as_bytes());
+            if output.len() >= buf_len as usize {
+                break;
+            }
+        }
+        // The last entry can be truncated, which tells the guest that the buffer is full.
+        output.truncate(buf_len as usize);
+        wasi_try!(write_bytes(memory, buf.offset(), &output));
+        wasi_try!(
+            bufused.deref(memory).write(output.len() as u32).ok(),
+            __WASI_EFAULT
+        );
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_renumber(
-    _env: FunctionEnvMut<WasiEnv>,
-    _from: __wasi_fd_t,
-    _to: __wasi_fd_t,
+    env: FunctionEnvMut<WasiEnv>,
+    from: __wasi_fd_t,
+    to: __wasi_fd_t,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, _| {
+        if is_stdio(from) || is_stdio(to) {
+            return __WASI_ENOTSUP;
+        }
+        wasi_try!(state.fds.renumber(from, to).map_err(|err| match err {
+            FsError::NotFound => __WASI_EBADF,
+            err => fs_errno(err),
+        }));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_seek(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _offset: __wasi_filedelta_t,
-    _whence: __wasi_whence_t,
-    _newoffset: WasmPtr<__wasi_filesize_t>,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    offset: __wasi_filedelta_t,
+    whence: __wasi_whence_t,
+    newoffset: WasmPtr<__wasi_filesize_t>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let file = wasi_try!(open_file(state, fd, NodeKind::File));
+        let (path, current) = (file.path.clone(), file.offset);
+        // The whence values defined in wasi_snapshot_preview1.
+        let base = match whence {
+            0 => 0,
+            1 => current,
+            2 => wasi_try!(state.fs.stat(&path).map_err(fs_errno)).size,
+            _ => return __WASI_EINVAL,
+        };
+        let file = wasi_try!(open_file(state, fd, NodeKind::File));
+        let pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+        };
+        file.offset = wasi_try!(pos, __WASI_EINVAL);
+        wasi_try!(
+            newoffset.deref(memory).write(file.offset).ok(),
+            __WASI_EFAULT
+        );
+        __WASI_ESUCCESS
+    })
 }
 
-pub fn fd_sync(_env: FunctionEnvMut<WasiEnv>, _fd: __wasi_fd_t) -> __wasi_errno_t {
-    __WASI_ENOSYS
+pub fn fd_sync(env: FunctionEnvMut<WasiEnv>, fd: __wasi_fd_t) -> __wasi_errno_t {
+    // Everything is in memory, nothing to flush.
+    with_state(&env, |state, _| {
+        wasi_try!(state.fds.get_mut(fd).ok_or(__WASI_EBADF));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_tell(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _offset: WasmPtr<__wasi_filesize_t>,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    offset: WasmPtr<__wasi_filesize_t>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let file = wasi_try!(open_file(state, fd, NodeKind::File));
+        wasi_try!(offset.deref(memory).write(file.offset).ok(), __WASI_EFAULT);
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn fd_write(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _iovs: WasmPtr<__wasi_ciovec_t<Memory32>>,
-    _iovs_len: u32,
-    _nwritten: WasmPtr<u32>,
-) -> __wasi_errno_t {
-    __WASI_ESUCCESS
+    mut env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    iovs: WasmPtr<__wasi_ciovec_t<Memory32>>,
+    iovs_len: u32,
+    nwritten: WasmPtr<u32>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    do_fd_write(&mut env, fd, iovs.offset(), iovs_len, None, nwritten)
 }
 
 pub fn path_create_directory(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _path: WasmPtr<u8>,
-    _path_len: u32,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    path: WasmPtr<u8>,
+    path_len: u32,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let path = wasi_try!(resolve_path(state, memory, fd, path, path_len));
+        wasi_try!(state.fs.create_dir(&path).map_err(fs_errno));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn path_filestat_get(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
     _flags: __wasi_lookupflags_t,
-    _path: WasmPtr<u8>,
-    _path_len: u32,
-    _buf: WasmPtr<__wasi_filestat_t>,
+    path: WasmPtr<u8>,
+    path_len: u32,
+    buf: WasmPtr<__wasi_filestat_t>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let path = wasi_try!(resolve_path(state, memory, fd, path, path_len));
+        let stat = wasi_try!(state.fs.stat(&path).map_err(fs_errno));
+        wasi_try!(write_bytes(memory, buf.offset(), &encode_filestat(&stat)));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn path_filestat_set_times(
@@ -399,7 +556,8 @@ pub fn path_filestat_set_times(
     _st_mtim: __wasi_timestamp_t,
     _fst_flags: __wasi_fstflags_t,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    // Timestamps are not tracked in the virtual filesystem.
+    __WASI_ESUCCESS
 }
 
 pub fn path_link(
@@ -416,18 +574,43 @@ pub fn path_link(
 }
 
 pub fn path_open(
-    _env: FunctionEnvMut<WasiEnv>,
-    _dirfd: __wasi_fd_t,
+    env: FunctionEnvMut<WasiEnv>,
+    dirfd: __wasi_fd_t,
     _dirflags: __wasi_lookupflags_t,
-    _path: WasmPtr<u8>,
-    _path_len: u32,
-    _o_flags: __wasi_oflags_t,
-    _fs_rights_base: __wasi_rights_t,
+    path: WasmPtr<u8>,
+    path_len: u32,
+    o_flags: __wasi_oflags_t,
+    fs_rights_base: __wasi_rights_t,
     _fs_rights_inheriting: __wasi_rights_t,
-    _fs_flags: __wasi_fdflags_t,
-    _fd: WasmPtr<__wasi_fd_t>,
+    fs_flags: __wasi_fdflags_t,
+    fd: WasmPtr<__wasi_fd_t>,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let path = wasi_try!(resolve_path(state, memory, dirfd, path, path_len));
+        let options = OpenOptions {
+            create: o_flags & __WASI_O_CREAT != 0,
+            exclusive: o_flags & __WASI_O_EXCL != 0,
+            truncate: o_flags & __WASI_O_TRUNC != 0,
+            directory: o_flags & __WASI_O_DIRECTORY != 0,
+        };
+        let kind = wasi_try!(state.fs.open(&path, options).map_err(fs_errno));
+        let new_fd = wasi_try!(state
+            .fds
+            .insert(OpenFile {
+                path,
+                kind,
+                offset: 0,
+                append: fs_flags & __WASI_FDFLAG_APPEND != 0,
+                readable: fs_rights_base & __WASI_RIGHT_FD_READ != 0,
+                writable: fs_rights_base & __WASI_RIGHT_FD_WRITE != 0,
+            })
+            .map_err(fs_errno));
+        if fd.deref(memory).write(new_fd).is_err() {
+            state.fds.remove(new_fd);
+            return __WASI_EFAULT;
+        }
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn path_readlink(
@@ -443,24 +626,33 @@ pub fn path_readlink(
 }
 
 pub fn path_remove_directory(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _path: WasmPtr<u8>,
-    _path_len: u32,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    path: WasmPtr<u8>,
+    path_len: u32,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let path = wasi_try!(resolve_path(state, memory, fd, path, path_len));
+        wasi_try!(state.fs.remove_dir(&path).map_err(fs_errno));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn path_rename(
-    _env: FunctionEnvMut<WasiEnv>,
-    _old_fd: __wasi_fd_t,
-    _old_path: WasmPtr<u8>,
-    _old_path_len: u32,
-    _new_fd: __wasi_fd_t,
-    _new_path: WasmPtr<u8>,
-    _new_path_len: u32,
+    env: FunctionEnvMut<WasiEnv>,
+    old_fd: __wasi_fd_t,
+    old_path: WasmPtr<u8>,
+    old_path_len: u32,
+    new_fd: __wasi_fd_t,
+    new_path: WasmPtr<u8>,
+    new_path_len: u32,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let old_path = wasi_try!(resolve_path(state, memory, old_fd, old_path, old_path_len));
+        let new_path = wasi_try!(resolve_path(state, memory, new_fd, new_path, new_path_len));
+        wasi_try!(state.fs.rename(&old_path, &new_path).map_err(fs_errno));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn path_symlink(
@@ -475,12 +667,16 @@ pub fn path_symlink(
 }
 
 pub fn path_unlink_file(
-    _env: FunctionEnvMut<WasiEnv>,
-    _fd: __wasi_fd_t,
-    _path: WasmPtr<u8>,
-    _path_len: u32,
+    env: FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    path: WasmPtr<u8>,
+    path_len: u32,
 ) -> __wasi_errno_t {
-    __WASI_ENOSYS
+    with_state(&env, |state, memory| {
+        let path = wasi_try!(resolve_path(state, memory, fd, path, path_len));
+        wasi_try!(state.fs.unlink(&path).map_err(fs_errno));
+        __WASI_ESUCCESS
+    })
 }
 
 pub fn poll_oneoff(
@@ -555,3 +751,288 @@ pub fn sock_shutdown(
 ) -> __wasi_errno_t {
     __WASI_ENOSYS
 }
+
+/// The name of the preopened root directory.
+const ROOT_NAME: &str = "/";
+/// Max number of iovecs accepted by a single read or write, the same as the Linux `IOV_MAX`.
+const IOV_MAX: u32 = 1024;
+/// Max bytes logged by a single write to the stdout or stderr. Longer writes are truncated and
+/// reported as partial writes, so the guest would write the rest in another call.
+const MAX_STDIO_WRITE: usize = 64 * 1024;
+/// Rights reported for all fds. Access control is done when opening the file.
+const ALL_RIGHTS: __wasi_rights_t = 0x1fff_ffff;
+
+fn with_state<R>(
+    env: &FunctionEnvMut<WasiEnv>,
+    f: impl FnOnce(&mut EnvInner, &MemoryView) -> R,
+) -> R {
+    let inner = env.data().inner.clone();
+    let mut guard = inner.lock().unwrap();
+    let memory = guard.memory.unwrap_ref().clone();
+    let view = memory.view(env);
+    f(&mut guard, &view)
+}
+
+fn is_stdio(fd: __wasi_fd_t) -> bool {
+    fd <= 2
+}
+
+fn fs_errno(err: FsError) -> __wasi_errno_t {
+    match err {
+        FsError::NotFound => __WASI_ENOENT,
+        FsError::AlreadyExists => __WASI_EEXIST,
+        FsError::NotADirectory => __WASI_ENOTDIR,
+        FsError::IsADirectory => __WASI_EISDIR,
+        FsError::NotEmpty => __WASI_ENOTEMPTY,
+        FsError::InvalidPath => __WASI_ENOTCAPABLE,
+        FsError::QuotaExceeded => __WASI_EDQUOT,
+        FsError::TooLarge => __WASI_EFBIG,
+        FsError::TooManyOpenFiles => __WASI_EMFILE,
+        FsError::Preopened => __WASI_ENOTSUP,
+    }
+}
+
+fn filetype(kind: NodeKind) -> __wasi_filetype_t {
+    match kind {
+        NodeKind::File => __WASI_FILETYPE_REGULAR_FILE,
+        NodeKind::Dir => __WASI_FILETYPE_DIRECTORY,
+    }
+}
+
+fn encode_filestat(stat: &Stat) -> [u8; 64] {
+    // struct filestat { u64 dev; u64 ino; u8 filetype; u64 nlink; u64 size; u64 atim; u64 mtim; u64 ctim; }
+    let mut buf = [0u8; 64];
+    buf[8..16].copy_from_slice(&stat.ino.to_le_bytes());
+    buf[16] = filetype(stat.kind);
+    buf[24..32].copy_from_slice(&1u64.to_le_bytes());
+    buf[32..40].copy_from_slice(&stat.size.to_le_bytes());
+    buf
+}
+
+/// Get the open file or directory at `fd`, failing if it is of another kind.
+fn open_file(
+    state: &mut EnvInner,
+    fd: __wasi_fd_t,
+    kind: NodeKind,
+) -> Result<&mut OpenFile, __wasi_errno_t> {
+    let file = state.fds.get_mut(fd).ok_or(__WASI_EBADF)?;
+    match (file.kind, kind) {
+        (NodeKind::Dir, NodeKind::File) => Err(__WASI_EISDIR),
+        (NodeKind::File, NodeKind::Dir) => Err(__WASI_ENOTDIR),
+        _ => Ok(file),
+    }
+}
+
+fn write_bytes(memory: &MemoryView, offset: u32, data: &[u8]) -> Result<(), __wasi_errno_t> {
+    memory.write(offset as u64, data).or(Err(__WASI_EFAULT))
+}
+
+fn read_bytes(memory: &MemoryView, offset: u32, len: u32) -> Result<Vec<u8>, __wasi_errno_t> {
+    let end = (offset as u64) + (len as u64);
+    if end > memory.size().bytes().0 as u64 {
+        return Err(__WASI_EFAULT);
+    }
+    let mut buf = vec![0u8; len as usize];
+    memory
+        .read(offset as u64, &mut buf)
+        .or(Err(__WASI_EFAULT))?;
+    Ok(buf)
+}
+
+/// Read an array of `struct iovec { u32 buf; u32 buf_len; }` from the guest memory.
+fn read_iovecs(
+    memory: &MemoryView,
+    iovs: u32,
+    iovs_len: u32,
+) -> Result<Vec<(u32, u32)>, __wasi_errno_t> {
+    if iovs_len > IOV_MAX {
+        return Err(__WASI_EINVAL);
+    }
+    let raw = read_bytes(memory, iovs, iovs_len * 8)?;
+    Ok(raw
+        .chunks_exact(8)
+        .map(|chunk| {
+            let buf = u32::from_le_bytes(chunk[0..4].try_into().expect("4 bytes"));
+            let len = u32::from_le_bytes(chunk[4..8].try_into().expect("4 bytes"));
+            (buf, len)
+        })
+        .collect())
+}
+
+/// Resolve a guest path relative to the directory at `dirfd` to a path in the filesystem.
+fn resolve_path(
+    state: &mut EnvInner,
+    memory: &MemoryView,
+    dirfd: __wasi_fd_t,
+    path: WasmPtr<u8>,
+    path_len: u32,
+) -> Result<String, __wasi_errno_t> {
+    let raw = read_bytes(memory, path.offset(), path_len)?;
+    let path = String::from_utf8(raw).or(Err(__WASI_EILSEQ))?;
+    let dir = open_file(state, dirfd, NodeKind::Dir)?;
+    join_path(&dir.path, &path).map_err(fs_errno)
+}
+
+fn do_fd_read(
+    env: &mut FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    iovs: u32,
+    iovs_len: u32,
+    offset: Option<__wasi_filesize_t>,
+    nread: WasmPtr<u32>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    let inner = env.data().inner.clone();
+    let mut guard = inner.lock().unwrap();
+    let memory = guard.memory.unwrap_ref().clone();
+    let result = {
+        let view = memory.view(&*env);
+        read_into_iovecs(&mut guard, &view, fd, iovs, iovs_len, offset, nread)
+    };
+    pay_io(env, &mut guard, result)
+}
+
+fn do_fd_write(
+    env: &mut FunctionEnvMut<WasiEnv>,
+    fd: __wasi_fd_t,
+    iovs: u32,
+    iovs_len: u32,
+    offset: Option<__wasi_filesize_t>,
+    nwritten: WasmPtr<u32>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    let inner = env.data().inner.clone();
+    let mut guard = inner.lock().unwrap();
+    let memory = guard.memory.unwrap_ref().clone();
+    let result = {
+        let view = memory.view(&*env);
+        write_from_iovecs(&mut guard, &view, fd, iovs, iovs_len, offset, nwritten)
+    };
+    pay_io(env, &mut guard, result)
+}
+
+/// Charge the bytes transferred by a read or write like an ocall.
+fn pay_io(
+    env: &mut FunctionEnvMut<WasiEnv>,
+    state: &mut EnvInner,
+    result: Result<usize, __wasi_errno_t>,
+) -> Result<__wasi_errno_t, OcallAborted> {
+    let (errno, bytes) = match result {
+        Ok(bytes) => (__WASI_ESUCCESS, bytes),
+        Err(errno) => (errno, 0),
+    };
+    let cost = state.cost_table.ocall_cost(bytes);
+    state.pay(env, cost)?;
+    Ok(errno)
+}
+
+fn read_into_iovecs(
+    state: &mut EnvInner,
+    memory: &MemoryView,
+    fd: __wasi_fd_t,
+    iovs: u32,
+    iovs_len: u32,
+    offset: Option<__wasi_filesize_t>,
+    nread: WasmPtr<u32>,
+) -> Result<usize, __wasi_errno_t> {
+    let iovs = read_iovecs(memory, iovs, iovs_len)?;
+    let mut total = 0;
+    // The stdin is always empty.
+    if fd != 0 {
+        if is_stdio(fd) {
+            return Err(__WASI_EBADF);
+        }
+        let file = open_file(state, fd, NodeKind::File)?;
+        if !file.readable {
+            return Err(__WASI_EBADF);
+        }
+        let path = file.path.clone();
+        let mut pos = offset.unwrap_or(file.offset);
+        let size = state.fs.stat(&path).map_err(fs_errno)?.size;
+        for (buf, len) in iovs {
+            let len = (len as u64).min(size.saturating_sub(pos)) as usize;
+            if len == 0 {
+                break;
+            }
+            let mut data = vec![0u8; len];
+            let n = state.fs.read(&path, pos, &mut data).map_err(fs_errno)?;
+            write_bytes(memory, buf, &data[..n])?;
+            pos += n as u64;
+            total += n;
+        }
+        if offset.is_none() {
+            open_file(state, fd, NodeKind::File)?.offset = pos;
+        }
+    }
+    nread
+        .deref(memory)
+        .write(total as u32)
+        .or(Err(__WASI_EFAULT))?;
+    Ok(total)
+}
+
+fn write_from_iovecs(
+    state: &mut EnvInner,
+    memory: &MemoryView,
+    fd: __wasi_fd_t,
+    iovs: u32,
+    iovs_len: u32,
+    offset: Option<__wasi_filesize_t>,
+    nwritten: WasmPtr<u32>,
+) -> Result<usize, __wasi_errno_t> {
+    let iovs = read_iovecs(memory, iovs, iovs_len)?;
+    let total = match fd {
+        0 => return Err(__WASI_EBADF),
+        1 | 2 => {
+            let mut data = Vec::new();
+            for (buf, len) in iovs {
+                let len = (len as usize).min(MAX_STDIO_WRITE - data.len());
+                if len == 0 {
+                    break;
+                }
+                data.extend(read_bytes(memory, buf, len as u32)?);
+            }
+            let vm_id = ShortId(&state.id);
+            let text = String::from_utf8_lossy(&data);
+            log::info!(target: "sidevm", "[{}] {}", vm_id, text.trim_end());
+            data.len()
+        }
+        _ => {
+            let file = open_file(state, fd, NodeKind::File)?;
+            if !file.writable {
+                return Err(__WASI_EBADF);
+            }
+            let (path, append, current) = (file.path.clone(), file.append, file.offset);
+            let start = match offset {
+                Some(offset) => offset,
+                None if append => state.fs.stat(&path).map_err(fs_errno)?.size,
+                None => current,
+            };
+            // Copy each iovec straight from the guest memory into the file. The quota is checked
+            // before copying, so a huge iovec never gets buffered in the host.
+            let mut pos = start;
+            for (buf, len) in iovs {
+                let result = state
+                    .fs
+                    .write_with(&path, pos, len as usize, |dst| {
+                        memory.read(buf as u64, dst).or(Err(__WASI_EFAULT))
+                    })
+                    .map_err(fs_errno)
+                    .and_then(|result| result);
+                match result {
+                    Ok(()) => pos += len as u64,
+                    // Report a partial write if some of the iovecs have been written
+                    Err(_) if pos > start => break,
+                    Err(errno) => return Err(errno),
+                }
+            }
+            if offset.is_none() {
+                open_file(state, fd, NodeKind::File)?.offset = pos;
+            }
+            (pos - start) as usize
+        }
+    };
+    nwritten
+        .deref(memory)
+        .write(total as u32)
+        .or(Err(__WASI_EFAULT))?;
+    Ok(total)
+}
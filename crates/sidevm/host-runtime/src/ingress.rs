@@ -0,0 +1,300 @@
+//! A shared TLS ingress which routes the incoming connections to sidevm instances by SNI.
+//!
+//! Instead of binding its own port, a sidevm instance registers the hostnames it serves together
+//! with the certificates. The ingress accepts the connections on a single port, reads the server
+//! name from the TLS ClientHello and hands the stream over to the instance owning the hostname.
+//! The TLS handshake is then completed when the guest starts to read or write the stream.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use sidevm_env::{tls::TlsServerConfig, OcallError};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio_rustls::{
+    rustls::{server::Acceptor, ServerConfig},
+    LazyConfigAcceptor,
+};
+
+use crate::{
+    tls::{cert_is_trusted_for_hostname, load_tls_config, TlsStream},
+    ShortId, VmId,
+};
+
+/// Max number of accepted connections queued for an instance.
+const BACKLOG: usize = 128;
+/// Max time to wait for the ClientHello.
+const CLIENT_HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) type Incoming = (TlsStream, SocketAddr);
+
+struct Route {
+    vmid: VmId,
+    config: Arc<ServerConfig>,
+    tx: Sender<Incoming>,
+}
+
+/// The hostname routing table shared by all the instances of a service.
+#[derive(Clone, Default)]
+pub struct Ingress {
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    /// DER encoded root certificates trusted besides the webpki roots.
+    extra_roots: Arc<Vec<Vec<u8>>>,
+}
+
+fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= 253
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+impl Ingress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also trust the certificates issued by `roots` (DER encoded), e.g. a private CA.
+    pub fn with_extra_roots(mut self, roots: Vec<Vec<u8>>) -> Self {
+        self.extra_roots = Arc::new(roots);
+        self
+    }
+
+    /// Route the connections for `hostname` to the instance `vmid`.
+    ///
+    /// The certificate in `tls_config` must be issued for the hostname by a trusted CA, so that an
+    /// instance can't take over the traffic of a hostname by presenting a self-signed certificate.
+    ///
+    /// A hostname can only be owned by one instance at a time. It is released when the returned
+    /// receiver is dropped, e.g. when the instance closes the listener or exits.
+    pub(crate) fn register(
+        &self,
+        vmid: VmId,
+        hostname: &str,
+        tls_config: TlsServerConfig,
+    ) -> Result<Receiver<Incoming>, OcallError> {
+        let hostname = hostname.to_ascii_lowercase();
+        if !is_valid_hostname(&hostname) {
+            return Err(OcallError::InvalidParameter);
+        }
+        if !cert_is_trusted_for_hostname(&tls_config, &hostname, &self.extra_roots) {
+            warn!(
+                target: "sidevm",
+                "[{}] Certificate isn't trusted for ingress hostname {hostname}",
+                ShortId(&vmid)
+            );
+            return Err(OcallError::InvalidParameter);
+        }
+        let config = Arc::new(load_tls_config(tls_config)?);
+        let mut routes = self.routes.lock().unwrap();
+        if let Some(route) = routes.get(&hostname) {
+            if route.vmid != vmid && !route.tx.is_closed() {
+                return Err(OcallError::AlreadyExists);
+            }
+        }
+        let (tx, rx) = channel(BACKLOG);
+        info!(target: "sidevm", "[{}] Registered ingress hostname {hostname}", ShortId(&vmid));
+        routes.insert(hostname, Route { vmid, config, tx });
+        Ok(rx)
+    }
+
+    /// The registered hostnames and their owners.
+    pub fn hostnames(&self) -> Vec<(String, VmId)> {
+        let mut routes = self.routes.lock().unwrap();
+        routes.retain(|_, route| !route.tx.is_closed());
+        routes
+            .iter()
+            .map(|(hostname, route)| (hostname.clone(), route.vmid))
+            .collect()
+    }
+
+    fn route(&self, hostname: &str) -> Option<(Arc<ServerConfig>, Sender<Incoming>)> {
+        let mut routes = self.routes.lock().unwrap();
+        let route = routes.get(hostname)?;
+        if route.tx.is_closed() {
+            routes.remove(hostname);
+            return None;
+        }
+        Some((route.config.clone(), route.tx.clone()))
+    }
+
+    /// Accept connections on `addr` and dispatch them to the instances.
+    pub async fn serve(self, addr: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(target: "sidevm", "Ingress listening on {addr}");
+        loop {
+            let (stream, remote_addr) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    warn!(target: "sidevm", "Ingress failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            let ingress = self.clone();
+            tokio::spawn(async move {
+                if let Err(reason) = ingress.dispatch(stream, remote_addr).await {
+                    debug!(target: "sidevm", "Ingress dropped connection from {remote_addr}: {reason}");
+                }
+            });
+        }
+    }
+
+    async fn dispatch(&self, stream: TcpStream, remote_addr: SocketAddr) -> Result<(), String> {
+        let acceptor = Acceptor::new().map_err(|err| err.to_string())?;
+        let start = tokio::time::timeout(
+            CLIENT_HELLO_TIMEOUT,
+            LazyConfigAcceptor::new(acceptor, stream),
+        )
+        .await
+        .or(Err("ClientHello timed out"))?
+        .map_err(|err| err.to_string())?;
+        let hostname = start
+            .client_hello()
+            .server_name()
+            .ok_or("No SNI in ClientHello")?
+            .to_ascii_lowercase();
+        let (config, tx) = self
+            .route(&hostname)
+            .ok_or_else(|| format!("No route for {hostname}"))?;
+        let stream = TlsStream::ServerHandshaking(start.into_stream(config));
+        tx.try_send((stream, remote_addr))
+            .map_err(|_| format!("Backlog of {hostname} is full"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestCa(rcgen::Certificate);
+
+    impl TestCa {
+        fn new() -> Self {
+            let mut params = rcgen::CertificateParams::new(vec![]);
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, "Test CA");
+            Self(rcgen::Certificate::from_params(params).unwrap())
+        }
+
+        fn ingress(&self) -> Ingress {
+            Ingress::new().with_extra_roots(vec![self.0.serialize_der().unwrap()])
+        }
+
+        fn tls_config(&self, names: &[&str]) -> TlsServerConfig {
+            let names = names.iter().map(|name| name.to_string()).collect();
+            let cert = rcgen::generate_simple_self_signed(names).unwrap();
+            TlsServerConfig::V0 {
+                cert: cert.serialize_pem_with_signer(&self.0).unwrap(),
+                key: cert.serialize_private_key_pem(),
+            }
+        }
+    }
+
+    #[test]
+    fn is_valid_hostname_works() {
+        assert!(is_valid_hostname("localhost"));
+        assert!(is_valid_hostname("a-b.example.com"));
+        assert!(!is_valid_hostname(""));
+        assert!(!is_valid_hostname("example..com"));
+        assert!(!is_valid_hostname("example.com."));
+        assert!(!is_valid_hostname("*.example.com"));
+        assert!(!is_valid_hostname("exa_mple.com"));
+        assert!(!is_valid_hostname(&"a".repeat(64)));
+        assert!(is_valid_hostname(&"a".repeat(63)));
+        assert!(is_valid_hostname(&["a"; 127].join(".")));
+        assert!(!is_valid_hostname(&["a"; 128].join(".")));
+    }
+
+    #[test]
+    fn register_requires_certificate_for_hostname() {
+        let ca = TestCa::new();
+        let ingress = ca.ingress();
+        let config = ca.tls_config(&["a.example.com", "*.b.example.com"]);
+        assert!(matches!(
+            ingress.register([1; 32], "evil.com", config.clone()),
+            Err(OcallError::InvalidParameter)
+        ));
+        assert!(matches!(
+            ingress.register([1; 32], "b.example.com", config.clone()),
+            Err(OcallError::InvalidParameter)
+        ));
+        assert!(ingress
+            .register([1; 32], "A.Example.com", config.clone())
+            .is_ok());
+        assert!(ingress.register([1; 32], "x.b.example.com", config).is_ok());
+    }
+
+    #[test]
+    fn register_rejects_untrusted_certificate() {
+        let ca = TestCa::new();
+        let ingress = ca.ingress();
+        let names = vec!["a.example.com".to_string()];
+        let cert = rcgen::generate_simple_self_signed(names).unwrap();
+        let self_signed = TlsServerConfig::V0 {
+            cert: cert.serialize_pem().unwrap(),
+            key: cert.serialize_private_key_pem(),
+        };
+        assert!(matches!(
+            ingress.register([1; 32], "a.example.com", self_signed),
+            Err(OcallError::InvalidParameter)
+        ));
+        // Issued by a CA the ingress doesn't trust
+        let config = TestCa::new().tls_config(&["a.example.com"]);
+        assert!(matches!(
+            ingress.register([1; 32], "a.example.com", config),
+            Err(OcallError::InvalidParameter)
+        ));
+        // Nor trusted by the default ingress
+        let config = ca.tls_config(&["a.example.com"]);
+        assert!(matches!(
+            Ingress::new().register([1; 32], "a.example.com", config),
+            Err(OcallError::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    fn register_and_route_works() {
+        let ca = TestCa::new();
+        let ingress = ca.ingress();
+        let config = ca.tls_config(&["a.example.com"]);
+        let rx = ingress
+            .register([1; 32], "a.example.com", config.clone())
+            .unwrap();
+        assert!(ingress.route("a.example.com").is_some());
+        assert!(ingress.route("b.example.com").is_none());
+        assert_eq!(
+            ingress.hostnames(),
+            vec![("a.example.com".to_string(), [1; 32])]
+        );
+
+        // Owned by another instance
+        assert!(matches!(
+            ingress.register([2; 32], "a.example.com", config.clone()),
+            Err(OcallError::AlreadyExists)
+        ));
+        // The owner can register it again
+        let rx = ingress
+            .register([1; 32], "a.example.com", config.clone())
+            .unwrap();
+
+        // Released when the receiver is dropped
+        drop(rx);
+        assert!(ingress.route("a.example.com").is_none());
+        assert!(ingress.hostnames().is_empty());
+        let _rx = ingress.register([2; 32], "a.example.com", config).unwrap();
+        assert_eq!(
+            ingress.hostnames(),
+            vec![("a.example.com".to_string(), [2; 32])]
+        );
+    }
+}
@@ -1,15 +1,20 @@
 mod async_context;
 mod env;
+mod ingress;
 pub mod instrument;
 mod metering;
 mod resource;
 mod run;
 pub mod service;
 mod tls;
+mod vfs;
 
-pub use env::{CacheOps, DynCacheOps, OcallAborted, ShortId};
+pub use env::{CacheOps, DynCacheOps, GasCounter, OcallAborted, PersistedState, ShortId};
+pub use ingress::Ingress;
 
 pub type VmId = [u8; 32];
+pub use metering::CostTable;
 pub use run::WasmRun;
+pub use vfs::FileSystem;
 
 pub use sidevm_env::OcallError;
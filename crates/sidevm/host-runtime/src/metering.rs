@@ -1,230 +1,351 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use wasmer::{wasmparser::Operator, CompilerConfig};
 use wasmer_middlewares::metering::Metering;
 
-pub(crate) fn metering<C: CompilerConfig>(mut compiler: C) -> C {
-    compiler.push_middleware(Arc::new(Metering::new(u64::MAX, cost_function)));
+/// The gas cost table used to meter sidevm programs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostTable {
+    /// Gas costs of wasm instructions overriding the builtin ones, keyed by the operator name
+    /// such as `I64Add` or `CallIndirect`.
+    ///
+    /// Only the operators listed in the builtin table can be overridden. Others always cost the
+    /// builtin default.
+    pub instructions: BTreeMap<String, u64>,
+    /// Base gas charged for each ocall.
+    pub ocall_base: u64,
+    /// Gas charged for each byte copied between the guest memory and the host in an ocall.
+    ///
+    /// Slices of the guest memory borrowed by the host are not counted.
+    pub ocall_per_byte: u64,
+}
+
+/// An ocall takes about 1us to switch to the host, lock the env and dispatch, about 15 times
+/// a wasm `Call` (685).
+const DEFAULT_OCALL_BASE: u64 = 10_000;
+/// A memcpy is about 4 times faster per byte than moving the data with `I64Load` (72 per 8
+/// bytes).
+const DEFAULT_OCALL_PER_BYTE: u64 = 2;
+
+impl Default for CostTable {
+    fn default() -> Self {
+        Self {
+            instructions: Default::default(),
+            ocall_base: DEFAULT_OCALL_BASE,
+            ocall_per_byte: DEFAULT_OCALL_PER_BYTE,
+        }
+    }
+}
+
+impl CostTable {
+    /// Gas cost of the given wasm instruction.
+    pub fn instruction_cost(&self, operator: &Operator) -> u64 {
+        if !self.instructions.is_empty() {
+            if let Some(cost) = operator_name(operator).and_then(|name| self.instructions.get(name))
+            {
+                return *cost;
+            }
+        }
+        builtin_cost(operator)
+    }
+
+    /// Gas cost of an ocall which copied `bytes` bytes in or out of the guest memory.
+    pub fn ocall_cost(&self, bytes: usize) -> u64 {
+        self.ocall_per_byte
+            .saturating_mul(bytes as u64)
+            .saturating_add(self.ocall_base)
+    }
+}
+
+pub(crate) fn metering<C: CompilerConfig>(mut compiler: C, cost_table: Arc<CostTable>) -> C {
+    compiler.push_middleware(Arc::new(Metering::new(u64::MAX, move |op| {
+        cost_table.instruction_cost(op)
+    })));
     compiler
 }
 
-fn cost_function(operator: &Operator) -> u64 {
-    use Operator::*;
+/// Defines `builtin_cost` and `operator_name` out of the same list of operators, so that the
+/// overridable names never go out of sync with the builtin table.
+macro_rules! builtin_costs {
+    ($($op:ident => $cost:expr,)*) => {
+        fn builtin_cost(operator: &Operator) -> u64 {
+            let cost = match operator {
+                $(Operator::$op { .. } => $cost,)*
+                _ => 100000,
+            };
+            1.max(cost / 100)
+        }
 
-    let cost = match operator {
-        I64Const { .. } => 2960,
-        I64Load { .. } => 7280,
-        I64Store { .. } => 8360,
-        Select { .. } => 5980,
-        If { .. } => 9990,
-        Br { .. } => 3060,
-        BrIf { .. } => 5770,
-        BrTable { .. } => 7170,
-        Call { .. } => 68540,
-        CallIndirect { .. } => 85180,
-        LocalGet { .. } => 3050,
-        LocalSet { .. } => 3900,
-        LocalTee { .. } => 3030,
-        GlobalGet { .. } => 9050,
-        GlobalSet { .. } => 11140,
-        MemorySize { .. } => 3640,
-        MemoryGrow { .. } => 3640,
-        I64Clz => 3140,
-        I64Ctz => 3040,
-        I64Popcnt => 2970,
-        I64Eqz => 3160,
-        I64ExtendI32S => 2890,
-        I64ExtendI32U => 2830,
-        I32WrapI64 => 3140,
-        I64Eq => 4740,
-        I64Ne => 4720,
-        I64LtS => 4680,
-        I64LtU => 4690,
-        I64GtS => 4720,
-        I64GtU => 4840,
-        I64LeS => 4730,
-        I64LeU => 4710,
-        I64GeS => 4660,
-        I64GeU => 4690,
-        I64Add => 4450,
-        I64Sub => 4520,
-        I64Mul => 4520,
-        I64DivS => 11070,
-        I64DivU => 11620,
-        I64RemS => 11090,
-        I64RemU => 11730,
-        I64And => 4500,
-        I64Or => 4480,
-        I64Xor => 4570,
-        I64Shl => 4740,
-        I64ShrS => 4680,
-        I64ShrU => 4700,
-        I64Rotl => 4690,
-        I64Rotr => 4700,
-        F64Const { .. } => 2960,
-        F64Load { .. } => 7280,
-        F64Store { .. } => 8360,
-        F64ConvertI32S => 4700,
-        F64ConvertI32U => 4700,
-        F64ConvertI64S => 4700,
-        F64ConvertI64U => 4700,
-        Unreachable => 0,
-        Nop => 100,
-        Block { .. } => 100,
-        Loop { .. } => 100,
-        Else => 100,
-        Try { .. } => 100,
-        Catch { .. } => 1000,
-        Throw { .. } => 10000,
-        Rethrow { .. } => 10000,
-        End => 100,
-        Return => 1000,
-        ReturnCall { .. } => 1000,
-        ReturnCallIndirect { .. } => 2000,
-        Delegate { .. } => 1000,
-        CatchAll => 1000,
-        Drop => 100,
-        TypedSelect { .. } => 5000,
-        I32Load { .. } => 3000,
-        F32Load { .. } => 3000,
-        I32Load8S { .. } => 3000,
-        I32Load8U { .. } => 3000,
-        I32Load16S { .. } => 3000,
-        I32Load16U { .. } => 3000,
-        I64Load8S { .. } => 6000,
-        I64Load8U { .. } => 6000,
-        I64Load16S { .. } => 6000,
-        I64Load16U { .. } => 6000,
-        I64Load32S { .. } => 6000,
-        I64Load32U { .. } => 6000,
-        I32Store { .. } => 3000,
-        F32Store { .. } => 3000,
-        I32Store8 { .. } => 3000,
-        I32Store16 { .. } => 3000,
-        I64Store8 { .. } => 6000,
-        I64Store16 { .. } => 6000,
-        I64Store32 { .. } => 6000,
-        I32Const { .. } => 2000,
-        F32Const { .. } => 2000,
-        RefNull { .. } => 1000,
-        RefIsNull => 1000,
-        RefFunc { .. } => 2000,
-        I32Eqz => 2000,
-        I32Eq => 2000,
-        I32Ne => 2000,
-        I32LtS => 2000,
-        I32LtU => 2000,
-        I32GtS => 2000,
-        I32GtU => 2000,
-        I32LeS => 2000,
-        I32LeU => 2000,
-        I32GeS => 2000,
-        I32GeU => 2000,
-        F32Eq => 2000,
-        F32Ne => 2000,
-        F32Lt => 2000,
-        F32Gt => 2000,
-        F32Le => 2000,
-        F32Ge => 2000,
-        F64Eq => 2000,
-        F64Ne => 2000,
-        F64Lt => 2000,
-        F64Gt => 2000,
-        F64Le => 2000,
-        F64Ge => 2000,
-        I32Clz => 2000,
-        I32Ctz => 2000,
-        I32Popcnt => 2000,
-        I32Add => 2000,
-        I32Sub => 2000,
-        I32Mul => 2000,
-        I32DivS => 2000,
-        I32DivU => 2000,
-        I32RemS => 2000,
-        I32RemU => 2000,
-        I32And => 2000,
-        I32Or => 2000,
-        I32Xor => 2000,
-        I32Shl => 2000,
-        I32ShrS => 2000,
-        I32ShrU => 2000,
-        I32Rotl => 2000,
-        I32Rotr => 2000,
-        F32Abs => 2000,
-        F32Neg => 2000,
-        F32Ceil => 2000,
-        F32Floor => 2000,
-        F32Trunc => 2000,
-        F32Nearest => 2000,
-        F32Sqrt => 2000,
-        F32Add => 2000,
-        F32Sub => 2000,
-        F32Mul => 2000,
-        F32Div => 2000,
-        F32Min => 2000,
-        F32Max => 2000,
-        F32Copysign => 2000,
-        F64Abs => 2000,
-        F64Neg => 2000,
-        F64Ceil => 2000,
-        F64Floor => 2000,
-        F64Trunc => 2000,
-        F64Nearest => 2000,
-        F64Sqrt => 2000,
-        F64Add => 2000,
-        F64Sub => 2000,
-        F64Mul => 2000,
-        F64Div => 2000,
-        F64Min => 2000,
-        F64Max => 2000,
-        F64Copysign => 2000,
-        I32TruncF32S => 2000,
-        I32TruncF32U => 2000,
-        I32TruncF64S => 2000,
-        I32TruncF64U => 2000,
-        I64TruncF32S => 2000,
-        I64TruncF32U => 2000,
-        I64TruncF64S => 2000,
-        I64TruncF64U => 2000,
-        F32ConvertI32S => 2000,
-        F32ConvertI32U => 2000,
-        F32ConvertI64S => 2000,
-        F32ConvertI64U => 2000,
-        F32DemoteF64 => 2000,
-        F64PromoteF32 => 2000,
-        I32ReinterpretF32 => 2000,
-        I64ReinterpretF64 => 2000,
-        F32ReinterpretI32 => 2000,
-        F64ReinterpretI64 => 2000,
-        I32Extend8S => 2000,
-        I32Extend16S => 2000,
-        I64Extend8S => 2000,
-        I64Extend16S => 2000,
-        I64Extend32S => 2000,
-        I32TruncSatF32S => 2000,
-        I32TruncSatF32U => 2000,
-        I32TruncSatF64S => 2000,
-        I32TruncSatF64U => 2000,
-        I64TruncSatF32S => 2000,
-        I64TruncSatF32U => 2000,
-        I64TruncSatF64S => 2000,
-        I64TruncSatF64U => 2000,
-        MemoryInit { .. } => 20000,
-        DataDrop { .. } => 2000,
-        MemoryCopy { .. } => 20000,
-        MemoryFill { .. } => 20000,
-        TableInit { .. } => 20000,
-        ElemDrop { .. } => 1000,
-        TableCopy { .. } => 20000,
-        TableFill { .. } => 20000,
-        TableGet { .. } => 20000,
-        TableSet { .. } => 20000,
-        TableGrow { .. } => 20000,
-        TableSize { .. } => 2000,
-        MemoryAtomicNotify { .. } => 8000,
-        MemoryAtomicWait32 { .. } => 8000,
-        MemoryAtomicWait64 { .. } => 8000,
-        AtomicFence { .. } => 1000,
-        _ => 100000,
+        /// The variant name of the operator, e.g. `I64Const` for `I64Const { value: 1 }`.
+        fn operator_name(operator: &Operator) -> Option<&'static str> {
+            match operator {
+                $(Operator::$op { .. } => Some(stringify!($op)),)*
+                _ => None,
+            }
+        }
     };
-    1.max(cost / 100)
+}
+
+builtin_costs! {
+    I64Const => 2960,
+    I64Load => 7280,
+    I64Store => 8360,
+    Select => 5980,
+    If => 9990,
+    Br => 3060,
+    BrIf => 5770,
+    BrTable => 7170,
+    Call => 68540,
+    CallIndirect => 85180,
+    LocalGet => 3050,
+    LocalSet => 3900,
+    LocalTee => 3030,
+    GlobalGet => 9050,
+    GlobalSet => 11140,
+    MemorySize => 3640,
+    MemoryGrow => 3640,
+    I64Clz => 3140,
+    I64Ctz => 3040,
+    I64Popcnt => 2970,
+    I64Eqz => 3160,
+    I64ExtendI32S => 2890,
+    I64ExtendI32U => 2830,
+    I32WrapI64 => 3140,
+    I64Eq => 4740,
+    I64Ne => 4720,
+    I64LtS => 4680,
+    I64LtU => 4690,
+    I64GtS => 4720,
+    I64GtU => 4840,
+    I64LeS => 4730,
+    I64LeU => 4710,
+    I64GeS => 4660,
+    I64GeU => 4690,
+    I64Add => 4450,
+    I64Sub => 4520,
+    I64Mul => 4520,
+    I64DivS => 11070,
+    I64DivU => 11620,
+    I64RemS => 11090,
+    I64RemU => 11730,
+    I64And => 4500,
+    I64Or => 4480,
+    I64Xor => 4570,
+    I64Shl => 4740,
+    I64ShrS => 4680,
+    I64ShrU => 4700,
+    I64Rotl => 4690,
+    I64Rotr => 4700,
+    F64Const => 2960,
+    F64Load => 7280,
+    F64Store => 8360,
+    F64ConvertI32S => 4700,
+    F64ConvertI32U => 4700,
+    F64ConvertI64S => 4700,
+    F64ConvertI64U => 4700,
+    Unreachable => 0,
+    Nop => 100,
+    Block => 100,
+    Loop => 100,
+    Else => 100,
+    Try => 100,
+    Catch => 1000,
+    Throw => 10000,
+    Rethrow => 10000,
+    End => 100,
+    Return => 1000,
+    ReturnCall => 1000,
+    ReturnCallIndirect => 2000,
+    Delegate => 1000,
+    CatchAll => 1000,
+    Drop => 100,
+    TypedSelect => 5000,
+    I32Load => 3000,
+    F32Load => 3000,
+    I32Load8S => 3000,
+    I32Load8U => 3000,
+    I32Load16S => 3000,
+    I32Load16U => 3000,
+    I64Load8S => 6000,
+    I64Load8U => 6000,
+    I64Load16S => 6000,
+    I64Load16U => 6000,
+    I64Load32S => 6000,
+    I64Load32U => 6000,
+    I32Store => 3000,
+    F32Store => 3000,
+    I32Store8 => 3000,
+    I32Store16 => 3000,
+    I64Store8 => 6000,
+    I64Store16 => 6000,
+    I64Store32 => 6000,
+    I32Const => 2000,
+    F32Const => 2000,
+    RefNull => 1000,
+    RefIsNull => 1000,
+    RefFunc => 2000,
+    I32Eqz => 2000,
+    I32Eq => 2000,
+    I32Ne => 2000,
+    I32LtS => 2000,
+    I32LtU => 2000,
+    I32GtS => 2000,
+    I32GtU => 2000,
+    I32LeS => 2000,
+    I32LeU => 2000,
+    I32GeS => 2000,
+    I32GeU => 2000,
+    F32Eq => 2000,
+    F32Ne => 2000,
+    F32Lt => 2000,
+    F32Gt => 2000,
+    F32Le => 2000,
+    F32Ge => 2000,
+    F64Eq => 2000,
+    F64Ne => 2000,
+    F64Lt => 2000,
+    F64Gt => 2000,
+    F64Le => 2000,
+    F64Ge => 2000,
+    I32Clz => 2000,
+    I32Ctz => 2000,
+    I32Popcnt => 2000,
+    I32Add => 2000,
+    I32Sub => 2000,
+    I32Mul => 2000,
+    I32DivS => 2000,
+    I32DivU => 2000,
+    I32RemS => 2000,
+    I32RemU => 2000,
+    I32And => 2000,
+    I32Or => 2000,
+    I32Xor => 2000,
+    I32Shl => 2000,
+    I32ShrS => 2000,
+    I32ShrU => 2000,
+    I32Rotl => 2000,
+    I32Rotr => 2000,
+    F32Abs => 2000,
+    F32Neg => 2000,
+    F32Ceil => 2000,
+    F32Floor => 2000,
+    F32Trunc => 2000,
+    F32Nearest => 2000,
+    F32Sqrt => 2000,
+    F32Add => 2000,
+    F32Sub => 2000,
+    F32Mul => 2000,
+    F32Div => 2000,
+    F32Min => 2000,
+    F32Max => 2000,
+    F32Copysign => 2000,
+    F64Abs => 2000,
+    F64Neg => 2000,
+    F64Ceil => 2000,
+    F64Floor => 2000,
+    F64Trunc => 2000,
+    F64Nearest => 2000,
+    F64Sqrt => 2000,
+    F64Add => 2000,
+    F64Sub => 2000,
+    F64Mul => 2000,
+    F64Div => 2000,
+    F64Min => 2000,
+    F64Max => 2000,
+    F64Copysign => 2000,
+    I32TruncF32S => 2000,
+    I32TruncF32U => 2000,
+    I32TruncF64S => 2000,
+    I32TruncF64U => 2000,
+    I64TruncF32S => 2000,
+    I64TruncF32U => 2000,
+    I64TruncF64S => 2000,
+    I64TruncF64U => 2000,
+    F32ConvertI32S => 2000,
+    F32ConvertI32U => 2000,
+    F32ConvertI64S => 2000,
+    F32ConvertI64U => 2000,
+    F32DemoteF64 => 2000,
+    F64PromoteF32 => 2000,
+    I32ReinterpretF32 => 2000,
+    I64ReinterpretF64 => 2000,
+    F32ReinterpretI32 => 2000,
+    F64ReinterpretI64 => 2000,
+    I32Extend8S => 2000,
+    I32Extend16S => 2000,
+    I64Extend8S => 2000,
+    I64Extend16S => 2000,
+    I64Extend32S => 2000,
+    I32TruncSatF32S => 2000,
+    I32TruncSatF32U => 2000,
+    I32TruncSatF64S => 2000,
+    I32TruncSatF64U => 2000,
+    I64TruncSatF32S => 2000,
+    I64TruncSatF32U => 2000,
+    I64TruncSatF64S => 2000,
+    I64TruncSatF64U => 2000,
+    MemoryInit => 20000,
+    DataDrop => 2000,
+    MemoryCopy => 20000,
+    MemoryFill => 20000,
+    TableInit => 20000,
+    ElemDrop => 1000,
+    TableCopy => 20000,
+    TableFill => 20000,
+    TableGet => 20000,
+    TableSet => 20000,
+    TableGrow => 20000,
+    TableSize => 2000,
+    MemoryAtomicNotify => 8000,
+    MemoryAtomicWait32 => 8000,
+    MemoryAtomicWait64 => 8000,
+    AtomicFence => 1000,
+    _ => 100000,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_cost_works() {
+        let mut table = CostTable::default();
+        assert_eq!(table.instruction_cost(&Operator::I64Add), 44);
+        assert_eq!(table.instruction_cost(&Operator::I64Const { value: 1 }), 29);
+        // Not in the builtin table
+        assert_eq!(table.instruction_cost(&Operator::V128Not), 1000);
+
+        table.instructions.insert("I64Add".into(), 7);
+        table.instructions.insert("I64Const".into(), 3);
+        table.instructions.insert("V128Not".into(), 5);
+        assert_eq!(table.instruction_cost(&Operator::I64Add), 7);
+        assert_eq!(table.instruction_cost(&Operator::I64Const { value: 1 }), 3);
+        assert_eq!(table.instruction_cost(&Operator::I64Sub), 45);
+        // Unknown operators can't be overridden
+        assert_eq!(table.instruction_cost(&Operator::V128Not), 1000);
+    }
+
+    #[test]
+    fn ocall_cost_works() {
+        let mut table = CostTable::default();
+        assert_eq!(table.ocall_cost(0), 10_000);
+        assert_eq!(table.ocall_cost(1024), 12_048);
+        table.ocall_base = 100;
+        table.ocall_per_byte = 2;
+        assert_eq!(table.ocall_cost(0), 100);
+        assert_eq!(table.ocall_cost(1024), 2148);
+        table.ocall_per_byte = u64::MAX;
+        assert_eq!(table.ocall_cost(2), u64::MAX);
+    }
+
+    #[test]
+    fn cost_table_deserialize_with_defaults() {
+        let table: CostTable =
+            serde_json::from_str(r#"{"instructions":{"Call":1},"ocall_base":10}"#).unwrap();
+        assert_eq!(table.instructions.get("Call"), Some(&1));
+        assert_eq!(table.ocall_base, 10);
+        assert_eq!(table.ocall_per_byte, 0);
+    }
 }
@@ -14,6 +14,7 @@ use tokio_rustls::rustls::ServerConfig;
 use Resource::*;
 
 use crate::async_context::{get_task_cx, GuestWaker};
+use crate::ingress::Incoming;
 use crate::tls::TlsStream;
 
 pub enum Resource {
@@ -24,6 +25,7 @@ pub enum Resource {
         listener: TcpListener,
         tls_config: Option<Arc<ServerConfig>>,
     },
+    SniListener(Receiver<Incoming>),
     TcpStream(TcpStream),
     TlsStream(TlsStream),
     TcpConnect(Pin<Box<dyn Future<Output = std::io::Result<TcpStream>> + Send>>),
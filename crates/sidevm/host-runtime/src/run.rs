@@ -2,6 +2,7 @@ use anyhow::{Context as _, Result};
 use phala_scheduler::TaskScheduler;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use wasmer::{BaseTunables, Engine, Instance, Module, Pages, RuntimeError, Store, TypedFunction};
 #[cfg(feature = "wasmer-compiler-cranelift")]
@@ -11,8 +12,13 @@ use wasmer_compiler_llvm::LLVM;
 use wasmer_compiler_singlepass::Singlepass;
 use wasmer_tunables::LimitingTunables;
 
-use crate::env::DynCacheOps;
-use crate::{async_context, env, metering::metering, VmId};
+use crate::env::{DynCacheOps, PersistedState};
+use crate::{
+    async_context, env,
+    metering::{metering, CostTable},
+    vfs::FileSystem,
+    VmId,
+};
 
 pub struct WasmRun {
     id: VmId,
@@ -35,6 +41,9 @@ impl WasmRun {
         id: crate::VmId,
         gas_per_breath: u64,
         cache_ops: DynCacheOps,
+        persisted_state: PersistedState,
+        fs: FileSystem,
+        cost_table: Arc<CostTable>,
         scheduler: TaskScheduler<VmId>,
         weight: u32,
     ) -> Result<(WasmRun, env::Env)> {
@@ -45,9 +54,9 @@ impl WasmRun {
             .unwrap_or("singlepass");
 
         let engine: Engine = match compiler_env {
-            "singlepass" => metering(Singlepass::default()).into(),
+            "singlepass" => metering(Singlepass::default(), cost_table.clone()).into(),
             #[cfg(feature = "wasmer-compiler-cranelift")]
-            "cranelift" => metering(Cranelift::default()).into(),
+            "cranelift" => metering(Cranelift::default(), cost_table.clone()).into(),
             #[cfg(feature = "wasmer-compiler-llvm")]
             "llvm" => LLVM::default().into(),
             _ => panic!("Unsupported compiler engine: {}", compiler_env),
@@ -61,7 +70,7 @@ impl WasmRun {
         let tunables = LimitingTunables::new(base, Pages(max_pages));
         let mut store = Store::new_with_tunables(&engine, tunables);
         let module = Module::new(&store, code)?;
-        let (env, import_object) = env::create_env(id, &mut store, cache_ops);
+        let (env, import_object) = env::create_env(id, &mut store, cache_ops, persisted_state, fs);
         let instance = Instance::new(&mut store, &module, &import_object)?;
         let memory = instance
             .exports
@@ -72,6 +81,7 @@ impl WasmRun {
         env.set_instance(instance);
         env.set_gas_per_breath(gas_per_breath);
         env.set_weight(weight);
+        env.set_cost_table(cost_table);
         Ok((
             WasmRun {
                 env: env.clone(),
@@ -92,8 +102,9 @@ impl Future for WasmRun {
         let _guard = futures::ready!(self.scheduler.poll_resume(cx, &self.id, self.env.weight()));
         let run = self.get_mut();
         run.env.reset_gas_to_breath(&mut run.store);
-        match async_context::set_task_cx(cx, || run.wasm_poll_entry.call(&mut run.store))
-        {
+        let result = async_context::set_task_cx(cx, || run.wasm_poll_entry.call(&mut run.store));
+        run.env.account_gas_consumed(&mut run.store);
+        match result {
             Ok(rv) => {
                 if rv == 0 {
                     if run.env.has_more_ready() {
@@ -1,4 +1,7 @@
-use crate::env::DynCacheOps;
+use crate::env::{DynCacheOps, GasCounter, PersistedState};
+use crate::ingress::Ingress;
+use crate::metering::CostTable;
+use crate::vfs::FileSystem;
 use crate::{env::OcallAborted, run::WasmRun};
 use crate::{ShortId, VmId};
 use anyhow::{Context as _, Result};
@@ -7,6 +10,7 @@ use phala_scheduler::TaskScheduler;
 use serde::{Deserialize, Serialize};
 use sidevm_env::messages::AccountId;
 use std::future::Future;
+use std::sync::Arc;
 use tokio::{
     sync::mpsc::{channel, Receiver, Sender},
     sync::oneshot::Sender as OneshotSender,
@@ -18,7 +22,12 @@ pub type CommandSender = Sender<Command>;
 
 #[derive(Debug)]
 pub enum Report {
-    VmTerminated { id: VmId, reason: ExitReason },
+    VmTerminated {
+        id: VmId,
+        reason: ExitReason,
+        /// Total gas consumed by the instance during its lifetime.
+        gas_consumed: u64,
+    },
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, derive_more::Display)]
@@ -67,6 +76,7 @@ pub struct Spawner {
     runtime_handle: tokio::runtime::Handle,
     report_tx: Sender<Report>,
     scheduler: TaskScheduler<VmId>,
+    ingress: Option<Ingress>,
 }
 
 pub fn service(worker_threads: usize) -> (ServiceRun, Spawner) {
@@ -84,6 +94,7 @@ pub fn service(worker_threads: usize) -> (ServiceRun, Spawner) {
         runtime_handle,
         report_tx,
         scheduler: TaskScheduler::new(worker_threads as _),
+        ingress: None,
     };
     (run, spawner)
 }
@@ -114,6 +125,12 @@ impl ServiceRun {
 }
 
 impl Spawner {
+    /// Let the instances started by this spawner register hostnames at given ingress.
+    pub fn with_ingress(mut self, ingress: Ingress) -> Self {
+        self.ingress = Some(ingress);
+        self
+    }
+
     pub fn start(
         &self,
         wasm_bytes: &[u8],
@@ -121,8 +138,11 @@ impl Spawner {
         id: VmId,
         gas_per_breath: u64,
         cache_ops: DynCacheOps,
+        persisted_state: PersistedState,
+        fs: FileSystem,
+        cost_table: Arc<CostTable>,
         weight: u32,
-    ) -> Result<(CommandSender, JoinHandle<ExitReason>)> {
+    ) -> Result<(CommandSender, JoinHandle<ExitReason>, GasCounter)> {
         let (cmd_tx, mut cmd_rx) = channel(128);
         let (mut wasm_run, env) = WasmRun::run(
             wasm_bytes,
@@ -130,10 +150,18 @@ impl Spawner {
             id,
             gas_per_breath,
             cache_ops,
+            persisted_state,
+            fs,
+            cost_table,
             self.scheduler.clone(),
             weight,
         )
         .context("Failed to create sidevm instance")?;
+        if let Some(ingress) = &self.ingress {
+            env.set_ingress(ingress.clone());
+        }
+        let stat_env = env.clone();
+        let gas_counter = env.gas_counter();
         let spawner = self.runtime_handle.clone();
         let handle = self.spawn(async move {
             let vmid = ShortId(&id);
@@ -217,12 +245,19 @@ impl Spawner {
                     }
                 }
             };
-            if let Err(err) = report_tx.send(Report::VmTerminated { id, reason }).await {
+            let gas_consumed = stat_env.gas_consumed();
+            info!(target: "sidevm", "[{vmid}] The sidevm instance consumed {gas_consumed} gas in total.");
+            let report = Report::VmTerminated {
+                id,
+                reason,
+                gas_consumed,
+            };
+            if let Err(err) = report_tx.send(report).await {
                 warn!(target: "sidevm", "[{vmid}] Failed to send report to sidevm service: {}", err);
             }
             reason
         });
-        Ok((cmd_tx, handle))
+        Ok((cmd_tx, handle, gas_counter))
     }
 
     pub fn spawn<O: Send + 'static>(
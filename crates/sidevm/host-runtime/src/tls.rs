@@ -3,6 +3,7 @@ use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 
 use futures::ready;
 use once_cell::sync::Lazy;
@@ -226,6 +227,77 @@ pub(crate) fn load_tls_config(config: TlsServerConfig) -> Result<ServerConfig, O
         .or(Err(OcallError::InvalidParameter))
 }
 
+/// The signature algorithms accepted in the certificate chains of the ingress hostnames.
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Whether the certificate chain in `config` is trusted for `hostname`.
+///
+/// The chain must lead to one of the webpki roots or one of `extra_roots` (DER encoded), and the
+/// leaf certificate must be currently valid with the hostname, wildcards included, in its
+/// subjectAltName. A self-signed certificate is never trusted unless it is one of the roots.
+pub(crate) fn cert_is_trusted_for_hostname(
+    config: &TlsServerConfig,
+    hostname: &str,
+    extra_roots: &[Vec<u8>],
+) -> bool {
+    let cert_pem = match config {
+        TlsServerConfig::V0 { cert, .. } => cert,
+    };
+    let certs = match load_certs(cert_pem) {
+        Ok(certs) => certs,
+        Err(_) => return false,
+    };
+    let (leaf, intermediates) = match certs.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    let intermediates: Vec<&[u8]> = intermediates.iter().map(|cert| &cert.0[..]).collect();
+    let mut anchors: Vec<_> = webpki_roots::TLS_SERVER_ROOTS
+        .0
+        .iter()
+        .map(|ta| webpki::TrustAnchor {
+            subject: ta.subject,
+            spki: ta.spki,
+            name_constraints: ta.name_constraints,
+        })
+        .collect();
+    for root in extra_roots {
+        match webpki::TrustAnchor::try_from_cert_der(root) {
+            Ok(anchor) => anchors.push(anchor),
+            Err(_) => return false,
+        }
+    }
+    let (cert, name, now) = match (
+        webpki::EndEntityCert::try_from(&leaf.0[..]),
+        webpki::DnsNameRef::try_from_ascii_str(hostname),
+        webpki::Time::try_from(SystemTime::now()),
+    ) {
+        (Ok(cert), Ok(name), Ok(now)) => (cert, name, now),
+        _ => return false,
+    };
+    cert.verify_is_valid_tls_server_cert(
+        SUPPORTED_SIG_ALGS,
+        &webpki::TlsServerTrustAnchors(&anchors),
+        &intermediates,
+        now,
+    )
+    .is_ok()
+        && cert.verify_is_valid_for_dns_name(name).is_ok()
+}
+
 fn load_certs(pem_str: &str) -> Result<Vec<rustls::Certificate>, OcallError> {
     let certs =
         rustls_pemfile::certs(&mut pem_str.as_bytes()).or(Err(OcallError::InvalidParameter))?;
@@ -0,0 +1,728 @@
+//! An in-memory virtual filesystem backing the WASI file APIs of sidevm instances.
+//!
+//! Each sidevm program owns a [`FileSystem`] which is shared between the restarts of the program.
+//! The filesystem lives entirely in host memory and is limited by a size quota. The embedder can
+//! serialize it, e.g. into the encrypted worker checkpoint, to persist the files.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Max length in bytes of a path in the filesystem.
+const MAX_PATH_LEN: usize = 4096;
+/// Bytes accounted for each file or directory entry besides its content.
+const ENTRY_OVERHEAD: usize = 128;
+/// Max number of files a program can open at the same time.
+const MAX_OPEN_FILES: usize = 1024;
+/// The fd of the preopened root directory. 0, 1 and 2 are reserved for the stdio.
+pub const ROOT_FD: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// The path or any of its parent does not exist.
+    NotFound,
+    /// The path already exists.
+    AlreadyExists,
+    /// A directory is expected but found a file.
+    NotADirectory,
+    /// A file is expected but found a directory.
+    IsADirectory,
+    /// Removing a directory which is not empty.
+    NotEmpty,
+    /// The path escapes the root of the filesystem or is malformed.
+    InvalidPath,
+    /// The size quota of the filesystem is exceeded.
+    QuotaExceeded,
+    /// The file offset or size is out of range.
+    TooLarge,
+    /// Too many files are opened.
+    TooManyOpenFiles,
+    /// The preopened root directory can not be closed or replaced.
+    Preopened,
+}
+
+pub type Result<T, E = FsError> = core::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Dir,
+}
+
+/// Metadata of a file or directory.
+#[derive(Debug, Clone, Copy)]
+pub struct Stat {
+    pub kind: NodeKind,
+    pub ino: u64,
+    pub size: u64,
+}
+
+/// Options used to open a path.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenOptions {
+    /// Create the file if it does not exist.
+    pub create: bool,
+    /// Fail if the file already exists. Only valid with `create`.
+    pub exclusive: bool,
+    /// Truncate the file to zero length.
+    pub truncate: bool,
+    /// Fail if the path is not a directory.
+    pub directory: bool,
+}
+
+#[derive(Clone, Default)]
+struct FileData(Vec<u8>);
+
+impl Serialize for FileData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = FileData;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("file content bytes")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(FileData(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(FileData(v))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(b) = seq.next_element()? {
+                    data.push(b);
+                }
+                Ok(FileData(data))
+            }
+        }
+        deserializer.deserialize_byte_buf(Visitor)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum Node {
+    File { ino: u64, data: FileData },
+    Dir { ino: u64 },
+}
+
+impl Node {
+    fn kind(&self) -> NodeKind {
+        match self {
+            Node::File { .. } => NodeKind::File,
+            Node::Dir { .. } => NodeKind::Dir,
+        }
+    }
+
+    fn stat(&self) -> Stat {
+        match self {
+            Node::File { ino, data } => Stat {
+                kind: NodeKind::File,
+                ino: *ino,
+                size: data.0.len() as u64,
+            },
+            Node::Dir { ino } => Stat {
+                kind: NodeKind::Dir,
+                ino: *ino,
+                size: 0,
+            },
+        }
+    }
+
+    fn footprint(&self) -> usize {
+        match self {
+            Node::File { data, .. } => ENTRY_OVERHEAD + data.0.len(),
+            Node::Dir { .. } => ENTRY_OVERHEAD,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct FsInner {
+    /// All the nodes except the root, keyed by the normalized path.
+    nodes: BTreeMap<String, Node>,
+    next_ino: u64,
+    used: usize,
+    quota: usize,
+}
+
+/// A handle to the in-memory filesystem of a sidevm program.
+#[derive(Clone)]
+pub struct FileSystem(Arc<Mutex<FsInner>>);
+
+impl Default for FileSystem {
+    fn default() -> Self {
+        // 16MB by default
+        Self::new(16 * 1024 * 1024)
+    }
+}
+
+impl Serialize for FileSystem {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.lock().unwrap().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileSystem {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Self(Arc::new(Mutex::new(FsInner::deserialize(
+            deserializer,
+        )?))))
+    }
+}
+
+/// Normalize a path relative to `base`. Both the input and output are relative to the root.
+pub fn join_path(base: &str, path: &str) -> Result<String> {
+    if base.len() + path.len() > MAX_PATH_LEN {
+        return Err(FsError::InvalidPath);
+    }
+    let mut parts: Vec<&str> = if path.starts_with('/') {
+        vec![]
+    } else {
+        base.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(FsError::InvalidPath);
+                }
+            }
+            part => {
+                if part.contains('\0') {
+                    return Err(FsError::InvalidPath);
+                }
+                parts.push(part)
+            }
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+fn parent_of(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(pos) => &path[..pos],
+        None => "",
+    }
+}
+
+fn is_child_of(path: &str, dir: &str) -> bool {
+    if dir.is_empty() {
+        return !path.is_empty();
+    }
+    path.len() > dir.len() && path.starts_with(dir) && path.as_bytes()[dir.len()] == b'/'
+}
+
+impl FsInner {
+    fn node(&self, path: &str) -> Result<&Node> {
+        self.nodes.get(path).ok_or(FsError::NotFound)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        path.is_empty() || matches!(self.nodes.get(path), Some(Node::Dir { .. }))
+    }
+
+    fn check_parent(&self, path: &str) -> Result<()> {
+        if path.is_empty() {
+            return Err(FsError::AlreadyExists);
+        }
+        let parent = parent_of(path);
+        if parent.is_empty() {
+            return Ok(());
+        }
+        match self.nodes.get(parent) {
+            None => Err(FsError::NotFound),
+            Some(Node::File { .. }) => Err(FsError::NotADirectory),
+            Some(Node::Dir { .. }) => Ok(()),
+        }
+    }
+
+    fn reserve(&mut self, bytes: usize) -> Result<()> {
+        let used = self.used.checked_add(bytes).ok_or(FsError::QuotaExceeded)?;
+        if used > self.quota {
+            return Err(FsError::QuotaExceeded);
+        }
+        self.used = used;
+        Ok(())
+    }
+
+    fn release(&mut self, bytes: usize) {
+        self.used = self.used.saturating_sub(bytes);
+    }
+
+    fn insert(&mut self, path: &str, kind: NodeKind) -> Result<()> {
+        self.check_parent(path)?;
+        if self.nodes.contains_key(path) {
+            return Err(FsError::AlreadyExists);
+        }
+        self.reserve(ENTRY_OVERHEAD)?;
+        self.next_ino += 1;
+        let ino = self.next_ino;
+        let node = match kind {
+            NodeKind::File => Node::File {
+                ino,
+                data: Default::default(),
+            },
+            NodeKind::Dir => Node::Dir { ino },
+        };
+        self.nodes.insert(path.into(), node);
+        Ok(())
+    }
+
+    fn file_mut(&mut self, path: &str) -> Result<&mut Vec<u8>> {
+        match self.nodes.get_mut(path) {
+            None if path.is_empty() => Err(FsError::IsADirectory),
+            None => Err(FsError::NotFound),
+            Some(Node::Dir { .. }) => Err(FsError::IsADirectory),
+            Some(Node::File { data, .. }) => Ok(&mut data.0),
+        }
+    }
+
+    fn resize(&mut self, path: &str, len: usize) -> Result<()> {
+        let cur_len = self.file_mut(path)?.len();
+        if len > cur_len {
+            self.reserve(len - cur_len)?;
+        } else {
+            self.release(cur_len - len);
+        }
+        self.file_mut(path)?.resize(len, 0);
+        Ok(())
+    }
+}
+
+impl FileSystem {
+    /// Create an empty filesystem which can hold at most `quota` bytes.
+    pub fn new(quota: usize) -> Self {
+        Self(Arc::new(Mutex::new(FsInner {
+            nodes: Default::default(),
+            next_ino: 1,
+            used: 0,
+            quota,
+        })))
+    }
+
+    /// Bytes used by the files and directories.
+    pub fn used(&self) -> usize {
+        self.0.lock().unwrap().used
+    }
+
+    /// Change the quota of the filesystem. Existing files are kept even if they exceed the quota.
+    pub fn set_quota(&self, quota: usize) {
+        self.0.lock().unwrap().quota = quota;
+    }
+
+    /// Open or create the file or directory at given normalized path.
+    pub fn open(&self, path: &str, opts: OpenOptions) -> Result<NodeKind> {
+        let mut fs = self.0.lock().unwrap();
+        if fs.is_dir(path) {
+            if opts.create && opts.exclusive {
+                return Err(FsError::AlreadyExists);
+            }
+            if opts.truncate {
+                return Err(FsError::IsADirectory);
+            }
+            return Ok(NodeKind::Dir);
+        }
+        match fs.nodes.get(path) {
+            Some(_) => {
+                if opts.directory {
+                    return Err(FsError::NotADirectory);
+                }
+                if opts.create && opts.exclusive {
+                    return Err(FsError::AlreadyExists);
+                }
+                if opts.truncate {
+                    fs.resize(path, 0)?;
+                }
+            }
+            None => {
+                if !opts.create || opts.directory {
+                    return Err(FsError::NotFound);
+                }
+                fs.insert(path, NodeKind::File)?;
+            }
+        }
+        Ok(NodeKind::File)
+    }
+
+    pub fn stat(&self, path: &str) -> Result<Stat> {
+        let fs = self.0.lock().unwrap();
+        if path.is_empty() {
+            return Ok(Stat {
+                kind: NodeKind::Dir,
+                ino: 1,
+                size: 0,
+            });
+        }
+        Ok(fs.node(path)?.stat())
+    }
+
+    /// Read the file content at `offset` into `buf`. Returns the number of bytes read.
+    pub fn read(&self, path: &str, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let mut fs = self.0.lock().unwrap();
+        let data = fs.file_mut(path)?;
+        let offset = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        Ok(len)
+    }
+
+    /// Write `buf` into the file at `offset`, extending the file if needed.
+    pub fn write(&self, path: &str, offset: u64, buf: &[u8]) -> Result<usize> {
+        self.write_with(path, offset, buf.len(), |dst| {
+            dst.copy_from_slice(buf);
+            Ok::<_, core::convert::Infallible>(())
+        })?
+        .unwrap_or_else(|never| match never {});
+        Ok(buf.len())
+    }
+
+    /// Write `len` bytes into the file at `offset`, extending the file if needed, with the bytes
+    /// filled in place by `fill`.
+    ///
+    /// The quota is checked before `fill` is called, so the caller can copy the data straight
+    /// into the file without buffering it. If `fill` fails, the file is left at its previous
+    /// length.
+    pub fn write_with<E>(
+        &self,
+        path: &str,
+        offset: u64,
+        len: usize,
+        fill: impl FnOnce(&mut [u8]) -> Result<(), E>,
+    ) -> Result<Result<(), E>> {
+        let mut fs = self.0.lock().unwrap();
+        let offset: usize = offset.try_into().or(Err(FsError::TooLarge))?;
+        let end = offset.checked_add(len).ok_or(FsError::TooLarge)?;
+        let cur_len = fs.file_mut(path)?.len();
+        if end > cur_len {
+            fs.resize(path, end)?;
+        }
+        let result = fill(&mut fs.file_mut(path)?[offset..end]);
+        if result.is_err() && end > cur_len {
+            fs.resize(path, cur_len)?;
+        }
+        Ok(result)
+    }
+
+    /// Truncate or extend the file to given length.
+    pub fn set_len(&self, path: &str, len: u64) -> Result<()> {
+        let len = len.try_into().or(Err(FsError::TooLarge))?;
+        self.0.lock().unwrap().resize(path, len)
+    }
+
+    pub fn create_dir(&self, path: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(path, NodeKind::Dir)
+    }
+
+    pub fn remove_dir(&self, path: &str) -> Result<()> {
+        let mut fs = self.0.lock().unwrap();
+        if path.is_empty() {
+            return Err(FsError::InvalidPath);
+        }
+        match fs.node(path)? {
+            Node::File { .. } => return Err(FsError::NotADirectory),
+            Node::Dir { .. } => {}
+        }
+        if fs.nodes.keys().any(|p| is_child_of(p, path)) {
+            return Err(FsError::NotEmpty);
+        }
+        if let Some(node) = fs.nodes.remove(path) {
+            fs.release(node.footprint());
+        }
+        Ok(())
+    }
+
+    pub fn unlink(&self, path: &str) -> Result<()> {
+        let mut fs = self.0.lock().unwrap();
+        match fs.node(path)? {
+            Node::Dir { .. } => return Err(FsError::IsADirectory),
+            Node::File { .. } => {}
+        }
+        if let Some(node) = fs.nodes.remove(path) {
+            fs.release(node.footprint());
+        }
+        Ok(())
+    }
+
+    /// Rename a file or directory. Replaces the destination if it is a file.
+    pub fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut fs = self.0.lock().unwrap();
+        if from.is_empty() || to.is_empty() || is_child_of(to, from) {
+            return Err(FsError::InvalidPath);
+        }
+        if from == to {
+            fs.node(from)?;
+            return Ok(());
+        }
+        let kind = fs.node(from)?.kind();
+        fs.check_parent(to)?;
+        match (kind, fs.nodes.get(to).map(Node::kind)) {
+            (_, None) => {}
+            (NodeKind::File, Some(NodeKind::File)) => {
+                if let Some(node) = fs.nodes.remove(to) {
+                    fs.release(node.footprint());
+                }
+            }
+            (NodeKind::File, Some(NodeKind::Dir)) => return Err(FsError::IsADirectory),
+            (NodeKind::Dir, Some(_)) => return Err(FsError::AlreadyExists),
+        }
+        let moving: Vec<String> = fs
+            .nodes
+            .keys()
+            .filter(|p| p.as_str() == from || is_child_of(p, from))
+            .cloned()
+            .collect();
+        for path in moving {
+            if let Some(node) = fs.nodes.remove(&path) {
+                let new_path = format!("{}{}", to, &path[from.len()..]);
+                fs.nodes.insert(new_path, node);
+            }
+        }
+        Ok(())
+    }
+
+    /// List the entries of a directory, sorted by name.
+    pub fn read_dir(&self, path: &str) -> Result<Vec<(String, Stat)>> {
+        let fs = self.0.lock().unwrap();
+        if !fs.is_dir(path) {
+            fs.node(path)?;
+            return Err(FsError::NotADirectory);
+        }
+        Ok(fs
+            .nodes
+            .iter()
+            .filter(|(p, _)| is_child_of(p, path) && parent_of(p) == path)
+            .map(|(p, node)| {
+                let name = match path.is_empty() {
+                    true => p.clone(),
+                    false => p[path.len() + 1..].to_string(),
+                };
+                (name, node.stat())
+            })
+            .collect())
+    }
+}
+
+/// An open file or directory.
+pub struct OpenFile {
+    /// Normalized path of the file.
+    pub path: String,
+    pub kind: NodeKind,
+    /// Current position for reading or writing.
+    pub offset: u64,
+    /// Whether writes always go to the end of the file.
+    pub append: bool,
+    pub readable: bool,
+    pub writable: bool,
+}
+
+/// The file descriptor table of a sidevm instance.
+///
+/// Unlike the [`FileSystem`], the fds are not kept across restarts of the program.
+pub struct FdTable {
+    files: BTreeMap<u32, OpenFile>,
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        let mut files = BTreeMap::new();
+        files.insert(
+            ROOT_FD,
+            OpenFile {
+                path: String::new(),
+                kind: NodeKind::Dir,
+                offset: 0,
+                append: false,
+                readable: true,
+                writable: true,
+            },
+        );
+        Self { files }
+    }
+}
+
+impl FdTable {
+    pub fn get_mut(&mut self, fd: u32) -> Option<&mut OpenFile> {
+        self.files.get_mut(&fd)
+    }
+
+    /// Allocate the lowest available fd for the given file.
+    pub fn insert(&mut self, file: OpenFile) -> Result<u32> {
+        if self.files.len() >= MAX_OPEN_FILES {
+            return Err(FsError::TooManyOpenFiles);
+        }
+        let mut fd = ROOT_FD + 1;
+        for &used in self.files.range(fd..).map(|(fd, _)| fd) {
+            if used != fd {
+                break;
+            }
+            fd += 1;
+        }
+        self.files.insert(fd, file);
+        Ok(fd)
+    }
+
+    pub fn remove(&mut self, fd: u32) -> Option<OpenFile> {
+        self.files.remove(&fd)
+    }
+
+    /// Move the file at `from` to `to`, closing the previous file at `to`.
+    pub fn renumber(&mut self, from: u32, to: u32) -> Result<()> {
+        if from == ROOT_FD || to == ROOT_FD {
+            return Err(FsError::Preopened);
+        }
+        let file = self.files.remove(&from).ok_or(FsError::NotFound)?;
+        self.files.insert(to, file);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create() -> OpenOptions {
+        OpenOptions {
+            create: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn join_path_works() {
+        assert_eq!(join_path("", "a/./b//c").unwrap(), "a/b/c");
+        assert_eq!(join_path("a/b", "../c").unwrap(), "a/c");
+        assert_eq!(join_path("a/b", "/c").unwrap(), "c");
+        assert_eq!(join_path("a", "../.."), Err(FsError::InvalidPath));
+    }
+
+    #[test]
+    fn read_write_works() {
+        let fs = FileSystem::new(1024);
+        assert_eq!(fs.open("f", Default::default()), Err(FsError::NotFound));
+        assert_eq!(fs.open("f", create()), Ok(NodeKind::File));
+        assert_eq!(fs.write("f", 2, b"hello").unwrap(), 5);
+        let mut buf = [0u8; 16];
+        assert_eq!(fs.read("f", 0, &mut buf).unwrap(), 7);
+        assert_eq!(&buf[..7], b"\0\0hello");
+        assert_eq!(fs.stat("f").unwrap().size, 7);
+        fs.set_len("f", 3).unwrap();
+        assert_eq!(fs.read("f", 1, &mut buf).unwrap(), 2);
+    }
+
+    #[test]
+    fn quota_is_enforced() {
+        let fs = FileSystem::new(ENTRY_OVERHEAD + 10);
+        fs.open("f", create()).unwrap();
+        assert_eq!(fs.write("f", 0, &[0; 11]), Err(FsError::QuotaExceeded));
+        assert_eq!(fs.write("f", 0, &[0; 10]), Ok(10));
+        assert_eq!(fs.open("g", create()), Err(FsError::QuotaExceeded));
+        fs.unlink("f").unwrap();
+        assert_eq!(fs.used(), 0);
+    }
+
+    #[test]
+    fn write_with_checks_quota_before_filling() {
+        let fs = FileSystem::new(ENTRY_OVERHEAD + 10);
+        fs.open("f", create()).unwrap();
+        let result = fs.write_with("f", 0, 11, |_| -> Result<(), ()> {
+            panic!("should not be called")
+        });
+        assert_eq!(result, Err(FsError::QuotaExceeded));
+        assert_eq!(fs.write_with("f", 0, 4, |_| Err("fault")), Ok(Err("fault")));
+        assert_eq!(fs.stat("f").unwrap().size, 0);
+        assert_eq!(fs.used(), ENTRY_OVERHEAD);
+        let result = fs.write_with("f", 2, 3, |dst| {
+            dst.copy_from_slice(b"abc");
+            Ok::<_, ()>(())
+        });
+        assert_eq!(result, Ok(Ok(())));
+        let mut buf = [0u8; 8];
+        assert_eq!(fs.read("f", 0, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"\0\0abc");
+    }
+
+    #[test]
+    fn dirs_work() {
+        let fs = FileSystem::new(4096);
+        assert_eq!(fs.open("a/f", create()), Err(FsError::NotFound));
+        fs.create_dir("a").unwrap();
+        fs.open("a/f", create()).unwrap();
+        fs.create_dir("a/b").unwrap();
+        fs.create_dir("ab").unwrap();
+        let names: Vec<_> = fs.read_dir("a").unwrap().into_iter().map(|e| e.0).collect();
+        assert_eq!(names, vec!["b", "f"]);
+        assert_eq!(fs.remove_dir("a"), Err(FsError::NotEmpty));
+        fs.rename("a", "c").unwrap();
+        assert_eq!(fs.stat("c/f").unwrap().kind, NodeKind::File);
+        assert_eq!(fs.stat("a/f").unwrap_err(), FsError::NotFound);
+        let names: Vec<_> = fs.read_dir("").unwrap().into_iter().map(|e| e.0).collect();
+        assert_eq!(names, vec!["ab", "c"]);
+    }
+
+    #[test]
+    fn fd_allocation_works() {
+        let mut fds = FdTable::default();
+        let open = || OpenFile {
+            path: "f".into(),
+            kind: NodeKind::File,
+            offset: 0,
+            append: false,
+            readable: true,
+            writable: false,
+        };
+        assert_eq!(fds.insert(open()), Ok(4));
+        assert_eq!(fds.insert(open()), Ok(5));
+        assert!(fds.remove(4).is_some());
+        assert_eq!(fds.insert(open()), Ok(4));
+        assert_eq!(fds.insert(open()), Ok(6));
+    }
+
+    #[test]
+    fn renumber_keeps_the_root() {
+        let mut fds = FdTable::default();
+        let fd = fds
+            .insert(OpenFile {
+                path: "f".into(),
+                kind: NodeKind::File,
+                offset: 0,
+                append: false,
+                readable: true,
+                writable: false,
+            })
+            .unwrap();
+        assert_eq!(fds.renumber(fd, ROOT_FD), Err(FsError::Preopened));
+        assert_eq!(fds.renumber(ROOT_FD, fd), Err(FsError::Preopened));
+        assert_eq!(fds.get_mut(ROOT_FD).unwrap().kind, NodeKind::Dir);
+        assert_eq!(fds.get_mut(fd).unwrap().path, "f");
+        assert_eq!(fds.renumber(fd + 1, fd), Err(FsError::NotFound));
+        assert_eq!(fds.renumber(fd, 10), Ok(()));
+        assert!(fds.get_mut(fd).is_none());
+        assert_eq!(fds.get_mut(10).unwrap().path, "f");
+    }
+}
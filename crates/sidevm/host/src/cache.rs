@@ -0,0 +1,60 @@
+//! A local cache backend persisting the entries as files in a directory.
+
+use sidevm_host_runtime::{CacheOps, DynCacheOps, OcallError};
+use std::path::PathBuf;
+
+type OpResult<T> = Result<T, OcallError>;
+
+struct DirCache {
+    dir: PathBuf,
+}
+
+impl DirCache {
+    fn path_of(&self, contract: &[u8], key: &[u8]) -> PathBuf {
+        self.dir.join(hex::encode(contract)).join(hex::encode(key))
+    }
+}
+
+impl CacheOps for DirCache {
+    fn get(&self, contract: &[u8], key: &[u8]) -> OpResult<Option<Vec<u8>>> {
+        match std::fs::read(self.path_of(contract, key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                log::error!("Failed to read cache entry: {err}");
+                Err(OcallError::IoError)
+            }
+        }
+    }
+
+    fn set(&self, contract: &[u8], key: &[u8], value: &[u8]) -> OpResult<()> {
+        let path = self.path_of(contract, key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).or(Err(OcallError::IoError))?;
+        }
+        std::fs::write(path, value).or(Err(OcallError::IoError))
+    }
+
+    fn set_expiration(
+        &self,
+        _contract: &[u8],
+        _key: &[u8],
+        _expire_after_secs: u64,
+    ) -> OpResult<()> {
+        // Entries never expire in the development host.
+        Ok(())
+    }
+
+    fn remove(&self, contract: &[u8], key: &[u8]) -> OpResult<Option<Vec<u8>>> {
+        let value = self.get(contract, key)?;
+        if value.is_some() {
+            std::fs::remove_file(self.path_of(contract, key)).or(Err(OcallError::IoError))?;
+        }
+        Ok(value)
+    }
+}
+
+/// Create a cache backend storing the entries under `dir`.
+pub fn dir_cache(dir: impl Into<PathBuf>) -> DynCacheOps {
+    Box::leak(Box::new(DirCache { dir: dir.into() }))
+}
@@ -0,0 +1,113 @@
+//! Captures the logs of the sidevm instances so that they can be served by the web API.
+//!
+//! The host runtime emits the logs of a guest program with the target `sidevm` and the short vmid
+//! as a `[xxxxxxxxxxxx]` prefix. This logger forwards everything to `env_logger` and keeps the last
+//! lines of each instance in a ring buffer, plus a broadcast channel for log streaming.
+
+use once_cell::sync::OnceCell;
+use sidevm_host_runtime::{ShortId, VmId};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+static COLLECTOR: OnceCell<Collector> = OnceCell::new();
+
+struct Buffer {
+    lines: VecDeque<String>,
+    tx: broadcast::Sender<String>,
+}
+
+struct Collector {
+    capacity: usize,
+    buffers: Mutex<HashMap<String, Buffer>>,
+}
+
+impl Collector {
+    fn with_buffer<T>(&self, key: String, f: impl FnOnce(&mut Buffer) -> T) -> T {
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.entry(key).or_insert_with(|| Buffer {
+            lines: VecDeque::new(),
+            tx: broadcast::channel(self.capacity.max(1)).0,
+        });
+        f(buffer)
+    }
+
+    fn push(&self, key: String, line: String) {
+        let capacity = self.capacity;
+        self.with_buffer(key, |buffer| {
+            if buffer.lines.len() >= capacity {
+                buffer.lines.pop_front();
+            }
+            buffer.lines.push_back(line.clone());
+            // No one is streaming the logs if it fails.
+            let _ = buffer.tx.send(line);
+        })
+    }
+}
+
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == "sidevm" || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if record.target() != "sidevm" {
+            return;
+        }
+        let collector = match COLLECTOR.get() {
+            Some(collector) => collector,
+            None => return,
+        };
+        let message = record.args().to_string();
+        let key = match message
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+        {
+            Some((key, _)) => key.to_string(),
+            None => return,
+        };
+        collector.push(key, format!("{:<5} {}", record.level(), message));
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Install the logger, keeping at most `capacity` lines for each instance.
+pub fn init(capacity: usize) {
+    let _ = COLLECTOR.set(Collector {
+        capacity,
+        buffers: Default::default(),
+    });
+    let inner = env_logger::Builder::from_default_env().build();
+    log::set_boxed_logger(Box::new(Logger { inner })).expect("Logger already initialized");
+    log::set_max_level(log::LevelFilter::Trace);
+}
+
+fn key_of(vmid: &VmId) -> String {
+    ShortId(vmid).to_string()
+}
+
+/// The recent log lines of given instance.
+pub fn recent(vmid: &VmId) -> Vec<String> {
+    match COLLECTOR.get() {
+        Some(collector) => collector.with_buffer(key_of(vmid), |buffer| {
+            buffer.lines.iter().cloned().collect()
+        }),
+        None => vec![],
+    }
+}
+
+/// Subscribe to the log lines emitted by given instance from now on.
+pub fn subscribe(vmid: &VmId) -> Option<broadcast::Receiver<String>> {
+    let collector = COLLECTOR.get()?;
+    Some(collector.with_buffer(key_of(vmid), |buffer| buffer.tx.subscribe()))
+}
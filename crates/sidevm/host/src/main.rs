@@ -5,6 +5,9 @@ use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::sync::RwLock;
 
+mod cache;
+mod logs;
+mod manifest;
 mod web_api;
 
 #[derive(Parser)]
@@ -16,6 +19,21 @@ pub struct Args {
     gas_per_breath: u64,
     #[clap(long, default_value_t = 1)]
     workers: usize,
+    /// Path to a JSON file containing the gas cost table.
+    #[clap(long)]
+    cost_table: Option<String>,
+    /// Persist the local cache into given directory instead of keeping it in memory.
+    #[clap(long)]
+    cache_dir: Option<String>,
+    /// Path to a JSON manifest listing the programs to launch at startup.
+    #[clap(long)]
+    manifest: Option<String>,
+    /// Serve the shared TLS ingress on given address, e.g. 127.0.0.1:8443.
+    #[clap(long)]
+    ingress: Option<String>,
+    /// Max number of log lines kept for each instance.
+    #[clap(long, default_value_t = 1000)]
+    log_lines: usize,
     /// The WASM program to run
     program: Option<String>,
 }
@@ -57,10 +75,11 @@ fn simple_cache() -> DynCacheOps {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    let args = Args::parse();
+    logs::init(args.log_lines);
     if std::env::var("ROCKET_PORT").is_err() {
         std::env::set_var("ROCKET_PORT", "8003");
     }
-    web_api::serve(Args::parse()).await.unwrap();
+    web_api::serve(args).await.unwrap();
     Ok(())
 }
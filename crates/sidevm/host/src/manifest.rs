@@ -0,0 +1,50 @@
+//! The manifest describing the programs to launch when the host starts.
+//!
+//! ```json
+//! {
+//!     "programs": [
+//!         { "name": "echo", "path": "echo.wasm", "weight": 2 },
+//!         { "path": "../timer/timer.wasm" }
+//!     ]
+//! }
+//! ```
+//!
+//! Relative paths are resolved against the directory containing the manifest.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub programs: Vec<Program>,
+}
+
+#[derive(Deserialize)]
+pub struct Program {
+    /// A human readable name shown in the instance list.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Path to the wasm file.
+    pub path: PathBuf,
+    /// The task scheduling weight of the instance.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Manifest> {
+    let path = path.as_ref();
+    let content =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut manifest: Manifest =
+        serde_json::from_slice(&content).context("Failed to parse the manifest")?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for program in manifest.programs.iter_mut() {
+        program.path = base.join(&program.path);
+    }
+    Ok(manifest)
+}
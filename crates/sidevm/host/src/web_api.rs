@@ -1,78 +1,301 @@
 use rocket::data::ToByteUnit;
 use rocket::http::Status;
 use rocket::response::status::Custom;
-use rocket::{post, routes};
-use rocket::{Data, State};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::Json;
+use rocket::{get, post, routes};
+use rocket::{Data, Shutdown, State};
 use scale::Decode;
+use serde::{Deserialize, Serialize};
 use sp_core::crypto::AccountId32;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::str::FromStr;
-use tokio::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::select;
+use tokio::sync::{broadcast::error::RecvError, watch, Mutex};
 
-use sidevm_host_runtime::service as sidevm;
-use sidevm::{Command, CommandSender, Spawner, SystemMessage};
+use sidevm::{Command, CommandSender, ExitReason, Spawner, SystemMessage};
+use sidevm_host_runtime::{
+    service as sidevm, CostTable, DynCacheOps, FileSystem, GasCounter, Ingress, PersistedState,
+    VmId,
+};
+use std::sync::Arc;
+
+use crate::{logs, manifest, Args};
+
+type HttpResult<T> = Result<T, Custom<&'static str>>;
+
+#[derive(Clone, Copy)]
+enum InstanceStatus {
+    Running,
+    Stopped(ExitReason),
+}
+
+struct Instance {
+    name: Option<String>,
+    code: Arc<Vec<u8>>,
+    weight: u32,
+    sender: CommandSender,
+    status: watch::Receiver<InstanceStatus>,
+    started_at: u64,
+    restarts: u32,
+    /// Kept across restarts like pRuntime does.
+    persisted_state: PersistedState,
+    fs: FileSystem,
+    gas_counter: GasCounter,
+}
+
+impl Instance {
+    fn is_running(&self) -> bool {
+        matches!(*self.status.borrow(), InstanceStatus::Running)
+    }
+
+    /// Sends the stop command and returns a future that resolves once the instance stopped.
+    ///
+    /// The future doesn't borrow the instance, so the caller can release the app lock before
+    /// awaiting it.
+    fn stop(&self) -> impl std::future::Future<Output = ()> {
+        let sender = self.sender.clone();
+        let mut status = self.status.clone();
+        async move {
+            // The instance might exit by itself in the meantime, so ignore the error.
+            let _ = sender.send(Command::Stop).await;
+            while matches!(*status.borrow(), InstanceStatus::Running) {
+                if status.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct InstanceInfo {
+    id: u32,
+    vmid: String,
+    name: Option<String>,
+    weight: u32,
+    status: String,
+    started_at: u64,
+    restarts: u32,
+    fs_used: usize,
+    /// Gas consumed by the current run of the instance so far.
+    gas_consumed: u64,
+}
 
-use crate::Args;
 struct AppInner {
     next_id: u32,
-    instances: HashMap<u32, CommandSender>,
+    instances: BTreeMap<u32, Instance>,
     args: Args,
     spawner: Spawner,
+    cost_table: Arc<CostTable>,
+    cache_ops: DynCacheOps,
 }
 
 struct App {
     inner: Mutex<AppInner>,
 }
 
+fn vmid_of(id: u32) -> VmId {
+    let mut vmid = [0u8; 32];
+    vmid[0..4].copy_from_slice(&id.to_be_bytes());
+    vmid
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl AppInner {
+    #[allow(clippy::too_many_arguments)]
+    fn start_instance(
+        &self,
+        id: u32,
+        name: Option<String>,
+        weight: u32,
+        code: Arc<Vec<u8>>,
+        persisted_state: PersistedState,
+        fs: FileSystem,
+        restarts: u32,
+    ) -> Result<Instance, &'static str> {
+        println!("VM {id} running...");
+        let (sender, handle, gas_counter) = self
+            .spawner
+            .start(
+                &code,
+                1024,
+                vmid_of(id),
+                self.args.gas_per_breath,
+                self.cache_ops,
+                persisted_state.clone(),
+                fs.clone(),
+                self.cost_table.clone(),
+                weight,
+            )
+            .map_err(|err| {
+                log::error!("Failed to start VM {id}: {err:?}");
+                "Failed to start the instance"
+            })?;
+        let (status_tx, status) = watch::channel(InstanceStatus::Running);
+        tokio::spawn(async move {
+            let reason = handle.await.unwrap_or(ExitReason::Cancelled);
+            println!("VM {id} stopped: {reason}");
+            let _ = status_tx.send(InstanceStatus::Stopped(reason));
+        });
+        Ok(Instance {
+            name,
+            code,
+            weight,
+            sender,
+            status,
+            started_at: now_secs(),
+            restarts,
+            persisted_state,
+            fs,
+            gas_counter,
+        })
+    }
+
+    fn instance_mut(&mut self, id: u32) -> HttpResult<&mut Instance> {
+        self.instances
+            .get_mut(&id)
+            .ok_or(Custom(Status::NotFound, "Instance not found"))
+    }
+}
+
 impl App {
-    fn new(spawner: Spawner, args: Args) -> Self {
+    fn new(spawner: Spawner, args: Args, cost_table: CostTable, cache_ops: DynCacheOps) -> Self {
         Self {
             inner: Mutex::new(AppInner {
-                instances: HashMap::new(),
+                instances: BTreeMap::new(),
                 next_id: 0,
                 spawner,
                 args,
+                cost_table: Arc::new(cost_table),
+                cache_ops,
             }),
         }
     }
 
     async fn send(&self, vmid: u32, message: Command) -> Result<(), (u16, &'static str)> {
-        self.inner
+        // Don't hold the lock while waiting for the channel of a busy instance
+        let sender = self
+            .inner
             .lock()
             .await
             .instances
             .get(&vmid)
             .ok_or((404, "Instance not found"))?
+            .sender
+            .clone();
+        sender
             .send(message)
             .await
             .or(Err((500, "Failed to send message")))?;
         Ok(())
     }
 
-    async fn run_wasm(&self, weight: u32, wasm_bytes: Vec<u8>) -> Result<u32, &'static str> {
+    async fn run_wasm(
+        &self,
+        name: Option<String>,
+        weight: u32,
+        wasm_bytes: Vec<u8>,
+    ) -> Result<u32, &'static str> {
         let mut inner = self.inner.lock().await;
         let id = inner.next_id;
+        let instance = inner.start_instance(
+            id,
+            name,
+            weight,
+            Arc::new(wasm_bytes),
+            Default::default(),
+            Default::default(),
+            0,
+        )?;
         inner.next_id += 1;
+        inner.instances.insert(id, instance);
+        Ok(id)
+    }
 
-        let mut vmid = [0u8; 32];
+    async fn info(&self, id: Option<u32>) -> HttpResult<Vec<InstanceInfo>> {
+        let inner = self.inner.lock().await;
+        let info = |(id, instance): (&u32, &Instance)| InstanceInfo {
+            id: *id,
+            vmid: hex::encode(vmid_of(*id)),
+            name: instance.name.clone(),
+            weight: instance.weight,
+            status: match *instance.status.borrow() {
+                InstanceStatus::Running => "running".into(),
+                InstanceStatus::Stopped(reason) => format!("stopped: {reason}"),
+            },
+            started_at: instance.started_at,
+            restarts: instance.restarts,
+            fs_used: instance.fs.used(),
+            gas_consumed: instance.gas_counter.get(),
+        };
+        match id {
+            Some(id) => {
+                let instance = inner
+                    .instances
+                    .get_key_value(&id)
+                    .ok_or(Custom(Status::NotFound, "Instance not found"))?;
+                Ok(vec![info(instance)])
+            }
+            None => Ok(inner.instances.iter().map(info).collect()),
+        }
+    }
 
-        vmid[0..4].copy_from_slice(&id.to_be_bytes());
+    async fn stop(&self, id: u32) -> HttpResult<()> {
+        let stopped = {
+            let mut inner = self.inner.lock().await;
+            let instance = inner.instance_mut(id)?;
+            if !instance.is_running() {
+                return Err(Custom(Status::Conflict, "Instance is not running"));
+            }
+            instance.stop()
+        };
+        stopped.await;
+        Ok(())
+    }
 
-        println!("VM {id} running...");
-        let (sender, handle) = inner
-            .spawner
-            .start(
-                &wasm_bytes,
-                1024,
-                vmid,
-                inner.args.gas_per_breath,
-                crate::simple_cache(),
-                weight,
-            )
-            .unwrap();
-        inner.instances.insert(id, sender);
-        tokio::spawn(handle);
-        Ok(id)
+    async fn restart(&self, id: u32) -> HttpResult<()> {
+        let stopped = {
+            let mut inner = self.inner.lock().await;
+            let instance = inner.instance_mut(id)?;
+            instance.is_running().then(|| instance.stop())
+        };
+        if let Some(stopped) = stopped {
+            stopped.await;
+        }
+        let mut inner = self.inner.lock().await;
+        let new_instance = {
+            let instance = inner.instance_mut(id)?;
+            if instance.is_running() {
+                return Err(Custom(
+                    Status::Conflict,
+                    "Instance was restarted by another request",
+                ));
+            }
+            let (name, weight, code) = (
+                instance.name.clone(),
+                instance.weight,
+                instance.code.clone(),
+            );
+            let (persisted_state, fs) = (instance.persisted_state.clone(), instance.fs.clone());
+            let restarts = instance.restarts + 1;
+            inner
+                .start_instance(id, name, weight, code, persisted_state, fs, restarts)
+                .map_err(|reason| Custom(Status::InternalServerError, reason))?
+        };
+        inner.instances.insert(id, new_instance);
+        Ok(())
+    }
+
+    async fn ensure_exists(&self, id: u32) -> HttpResult<()> {
+        self.inner.lock().await.instance_mut(id).map(drop)
     }
 }
 
@@ -116,6 +339,79 @@ async fn push_sys_message(
     Ok(())
 }
 
+#[derive(Deserialize)]
+struct PinkLogArgs {
+    #[serde(default)]
+    block_number: u32,
+    /// 1 for error to 5 for trace.
+    #[serde(default = "default_log_level")]
+    level: u8,
+    #[serde(default)]
+    in_query: bool,
+    message: String,
+}
+
+fn default_log_level() -> u8 {
+    log::Level::Info as u8
+}
+
+/// Simulate a `SystemMessage::PinkLog` emitted by the contract owning the sidevm instance.
+#[post("/push/pink-log/<id>", data = "<args>")]
+async fn push_pink_log(app: &State<App>, id: u32, args: Json<PinkLogArgs>) -> HttpResult<()> {
+    let args = args.into_inner();
+    let message = SystemMessage::PinkLog {
+        block_number: args.block_number,
+        contract: vmid_of(id),
+        in_query: args.in_query,
+        timestamp_ms: now_secs() * 1000,
+        level: args.level,
+        message: args.message,
+    };
+    app.send(id, Command::PushSystemMessage(message))
+        .await
+        .map_err(|(code, reason)| Custom(Status { code }, reason))
+}
+
+#[derive(Deserialize)]
+struct PinkEventArgs {
+    #[serde(default)]
+    block_number: u32,
+    /// Hex encoded 32 bytes topics.
+    #[serde(default)]
+    topics: Vec<String>,
+    /// Hex encoded event payload.
+    payload: String,
+}
+
+fn decode_hex(hex_str: &str) -> HttpResult<Vec<u8>> {
+    hex::decode(hex_str.trim_start_matches("0x"))
+        .or(Err(Custom(Status::BadRequest, "Invalid hex string")))
+}
+
+/// Simulate a `SystemMessage::PinkEvent` emitted by the contract owning the sidevm instance.
+#[post("/push/pink-event/<id>", data = "<args>")]
+async fn push_pink_event(app: &State<App>, id: u32, args: Json<PinkEventArgs>) -> HttpResult<()> {
+    let args = args.into_inner();
+    let topics = args
+        .topics
+        .iter()
+        .map(|topic| -> HttpResult<[u8; 32]> {
+            decode_hex(topic)?
+                .try_into()
+                .or(Err(Custom(Status::BadRequest, "Topic must be 32 bytes")))
+        })
+        .collect::<HttpResult<Vec<_>>>()?;
+    let message = SystemMessage::PinkEvent {
+        block_number: args.block_number,
+        contract: vmid_of(id),
+        topics,
+        payload: decode_hex(&args.payload)?,
+    };
+    app.send(id, Command::PushSystemMessage(message))
+        .await
+        .map_err(|(code, reason)| Custom(Status { code }, reason))
+}
+
 #[post("/push/query/<id>", data = "<data>")]
 async fn push_query_no_origin(
     app: &State<App>,
@@ -166,32 +462,122 @@ async fn push_query(
     Ok(reply)
 }
 
-#[post("/run/<weight>", data = "<data>")]
-async fn run(app: &State<App>, weight: u32, data: Data<'_>) -> Result<String, Custom<&'static str>> {
+#[post("/run/<weight>?<name>", data = "<data>")]
+async fn run(
+    app: &State<App>,
+    weight: u32,
+    name: Option<String>,
+    data: Data<'_>,
+) -> Result<String, Custom<&'static str>> {
     let code = read_data(data)
         .await
         .ok_or(Custom(Status::BadRequest, "No message payload"))?;
     let id = app
-        .run_wasm(weight, code)
+        .run_wasm(name, weight, code)
         .await
         .map_err(|reason| Custom(Status::InternalServerError, reason))?;
     Ok(id.to_string())
 }
 
+#[get("/instances")]
+async fn list_instances(app: &State<App>) -> HttpResult<Json<Vec<InstanceInfo>>> {
+    app.info(None).await.map(Json)
+}
+
+#[get("/instances/<id>")]
+async fn instance_info(app: &State<App>, id: u32) -> HttpResult<Json<InstanceInfo>> {
+    let mut info = app.info(Some(id)).await?;
+    Ok(Json(info.remove(0)))
+}
+
+#[post("/instances/<id>/stop")]
+async fn stop_instance(app: &State<App>, id: u32) -> HttpResult<()> {
+    app.stop(id).await
+}
+
+#[post("/instances/<id>/restart")]
+async fn restart_instance(app: &State<App>, id: u32) -> HttpResult<()> {
+    app.restart(id).await
+}
+
+#[get("/instances/<id>/logs")]
+async fn instance_logs(app: &State<App>, id: u32) -> HttpResult<String> {
+    app.ensure_exists(id).await?;
+    let mut lines = logs::recent(&vmid_of(id)).join("\n");
+    lines.push('\n');
+    Ok(lines)
+}
+
+/// Stream the logs of given instance as server-sent events.
+#[get("/instances/<id>/logs/stream")]
+async fn stream_instance_logs(
+    app: &State<App>,
+    id: u32,
+    mut shutdown: Shutdown,
+) -> HttpResult<EventStream![]> {
+    app.ensure_exists(id).await?;
+    let mut rx = logs::subscribe(&vmid_of(id)).ok_or(Custom(
+        Status::ServiceUnavailable,
+        "Log collector is not installed",
+    ))?;
+    Ok(EventStream! {
+        loop {
+            let line = select! {
+                line = rx.recv() => match line {
+                    Ok(line) => line,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut shutdown => break,
+            };
+            yield Event::data(line);
+        }
+    })
+}
+
 pub async fn serve(args: Args) -> anyhow::Result<()> {
     let (run, spawner) = sidevm::service(args.workers);
+    let ingress = Ingress::new();
+    let spawner = spawner.with_ingress(ingress.clone());
+    if let Some(addr) = args.ingress.clone() {
+        tokio::spawn(async move {
+            if let Err(err) = ingress.serve(&addr).await {
+                log::error!("Ingress stopped: {err}");
+            }
+        });
+    }
     std::thread::spawn(move || {
         run.blocking_run(|evt| {
             println!("event: {:?}", evt);
         });
     });
     let program = args.program.clone();
-    let app = App::new(spawner, args);
+    let manifest = match &args.manifest {
+        Some(path) => Some(manifest::load(path)?),
+        None => None,
+    };
+    let cost_table = match &args.cost_table {
+        Some(path) => serde_json::from_slice(&std::fs::read(path)?)?,
+        None => CostTable::default(),
+    };
+    let cache_ops = match &args.cache_dir {
+        Some(dir) => crate::cache::dir_cache(dir),
+        None => crate::simple_cache(),
+    };
+    let app = App::new(spawner, args, cost_table, cache_ops);
     if let Some(program) = program {
         let wasm_codes = std::fs::read(&program)?;
-        app.run_wasm(1, wasm_codes).await.map_err(|reason| {
-            anyhow::anyhow!("Failed to run wasm: {}", reason)
-        })?;
+        app.run_wasm(None, 1, wasm_codes)
+            .await
+            .map_err(|reason| anyhow::anyhow!("Failed to run wasm: {}", reason))?;
+    }
+    for program in manifest.into_iter().flat_map(|m| m.programs) {
+        let wasm_codes = std::fs::read(&program.path)?;
+        app.run_wasm(program.name, program.weight, wasm_codes)
+            .await
+            .map_err(|reason| {
+                anyhow::anyhow!("Failed to run {}: {}", program.path.display(), reason)
+            })?;
     }
     let _rocket = rocket::build()
         .manage(app)
@@ -200,9 +586,17 @@ pub async fn serve(args: Args) -> anyhow::Result<()> {
             routes![
                 push_message,
                 push_sys_message,
+                push_pink_log,
+                push_pink_event,
                 push_query,
                 push_query_no_origin,
                 run,
+                list_instances,
+                instance_info,
+                stop_instance,
+                restart_instance,
+                instance_logs,
+                stream_instance_logs,
             ],
         )
         .launch()
@@ -172,8 +172,8 @@ fn gen_dispatcher(methods: &[OcallMethod], trait_name: &Ident) -> Result<TokenSt
         } else {
             parse_quote! {
                 let (#(#args),*) = {
-                    let mut buf = vm.slice_from_vm(p0, p1)?;
-                    Decode::decode(&mut buf).or(Err(OcallError::InvalidParameter))?
+                    let buf = vm.copy_from_vm(p0, p1)?;
+                    Decode::decode(&mut &buf[..]).or(Err(OcallError::InvalidParameter))?
                 };
             }
         };
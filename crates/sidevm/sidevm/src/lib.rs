@@ -14,6 +14,7 @@ pub use env::tasks as task;
 
 pub mod channel;
 pub mod net;
+pub mod state;
 pub mod time;
 pub mod exec;
 
@@ -68,6 +68,15 @@ impl TcpListener {
         Ok(Self { res_id })
     }
 
+    /// Serve `hostname` over TLS through the shared ingress of the host.
+    ///
+    /// Connections to the ingress port with given server name (SNI) are routed to the returned
+    /// listener. This allows many sidevm programs on a worker to serve HTTPS on the same port.
+    pub async fn bind_sni(hostname: &str, config: TlsServerConfig) -> Result<Self> {
+        let res_id = ResourceId(ocall::tcp_listen_sni(hostname.into(), config)?);
+        Ok(Self { res_id })
+    }
+
     /// Accept a new incoming connection.
     pub fn accept(&self) -> Acceptor {
         Acceptor { listener: self }
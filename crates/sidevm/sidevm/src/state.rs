@@ -0,0 +1,22 @@
+//! Persisting program state across restarts of the sidevm instance.
+//!
+//! The in-memory state of a sidevm program is lost when the worker restarts. A program can save a
+//! snapshot of its state with [`persist`] from time to time, and read it back with [`restore`] at
+//! startup to resume from where it left off.
+
+use crate::env::Result;
+use crate::ocall;
+
+/// Persist the given state blob, overwriting the previously persisted one.
+///
+/// The blob is limited to 4MB.
+pub fn persist(data: &[u8]) -> Result<()> {
+    ocall::persist_state(data)
+}
+
+/// Get the last persisted state blob.
+///
+/// Returns `None` if the program has never persisted any state.
+pub fn restore() -> Result<Option<Vec<u8>>> {
+    ocall::persisted_state()
+}
@@ -1,7 +1,28 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+use codec::{Decode, Encode};
+use phala_mq::Message;
+use scale_info::TypeInfo;
+use sp_runtime::{traits::NumberFor, DispatchError};
+use sp_std::vec::Vec;
+
+/// A message whose handler failed on chain, kept for the governance to retry or purge
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct DeadLetter<BlockNumber> {
+	/// The failed message
+	pub message: Message,
+	/// The error returned by the handler
+	pub error: DispatchError,
+	/// The block the message was dispatched at
+	pub block: BlockNumber,
+}
+
 sp_api::decl_runtime_apis! {
+	#[api_version(2)]
 	pub trait MqApi {
 		fn sender_sequence(sender: &phala_mq::MessageOrigin) -> Option<u64>;
+		/// At most `limit` dead letters with their ids from `start`, in ascending order of id
+		fn dead_letters(start: u64, limit: u32) -> Vec<(u64, DeadLetter<NumberFor<Block>>)>;
 	}
 }
@@ -5,11 +5,15 @@ pub use self::pallet::*;
 #[frame_support::pallet]
 pub mod pallet {
 	use codec::Encode;
-	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::StorageVersion};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, ReservableCurrency, StorageVersion},
+	};
 	use frame_system::pallet_prelude::*;
 	use sp_core::H256;
-	use sp_runtime::AccountId32;
-	use sp_std::prelude::*;
+	use sp_runtime::{traits::Zero, AccountId32};
+	use sp_std::{fmt::Debug, prelude::*};
 
 	use crate::{mq::MessageOriginInfo, registry};
 	// Re-export
@@ -50,6 +54,39 @@ pub mod pallet {
 		type RuntimeEvent: From<Event<Self>> + IsType<<Self as frame_system::Config>::RuntimeEvent>;
 		type InkCodeSizeLimit: Get<u32>;
 		type SidevmCodeSizeLimit: Get<u32>;
+		type Currency: ReservableCurrency<Self::AccountId>;
+
+		/// The max number of workers in a cluster
+		#[pallet::constant]
+		type MaxClusterWorkers: Get<u32>;
+
+		/// The max number of code hashes a deployer can be restricted to in a cluster
+		#[pallet::constant]
+		type MaxDeployerCodeHashes: Get<u32>;
+	}
+
+	type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+	/// The permission of a deployer in a cluster with the `AllowList` permission
+	#[derive(
+		Encode,
+		Decode,
+		TypeInfo,
+		MaxEncodedLen,
+		CloneNoBound,
+		PartialEqNoBound,
+		EqNoBound,
+		RuntimeDebugNoBound,
+	)]
+	#[codec(mel_bound())]
+	#[scale_info(skip_type_params(MaxCodeHashes))]
+	pub struct DeployerPermission<
+		CodeHash: Encode + Decode + MaxEncodedLen + Clone + Debug + Eq,
+		MaxCodeHashes: Get<u32>,
+	> {
+		/// The code the deployer can instantiate, or any code if `None`
+		pub code_hashes: Option<BoundedVec<CodeHash, MaxCodeHashes>>,
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -80,6 +117,14 @@ pub mod pallet {
 	pub type ClusterWorkers<T> =
 		StorageMap<_, Twox64Concat, ContractClusterId, Vec<WorkerPublicKey>, ValueQuery>;
 
+	/// The workers added to a deployed cluster that haven't reported to have caught up with it
+	///
+	/// They are moved to [`ClusterWorkers`] once they report `ClusterDeployed`, or dropped from the
+	/// cluster if they report `ClusterDeploymentFailed`.
+	#[pallet::storage]
+	pub type ClusterJoiningWorkers<T> =
+		StorageMap<_, Twox64Concat, ContractClusterId, Vec<WorkerPublicKey>, ValueQuery>;
+
 	/// The pink-system contract code used to deploy new clusters
 	#[pallet::storage]
 	pub type PinkSystemCode<T> = StorageValue<_, (u16, Vec<u8>), ValueQuery>;
@@ -91,6 +136,35 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type NextPinkSystemCode<T> = StorageValue<_, Vec<u8>, OptionQuery>;
 
+	/// The deployers allowed by the clusters with the `AllowList` permission
+	#[pallet::storage]
+	pub type ClusterDeployers<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		ContractClusterId,
+		Twox64Concat,
+		T::AccountId,
+		DeployerPermission<CodeHash<T>, T::MaxDeployerCodeHashes>,
+	>;
+
+	/// The deposit reserved from the deployer for each contract instantiated in the cluster
+	#[pallet::storage]
+	pub type ClusterDeposits<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, BalanceOf<T>, ValueQuery>;
+
+	/// The deposits reserved for the contracts
+	///
+	/// Released when the contract is removed with its cluster, or by the governance.
+	#[pallet::storage]
+	pub type ContractDeposits<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		ContractClusterId,
+		Twox64Concat,
+		ContractId,
+		(T::AccountId, BalanceOf<T>),
+	>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -129,6 +203,35 @@ pub mod pallet {
 		ClusterDestroyed {
 			cluster: ContractClusterId,
 		},
+		ClusterWorkersAdded {
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		},
+		ClusterWorkerRemoved {
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		},
+		ClusterPermissionUpdated {
+			cluster: ContractClusterId,
+		},
+		ClusterDeployerSet {
+			cluster: ContractClusterId,
+			deployer: T::AccountId,
+		},
+		ClusterDeployerRemoved {
+			cluster: ContractClusterId,
+			deployer: T::AccountId,
+		},
+		ClusterDepositSet {
+			cluster: ContractClusterId,
+			deposit: BalanceOf<T>,
+		},
+		ContractDepositReleased {
+			cluster: ContractClusterId,
+			contract: ContractId,
+			deployer: T::AccountId,
+			deposit: BalanceOf<T>,
+		},
 	}
 
 	#[pallet::error]
@@ -144,18 +247,86 @@ pub mod pallet {
 		WorkerNotFound,
 		PayloadTooLarge,
 		NoPinkSystemCode,
+		WorkerAlreadyInCluster,
+		WorkerNotInCluster,
+		TooManyWorkers,
+		CodeNotAllowed,
+		DeployerNotFound,
+		DepositNotFound,
 	}
 
 	type CodeHash<T> = <T as frame_system::Config>::Hash;
 
 	fn check_cluster_permission<T: Config>(
 		deployer: &T::AccountId,
+		cluster_id: &ContractClusterId,
 		cluster: &ClusterInfo<T::AccountId>,
 	) -> bool {
 		match &cluster.permission {
 			ClusterPermission::Public => true,
 			ClusterPermission::OnlyOwner(owner) => deployer == owner,
+			ClusterPermission::AllowList => {
+				deployer == &cluster.owner
+					|| ClusterDeployers::<T>::contains_key(cluster_id, deployer)
+			}
+		}
+	}
+
+	/// Checks if the deployer is allowed to instantiate the code in the cluster
+	fn check_code_permission<T: Config>(
+		deployer: &T::AccountId,
+		cluster_id: &ContractClusterId,
+		cluster: &ClusterInfo<T::AccountId>,
+		code_index: &CodeIndex<CodeHash<T>>,
+	) -> bool {
+		if !matches!(cluster.permission, ClusterPermission::AllowList) || deployer == &cluster.owner
+		{
+			return true;
 		}
+		match ClusterDeployers::<T>::get(cluster_id, deployer) {
+			Some(DeployerPermission {
+				code_hashes: Some(code_hashes),
+			}) => match code_index {
+				CodeIndex::WasmCode(code_hash) => code_hashes.contains(code_hash),
+			},
+			Some(_) => true,
+			None => false,
+		}
+	}
+
+	/// The weight of `add_cluster_workers` adding `n` workers
+	fn add_cluster_workers_weight<T: Config>(n: u32) -> Weight {
+		// Each worker is read from the registry and checked against the workers in the cluster.
+		let per_worker = 10_000u64 + 1_000u64 * T::MaxClusterWorkers::get() as u64;
+		Weight::from_ref_time(10_000u64 + per_worker * n as u64)
+			+ T::DbWeight::get().reads_writes(3u64 + n as u64, 3u64)
+	}
+
+	/// The weight of `remove_cluster_worker`
+	fn remove_cluster_worker_weight<T: Config>() -> Weight {
+		// The worker lists of the cluster are scanned, which are bounded by `MaxClusterWorkers`.
+		Weight::from_ref_time(10_000u64 + 3_000u64 * T::MaxClusterWorkers::get() as u64)
+			+ T::DbWeight::get().reads_writes(3u64, 4u64)
+	}
+
+	/// The weight of `set_cluster_deployer` restricting the deployer to `n` code hashes
+	fn set_cluster_deployer_weight<T: Config>(n: u32) -> Weight {
+		Weight::from_ref_time(10_000u64 + 1_000u64 * n as u64)
+			+ T::DbWeight::get().reads_writes(1u64, 1u64)
+	}
+
+	/// Ensures the origin is either the governance or the owner of the cluster
+	fn ensure_cluster_admin<T: Config + registry::Config>(
+		origin: OriginFor<T>,
+		cluster: &ClusterInfo<T::AccountId>,
+	) -> DispatchResult {
+		let origin = match T::GovernanceOrigin::try_origin(origin) {
+			Ok(_) => return Ok(()),
+			Err(origin) => origin,
+		};
+		let who = ensure_signed(origin)?;
+		ensure!(who == cluster.owner, Error::<T>::ClusterPermissionDenied);
+		Ok(())
 	}
 
 	#[pallet::call]
@@ -174,6 +345,10 @@ pub mod pallet {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 
 			ensure!(deploy_workers.len() > 0, Error::<T>::NoWorkerSpecified);
+			ensure!(
+				deploy_workers.len() <= T::MaxClusterWorkers::get() as usize,
+				Error::<T>::TooManyWorkers
+			);
 			let workers = deploy_workers
 				.iter()
 				.map(|worker| {
@@ -236,7 +411,7 @@ pub mod pallet {
 			let origin: T::AccountId = ensure_signed(origin)?;
 			let cluster_info = Clusters::<T>::get(cluster_id).ok_or(Error::<T>::ClusterNotFound)?;
 			ensure!(
-				check_cluster_permission::<T>(&origin, &cluster_info),
+				check_cluster_permission::<T>(&origin, &cluster_id, &cluster_info),
 				Error::<T>::ClusterPermissionDenied
 			);
 
@@ -269,12 +444,16 @@ pub mod pallet {
 			let deployer = ensure_signed(origin)?;
 			let cluster_info = Clusters::<T>::get(cluster_id).ok_or(Error::<T>::ClusterNotFound)?;
 			ensure!(
-				check_cluster_permission::<T>(&deployer, &cluster_info),
+				check_cluster_permission::<T>(&deployer, &cluster_id, &cluster_info),
 				Error::<T>::ClusterPermissionDenied
 			);
+			ensure!(
+				check_code_permission::<T>(&deployer, &cluster_id, &cluster_info, &code_index),
+				Error::<T>::CodeNotAllowed
+			);
 
 			let contract_info = ContractInfo {
-				deployer,
+				deployer: deployer.clone(),
 				code_index,
 				salt,
 				cluster_id,
@@ -285,6 +464,11 @@ pub mod pallet {
 				!Contracts::<T>::contains_key(contract_id),
 				Error::<T>::DuplicatedContract
 			);
+			let deposit = ClusterDeposits::<T>::get(cluster_id);
+			if !deposit.is_zero() {
+				T::Currency::reserve(&deployer, deposit)?;
+				ContractDeposits::<T>::insert(cluster_id, contract_id, (deployer, deposit));
+			}
 			Contracts::<T>::insert(&contract_id, &contract_info);
 
 			Self::push_message(ContractOperation::instantiate_code(contract_info.clone()));
@@ -302,6 +486,13 @@ pub mod pallet {
 			ensure_root(origin)?;
 
 			Clusters::<T>::take(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			let contracts: Vec<_> = ContractDeposits::<T>::iter_key_prefix(&cluster).collect();
+			for contract in contracts.iter() {
+				Self::do_release_contract_deposit(&cluster, contract);
+			}
+			let _ = ClusterDeployers::<T>::clear_prefix(&cluster, u32::MAX, None);
+			ClusterJoiningWorkers::<T>::remove(&cluster);
+			ClusterDeposits::<T>::remove(&cluster);
 			Self::push_message(
 				ClusterOperation::<T::AccountId, T::BlockNumber>::DestroyCluster(cluster),
 			);
@@ -309,6 +500,191 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Adds workers to a deployed cluster
+		///
+		/// The gatekeeper distributes the cluster key to the new workers. They are not listed in
+		/// [`ClusterWorkers`] until they report `ClusterDeployed`, which they only do once the
+		/// cluster state exported by another worker of the cluster (`/bin_api/export_cluster_state`)
+		/// is imported to them (`/bin_api/import_cluster_state`) and its state root matches. Can be
+		/// called by the governance or the cluster owner.
+		#[pallet::weight(add_cluster_workers_weight::<T>(workers.len() as u32))]
+		pub fn add_cluster_workers(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			workers: BoundedVec<WorkerPublicKey, T::MaxClusterWorkers>,
+		) -> DispatchResult {
+			let mut cluster_info =
+				Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure_cluster_admin::<T>(origin, &cluster_info)?;
+			ensure!(!workers.is_empty(), Error::<T>::NoWorkerSpecified);
+			ensure!(
+				cluster_info.workers.len() + workers.len() <= T::MaxClusterWorkers::get() as usize,
+				Error::<T>::TooManyWorkers
+			);
+			let pubkey =
+				registry::ClusterKeys::<T>::get(cluster).ok_or(Error::<T>::ClusterNotDeployed)?;
+
+			let mut identities = Vec::new();
+			for worker in workers.iter() {
+				ensure!(
+					!cluster_info.workers.contains(worker),
+					Error::<T>::WorkerAlreadyInCluster
+				);
+				let worker_info =
+					registry::Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotFound)?;
+				identities.push(WorkerIdentity {
+					pubkey: worker_info.pubkey,
+					ecdh_pubkey: worker_info.ecdh_pubkey,
+				});
+				cluster_info.workers.push(*worker);
+			}
+
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			ClusterJoiningWorkers::<T>::mutate(&cluster, |joining| {
+				joining.extend(workers.iter().cloned())
+			});
+			Self::push_message(ClusterEvent::AddWorkers {
+				owner: cluster_info.owner,
+				cluster,
+				pubkey,
+				workers: identities,
+			});
+			Self::deposit_event(Event::ClusterWorkersAdded {
+				cluster,
+				workers: workers.into_inner(),
+			});
+			Ok(())
+		}
+
+		/// Removes a worker from a cluster
+		///
+		/// The removed worker purges the cluster state and its contracts. Can be called by the
+		/// governance or the cluster owner.
+		#[pallet::weight(remove_cluster_worker_weight::<T>())]
+		pub fn remove_cluster_worker(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let mut cluster_info =
+				Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure_cluster_admin::<T>(origin, &cluster_info)?;
+			ensure!(
+				cluster_info.workers.contains(&worker),
+				Error::<T>::WorkerNotInCluster
+			);
+
+			cluster_info.workers.retain(|w| w != &worker);
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			ClusterWorkers::<T>::mutate(&cluster, |workers| workers.retain(|w| w != &worker));
+			ClusterJoiningWorkers::<T>::mutate(&cluster, |workers| {
+				workers.retain(|w| w != &worker)
+			});
+			Self::push_message(
+				ClusterOperation::<T::AccountId, T::BlockNumber>::RemoveWorker { cluster, worker },
+			);
+			Self::deposit_event(Event::ClusterWorkerRemoved { cluster, worker });
+			Ok(())
+		}
+
+		/// Sets the deployment permission of a cluster
+		///
+		/// Can be called by the governance or the cluster owner.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().reads_writes(1u64, 1u64))]
+		pub fn set_cluster_permission(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			permission: ClusterPermission<T::AccountId>,
+		) -> DispatchResult {
+			let mut cluster_info =
+				Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure_cluster_admin::<T>(origin, &cluster_info)?;
+
+			cluster_info.permission = permission;
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			Self::deposit_event(Event::ClusterPermissionUpdated { cluster });
+			Ok(())
+		}
+
+		/// Allows a deployer to deploy contracts in a cluster with the `AllowList` permission
+		///
+		/// The deployer is restricted to instantiate the given `code_hashes` if specified. Calling
+		/// it again overrides the previous restriction. Can be called by the governance or the
+		/// cluster owner.
+		#[pallet::weight(set_cluster_deployer_weight::<T>(
+			code_hashes.as_ref().map_or(0, |hashes| hashes.len() as u32)
+		))]
+		pub fn set_cluster_deployer(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			deployer: T::AccountId,
+			code_hashes: Option<BoundedVec<CodeHash<T>, T::MaxDeployerCodeHashes>>,
+		) -> DispatchResult {
+			let cluster_info = Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure_cluster_admin::<T>(origin, &cluster_info)?;
+
+			ClusterDeployers::<T>::insert(cluster, &deployer, DeployerPermission { code_hashes });
+			Self::deposit_event(Event::ClusterDeployerSet { cluster, deployer });
+			Ok(())
+		}
+
+		/// Removes a deployer from the allow-list of a cluster
+		///
+		/// Can be called by the governance or the cluster owner.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().reads_writes(2u64, 1u64))]
+		pub fn remove_cluster_deployer(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			deployer: T::AccountId,
+		) -> DispatchResult {
+			let cluster_info = Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure_cluster_admin::<T>(origin, &cluster_info)?;
+			ensure!(
+				ClusterDeployers::<T>::contains_key(cluster, &deployer),
+				Error::<T>::DeployerNotFound
+			);
+
+			ClusterDeployers::<T>::remove(cluster, &deployer);
+			Self::deposit_event(Event::ClusterDeployerRemoved { cluster, deployer });
+			Ok(())
+		}
+
+		/// Sets the deposit reserved from the deployer for each contract instantiated in a cluster
+		///
+		/// The deposits are released when the contracts are removed with the cluster. Changing it
+		/// doesn't affect the deposits already reserved. Can be called by the governance or the
+		/// cluster owner.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().reads_writes(1u64, 1u64))]
+		pub fn set_cluster_deposit(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			deposit: BalanceOf<T>,
+		) -> DispatchResult {
+			let cluster_info = Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure_cluster_admin::<T>(origin, &cluster_info)?;
+
+			ClusterDeposits::<T>::insert(cluster, deposit);
+			Self::deposit_event(Event::ClusterDepositSet { cluster, deposit });
+			Ok(())
+		}
+
+		/// Releases the deposit reserved for a contract to its deployer
+		///
+		/// Can only be called by the governance.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().reads_writes(2u64, 2u64))]
+		pub fn release_contract_deposit(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			contract: ContractId,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				Self::do_release_contract_deposit(&cluster, &contract),
+				Error::<T>::DepositNotFound
+			);
+			Ok(())
+		}
+
 		#[pallet::weight(0)]
 		pub fn set_pink_system_code(
 			origin: OriginFor<T>,
@@ -376,6 +752,7 @@ pub mod pallet {
 			};
 			match message.payload {
 				WorkerClusterReport::ClusterDeployed { id, pubkey } => {
+					Self::take_joining_worker(&id, &worker_pubkey);
 					// TODO.shelven: scalability concern for large number of workers
 					ClusterWorkers::<T>::append(&id, &worker_pubkey);
 					Self::deposit_event(Event::ClusterDeployed {
@@ -385,6 +762,14 @@ pub mod pallet {
 					});
 				}
 				WorkerClusterReport::ClusterDeploymentFailed { id } => {
+					// A worker failed to join is dropped from the cluster
+					if Self::take_joining_worker(&id, &worker_pubkey) {
+						Clusters::<T>::mutate(&id, |cluster_info| {
+							if let Some(cluster_info) = cluster_info {
+								cluster_info.workers.retain(|w| w != &worker_pubkey);
+							}
+						});
+					}
 					Self::deposit_event(Event::ClusterDeploymentFailed {
 						cluster: id,
 						worker: worker_pubkey,
@@ -394,6 +779,36 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Removes the worker from the joining workers of the cluster. Returns true if it was there.
+		fn take_joining_worker(cluster: &ContractClusterId, worker: &WorkerPublicKey) -> bool {
+			ClusterJoiningWorkers::<T>::mutate(cluster, |workers| {
+				let len = workers.len();
+				workers.retain(|w| w != worker);
+				workers.len() != len
+			})
+		}
+
+		/// Unreserves the deposit of a contract. Returns false if there is no deposit for it.
+		///
+		/// Should be called whenever a contract is removed.
+		pub fn do_release_contract_deposit(
+			cluster: &ContractClusterId,
+			contract: &ContractId,
+		) -> bool {
+			let (deployer, deposit) = match ContractDeposits::<T>::take(cluster, contract) {
+				Some(deposit) => deposit,
+				None => return false,
+			};
+			T::Currency::unreserve(&deployer, deposit);
+			Self::deposit_event(Event::ContractDepositReleased {
+				cluster: *cluster,
+				contract: *contract,
+				deployer,
+				deposit,
+			});
+			true
+		}
+
 		pub fn get_system_contract(contract: &ContractId) -> Option<ContractId> {
 			let contract_info = Contracts::<T>::get(&contract)?;
 			let cluster_info = Clusters::<T>::get(&contract_info.cluster_id)?;
@@ -421,3 +836,6 @@ pub mod pallet {
 		type Config = T;
 	}
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,568 @@
+use super::*;
+use crate::registry;
+use codec::Decode;
+use frame_support::{
+	assert_noop, assert_ok,
+	dispatch::{DispatchResult, GetDispatchInfo},
+	traits::ReservableCurrency,
+	BoundedVec,
+};
+use mock::{worker_pubkey, RuntimeOrigin as Origin, Test, DOLLARS};
+use phala_types::{
+	contract::{
+		messaging::{ClusterEvent, ClusterOperation, WorkerClusterReport},
+		ClusterPermission, CodeIndex, ContractClusterId, ContractId, ContractInfo,
+	},
+	messaging::{DecodedMessage, MessageOrigin, Topic},
+	ClusterPublicKey, EcdhPublicKey, WorkerPublicKey,
+};
+use sp_core::crypto::AccountId32;
+use sp_core::H256;
+use sp_runtime::{traits::BadOrigin, DispatchError};
+
+mod mock;
+
+const OWNER: AccountId32 = AccountId32::new([1u8; 32]);
+const ALICE: AccountId32 = AccountId32::new([2u8; 32]);
+const CODE_A: H256 = H256([10u8; 32]);
+const CODE_B: H256 = H256([11u8; 32]);
+
+fn cluster_pubkey() -> ClusterPublicKey {
+	ClusterPublicKey::from_raw([9u8; 32])
+}
+
+fn register_workers(n: u8) {
+	for i in 1..=n {
+		assert_ok!(registry::Pallet::<Test>::force_register_worker(
+			Origin::root(),
+			worker_pubkey(i),
+			EcdhPublicKey([i; 32]),
+			None
+		));
+	}
+}
+
+/// Creates a cluster deployed to the given workers
+fn setup_cluster(workers: &[u8]) -> ContractClusterId {
+	mock::System::set_block_number(1);
+	register_workers(3);
+	PinkSystemCodeHash::<Test>::put(H256::repeat_byte(1));
+	let cluster = ContractClusterId::from_low_u64_be(ClusterCounter::<Test>::get());
+	assert_ok!(Pallet::<Test>::add_cluster(
+		Origin::root(),
+		OWNER,
+		ClusterPermission::Public,
+		workers.iter().map(|i| worker_pubkey(*i)).collect()
+	));
+	registry::ClusterKeys::<Test>::insert(cluster, cluster_pubkey());
+	for i in workers {
+		assert_ok!(report(
+			*i,
+			WorkerClusterReport::ClusterDeployed {
+				id: cluster,
+				pubkey: cluster_pubkey(),
+			}
+		));
+	}
+	let _ = mock::take_messages();
+	let _ = mock::take_events();
+	cluster
+}
+
+fn report(worker: u8, report: WorkerClusterReport) -> DispatchResult {
+	Pallet::<Test>::on_worker_cluster_message_received(DecodedMessage {
+		sender: MessageOrigin::Worker(worker_pubkey(worker)),
+		destination: Topic::new("phala/cluster/worker/report"),
+		payload: report,
+	})
+}
+
+/// Takes the outbound messages to the `topic`
+fn take_payloads<M: Decode>(topic: &str) -> Vec<M> {
+	mock::take_messages()
+		.into_iter()
+		.filter(|m| m.destination == Topic::new(topic))
+		.filter_map(|m| m.decode_payload())
+		.collect()
+}
+
+fn instantiate(
+	deployer: AccountId32,
+	code_hash: H256,
+	cluster: ContractClusterId,
+) -> Result<ContractId, DispatchError> {
+	let salt = vec![];
+	let data = vec![];
+	let contract_info = ContractInfo {
+		deployer: deployer.clone(),
+		code_index: CodeIndex::WasmCode(code_hash),
+		salt: salt.clone(),
+		cluster_id: cluster,
+		instantiate_data: data.clone(),
+	};
+	Pallet::<Test>::instantiate_contract(
+		Origin::signed(deployer),
+		CodeIndex::WasmCode(code_hash),
+		data,
+		salt,
+		cluster,
+	)?;
+	Ok(contract_info.contract_id(crate::hashing::blake2_256))
+}
+
+fn reserved(who: &AccountId32) -> u128 {
+	mock::Balances::reserved_balance(who)
+}
+
+/// The workers to be added to a cluster
+fn workers(ids: &[u8]) -> BoundedVec<WorkerPublicKey, <Test as Config>::MaxClusterWorkers> {
+	ids.iter()
+		.map(|i| worker_pubkey(*i))
+		.collect::<Vec<_>>()
+		.try_into()
+		.unwrap()
+}
+
+fn cluster_workers(cluster: ContractClusterId) -> Vec<WorkerPublicKey> {
+	Clusters::<Test>::get(cluster).unwrap().workers
+}
+
+#[test]
+fn add_cluster_workers_works() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_eq!(ClusterWorkers::<Test>::get(cluster), vec![worker_pubkey(1)]);
+
+		assert_noop!(
+			Pallet::<Test>::add_cluster_workers(Origin::signed(ALICE), cluster, workers(&[2])),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_noop!(
+			Pallet::<Test>::add_cluster_workers(Origin::signed(OWNER), cluster, workers(&[])),
+			Error::<Test>::NoWorkerSpecified
+		);
+		assert_noop!(
+			Pallet::<Test>::add_cluster_workers(Origin::signed(OWNER), cluster, workers(&[1])),
+			Error::<Test>::WorkerAlreadyInCluster
+		);
+		assert_noop!(
+			Pallet::<Test>::add_cluster_workers(Origin::signed(OWNER), cluster, workers(&[9])),
+			Error::<Test>::WorkerNotFound
+		);
+		registry::ClusterKeys::<Test>::remove(cluster);
+		assert_noop!(
+			Pallet::<Test>::add_cluster_workers(Origin::signed(OWNER), cluster, workers(&[2])),
+			Error::<Test>::ClusterNotDeployed
+		);
+		registry::ClusterKeys::<Test>::insert(cluster, cluster_pubkey());
+
+		assert_ok!(Pallet::<Test>::add_cluster_workers(
+			Origin::signed(OWNER),
+			cluster,
+			workers(&[2, 3])
+		));
+		let events: Vec<ClusterEvent> = take_payloads("phala/cluster/event");
+		assert!(matches!(
+			events.as_slice(),
+			[ClusterEvent::AddWorkers { cluster: c, pubkey, workers, .. }]
+				if c == &cluster && pubkey == &cluster_pubkey() && workers.len() == 2
+		));
+		assert_eq!(
+			cluster_workers(cluster),
+			vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)]
+		);
+		// Not serving the cluster until they have caught up
+		assert_eq!(ClusterWorkers::<Test>::get(cluster), vec![worker_pubkey(1)]);
+		assert_eq!(
+			ClusterJoiningWorkers::<Test>::get(cluster),
+			vec![worker_pubkey(2), worker_pubkey(3)]
+		);
+
+		// Worker 2 caught up
+		assert_ok!(report(
+			2,
+			WorkerClusterReport::ClusterDeployed {
+				id: cluster,
+				pubkey: cluster_pubkey(),
+			}
+		));
+		assert_eq!(
+			ClusterWorkers::<Test>::get(cluster),
+			vec![worker_pubkey(1), worker_pubkey(2)]
+		);
+		assert_eq!(
+			ClusterJoiningWorkers::<Test>::get(cluster),
+			vec![worker_pubkey(3)]
+		);
+		// Worker 3 failed to join and is dropped
+		assert_ok!(report(
+			3,
+			WorkerClusterReport::ClusterDeploymentFailed { id: cluster }
+		));
+		assert!(ClusterJoiningWorkers::<Test>::get(cluster).is_empty());
+		assert_eq!(
+			cluster_workers(cluster),
+			vec![worker_pubkey(1), worker_pubkey(2)]
+		);
+		assert_eq!(
+			ClusterWorkers::<Test>::get(cluster),
+			vec![worker_pubkey(1), worker_pubkey(2)]
+		);
+	});
+}
+
+#[test]
+fn cluster_workers_are_bounded() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1, 2]);
+		assert_noop!(
+			Pallet::<Test>::add_cluster_workers(Origin::signed(OWNER), cluster, workers(&[3, 4])),
+			Error::<Test>::TooManyWorkers
+		);
+		assert_ok!(Pallet::<Test>::add_cluster_workers(
+			Origin::signed(OWNER),
+			cluster,
+			workers(&[3])
+		));
+		assert_noop!(
+			Pallet::<Test>::add_cluster(
+				Origin::root(),
+				OWNER,
+				ClusterPermission::Public,
+				(1..=4).map(worker_pubkey).collect()
+			),
+			Error::<Test>::TooManyWorkers
+		);
+	});
+}
+
+#[test]
+fn remove_cluster_worker_works() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1, 2]);
+		assert_ok!(Pallet::<Test>::add_cluster_workers(
+			Origin::signed(OWNER),
+			cluster,
+			workers(&[3])
+		));
+		let _ = mock::take_messages();
+
+		assert_noop!(
+			Pallet::<Test>::remove_cluster_worker(Origin::signed(ALICE), cluster, worker_pubkey(1)),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_noop!(
+			Pallet::<Test>::remove_cluster_worker(Origin::signed(OWNER), cluster, worker_pubkey(9)),
+			Error::<Test>::WorkerNotInCluster
+		);
+
+		assert_ok!(Pallet::<Test>::remove_cluster_worker(
+			Origin::root(),
+			cluster,
+			worker_pubkey(1)
+		));
+		let ops: Vec<ClusterOperation<AccountId32, u64>> = take_payloads("phala/cluster/key");
+		assert!(matches!(
+			ops.as_slice(),
+			[ClusterOperation::RemoveWorker { cluster: c, worker }]
+				if c == &cluster && worker == &worker_pubkey(1)
+		));
+		assert_eq!(
+			cluster_workers(cluster),
+			vec![worker_pubkey(2), worker_pubkey(3)]
+		);
+		assert_eq!(ClusterWorkers::<Test>::get(cluster), vec![worker_pubkey(2)]);
+
+		// A joining worker can be removed as well
+		assert_ok!(Pallet::<Test>::remove_cluster_worker(
+			Origin::signed(OWNER),
+			cluster,
+			worker_pubkey(3)
+		));
+		assert!(ClusterJoiningWorkers::<Test>::get(cluster).is_empty());
+		assert_eq!(cluster_workers(cluster), vec![worker_pubkey(2)]);
+		assert_eq!(
+			mock::take_events().last(),
+			Some(&mock::RuntimeEvent::FatContracts(
+				Event::ClusterWorkerRemoved {
+					cluster,
+					worker: worker_pubkey(3),
+				}
+			))
+		);
+	});
+}
+
+#[test]
+fn only_workers_can_report_cluster_deployment() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_noop!(
+			Pallet::<Test>::on_worker_cluster_message_received(DecodedMessage {
+				sender: MessageOrigin::Gatekeeper,
+				destination: Topic::new("phala/cluster/worker/report"),
+				payload: WorkerClusterReport::ClusterDeploymentFailed { id: cluster },
+			}),
+			DispatchError::from(Error::<Test>::InvalidSender)
+		);
+	});
+}
+
+#[test]
+fn set_cluster_deposit_works() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_noop!(
+			Pallet::<Test>::set_cluster_deposit(Origin::signed(ALICE), cluster, 10 * DOLLARS),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_ok!(Pallet::<Test>::set_cluster_deposit(
+			Origin::signed(OWNER),
+			cluster,
+			10 * DOLLARS
+		));
+		assert_eq!(ClusterDeposits::<Test>::get(cluster), 10 * DOLLARS);
+		assert_ok!(Pallet::<Test>::set_cluster_deposit(
+			Origin::root(),
+			cluster,
+			20 * DOLLARS
+		));
+		assert_eq!(ClusterDeposits::<Test>::get(cluster), 20 * DOLLARS);
+	});
+}
+
+#[test]
+fn instantiate_contract_reserves_deposit() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_ok!(Pallet::<Test>::set_cluster_deposit(
+			Origin::root(),
+			cluster,
+			10 * DOLLARS
+		));
+		mock::Balances::set_balance(Origin::root(), ALICE, 5 * DOLLARS, 0).unwrap();
+		assert_noop!(
+			instantiate(ALICE, CODE_A, cluster),
+			pallet_balances::Error::<Test>::InsufficientBalance
+		);
+
+		mock::Balances::set_balance(Origin::root(), ALICE, 100 * DOLLARS, 0).unwrap();
+		let contract = instantiate(ALICE, CODE_A, cluster).unwrap();
+		assert_eq!(reserved(&ALICE), 10 * DOLLARS);
+		assert_eq!(
+			ContractDeposits::<Test>::get(cluster, contract),
+			Some((ALICE, 10 * DOLLARS))
+		);
+		// Changing the deposit doesn't affect the reserved one
+		assert_ok!(Pallet::<Test>::set_cluster_deposit(
+			Origin::root(),
+			cluster,
+			DOLLARS
+		));
+		let _ = instantiate(ALICE, CODE_B, cluster).unwrap();
+		assert_eq!(reserved(&ALICE), 11 * DOLLARS);
+		assert_eq!(
+			ContractDeposits::<Test>::get(cluster, contract),
+			Some((ALICE, 10 * DOLLARS))
+		);
+	});
+}
+
+#[test]
+fn release_contract_deposit_works() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_ok!(Pallet::<Test>::set_cluster_deposit(
+			Origin::root(),
+			cluster,
+			10 * DOLLARS
+		));
+		mock::Balances::set_balance(Origin::root(), ALICE, 100 * DOLLARS, 0).unwrap();
+		let contract_a = instantiate(ALICE, CODE_A, cluster).unwrap();
+		let _ = instantiate(ALICE, CODE_B, cluster).unwrap();
+		assert_eq!(reserved(&ALICE), 20 * DOLLARS);
+		let _ = mock::take_events();
+
+		assert_noop!(
+			Pallet::<Test>::release_contract_deposit(Origin::signed(OWNER), cluster, contract_a),
+			BadOrigin
+		);
+		assert_ok!(Pallet::<Test>::release_contract_deposit(
+			Origin::root(),
+			cluster,
+			contract_a
+		));
+		assert_eq!(reserved(&ALICE), 10 * DOLLARS);
+		assert!(!ContractDeposits::<Test>::contains_key(cluster, contract_a));
+		assert_eq!(
+			mock::take_events().last(),
+			Some(&mock::RuntimeEvent::FatContracts(
+				Event::ContractDepositReleased {
+					cluster,
+					contract: contract_a,
+					deployer: ALICE,
+					deposit: 10 * DOLLARS,
+				}
+			))
+		);
+		assert_noop!(
+			Pallet::<Test>::release_contract_deposit(Origin::root(), cluster, contract_a),
+			Error::<Test>::DepositNotFound
+		);
+	});
+}
+
+#[test]
+fn cluster_destroy_releases_deposits() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_ok!(Pallet::<Test>::set_cluster_deposit(
+			Origin::root(),
+			cluster,
+			10 * DOLLARS
+		));
+		mock::Balances::set_balance(Origin::root(), ALICE, 100 * DOLLARS, 0).unwrap();
+		mock::Balances::set_balance(Origin::root(), OWNER, 100 * DOLLARS, 0).unwrap();
+		let _ = instantiate(ALICE, CODE_A, cluster).unwrap();
+		let _ = instantiate(OWNER, CODE_A, cluster).unwrap();
+		assert_eq!(reserved(&ALICE), 10 * DOLLARS);
+		assert_eq!(reserved(&OWNER), 10 * DOLLARS);
+
+		assert_ok!(Pallet::<Test>::cluster_destroy(Origin::root(), cluster));
+		assert_eq!(reserved(&ALICE), 0);
+		assert_eq!(reserved(&OWNER), 0);
+		assert_eq!(ContractDeposits::<Test>::iter_prefix(cluster).count(), 0);
+		assert_eq!(ClusterDeposits::<Test>::get(cluster), 0);
+	});
+}
+
+#[test]
+fn cluster_permission_works() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_noop!(
+			Pallet::<Test>::set_cluster_permission(
+				Origin::signed(ALICE),
+				cluster,
+				ClusterPermission::Public
+			),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_ok!(Pallet::<Test>::set_cluster_permission(
+			Origin::signed(OWNER),
+			cluster,
+			ClusterPermission::OnlyOwner(OWNER)
+		));
+		assert_noop!(
+			instantiate(ALICE, CODE_A, cluster),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_ok!(instantiate(OWNER, CODE_A, cluster));
+
+		assert_ok!(Pallet::<Test>::set_cluster_permission(
+			Origin::root(),
+			cluster,
+			ClusterPermission::Public
+		));
+		assert_ok!(instantiate(ALICE, CODE_A, cluster));
+	});
+}
+
+#[test]
+fn cluster_allow_list_works() {
+	mock::new_test_ext().execute_with(|| {
+		let cluster = setup_cluster(&[1]);
+		assert_ok!(Pallet::<Test>::set_cluster_permission(
+			Origin::signed(OWNER),
+			cluster,
+			ClusterPermission::AllowList
+		));
+		// The owner is always allowed
+		assert_ok!(instantiate(OWNER, CODE_A, cluster));
+		assert_noop!(
+			instantiate(ALICE, CODE_A, cluster),
+			Error::<Test>::ClusterPermissionDenied
+		);
+
+		assert_noop!(
+			Pallet::<Test>::set_cluster_deployer(Origin::signed(ALICE), cluster, ALICE, None),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_ok!(Pallet::<Test>::set_cluster_deployer(
+			Origin::signed(OWNER),
+			cluster,
+			ALICE,
+			Some(vec![CODE_A].try_into().unwrap())
+		));
+		assert_noop!(
+			instantiate(ALICE, CODE_B, cluster),
+			Error::<Test>::CodeNotAllowed
+		);
+		assert_ok!(instantiate(ALICE, CODE_A, cluster));
+		// Overrides the code restriction
+		assert_ok!(Pallet::<Test>::set_cluster_deployer(
+			Origin::root(),
+			cluster,
+			ALICE,
+			None
+		));
+		assert_ok!(instantiate(ALICE, CODE_B, cluster));
+
+		assert_noop!(
+			Pallet::<Test>::remove_cluster_deployer(Origin::signed(ALICE), cluster, ALICE),
+			Error::<Test>::ClusterPermissionDenied
+		);
+		assert_ok!(Pallet::<Test>::remove_cluster_deployer(
+			Origin::signed(OWNER),
+			cluster,
+			ALICE
+		));
+		assert_noop!(
+			Pallet::<Test>::remove_cluster_deployer(Origin::signed(OWNER), cluster, ALICE),
+			Error::<Test>::DeployerNotFound
+		);
+		assert_noop!(
+			instantiate(ALICE, CODE_A, cluster),
+			Error::<Test>::ClusterPermissionDenied
+		);
+	});
+}
+
+#[test]
+fn cluster_admin_calls_are_weighed() {
+	let cluster = ContractClusterId::from_low_u64_be(0);
+	let set_deployer = |code_hashes: Option<Vec<H256>>| {
+		Call::<Test>::set_cluster_deployer {
+			cluster,
+			deployer: ALICE,
+			code_hashes: code_hashes.map(|hashes| hashes.try_into().unwrap()),
+		}
+		.get_dispatch_info()
+		.weight
+		.ref_time()
+	};
+	assert!(set_deployer(None) > 0);
+	assert!(set_deployer(Some(vec![CODE_A, CODE_B])) > set_deployer(Some(vec![CODE_A])));
+
+	let calls = [
+		Call::<Test>::set_cluster_permission {
+			cluster,
+			permission: ClusterPermission::Public,
+		},
+		Call::<Test>::remove_cluster_deployer {
+			cluster,
+			deployer: ALICE,
+		},
+		Call::<Test>::set_cluster_deposit {
+			cluster,
+			deposit: DOLLARS,
+		},
+		Call::<Test>::release_contract_deposit {
+			cluster,
+			contract: ContractId::repeat_byte(1),
+		},
+	];
+	for call in calls {
+		assert!(call.get_dispatch_info().weight.ref_time() > 0);
+	}
+}
@@ -28,7 +28,7 @@ frame_support::construct_runtime!(
 		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
 		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
 		// Phala pallets
-		PhalaMq: mq::{Pallet, Call},
+		PhalaMq: mq::{Pallet, Call, Event<T>},
 		PhalaRegistry: registry::{Pallet, Event<T>, Storage, Config<T>},
 		FatContracts: fat,
 		FatTokenomic: fat_tokenomic,
@@ -92,6 +92,7 @@ impl pallet_timestamp::Config for Test {
 pub const DOLLARS: Balance = 1_000_000_000_000;
 
 impl mq::Config for Test {
+	type RuntimeEvent = RuntimeEvent;
 	type QueueNotifyConfig = ();
 	type CallMatcher = MqCallMatcher;
 }
@@ -120,6 +121,9 @@ impl fat::Config for Test {
 	type RuntimeEvent = RuntimeEvent;
 	type InkCodeSizeLimit = ConstU32<{ 1024 * 1024 }>;
 	type SidevmCodeSizeLimit = ConstU32<{ 1024 * 1024 }>;
+	type Currency = Balances;
+	type MaxClusterWorkers = ConstU32<3>;
+	type MaxDeployerCodeHashes = ConstU32<2>;
 }
 
 impl fat_tokenomic::Config for Test {
@@ -135,6 +139,7 @@ impl AttestationValidator for MockValidator {
 		_now: u64,
 		_verify_pruntime: bool,
 		_pruntime_allowlist: Vec<Vec<u8>>,
+		_dcap_extra_root_cas: Vec<Vec<u8>>,
 	) -> Result<IasFields, AttestationError> {
 		Ok(IasFields {
 			mr_enclave: [0u8; 32],
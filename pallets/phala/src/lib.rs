@@ -7,11 +7,14 @@
 #[cfg(target_arch = "wasm32")]
 extern crate webpki_wasm as webpki;
 
+#[cfg(target_arch = "wasm32")]
+extern crate ring_wasm as ring;
+
 #[cfg(not(feature = "std"))]
 extern crate alloc;
 
 // Re-export
-use utils::{accumulator, attestation, balance_convert, constants, fixed_point};
+use utils::{accumulator, attestation, balance_convert, constants, dcap, fixed_point};
 
 pub mod migrations;
 pub mod utils;
@@ -21,8 +21,8 @@ pub mod pallet {
 	use phala_types::{
 		messaging::{
 			DecodedMessage, GatekeeperEvent, HeartbeatChallenge, MessageOrigin,
-			MiningInfoUpdateEvent, MiningReportEvent, SettleInfo, SystemEvent,
-			TokenomicParameters as TokenomicParams, WorkerEvent,
+			MiningInfoUpdateEvent, MiningReportEvent, ReliabilityScore, SettleInfo, SystemEvent,
+			TokenomicParameters as TokenomicParams, WorkerEvent, WorkerReliabilityEvent,
 		},
 		WorkerPublicKey,
 	};
@@ -157,6 +157,17 @@ pub mod pallet {
 		}
 	}
 
+	/// The reliability of a worker published by the gatekeeper
+	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct WorkerReliabilityInfo {
+		/// The score in the last report window
+		pub score: ReliabilityScore,
+		/// Number of blocks covered by the score
+		pub window: u32,
+		/// The unix timestamp of the last update
+		pub updated_at: u64,
+	}
+
 	pub trait OnReward {
 		fn on_reward(settle: &[SettleInfo]) {}
 	}
@@ -274,6 +285,14 @@ pub mod pallet {
 	#[pallet::getter(fn stakes)]
 	pub type Stakes<T: Config> = StorageMap<_, Twox64Concat, T::AccountId, BalanceOf<T>>;
 
+	/// The latest reliability score of the mining workers, periodically published by the gatekeeper.
+	///
+	/// Removed when the worker stops mining.
+	#[pallet::storage]
+	#[pallet::getter(fn worker_reliability)]
+	pub type WorkerReliability<T> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, WorkerReliabilityInfo>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -371,7 +390,9 @@ pub mod pallet {
 		BenchmarkUpdated {
 			miner: T::AccountId,
 			p_instant: u32,
-		}
+		},
+		/// The gatekeeper is told to start publishing the worker reliability scores
+		ReliabilityReportEnabled,
 	}
 
 	#[pallet::error]
@@ -503,6 +524,20 @@ pub mod pallet {
 			ScheduledTokenomicUpdate::<T>::put(new_params);
 			Ok(())
 		}
+
+		/// Tells the gatekeeper to start publishing the worker reliability scores.
+		///
+		/// The scores are collected and sent only after the gatekeeper has seen this signal, so
+		/// all the gatekeepers produce the same messages.
+		///
+		/// Can only be called by the tokenomic admin.
+		#[pallet::weight(1)]
+		pub fn enable_reliability_report(origin: OriginFor<T>) -> DispatchResult {
+			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			Self::push_message(GatekeeperEvent::EnableReliabilityReport);
+			Self::deposit_event(Event::<T>::ReliabilityReportEnabled);
+			Ok(())
+		}
 	}
 
 	#[pallet::hooks]
@@ -682,6 +717,36 @@ pub mod pallet {
 			Ok(())
 		}
 
+		pub fn on_reliability_message_received(
+			message: DecodedMessage<WorkerReliabilityEvent<T::BlockNumber>>,
+		) -> DispatchResult {
+			if !matches!(message.sender, MessageOrigin::Gatekeeper) {
+				return Err(Error::<T>::BadSender.into());
+			}
+
+			let event = message.payload;
+			let now = Self::now_sec();
+			for (worker, score) in event.scores {
+				// Skip the workers stopped mining since the report
+				let mining = WorkerBindings::<T>::get(&worker)
+					.and_then(|miner| Miners::<T>::get(&miner))
+					.map(|info| info.state.is_mining())
+					.unwrap_or(false);
+				if !mining {
+					continue;
+				}
+				WorkerReliability::<T>::insert(
+					&worker,
+					WorkerReliabilityInfo {
+						score,
+						window: event.window,
+						updated_at: now,
+					},
+				);
+			}
+			Ok(())
+		}
+
 		/// Tries to handle settlement of a miner.
 		///
 		/// We really don't want to crash the interrupt the message processing. So when there's an
@@ -730,6 +795,15 @@ pub mod pallet {
 			now - miner_info.cool_down_start >= Self::cool_down_period()
 		}
 
+		/// Returns the unix timestamp when a cooling down miner can be reclaimed
+		pub fn cool_down_expiration(miner: &T::AccountId) -> Option<u64> {
+			let miner_info = Miners::<T>::get(miner)?;
+			if miner_info.state != MinerState::MiningCoolingDown {
+				return None;
+			}
+			Some(miner_info.cool_down_start + Self::cool_down_period())
+		}
+
 		/// Turns the miner back to Ready state after cooling down and trigger stake releasing.
 		///
 		/// Requires:
@@ -933,6 +1007,7 @@ pub mod pallet {
 			miner_info.cool_down_start = now;
 			Miners::<T>::insert(&miner, &miner_info);
 			OnlineMiners::<T>::mutate(|v| *v -= 1); // v cannot be 0
+			WorkerReliability::<T>::remove(&worker);
 
 			// Calculate remaining stake (assume there's no more slash after calling `stop_mining`)
 			let orig_stake = Stakes::<T>::get(&miner).unwrap_or_default();
@@ -1244,7 +1319,7 @@ pub mod pallet {
 			BlockNumber, RuntimeEvent as TestEvent, RuntimeOrigin as Origin, Test, DOLLARS,
 		};
 		// Pallets
-		use crate::mock::{PhalaMining, PhalaRegistry, System};
+		use crate::mock::{PhalaMining, PhalaRegistry, PhalaStakePool, System};
 
 		use fixed_macro::types::U64F64 as fp;
 		use frame_support::{assert_noop, assert_ok};
@@ -1650,6 +1725,111 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_worker_reliability_update() {
+			new_test_ext().execute_with(|| {
+				use phala_types::messaging::Topic;
+
+				set_block_1();
+				setup_workers(2);
+				assert_ok!(PhalaStakePool::create(Origin::signed(1)));
+				assert_ok!(PhalaStakePool::add_worker(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1)
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					1000 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1),
+					1000 * DOLLARS
+				));
+				elapse_seconds(100);
+				let score = ReliabilityScore {
+					uptime_ppm: 990_000,
+					heartbeats: 3,
+					heartbeat_latency: 2,
+					unresponsive_blocks: 6,
+				};
+				let message = |sender| DecodedMessage::<WorkerReliabilityEvent<BlockNumber>> {
+					sender,
+					destination: Topic::new(*b"^phala/mining/reliability"),
+					payload: WorkerReliabilityEvent::<BlockNumber> {
+						block_number: 600,
+						window: 600,
+						scores: vec![(worker_pubkey(1), score), (worker_pubkey(2), score)],
+					},
+				};
+				// Only accepted from the gatekeeper
+				assert_noop!(
+					PhalaMining::on_reliability_message_received(message(MessageOrigin::Worker(
+						worker_pubkey(1)
+					))),
+					Error::<Test>::BadSender
+				);
+				assert_ok!(PhalaMining::on_reliability_message_received(message(
+					MessageOrigin::Gatekeeper
+				)));
+				assert_eq!(
+					PhalaMining::worker_reliability(worker_pubkey(1)),
+					Some(WorkerReliabilityInfo {
+						score,
+						window: 600,
+						updated_at: 100,
+					})
+				);
+				// Workers not mining are ignored
+				assert_eq!(PhalaMining::worker_reliability(worker_pubkey(2)), None);
+				// Removed once the worker stops mining
+				assert_ok!(PhalaStakePool::stop_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1)
+				));
+				assert_eq!(PhalaMining::worker_reliability(worker_pubkey(1)), None);
+				assert_ok!(PhalaMining::on_reliability_message_received(message(
+					MessageOrigin::Gatekeeper
+				)));
+				assert_eq!(PhalaMining::worker_reliability(worker_pubkey(1)), None);
+			});
+		}
+
+		#[test]
+		fn test_enable_reliability_report() {
+			new_test_ext().execute_with(|| {
+				use phala_types::messaging::{GatekeeperEvent, Topic};
+
+				set_block_1();
+				assert_noop!(
+					PhalaMining::enable_reliability_report(Origin::signed(1)),
+					DispatchError::BadOrigin
+				);
+				let _ = take_messages();
+				assert_ok!(PhalaMining::enable_reliability_report(Origin::root()));
+				let msgs = take_messages();
+				let message = match msgs.as_slice() {
+					[m] => m,
+					_ => panic!("Wrong message events"),
+				};
+				assert_eq!(message.destination, Topic::new("phala/gatekeeper/event"));
+				assert_eq!(
+					message.decode_payload::<GatekeeperEvent>(),
+					Some(GatekeeperEvent::EnableReliabilityReport)
+				);
+				assert_eq!(
+					take_events().last(),
+					Some(&TestEvent::PhalaMining(
+						Event::<Test>::ReliabilityReportEnabled
+					))
+				);
+			});
+		}
+
 		#[test]
 		fn phala_params_migration_not_crash() {
 			new_test_ext().execute_with(|| {
@@ -4,9 +4,11 @@ use crate::{
 };
 
 use frame_support::{
+	dispatch::DispatchResult,
 	pallet_prelude::ConstU32,
 	parameter_types,
-	traits::{GenesisBuild, OnFinalize, OnInitialize},
+	traits::{GenesisBuild, Get, OnFinalize, OnInitialize},
+	weights::RuntimeDbWeight,
 };
 use frame_support_test::TestRandomness;
 use frame_system as system;
@@ -15,6 +17,7 @@ use sp_core::H256;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
+	DispatchError,
 };
 
 pub(crate) type Balance = u128;
@@ -34,7 +37,7 @@ frame_support::construct_runtime!(
 		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
 		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
 		// Pallets to test
-		PhalaMq: mq::{Pallet, Call},
+		PhalaMq: mq::{Pallet, Call, Event<T>},
 		PhalaRegistry: registry::{Pallet, Event<T>, Storage, Config<T>},
 		PhalaMining: mining::{Pallet, Event<T>, Storage, Config},
 		PhalaStakePool: stakepool::{Pallet, Event<T>},
@@ -51,6 +54,7 @@ parameter_types! {
 	pub const MinMiningStaking: Balance = 1 * DOLLARS;
 	pub const MinContribution: Balance = 1 * CENTS;
 	pub const MiningGracePeriod: u64 = 7 * 24 * 3600;
+	pub const CommissionIncreaseDelay: u64 = 7 * 24 * 3600;
 	pub const MinInitP: u32 = 1;
 	pub const MiningEnabledByDefault: bool = true;
 	pub const MaxPoolWorkers: u32 = 10;
@@ -72,7 +76,7 @@ impl system::Config for Test {
 	type Header = Header;
 	type RuntimeEvent = RuntimeEvent;
 	type BlockHashCount = BlockHashCount;
-	type DbWeight = ();
+	type DbWeight = MockDbWeight;
 	type Version = ();
 	type PalletInfo = PalletInfo;
 	type AccountData = pallet_balances::AccountData<Balance>;
@@ -84,6 +88,23 @@ impl system::Config for Test {
 	type MaxConsumers = ConstU32<2>;
 }
 
+thread_local! {
+	static DB_WEIGHT: std::cell::Cell<RuntimeDbWeight> =
+		std::cell::Cell::new(RuntimeDbWeight { read: 0, write: 0 });
+}
+
+/// Sets the weight of a database read and write, which is zero by default
+pub fn set_db_weight(read: u64, write: u64) {
+	DB_WEIGHT.with(|w| w.set(RuntimeDbWeight { read, write }));
+}
+
+pub struct MockDbWeight;
+impl Get<RuntimeDbWeight> for MockDbWeight {
+	fn get() -> RuntimeDbWeight {
+		DB_WEIGHT.with(|w| w.get())
+	}
+}
+
 impl pallet_balances::Config for Test {
 	type Balance = Balance;
 	type DustRemoval = ();
@@ -107,7 +128,8 @@ pub const DOLLARS: Balance = 1_000_000_000_000;
 pub const CENTS: Balance = DOLLARS / 100;
 
 impl mq::Config for Test {
-	type QueueNotifyConfig = ();
+	type RuntimeEvent = RuntimeEvent;
+	type QueueNotifyConfig = MockQueueNotifyConfig;
 	type CallMatcher = MqCallMatcher;
 }
 
@@ -121,6 +143,28 @@ impl mq::CallMatcher<Test> for MqCallMatcher {
 	}
 }
 
+thread_local! {
+	static FAIL_MESSAGE_HANDLING: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Makes the mock message handler fail or succeed
+pub fn set_fail_message_handling(fail: bool) {
+	FAIL_MESSAGE_HANDLING.with(|f| f.set(fail));
+}
+
+pub struct MockQueueNotifyConfig;
+impl mq::QueueNotifyConfig for MockQueueNotifyConfig {
+	fn on_message_received(_message: &Message) -> DispatchResult {
+		if FAIL_MESSAGE_HANDLING.with(|f| f.get()) {
+			// Leave a change to check it's reverted
+			frame_system::Pallet::<Test>::inc_providers(&0);
+			Err(DispatchError::BadOrigin)
+		} else {
+			Ok(())
+		}
+	}
+}
+
 impl registry::Config for Test {
 	type RuntimeEvent = RuntimeEvent;
 	type Currency = Balances;
@@ -149,6 +193,7 @@ impl stakepool::Config for Test {
 	type Currency = Balances;
 	type MinContribution = MinContribution;
 	type GracePeriod = MiningGracePeriod;
+	type CommissionIncreaseDelay = CommissionIncreaseDelay;
 	type MiningEnabledByDefault = MiningEnabledByDefault;
 	type MaxPoolWorkers = MaxPoolWorkers;
 	type OnSlashed = ();
@@ -169,6 +214,7 @@ impl AttestationValidator for MockValidator {
 		_now: u64,
 		_verify_pruntime: bool,
 		_pruntime_allowlist: Vec<Vec<u8>>,
+		_dcap_extra_root_cas: Vec<Vec<u8>>,
 	) -> Result<IasFields, AttestationError> {
 		Ok(IasFields {
 			mr_enclave: [0u8; 32],
@@ -8,10 +8,13 @@ pub mod pallet {
 	use frame_support::{
 		dispatch::DispatchResult,
 		pallet_prelude::*,
+		storage::with_transaction,
 		traits::{PalletInfo, StorageVersion},
 	};
 	use frame_system::pallet_prelude::*;
+	use sp_runtime::TransactionOutcome;
 
+	use pallet_mq_runtime_api::DeadLetter;
 	use phala_types::contract::{command_topic, InkCommand};
 	use phala_types::messaging::ContractId;
 	use phala_types::messaging::{
@@ -22,12 +25,37 @@ pub mod pallet {
 
 	#[pallet::config]
 	pub trait Config: frame_system::Config + crate::registry::Config {
+		type RuntimeEvent: From<Event<Self>> + IsType<<Self as frame_system::Config>::RuntimeEvent>;
 		type QueueNotifyConfig: QueueNotifyConfig;
 		type CallMatcher: CallMatcher<Self>;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
 
+	/// The max number of dead letters kept. The oldest one is dropped when it's exceeded.
+	const MAX_DEAD_LETTERS: u64 = 1000;
+
+	/// The max number of messages accepted by a single `sync_offchain_messages` call.
+	pub const MAX_MESSAGES_PER_BATCH: u32 = 100;
+
+	/// The storage reads and writes estimated for the on-chain subscribers to handle a message.
+	const MESSAGE_HANDLER_READS: u64 = 5;
+	const MESSAGE_HANDLER_WRITES: u64 = 5;
+
+	/// The weight to sync an offchain message, including the handling by the on-chain subscribers.
+	///
+	/// Besides the handler, the ingress sequence, the sender's key and the dead letter ids are
+	/// read, and the ingress sequence and the outbound messages are written.
+	fn sync_message_weight<T: Config>() -> Weight {
+		Weight::from_ref_time(10_000u64)
+			+ T::DbWeight::get().reads_writes(MESSAGE_HANDLER_READS + 3, MESSAGE_HANDLER_WRITES + 2)
+	}
+
+	/// The weight of a batch of `n` offchain messages.
+	fn sync_offchain_messages_weight<T: Config>(n: u32) -> Weight {
+		Weight::from_ref_time(10_000u64) + sync_message_weight::<T>().saturating_mul(n as u64)
+	}
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
 	#[pallet::storage_version(STORAGE_VERSION)]
@@ -48,11 +76,39 @@ pub mod pallet {
 	#[pallet::getter(fn messages)]
 	pub type OutboundMessages<T> = StorageValue<_, Vec<Message>, ValueQuery>;
 
+	/// The messages failed to be handled on chain, indexed by id
+	#[pallet::storage]
+	pub type DeadLetters<T: Config> = StorageMap<_, Twox64Concat, u64, DeadLetter<T::BlockNumber>>;
+
+	/// The id range `[first, next)` the existing dead letters fall in
+	#[pallet::storage]
+	pub type DeadLetterIds<T> = StorageValue<_, (u64, u64), ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// A message failed to be handled and was kept as a dead letter.
+		MessageDispatchFailed {
+			id: u64,
+			sender: MessageOrigin,
+			error: DispatchError,
+		},
+		/// A dead letter was handed to the on-chain subscribers again.
+		DeadLetterRetried { id: u64 },
+		/// A dead letter was removed without being dispatched.
+		DeadLetterPurged { id: u64 },
+	}
+
 	#[pallet::error]
 	pub enum Error<T> {
 		BadSender,
 		BadSequence,
 		BadDestination,
+		/// The batch has more than `MAX_MESSAGES_PER_BATCH` messages.
+		TooManyMessages,
+		/// The batch has no message.
+		EmptyBatch,
+		DeadLetterNotFound,
 	}
 
 	#[pallet::call]
@@ -62,35 +118,34 @@ pub mod pallet {
 		T::AccountId: IntoH256,
 	{
 		/// Syncs an unverified offchain message to the message queue
-		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().writes(1u64))]
+		#[pallet::weight(sync_offchain_messages_weight::<T>(1))]
 		pub fn sync_offchain_message(
 			origin: OriginFor<T>,
 			signed_message: SignedMessage,
 		) -> DispatchResult {
 			ensure_signed(origin)?;
+			Self::sync_message(signed_message)
+		}
 
-			// Check sender
-			let sender = &signed_message.message.sender;
-			ensure!(sender.is_offchain(), Error::<T>::BadSender);
-
-			// Check destination
-			ensure!(
-				signed_message.message.destination.is_valid(),
-				Error::<T>::BadDestination
-			);
-
-			// Check ingress sequence
-			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+		/// Syncs a batch of unverified offchain messages to the message queue
+		///
+		/// The messages are synced in order as if they were submitted by `sync_offchain_message`
+		/// one by one. The whole batch fails if any of the messages is rejected. At most
+		/// `MAX_MESSAGES_PER_BATCH` messages, and at least one, can be synced at once.
+		#[pallet::weight(sync_offchain_messages_weight::<T>(signed_messages.len() as u32))]
+		pub fn sync_offchain_messages(
+			origin: OriginFor<T>,
+			signed_messages: Vec<SignedMessage>,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			ensure!(!signed_messages.is_empty(), Error::<T>::EmptyBatch);
 			ensure!(
-				signed_message.sequence == expected_seq,
-				Error::<T>::BadSequence
+				signed_messages.len() <= MAX_MESSAGES_PER_BATCH as usize,
+				Error::<T>::TooManyMessages
 			);
-			// Validate signature
-			crate::registry::Pallet::<T>::check_message(&signed_message)?;
-			// Update ingress
-			OffchainIngress::<T>::insert(sender.clone(), expected_seq + 1);
-			// Call dispatch_message
-			Self::dispatch_message(signed_message.message);
+			for signed_message in signed_messages {
+				Self::sync_message(signed_message)?;
+			}
 			Ok(())
 		}
 
@@ -122,21 +177,103 @@ pub mod pallet {
 			Self::dispatch_message(message);
 			Ok(())
 		}
+
+		/// Hands a dead letter to the on-chain subscribers again
+		///
+		/// The message is not pushed to the off-chain components again. It's kept as a new dead
+		/// letter if it fails again.
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().writes(1u64))]
+		pub fn retry_dead_letter(origin: OriginFor<T>, id: u64) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			let dead_letter = DeadLetters::<T>::take(id).ok_or(Error::<T>::DeadLetterNotFound)?;
+			Self::deposit_event(Event::<T>::DeadLetterRetried { id });
+			Self::notify_subscribers(&dead_letter.message);
+			Ok(())
+		}
+
+		/// Removes dead letters without dispatching them
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(
+			Weight::from_ref_time(10_000u64).saturating_mul(ids.len() as u64)
+				+ T::DbWeight::get().writes(ids.len() as u64)
+		)]
+		pub fn purge_dead_letters(origin: OriginFor<T>, ids: Vec<u64>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			for id in ids {
+				ensure!(
+					DeadLetters::<T>::contains_key(id),
+					Error::<T>::DeadLetterNotFound
+				);
+				DeadLetters::<T>::remove(id);
+				Self::deposit_event(Event::<T>::DeadLetterPurged { id });
+			}
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T>
+	where
+		T::AccountId: IntoH256,
+	{
+		/// Checks and dispatches an offchain message
+		fn sync_message(signed_message: SignedMessage) -> DispatchResult {
+			// Check sender
+			let sender = &signed_message.message.sender;
+			ensure!(sender.is_offchain(), Error::<T>::BadSender);
+
+			// Check destination
+			ensure!(
+				signed_message.message.destination.is_valid(),
+				Error::<T>::BadDestination
+			);
+
+			// Check ingress sequence
+			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+			ensure!(
+				signed_message.sequence == expected_seq,
+				Error::<T>::BadSequence
+			);
+			// Validate signature
+			crate::registry::Pallet::<T>::check_message(&signed_message)?;
+			// Update ingress
+			OffchainIngress::<T>::insert(sender.clone(), expected_seq + 1);
+			// Call dispatch_message
+			Self::dispatch_message(signed_message.message);
+			Ok(())
+		}
 	}
 
 	impl<T: Config> Pallet<T> {
 		/// Push a validated message to the queue
 		pub fn dispatch_message(message: Message) {
-			// Notify subscribers
-			if let Err(_err) = T::QueueNotifyConfig::on_message_received(&message) {
-				// TODO: Consider to emit a message as warning. We can't stop dispatching message in any situation.
-			}
+			Self::notify_subscribers(&message);
 			// Notify the off-chain components
 			if T::QueueNotifyConfig::should_push_message(&message) {
 				OutboundMessages::<T>::append(message);
 			}
 		}
 
+		/// Notifies the on-chain subscribers of a message
+		///
+		/// We can't stop dispatching message in any situation, so the changes made by a failed
+		/// handler are reverted and the message is kept as a dead letter instead.
+		fn notify_subscribers(message: &Message) {
+			let result = with_transaction(|| {
+				let result = T::QueueNotifyConfig::on_message_received(message);
+				if result.is_ok() {
+					TransactionOutcome::Commit(result)
+				} else {
+					TransactionOutcome::Rollback(result)
+				}
+			});
+			if let Err(err) = result {
+				Self::store_dead_letter(message.clone(), err);
+			}
+		}
+
 		pub fn push_message_to<M: Encode>(
 			topic: impl Into<Path>,
 			sender: MessageOrigin,
@@ -158,6 +295,43 @@ pub mod pallet {
 		pub fn offchain_ingress(sender: &MessageOrigin) -> Option<u64> {
 			OffchainIngress::<T>::get(sender)
 		}
+
+		/// At most `limit` dead letters with their ids from `start`, in ascending order of id
+		pub fn dead_letters(start: u64, limit: u32) -> Vec<(u64, DeadLetter<T::BlockNumber>)> {
+			let (first, next) = DeadLetterIds::<T>::get();
+			(first.max(start)..next)
+				.filter_map(|id| DeadLetters::<T>::get(id).map(|letter| (id, letter)))
+				.take(limit as usize)
+				.collect()
+		}
+
+		fn store_dead_letter(message: Message, error: DispatchError) {
+			log::error!(
+				"Failed to handle message from {} to {:?}: {:?}",
+				message.sender,
+				message.destination,
+				error
+			);
+			let (mut first, id) = DeadLetterIds::<T>::get();
+			while id - first >= MAX_DEAD_LETTERS {
+				DeadLetters::<T>::remove(first);
+				first += 1;
+			}
+			Self::deposit_event(Event::<T>::MessageDispatchFailed {
+				id,
+				sender: message.sender.clone(),
+				error,
+			});
+			DeadLetters::<T>::insert(
+				id,
+				DeadLetter {
+					message,
+					error,
+					block: frame_system::Pallet::<T>::block_number(),
+				},
+			);
+			DeadLetterIds::<T>::put((first, id + 1));
+		}
 	}
 
 	#[pallet::hooks]
@@ -281,6 +455,202 @@ pub mod pallet {
 			Pallet::<Self::Config>::queue_bound_message(Self::message_origin(), payload);
 		}
 	}
+
+	#[cfg(test)]
+	mod test {
+		use frame_support::{assert_noop, assert_ok};
+
+		use super::*;
+		use crate::mock::{
+			new_test_ext, set_block_1, set_db_weight, set_fail_message_handling, take_events,
+			take_messages, RuntimeEvent as TestEvent, RuntimeOrigin as Origin, System, Test,
+		};
+		// Pallets
+		use crate::mock::PhalaMq;
+
+		fn unsigned_message(sequence: u64) -> SignedMessage {
+			SignedMessage {
+				message: Message::new(
+					MessageOrigin::Gatekeeper,
+					b"phala/test".to_vec(),
+					Vec::new(),
+				),
+				sequence,
+				signature: Vec::new(),
+			}
+		}
+
+		fn dead_letter_ids() -> Vec<u64> {
+			PhalaMq::dead_letters(0, u32::MAX)
+				.into_iter()
+				.map(|(id, _)| id)
+				.collect()
+		}
+
+		#[test]
+		fn test_failed_message_kept_as_dead_letter() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let sender = MessageOrigin::Pallet(b"Test".to_vec());
+				let providers = System::providers(&0);
+				set_fail_message_handling(true);
+				PhalaMq::push_message_to(b"phala/test".to_vec(), sender.clone(), 1u32);
+				// The changes made by the failed handler are reverted
+				assert_eq!(System::providers(&0), providers);
+				// The message is still pushed to the off-chain components
+				assert_eq!(take_messages().len(), 1);
+				let dead_letters = PhalaMq::dead_letters(0, u32::MAX);
+				assert_eq!(dead_letters.len(), 1);
+				let (id, dead_letter) = &dead_letters[0];
+				assert_eq!(*id, 0);
+				assert_eq!(dead_letter.message.sender, sender);
+				assert_eq!(dead_letter.error, DispatchError::BadOrigin);
+				assert_eq!(dead_letter.block, 1);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaMq(Event::MessageDispatchFailed {
+						id: 0,
+						sender,
+						error: DispatchError::BadOrigin,
+					})]
+				);
+				// Only the governance can retry
+				assert_noop!(
+					PhalaMq::retry_dead_letter(Origin::signed(1), 0),
+					DispatchError::BadOrigin
+				);
+				// Failed again and kept as a new dead letter, without pushing it off-chain again
+				assert_ok!(PhalaMq::retry_dead_letter(Origin::root(), 0));
+				assert!(take_messages().is_empty());
+				assert_eq!(dead_letter_ids(), vec![1]);
+				// Succeeded
+				set_fail_message_handling(false);
+				assert_ok!(PhalaMq::retry_dead_letter(Origin::root(), 1));
+				assert!(PhalaMq::dead_letters(0, u32::MAX).is_empty());
+				assert_noop!(
+					PhalaMq::retry_dead_letter(Origin::root(), 1),
+					Error::<Test>::DeadLetterNotFound
+				);
+			});
+		}
+
+		#[test]
+		fn test_purge_dead_letters() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				set_fail_message_handling(true);
+				for i in 0..3u32 {
+					PhalaMq::push_message_to(b"phala/test".to_vec(), MessageOrigin::Gatekeeper, i);
+				}
+				assert_eq!(dead_letter_ids(), vec![0, 1, 2]);
+				assert_noop!(
+					PhalaMq::purge_dead_letters(Origin::signed(1), vec![0]),
+					DispatchError::BadOrigin
+				);
+				// The whole call fails if any of the dead letters doesn't exist
+				assert_noop!(
+					PhalaMq::purge_dead_letters(Origin::root(), vec![0, 3]),
+					Error::<Test>::DeadLetterNotFound
+				);
+				assert_ok!(PhalaMq::purge_dead_letters(Origin::root(), vec![0, 2]));
+				assert_eq!(dead_letter_ids(), vec![1]);
+			});
+		}
+
+		#[test]
+		fn test_dead_letters_paging() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				set_fail_message_handling(true);
+				for i in 0..5u32 {
+					PhalaMq::push_message_to(b"phala/test".to_vec(), MessageOrigin::Gatekeeper, i);
+				}
+				assert_ok!(PhalaMq::purge_dead_letters(Origin::root(), vec![2]));
+				let page = |start, limit| -> Vec<u64> {
+					PhalaMq::dead_letters(start, limit)
+						.into_iter()
+						.map(|(id, _)| id)
+						.collect()
+				};
+				assert_eq!(page(0, 2), vec![0, 1]);
+				// The purged ones are skipped
+				assert_eq!(page(2, 2), vec![3, 4]);
+				assert_eq!(page(4, 10), vec![4]);
+				assert!(page(5, 10).is_empty());
+				assert!(page(0, 0).is_empty());
+			});
+		}
+
+		#[test]
+		fn test_dead_letters_bounded() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				set_fail_message_handling(true);
+				for i in 0..MAX_DEAD_LETTERS + 2 {
+					PhalaMq::push_message_to(b"phala/test".to_vec(), MessageOrigin::Gatekeeper, i);
+				}
+				// The oldest ones are dropped
+				let ids = dead_letter_ids();
+				assert_eq!(ids.len() as u64, MAX_DEAD_LETTERS);
+				assert_eq!(ids[0], 2);
+				assert_eq!(DeadLetterIds::<Test>::get(), (2, MAX_DEAD_LETTERS + 2));
+			});
+		}
+
+		#[test]
+		fn test_sync_offchain_messages_bounded() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let signed_messages: Vec<_> = (0..MAX_MESSAGES_PER_BATCH as u64 + 1)
+					.map(unsigned_message)
+					.collect();
+				assert_noop!(
+					PhalaMq::sync_offchain_messages(Origin::signed(1), signed_messages),
+					Error::<Test>::TooManyMessages
+				);
+			});
+		}
+
+		#[test]
+		fn test_sync_offchain_messages_rejects_empty_batch() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_noop!(
+					PhalaMq::sync_offchain_messages(Origin::signed(1), vec![]),
+					Error::<Test>::EmptyBatch
+				);
+			});
+		}
+
+		#[test]
+		fn test_sync_offchain_messages_weight() {
+			use frame_support::dispatch::GetDispatchInfo;
+
+			set_db_weight(1_000, 10_000);
+			let weight = |n: u64| {
+				let signed_messages = (0..n).map(unsigned_message).collect();
+				Call::<Test>::sync_offchain_messages { signed_messages }
+					.get_dispatch_info()
+					.weight
+					.ref_time()
+			};
+			let per_message = weight(2) - weight(1);
+			// The handler reads and writes are counted for every message
+			assert!(per_message >= 10_000 + 5 * 1_000 + 5 * 10_000);
+			assert_eq!(weight(10), weight(1) + 9 * per_message);
+			// There's a base weight besides the messages
+			assert!(weight(1) > per_message);
+			assert_eq!(
+				Call::<Test>::sync_offchain_message {
+					signed_message: unsigned_message(0)
+				}
+				.get_dispatch_info()
+				.weight
+				.ref_time(),
+				weight(1)
+			);
+		}
+	}
 }
 
 /// Provides `SignedExtension` to check message sequence.
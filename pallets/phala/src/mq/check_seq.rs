@@ -14,7 +14,8 @@ use sp_std::vec::Vec;
 
 /// Requires a message queue message must has correct sequence id.
 ///
-/// We only care about `sync_offchain_message` call.
+/// We only care about `sync_offchain_message` and `sync_offchain_messages` calls. The messages of a
+/// batch are treated as if they were submitted one by one in a row.
 ///
 /// When a message comes to the transaction pool, we drop it immediately if its sequence is
 /// less than the expected one. Otherwise we keep the message in the pool for a while, hoping there
@@ -27,6 +28,39 @@ pub fn tag(sender: &MessageOrigin, seq: u64) -> Vec<u8> {
 	("PhalaMqOffchainMessages", sender, seq).encode()
 }
 
+/// The sequence ranges `(sender, first, last)` of the messages carried by the call, in the order
+/// of their first appearance.
+///
+/// The messages from the same sender in a batch must have continuous sequences, otherwise the call
+/// can never be dispatched.
+fn sequence_ranges<T: Config>(
+	call: &Call<T>,
+) -> Result<Vec<(&MessageOrigin, u64, u64)>, TransactionValidityError>
+where
+	T::AccountId: IntoH256,
+{
+	let signed_messages = match call {
+		Call::sync_offchain_message { signed_message } => sp_std::slice::from_ref(signed_message),
+		Call::sync_offchain_messages { signed_messages } => &signed_messages[..],
+		_ => return Ok(vec![]),
+	};
+	let mut ranges: Vec<(&MessageOrigin, u64, u64)> = vec![];
+	for signed_message in signed_messages {
+		let sender = &signed_message.message.sender;
+		let sequence = signed_message.sequence;
+		match ranges.iter_mut().find(|(s, _, _)| *s == sender) {
+			Some((_, _, last)) => {
+				if Some(sequence) != last.checked_add(1) {
+					return Err(InvalidTransaction::Call.into());
+				}
+				*last = sequence;
+			}
+			None => ranges.push((sender, sequence, sequence)),
+		}
+	}
+	Ok(ranges)
+}
+
 impl<T> Default for CheckMqSequence<T> {
 	fn default() -> Self {
 		Self(Default::default())
@@ -74,21 +108,21 @@ where
 		_info: &DispatchInfoOf<Self::Call>,
 		_len: usize,
 	) -> Result<(), TransactionValidityError> {
-		let signed_message = match T::CallMatcher::match_call(call) {
-			Some(Call::sync_offchain_message { signed_message }) => signed_message,
-			_ => return Ok(()),
+		let ranges = match T::CallMatcher::match_call(call) {
+			Some(call) => sequence_ranges(call)?,
+			None => return Ok(()),
 		};
-		let sender = &signed_message.message.sender;
-		let sequence = signed_message.sequence;
-		let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
-		// Strictly require the message to include must match the expected sequence id
-		if sequence != expected_seq {
-			return Err(if sequence < expected_seq {
-				InvalidTransaction::Stale
-			} else {
-				InvalidTransaction::Future
+		for (sender, first_seq, _) in ranges {
+			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+			// Strictly require the messages to include must start at the expected sequence id
+			if first_seq != expected_seq {
+				return Err(if first_seq < expected_seq {
+					InvalidTransaction::Stale
+				} else {
+					InvalidTransaction::Future
+				}
+				.into());
 			}
-			.into());
 		}
 		Ok(())
 	}
@@ -100,26 +134,26 @@ where
 		_info: &DispatchInfoOf<Self::Call>,
 		_len: usize,
 	) -> TransactionValidity {
-		let signed_message = match T::CallMatcher::match_call(call) {
-			Some(Call::sync_offchain_message { signed_message }) => signed_message,
-			_ => return Ok(ValidTransaction::default()),
+		let ranges = match T::CallMatcher::match_call(call) {
+			Some(call) => sequence_ranges(call)?,
+			None => return Ok(ValidTransaction::default()),
 		};
-		let sender = &signed_message.message.sender;
-		let sequence = signed_message.sequence;
-		let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
-		// Drop the stale message immediately
-		if sequence < expected_seq {
-			return InvalidTransaction::Stale.into();
-		}
+		let mut provides = vec![];
+		let mut requires = vec![];
+		for (sender, first_seq, last_seq) in ranges {
+			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+			// Drop the stale message immediately
+			if first_seq < expected_seq {
+				return InvalidTransaction::Stale.into();
+			}
 
-		// Otherwise build a dependency graph based on (sender, sequence), hoping that it can be
-		// included later
-		let provides = vec![tag(sender, sequence)];
-		let requires = if sequence > expected_seq {
-			vec![tag(sender, sequence - 1)]
-		} else {
-			vec![]
-		};
+			// Otherwise build a dependency graph based on (sender, sequence), hoping that it can
+			// be included later
+			provides.extend((first_seq..=last_seq).map(|seq| tag(sender, seq)));
+			if first_seq > expected_seq {
+				requires.push(tag(sender, first_seq - 1));
+			}
+		}
 		Ok(ValidTransaction {
 			provides,
 			requires,
@@ -166,21 +200,86 @@ mod tests {
 		})
 	}
 
+	#[test]
+	fn test_check_mq_seq_batch_works() {
+		new_test_ext().execute_with(|| {
+			OffchainIngress::<Test>::insert(&MessageOrigin::Worker(worker_pubkey(1)), 1);
+			OffchainIngress::<Test>::insert(&MessageOrigin::Worker(worker_pubkey(2)), 2);
+			let info = DispatchInfo::default();
+			let len = 0_usize;
+			// stale
+			let call = sync_msgs_call(&[(1, 1), (2, 1)]);
+			assert_noop!(
+				extra().validate(&1, &call, &info, len),
+				InvalidTransaction::Stale
+			);
+			assert_noop!(
+				extra().pre_dispatch(&1, &call, &info, len),
+				InvalidTransaction::Stale
+			);
+			// correct, interleaved senders
+			let call = sync_msgs_call(&[(1, 1), (2, 2), (1, 2), (2, 3)]);
+			let valid = extra().validate(&1, &call, &info, len).unwrap();
+			assert_eq!(
+				valid.provides,
+				vec![
+					tag(&MessageOrigin::Worker(worker_pubkey(1)), 1),
+					tag(&MessageOrigin::Worker(worker_pubkey(1)), 2),
+					tag(&MessageOrigin::Worker(worker_pubkey(2)), 2),
+					tag(&MessageOrigin::Worker(worker_pubkey(2)), 3),
+				]
+			);
+			assert!(valid.requires.is_empty());
+			assert_ok!(extra().pre_dispatch(&1, &call, &info, len));
+			// future, depending on the previous message of the sender
+			let call = sync_msgs_call(&[(1, 1), (2, 3)]);
+			let valid = extra().validate(&1, &call, &info, len).unwrap();
+			assert_eq!(
+				valid.requires,
+				vec![tag(&MessageOrigin::Worker(worker_pubkey(2)), 2)]
+			);
+			assert_noop!(
+				extra().pre_dispatch(&1, &call, &info, len),
+				InvalidTransaction::Future
+			);
+			// gap in the batch
+			let call = sync_msgs_call(&[(1, 1), (1, 3)]);
+			assert_noop!(
+				extra().validate(&1, &call, &info, len),
+				InvalidTransaction::Call
+			);
+			assert_noop!(
+				extra().pre_dispatch(&1, &call, &info, len),
+				InvalidTransaction::Call
+			);
+		})
+	}
+
 	fn extra() -> CheckMqSequence<Test> {
 		CheckMqSequence::<Test>::new()
 	}
 
 	fn sync_msg_call(i: u8, seq: u64) -> TestCall {
 		TestCall::PhalaMq(Call::<Test>::sync_offchain_message {
-			signed_message: SignedMessage {
-				message: Message::new(
-					MessageOrigin::Worker(worker_pubkey(i)),
-					Topic::new(*b""),
-					Vec::new(),
-				),
-				sequence: seq,
-				signature: Vec::new(),
-			},
+			signed_message: signed_msg(i, seq),
 		})
 	}
+
+	fn sync_msgs_call(msgs: &[(u8, u64)]) -> TestCall {
+		TestCall::PhalaMq(Call::<Test>::sync_offchain_messages {
+			signed_messages: msgs.iter().map(|&(i, seq)| signed_msg(i, seq)).collect(),
+		})
+	}
+
+	fn signed_msg(i: u8, seq: u64) -> SignedMessage {
+		SignedMessage {
+			message: Message::new(
+				MessageOrigin::Worker(worker_pubkey(i)),
+				Topic::new(*b""),
+				Vec::new(),
+			),
+			sequence: seq,
+			signature: Vec::new(),
+		}
+	}
 }
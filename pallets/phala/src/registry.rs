@@ -20,7 +20,9 @@ pub mod pallet {
 	use crate::attestation::Error as AttestationError;
 	use crate::mq::MessageOriginInfo;
 	// Re-export
-	pub use crate::attestation::{Attestation, AttestationValidator, IasFields, IasValidator};
+	pub use crate::attestation::{
+		Attestation, AttestationValidator, IasFields, IasValidator, SgxQuoteCollateral,
+	};
 
 	use phala_types::{
 		messaging::{
@@ -144,6 +146,16 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type PRuntimeAddedAt<T: Config> = StorageMap<_, Twox64Concat, Vec<u8>, T::BlockNumber>;
 
+	/// The deprecation and retirement schedule of pRuntime binaries
+	#[pallet::storage]
+	pub type PRuntimeSchedules<T: Config> =
+		StorageMap<_, Twox64Concat, Vec<u8>, messaging::PRuntimeSchedule>;
+
+	/// The DER encoded root CA certificates trusted by DCAP attestations besides the Intel SGX Root
+	/// CA, which is always trusted
+	#[pallet::storage]
+	pub type DcapExtraRootCas<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+
 	/// Allow list of relaychain genesis
 	///
 	/// Only genesis within the list can do register.
@@ -233,6 +245,16 @@ pub mod pallet {
 		InvalidRotatedMasterPubkey,
 		// PRouter related
 		InvalidEndpointSigningTime,
+		// PRuntime schedule related
+		InvalidPRuntimeSchedule,
+		PRuntimeScheduleNotFound,
+		// DCAP related
+		InvalidDcapQuote,
+		InvalidPckCertChain,
+		InvalidDcapCollateral,
+		OutdatedDcapCollateral,
+		RevokedCertificate,
+		DcapRootCaNotFound,
 	}
 
 	#[pallet::call]
@@ -428,9 +450,21 @@ pub mod pallet {
 				now,
 				T::VerifyPRuntime::get(),
 				PRuntimeAllowList::<T>::get(),
+				DcapExtraRootCas::<T>::get(),
 			)
 			.map_err(Into::<Error<T>>::into)?;
 
+			if T::VerifyPRuntime::get() {
+				// Retired pRuntimes can no longer register
+				if let Some(schedule) = PRuntimeSchedules::<T>::get(fields.extend_mrenclave()) {
+					let now_block: u32 = frame_system::Pallet::<T>::block_number().saturated_into();
+					ensure!(
+						now_block < schedule.retired_at,
+						Error::<T>::PRuntimeRejected
+					);
+				}
+			}
+
 			if T::VerifyRelaychainGenesisBlockHash::get() {
 				let genesis_block_hash = pruntime_info.genesis_block_hash;
 				let allowlist = RelaychainGenesisBlockHashAllowList::<T>::get();
@@ -645,6 +679,61 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Schedules the deprecation and retirement of a pruntime binary in [`PRuntimeAllowList`]
+		///
+		/// Workers running the pruntime warn their operators from `deprecated_at` and stop at
+		/// `retired_at`, after which the pruntime can no longer register. Scheduling it again
+		/// overrides the previous schedule.
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(0)]
+		pub fn schedule_pruntime_retirement(
+			origin: OriginFor<T>,
+			pruntime_hash: Vec<u8>,
+			schedule: messaging::PRuntimeSchedule,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				PRuntimeAllowList::<T>::get().contains(&pruntime_hash),
+				Error::<T>::PRuntimeNotFound
+			);
+			let now: u32 = frame_system::Pallet::<T>::block_number().saturated_into();
+			ensure!(
+				schedule.deprecated_at <= schedule.retired_at && now < schedule.retired_at,
+				Error::<T>::InvalidPRuntimeSchedule
+			);
+
+			PRuntimeSchedules::<T>::insert(&pruntime_hash, schedule);
+			let event = PRuntimeManagementEvent::SchedulePRuntimeRetirement {
+				pruntime_hash,
+				schedule,
+			};
+			Self::push_message(event.clone());
+			Self::deposit_event(Event::<T>::PRuntimeManagement(event));
+			Ok(())
+		}
+
+		/// Cancels the scheduled retirement of a pruntime binary
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(0)]
+		pub fn cancel_pruntime_retirement(
+			origin: OriginFor<T>,
+			pruntime_hash: Vec<u8>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				PRuntimeSchedules::<T>::contains_key(&pruntime_hash),
+				Error::<T>::PRuntimeScheduleNotFound
+			);
+
+			PRuntimeSchedules::<T>::remove(&pruntime_hash);
+			let event = PRuntimeManagementEvent::CancelPRuntimeRetirement { pruntime_hash };
+			Self::push_message(event.clone());
+			Self::deposit_event(Event::<T>::PRuntimeManagement(event));
+			Ok(())
+		}
+
 		/// Set the consensus version used by pruntime. PRuntimes would switch some code path according
 		/// the current consensus version.
 		///
@@ -660,6 +749,38 @@ pub mod pallet {
 			Self::deposit_event(Event::<T>::PRuntimeManagement(event));
 			Ok(())
 		}
+
+		/// Trusts DCAP attestations issued under `root_ca` in addition to the Intel SGX Root CA
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().reads_writes(1u64, 1u64))]
+		pub fn add_dcap_root_ca(origin: OriginFor<T>, root_ca: Vec<u8>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			ensure!(
+				webpki::TrustAnchor::try_from_cert_der(&root_ca).is_ok(),
+				Error::<T>::InvalidInput
+			);
+			DcapExtraRootCas::<T>::mutate(|root_cas| {
+				if !root_cas.contains(&root_ca) {
+					root_cas.push(root_ca);
+				}
+			});
+			Ok(())
+		}
+
+		/// Stops trusting a root CA added by [`Pallet::add_dcap_root_ca`]
+		///
+		/// The Intel SGX Root CA can't be removed. Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(Weight::from_ref_time(10_000u64) + T::DbWeight::get().reads_writes(1u64, 1u64))]
+		pub fn remove_dcap_root_ca(origin: OriginFor<T>, root_ca: Vec<u8>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+			DcapExtraRootCas::<T>::try_mutate(|root_cas| {
+				let len = root_cas.len();
+				root_cas.retain(|ca| ca != &root_ca);
+				ensure!(root_cas.len() != len, Error::<T>::DcapRootCaNotFound);
+				Ok(())
+			})
+		}
 	}
 
 	// TODO.kevin: Move it to mq
@@ -938,6 +1059,11 @@ pub mod pallet {
 				AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
 				AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
 				AttestationError::InvalidUserDataHash => Self::InvalidRuntimeInfoHash,
+				AttestationError::InvalidDcapQuote => Self::InvalidDcapQuote,
+				AttestationError::InvalidPckCertChain => Self::InvalidPckCertChain,
+				AttestationError::InvalidDcapCollateral => Self::InvalidDcapCollateral,
+				AttestationError::OutdatedDcapCollateral => Self::OutdatedDcapCollateral,
+				AttestationError::RevokedCertificate => Self::RevokedCertificate,
 			}
 		}
 	}
@@ -949,7 +1075,8 @@ pub mod pallet {
 		use super::*;
 		use crate::mock::{
 			ecdh_pubkey, elapse_seconds, new_test_ext, set_block_1,
-			setup_relaychain_genesis_allowlist, worker_pubkey, RuntimeOrigin as Origin, Test,
+			setup_relaychain_genesis_allowlist, take_messages, worker_pubkey,
+			RuntimeOrigin as Origin, Test,
 		};
 		// Pallets
 		use crate::mock::PhalaRegistry;
@@ -1054,6 +1181,122 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_pruntime_retirement_schedule() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+
+				let sample: Vec<u8> = [1, 2, 3, 4].to_vec();
+				let schedule = messaging::PRuntimeSchedule {
+					deprecated_at: 10,
+					retired_at: 20,
+				};
+				assert_noop!(
+					PhalaRegistry::schedule_pruntime_retirement(
+						Origin::root(),
+						sample.clone(),
+						schedule
+					),
+					Error::<Test>::PRuntimeNotFound
+				);
+				assert_ok!(PhalaRegistry::add_pruntime(Origin::root(), sample.clone()));
+				// Retiring before deprecation is not allowed
+				assert_noop!(
+					PhalaRegistry::schedule_pruntime_retirement(
+						Origin::root(),
+						sample.clone(),
+						messaging::PRuntimeSchedule {
+							deprecated_at: 20,
+							retired_at: 10,
+						}
+					),
+					Error::<Test>::InvalidPRuntimeSchedule
+				);
+				// Retiring in the past is not allowed
+				assert_noop!(
+					PhalaRegistry::schedule_pruntime_retirement(
+						Origin::root(),
+						sample.clone(),
+						messaging::PRuntimeSchedule {
+							deprecated_at: 0,
+							retired_at: 1,
+						}
+					),
+					Error::<Test>::InvalidPRuntimeSchedule
+				);
+				let _ = take_messages();
+				assert_ok!(PhalaRegistry::schedule_pruntime_retirement(
+					Origin::root(),
+					sample.clone(),
+					schedule
+				));
+				assert_eq!(PRuntimeSchedules::<Test>::get(&sample), Some(schedule));
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(
+					messages[0].decode_payload::<PRuntimeManagementEvent>(),
+					Some(PRuntimeManagementEvent::SchedulePRuntimeRetirement {
+						pruntime_hash: sample.clone(),
+						schedule,
+					})
+				);
+
+				assert_ok!(PhalaRegistry::cancel_pruntime_retirement(
+					Origin::root(),
+					sample.clone()
+				));
+				assert!(!PRuntimeSchedules::<Test>::contains_key(&sample));
+				assert_noop!(
+					PhalaRegistry::cancel_pruntime_retirement(Origin::root(), sample.clone()),
+					Error::<Test>::PRuntimeScheduleNotFound
+				);
+			});
+		}
+
+		#[test]
+		fn test_dcap_root_cas() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+
+				let sample: serde_json::Value =
+					serde_json::from_slice(include_bytes!("../sample/dcap_attestation.json"))
+						.unwrap();
+				let root_ca = hex::decode(sample["rootCa"].as_str().unwrap()).unwrap();
+				assert_noop!(
+					PhalaRegistry::add_dcap_root_ca(Origin::root(), vec![1, 2, 3]),
+					Error::<Test>::InvalidInput
+				);
+				assert_noop!(
+					PhalaRegistry::add_dcap_root_ca(Origin::signed(1), root_ca.clone()),
+					sp_runtime::traits::BadOrigin
+				);
+				assert_ok!(PhalaRegistry::add_dcap_root_ca(
+					Origin::root(),
+					root_ca.clone()
+				));
+				// Added only once
+				assert_ok!(PhalaRegistry::add_dcap_root_ca(
+					Origin::root(),
+					root_ca.clone()
+				));
+				assert_eq!(DcapExtraRootCas::<Test>::get(), vec![root_ca.clone()]);
+
+				assert_ok!(PhalaRegistry::remove_dcap_root_ca(
+					Origin::root(),
+					root_ca.clone()
+				));
+				assert!(DcapExtraRootCas::<Test>::get().is_empty());
+				// The Intel SGX Root CA is not removable
+				assert_noop!(
+					PhalaRegistry::remove_dcap_root_ca(
+						Origin::root(),
+						crate::constants::INTEL_SGX_ROOT_CA.to_vec()
+					),
+					Error::<Test>::DcapRootCaNotFound
+				);
+			});
+		}
+
 		#[test]
 		fn test_relaychain_genesis_block_hash_allowlist_works() {
 			new_test_ext().execute_with(|| {
@@ -31,11 +31,14 @@ pub mod pallet {
 		dispatch::DispatchResult,
 		pallet_prelude::*,
 		traits::{
-			Currency, Imbalance, LockIdentifier, LockableCurrency, OnUnbalanced, StorageVersion,
-			UnixTime, WithdrawReasons,
+			Currency, ExistenceRequirement::KeepAlive, Imbalance, LockIdentifier, LockableCurrency,
+			OnUnbalanced, StorageVersion, UnixTime, WithdrawReasons,
 		},
 	};
 	use frame_system::pallet_prelude::*;
+	use pallet_stakepool_runtime_api::{
+		ReleasingProjection, StakerProjection, WithdrawalProjection,
+	};
 	use scale_info::TypeInfo;
 	use sp_runtime::{
 		traits::{Saturating, TrailingZeroInput, Zero},
@@ -49,6 +52,9 @@ pub mod pallet {
 
 	const MAX_WHITELIST_LEN: u32 = 100;
 
+	/// The weight to compound the rewards of a staker, excluding the storage access
+	const AUTO_COMPOUND_BASE_WEIGHT: u64 = 20_000_000;
+
 	pub struct DescMaxLen;
 
 	impl Get<u32> for DescMaxLen {
@@ -83,6 +89,10 @@ pub mod pallet {
 		#[pallet::constant]
 		type GracePeriod: Get<u64>;
 
+		/// The delay before a commission increase takes effect, in seconds.
+		#[pallet::constant]
+		type CommissionIncreaseDelay: Get<u64>;
+
 		/// If mining is enabled by default.
 		#[pallet::constant]
 		type MiningEnabledByDefault: Get<bool>;
@@ -180,6 +190,42 @@ pub mod pallet {
 	pub type PoolDescriptions<T: Config> =
 		StorageMap<_, Twox64Concat, u64, BoundedVec<u8, super::DescMaxLen>>;
 
+	/// Mapping from pool id to the scheduled commission increase and the unix timestamp it takes
+	/// effect
+	#[pallet::storage]
+	#[pallet::getter(fn pending_commissions)]
+	pub type PendingCommissions<T> = StorageMap<_, Twox64Concat, u64, (Permill, u64)>;
+
+	/// Mapping from the unix timestamp to the pools whose scheduled commission increase takes
+	/// effect at that time
+	///
+	/// The entries of the cancelled or rescheduled increases are left here and skipped when due.
+	#[pallet::storage]
+	pub type CommissionQueuedPools<T> = StorageMap<_, Twox64Concat, u64, Vec<u64>>;
+
+	/// The ordered timestamps in [`CommissionQueuedPools`]
+	#[pallet::storage]
+	pub type CommissionTimestamps<T> = StorageValue<_, VecDeque<u64>, ValueQuery>;
+
+	/// Mapping from pool id to the account proposed to be the new owner
+	///
+	/// The entry lasts until the proposed owner accepts the ownership.
+	#[pallet::storage]
+	#[pallet::getter(fn pending_owners)]
+	pub type PendingOwners<T: Config> = StorageMap<_, Twox64Concat, u64, T::AccountId>;
+
+	/// The stakers opted in to compound their rewards automatically, keyed by (pid, staker)
+	///
+	/// A staker is dropped once it has no stake left in the pool.
+	#[pallet::storage]
+	#[pallet::getter(fn auto_compound_stakers)]
+	pub type AutoCompoundStakers<T: Config> =
+		StorageDoubleMap<_, Twox64Concat, u64, Twox64Concat, T::AccountId, ()>;
+
+	/// The raw key in [`AutoCompoundStakers`] where the last `on_idle` sweep stopped
+	#[pallet::storage]
+	pub type AutoCompoundCursor<T> = StorageValue<_, Vec<u8>>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -344,7 +390,75 @@ pub mod pallet {
 			pid: u64,
 			worker: WorkerPublicKey,
 			amount: BalanceOf<T>,
-		}
+		},
+		/// Some shares of a staker are transferred to another account
+		///
+		/// Affected states:
+		/// - the shares and the locked stake of both stakers are updated in [`PoolStakers`]
+		/// - the stake is moved from the locking ledger of the sender to the receiver at
+		///   [`StakeLedger`]
+		SharesTransferred {
+			pid: u64,
+			from: T::AccountId,
+			to: T::AccountId,
+			shares: BalanceOf<T>,
+			amount: BalanceOf<T>,
+		},
+		/// A commission increase of a pool is scheduled
+		///
+		/// The new commission takes effect at the unix timestamp `effective_at`, when the
+		/// [`PoolCommissionSet`](#variant.PoolCommissionSet) event is emitted.
+		///
+		/// Affected states:
+		/// - the pool item in [`PendingCommissions`] is updated
+		PoolCommissionIncreaseScheduled {
+			pid: u64,
+			commission: u32,
+			effective_at: u64,
+		},
+		/// The owner of a pool proposed to transfer the ownership
+		///
+		/// Affected states:
+		/// - the pool item in [`PendingOwners`] is updated
+		PoolOwnerTransferProposed {
+			pid: u64,
+			owner: T::AccountId,
+			new_owner: T::AccountId,
+		},
+		/// The ownership of a pool is transferred
+		///
+		/// Affected states:
+		/// - the `owner` field in [`StakePools`] is updated, along with the right to claim the
+		///   `owner_reward`
+		/// - the pool item in [`PendingOwners`] is removed
+		PoolOwnerTransferred {
+			pid: u64,
+			old_owner: T::AccountId,
+			new_owner: T::AccountId,
+		},
+		/// A staker changed the auto-compound preference
+		///
+		/// Affected states:
+		/// - the staker is added to or removed from [`AutoCompoundStakers`]
+		AutoCompoundSet {
+			pid: u64,
+			user: T::AccountId,
+			enabled: bool,
+		},
+		/// The rewards of a staker are compounded into the pool
+		///
+		/// Affected states:
+		/// - the stake related fields in [`StakePools`]
+		/// - the user staking account at [`PoolStakers`]
+		/// - the locking ledger of the staker at [`StakeLedger`]
+		/// - when there was any request in the withdraw queue, the action may trigger withdrawals
+		///   ([`Withdrawal`](#variant.Withdrawal) event)
+		RewardsCompounded {
+			pid: u64,
+			user: T::AccountId,
+			amount: BalanceOf<T>,
+			shares: BalanceOf<T>,
+		},
 	}
 
 	#[pallet::error]
@@ -411,6 +525,12 @@ pub mod pallet {
 		NoWhitelistCreated,
 		/// Too long for pool description length
 		ExceedMaxDescriptionLen,
+		/// The shares to transfer are dust, or exceed the shares not queued for withdrawal
+		InvalidTransferAmount,
+		/// Cannot transfer shares to the staker itself
+		SelfTransfer,
+		/// The caller is not the proposed new owner of the pool
+		NotProposedOwner,
 	}
 
 	#[pallet::hooks]
@@ -419,12 +539,24 @@ pub mod pallet {
 		T: mining::Config<Currency = <T as Config>::Currency>,
 		BalanceOf<T>: FixedPointConvert + Display,
 	{
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			// The timestamp of the previous block, since the current one is not set yet
+			let now = <T as registry::Config>::UnixTime::now()
+				.as_secs()
+				.saturated_into::<u64>();
+			Self::apply_scheduled_commissions(now)
+		}
+
 		fn on_finalize(_n: T::BlockNumber) {
 			let now = <T as registry::Config>::UnixTime::now()
 				.as_secs()
 				.saturated_into::<u64>();
 			Self::maybe_force_withdraw(now);
 		}
+
+		fn on_idle(_n: T::BlockNumber, remaining_weight: Weight) -> Weight {
+			Self::sweep_auto_compounds(remaining_weight)
+		}
 	}
 
 	#[pallet::call]
@@ -595,6 +727,10 @@ pub mod pallet {
 
 		/// Change the pool commission rate
 		///
+		/// A commission increase only takes effect after `CommissionIncreaseDelay`, unless the
+		/// pool has no share yet. A decrease takes effect immediately and cancels the scheduled
+		/// increase if there's any.
+		///
 		/// Requires:
 		/// 1. The sender is the owner
 		#[pallet::weight(0)]
@@ -608,6 +744,25 @@ pub mod pallet {
 			// origin must be owner of pool
 			ensure!(pool_info.owner == owner, Error::<T>::UnauthorizedPoolOwner);
 
+			// Give the stakers some time to withdraw before the commission goes up
+			if payout_commission > pool_info.payout_commission.unwrap_or_default()
+				&& pool_info.total_shares > Zero::zero()
+			{
+				let now = <T as registry::Config>::UnixTime::now()
+					.as_secs()
+					.saturated_into::<u64>();
+				let effective_at = now + T::CommissionIncreaseDelay::get();
+				PendingCommissions::<T>::insert(pid, (payout_commission, effective_at));
+				Self::queue_commission(effective_at, pid);
+				Self::deposit_event(Event::<T>::PoolCommissionIncreaseScheduled {
+					pid,
+					commission: payout_commission.deconstruct(),
+					effective_at,
+				});
+				return Ok(());
+			}
+
+			PendingCommissions::<T>::remove(pid);
 			pool_info.payout_commission = Some(payout_commission);
 			StakePools::<T>::insert(&pid, &pool_info);
 
@@ -619,6 +774,62 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Proposes to transfer the ownership of a pool to `new_owner`
+		///
+		/// The transfer completes when the new owner accepts it by `accept_pool_ownership`. A
+		/// new proposal replaces the previous one.
+		///
+		/// Requires:
+		/// 1. The sender is the owner
+		#[pallet::weight(0)]
+		pub fn transfer_pool_ownership(
+			origin: OriginFor<T>,
+			pid: u64,
+			new_owner: T::AccountId,
+		) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+			let pool_info = Self::ensure_pool(pid)?;
+			ensure!(pool_info.owner == owner, Error::<T>::UnauthorizedPoolOwner);
+
+			PendingOwners::<T>::insert(pid, &new_owner);
+			Self::deposit_event(Event::<T>::PoolOwnerTransferProposed {
+				pid,
+				owner,
+				new_owner,
+			});
+
+			Ok(())
+		}
+
+		/// Accepts the ownership of a pool proposed by `transfer_pool_ownership`
+		///
+		/// The new owner takes over the management of the pool and its workers, as well as the
+		/// unclaimed owner rewards.
+		///
+		/// Requires:
+		/// 1. The sender is the proposed new owner
+		#[pallet::weight(0)]
+		pub fn accept_pool_ownership(origin: OriginFor<T>, pid: u64) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let mut pool_info = Self::ensure_pool(pid)?;
+			ensure!(
+				PendingOwners::<T>::get(pid).as_ref() == Some(&who),
+				Error::<T>::NotProposedOwner
+			);
+
+			PendingOwners::<T>::remove(pid);
+			let old_owner = pool_info.owner.clone();
+			pool_info.owner = who.clone();
+			StakePools::<T>::insert(&pid, &pool_info);
+			Self::deposit_event(Event::<T>::PoolOwnerTransferred {
+				pid,
+				old_owner,
+				new_owner: who,
+			});
+
+			Ok(())
+		}
+
 		/// Add a staker accountid to contribution whitelist.
 		///
 		/// Calling this method will forbide stakers contribute who isn't in the whitelist.
@@ -849,6 +1060,35 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Enables or disables compounding the staker rewards of the sender automatically
+		///
+		/// When enabled, the rewards are converted into new shares of the pool from time to time
+		/// in the idle time of the blocks, as long as the pool cap allows.
+		///
+		/// Requires:
+		/// 1. The sender is a staker of the pool, when enabling it
+		#[pallet::weight(0)]
+		pub fn set_auto_compound(origin: OriginFor<T>, pid: u64, enabled: bool) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::ensure_pool(pid)?;
+			if enabled {
+				ensure!(
+					PoolStakers::<T>::contains_key(&(pid, who.clone())),
+					Error::<T>::PoolStakeNotFound
+				);
+				AutoCompoundStakers::<T>::insert(pid, &who, ());
+			} else {
+				AutoCompoundStakers::<T>::remove(pid, &who);
+			}
+			Self::deposit_event(Event::<T>::AutoCompoundSet {
+				pid,
+				user: who,
+				enabled,
+			});
+
+			Ok(())
+		}
+
 		/// Contributes some stake to a pool
 		///
 		/// Requires:
@@ -961,6 +1201,108 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Transfers some shares of the caller's stake in a pool to another account
+		///
+		/// The stake backing the shares is unlocked, transferred to the receiver and locked in its
+		/// account again. The pending rewards of both stakers are settled before the transfer, so
+		/// the rewards accumulated so far stay with their original owners.
+		///
+		/// Requires:
+		/// 1. The receiver is in the contribution whitelist of the pool, if there's one
+		/// 2. The shares to transfer are not queued for withdrawal
+		#[pallet::weight(0)]
+		#[frame_support::transactional]
+		pub fn transfer_shares(
+			origin: OriginFor<T>,
+			pid: u64,
+			to: T::AccountId,
+			shares: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(who != to, Error::<T>::SelfTransfer);
+			let mut pool_info = Self::ensure_pool(pid)?;
+			if let Some(whitelist) = PoolContributionWhitelists::<T>::get(&pid) {
+				ensure!(
+					whitelist.contains(&to) || pool_info.owner == to,
+					Error::<T>::NotInContributeWhitelist
+				);
+			}
+			// Shares in a bankrupt pool are worthless. Don't bother with the pending slash.
+			ensure!(
+				pool_info.total_stake > Zero::zero(),
+				Error::<T>::PoolBankrupt
+			);
+			let from_key = (pid, who.clone());
+			let mut from_info =
+				Self::pool_stakers(&from_key).ok_or(Error::<T>::PoolStakeNotFound)?;
+			let queued_shares = pool_info
+				.withdraw_queue
+				.iter()
+				.find(|withdraw| withdraw.user == who)
+				.map(|withdraw| withdraw.shares)
+				.unwrap_or_default();
+			ensure!(
+				is_nondust_balance(shares) && shares + queued_shares <= from_info.shares,
+				Error::<T>::InvalidTransferAmount
+			);
+
+			// Clear the pending reward and slash of both stakers before changing the shares
+			pool_info.settle_user_pending_reward(&mut from_info);
+			Self::maybe_settle_slash(&pool_info, &mut from_info);
+			let to_key = (pid, to.clone());
+			let mut to_info = match Self::pool_stakers(&to_key) {
+				Some(mut user_info) => {
+					pool_info.settle_user_pending_reward(&mut user_info);
+					Self::maybe_settle_slash(&pool_info, &mut user_info);
+					user_info
+				}
+				None => UserStakeInfo {
+					user: to.clone(),
+					locked: Zero::zero(),
+					shares: Zero::zero(),
+					available_rewards: Zero::zero(),
+					reward_debt: Zero::zero(),
+				},
+			};
+
+			// Move the shares and the stake backing them. Any dust left to the sender is moved
+			// together, so the sender never keeps a dust position.
+			let (remaining_shares, shares_dust) = extract_dust(from_info.shares - shares);
+			let shares = shares + shares_dust;
+			let amount = if remaining_shares == Zero::zero() {
+				from_info.locked
+			} else {
+				let price = pool_info.share_price().expect("The pool has shares; qed.");
+				bmul(shares, &price).min(from_info.locked)
+			};
+			let (remaining_locked, locked_dust) = extract_dust(from_info.locked - amount);
+			let amount = amount + locked_dust;
+			from_info.shares = remaining_shares;
+			from_info.locked = remaining_locked;
+			to_info.shares.saturating_accrue(shares);
+			to_info.locked.saturating_accrue(amount);
+			pool_info.reset_pending_reward(&mut from_info);
+			pool_info.reset_pending_reward(&mut to_info);
+
+			// Move the locked funds
+			Self::ledger_reduce(&who, amount, Zero::zero());
+			<T as Config>::Currency::transfer(&who, &to, amount, KeepAlive)?;
+			Self::ledger_accrue(&to, amount);
+
+			// Persist
+			PoolStakers::<T>::insert(&from_key, &from_info);
+			PoolStakers::<T>::insert(&to_key, &to_info);
+			Self::maybe_stop_auto_compound(pid, &from_info);
+			Self::deposit_event(Event::<T>::SharesTransferred {
+				pid,
+				from: who,
+				to,
+				shares,
+				amount,
+			});
+			Ok(())
+		}
+
 		/// Starts a miner on behalf of the stake pool
 		///
 		/// Requires:
@@ -1250,6 +1592,7 @@ pub mod pallet {
 					.remove_stake(user_info, withdrawing_shares)
 					.expect("There are enough withdrawing_shares; qed.");
 				Self::ledger_reduce(&user_info.user, reduced, dust);
+				Self::maybe_stop_auto_compound(pool_info.pid, user_info);
 				Self::deposit_event(Event::<T>::Withdrawal {
 					pid: pool_info.pid,
 					user: user_info.user.clone(),
@@ -1330,6 +1673,7 @@ pub mod pallet {
 					withdraw.shares = shares;
 					// Withdraw the funds
 					Self::ledger_reduce(&user_info.user, reduced, dust);
+					Self::maybe_stop_auto_compound(pool_info.pid, &user_info);
 					Self::deposit_event(Event::<T>::Withdrawal {
 						pid: pool_info.pid,
 						user: user_info.user.clone(),
@@ -1484,6 +1828,269 @@ pub mod pallet {
 			}
 			WithdrawalTimestamps::<T>::put(&t);
 		}
+
+		/// Compounds the rewards of the opted-in stakers, until `max_weight` is used up
+		///
+		/// The sweep continues from where the last one stopped, and starts over after reaching the
+		/// end of [`AutoCompoundStakers`].
+		fn sweep_auto_compounds(max_weight: Weight) -> Weight {
+			let db_weight = T::DbWeight::get();
+			// Reads and writes the pool, the staker, the ledger, the lock and the subsidy pool
+			let staker_weight =
+				Weight::from_ref_time(AUTO_COMPOUND_BASE_WEIGHT) + db_weight.reads_writes(5, 5);
+			let mut used = db_weight.reads_writes(1, 1);
+			if (used + staker_weight).ref_time() > max_weight.ref_time() {
+				return Weight::zero();
+			}
+			let mut stakers = match AutoCompoundCursor::<T>::get() {
+				Some(cursor) => AutoCompoundStakers::<T>::iter_from(cursor),
+				None => AutoCompoundStakers::<T>::iter(),
+			};
+			let mut cursor = None;
+			while (used + staker_weight).ref_time() <= max_weight.ref_time() {
+				match stakers.next() {
+					Some((pid, user, ())) => {
+						used += staker_weight;
+						if !Self::compound_rewards(pid, &user) {
+							AutoCompoundStakers::<T>::remove(pid, &user);
+						}
+						cursor = Some(AutoCompoundStakers::<T>::hashed_key_for(pid, &user));
+					}
+					None => {
+						// Reached the end, start over in the next sweep
+						AutoCompoundCursor::<T>::kill();
+						return used;
+					}
+				}
+			}
+			if let Some(cursor) = cursor {
+				AutoCompoundCursor::<T>::put(cursor);
+			}
+			used
+		}
+
+		/// Converts the claimable rewards of a staker into new shares of the pool
+		///
+		/// The rewards are compounded only if they reach `MinContribution`, and are capped by the
+		/// room left in the pool. Stakers with a queued withdrawal are skipped.
+		///
+		/// Returns false if the staker has no stake in the pool anymore, and should be dropped
+		/// from [`AutoCompoundStakers`].
+		fn compound_rewards(pid: u64, user: &T::AccountId) -> bool {
+			let mut pool_info = match Self::stake_pools(pid) {
+				Some(pool_info) => pool_info,
+				None => return false,
+			};
+			let info_key = (pid, user.clone());
+			let mut user_info = match Self::pool_stakers(&info_key) {
+				Some(user_info) if user_info.shares != Zero::zero() => user_info,
+				_ => return false,
+			};
+			// Don't contribute to a bankrupt pool, or against the staker's own withdrawal
+			if pool_info.total_stake == Zero::zero()
+				|| pool_info.withdraw_queue.iter().any(|w| &w.user == user)
+			{
+				return true;
+			}
+			let pending_reward = pool_info.pending_reward(&user_info);
+			let mut amount = user_info.available_rewards.saturating_add(pending_reward);
+			if let Some(cap) = pool_info.cap {
+				amount = amount.min(cap.saturating_sub(pool_info.total_stake));
+			}
+			if amount < T::MinContribution::get() {
+				return true;
+			}
+			if mining::Pallet::<T>::withdraw_subsidy_pool(user, amount).is_err() {
+				return true;
+			}
+			// Clear the pending reward and slash before adding stake
+			pool_info.settle_user_pending_reward(&mut user_info);
+			Self::maybe_settle_slash(&pool_info, &mut user_info);
+			user_info.available_rewards.saturating_reduce(amount);
+			let shares = pool_info.add_stake(&mut user_info, amount);
+			Self::ledger_accrue(user, amount);
+			PoolStakers::<T>::insert(&info_key, &user_info);
+			// We have new free stake now, try to handle the waiting withdraw queue
+			Self::try_process_withdraw_queue(&mut pool_info);
+			StakePools::<T>::insert(pid, &pool_info);
+			Self::deposit_event(Event::<T>::RewardsCompounded {
+				pid,
+				user: user.clone(),
+				amount,
+				shares,
+			});
+			true
+		}
+
+		/// Stops compounding the rewards of a staker who has withdrawn all its stake
+		fn maybe_stop_auto_compound(
+			pid: u64,
+			user_info: &UserStakeInfo<T::AccountId, BalanceOf<T>>,
+		) {
+			if user_info.shares == Zero::zero() {
+				AutoCompoundStakers::<T>::remove(pid, &user_info.user);
+			}
+		}
+
+		/// Indexes the scheduled commission increase of the pool (`pid`) by its effective time
+		fn queue_commission(effective_at: u64, pid: u64) {
+			CommissionTimestamps::<T>::mutate(|t| {
+				// Usually appended, unless `CommissionIncreaseDelay` is changed
+				if let Err(pos) = t.binary_search(&effective_at) {
+					t.insert(pos, effective_at);
+				}
+			});
+			CommissionQueuedPools::<T>::mutate(effective_at, |pools| {
+				let pools = pools.get_or_insert_with(Vec::new);
+				if !pools.contains(&pid) {
+					pools.push(pid);
+				}
+			});
+		}
+
+		/// Applies the scheduled commission increases that are due. Returns the weight consumed.
+		fn apply_scheduled_commissions(now: u64) -> Weight {
+			let db_weight = T::DbWeight::get();
+			let mut weight = db_weight.reads(1);
+			let mut t = CommissionTimestamps::<T>::get();
+			if !matches!(t.front(), Some(effective_at) if *effective_at <= now) {
+				return weight;
+			}
+			while let Some(effective_at) = t.front().cloned() {
+				if effective_at > now {
+					break;
+				}
+				t.pop_front();
+				let pools = CommissionQueuedPools::<T>::take(effective_at).unwrap_or_default();
+				weight += db_weight.reads_writes(1, 1);
+				for pid in pools {
+					weight += db_weight.reads(1);
+					// Skip the cancelled or rescheduled ones
+					let commission = match PendingCommissions::<T>::get(pid) {
+						Some((commission, at)) if at == effective_at => commission,
+						_ => continue,
+					};
+					PendingCommissions::<T>::remove(pid);
+					weight += db_weight.reads_writes(1, 2);
+					if let Some(mut pool_info) = Self::stake_pools(pid) {
+						pool_info.payout_commission = Some(commission);
+						StakePools::<T>::insert(pid, &pool_info);
+						Self::deposit_event(Event::<T>::PoolCommissionSet {
+							pid,
+							commission: commission.deconstruct(),
+						});
+					}
+				}
+			}
+			CommissionTimestamps::<T>::put(&t);
+			weight + db_weight.writes(1)
+		}
+
+		/// Returns the rewards and the withdrawal projection of a staker (for the runtime API)
+		pub fn staker_projection(
+			pid: u64,
+			user: T::AccountId,
+		) -> Option<StakerProjection<BalanceOf<T>>> {
+			let pool_info = Self::stake_pools(pid)?;
+			let user_info = Self::pool_stakers(&(pid, user.clone()))?;
+			let value_of = |shares: BalanceOf<T>| match pool_info.share_price() {
+				Some(price) => bmul(shares, &price),
+				None => Zero::zero(),
+			};
+			let withdrawal = pool_info
+				.withdraw_queue
+				.iter()
+				.position(|withdraw| withdraw.user == user)
+				.map(|position| {
+					let queued_ahead = pool_info
+						.withdraw_queue
+						.iter()
+						.take(position)
+						.fold(Zero::zero(), |acc: BalanceOf<T>, withdraw| {
+							acc.saturating_add(value_of(withdraw.shares))
+						});
+					let withdraw = &pool_info.withdraw_queue[position];
+					let amount = value_of(withdraw.shares);
+					// The requests are fulfilled in order, so the request is covered once the
+					// stake released so far reaches all the requests up to it.
+					let required = queued_ahead.saturating_add(amount);
+					let mut budget = pool_info.free_stake;
+					let fulfilled_at = if budget >= required {
+						Some(
+							<T as registry::Config>::UnixTime::now()
+								.as_secs()
+								.saturated_into::<u64>(),
+						)
+					} else {
+						Self::releasing_timeline(&pool_info).into_iter().find_map(
+							|(time, stake)| {
+								budget.saturating_accrue(stake);
+								if budget >= required {
+									Some(time)
+								} else {
+									None
+								}
+							},
+						)
+					};
+					WithdrawalProjection {
+						position: position as u32,
+						shares: withdraw.shares,
+						amount,
+						queued_ahead,
+						start_time: withdraw.start_time,
+						expires_at: withdraw.start_time + T::GracePeriod::get(),
+						fulfilled_at,
+					}
+				});
+			Some(StakerProjection {
+				shares: user_info.shares,
+				locked: user_info.locked,
+				shares_value: value_of(user_info.shares),
+				available_rewards: user_info.available_rewards,
+				pending_rewards: pool_info.pending_reward(&user_info),
+				withdrawal,
+			})
+		}
+
+		/// Returns the releasing stake timeline of a pool (for the runtime API)
+		pub fn releasing_projection(pid: u64) -> Option<ReleasingProjection<BalanceOf<T>>> {
+			let pool_info = Self::stake_pools(pid)?;
+			let queued_withdrawals = match pool_info.share_price() {
+				Some(price) => pool_info
+					.withdraw_queue
+					.iter()
+					.fold(Zero::zero(), |acc: BalanceOf<T>, withdraw| {
+						acc.saturating_add(bmul(withdraw.shares, &price))
+					}),
+				None => Zero::zero(),
+			};
+			Some(ReleasingProjection {
+				free_stake: pool_info.free_stake,
+				releasing_stake: pool_info.releasing_stake,
+				queued_withdrawals,
+				timeline: Self::releasing_timeline(&pool_info),
+			})
+		}
+
+		/// Returns the stake of the cooling down workers of a pool and the time they can be
+		/// reclaimed, in ascending order of time
+		fn releasing_timeline(
+			pool_info: &PoolInfo<T::AccountId, BalanceOf<T>>,
+		) -> Vec<(u64, BalanceOf<T>)> {
+			let mut timeline: Vec<_> = pool_info
+				.workers
+				.iter()
+				.filter_map(|worker| {
+					let miner: T::AccountId = pool_sub_account(pool_info.pid, worker);
+					let expiration = <mining::pallet::Pallet<T>>::cool_down_expiration(&miner)?;
+					let stake = <mining::pallet::Pallet<T>>::stakes(&miner).unwrap_or_default();
+					Some((expiration, stake))
+				})
+				.collect();
+			timeline.sort_by_key(|(time, _)| *time);
+			timeline
+		}
 	}
 
 	impl<T: Config> mining::OnReward for Pallet<T>
@@ -1939,6 +2546,7 @@ pub mod pallet {
 		use sp_runtime::AccountId32;
 
 		use super::*;
+		use crate::mock::set_db_weight;
 		use crate::mock::{
 			ecdh_pubkey, elapse_cool_down, elapse_seconds, new_test_ext, set_block_1,
 			setup_workers, setup_workers_linked_operators, take_events, teleport_to_block,
@@ -2634,6 +3242,9 @@ pub mod pallet {
 					0,
 					Permill::from_percent(50)
 				));
+				// Wait for the commission increase to take effect
+				elapse_seconds(<Test as Config>::CommissionIncreaseDelay::get());
+				teleport_to_block(2);
 				let pool = PhalaStakePool::stake_pools(0).unwrap();
 				assert_eq!(pool.reward_acc.get(), fp!(0));
 				assert_eq!(pool.owner_reward, fp!(0));
@@ -2644,18 +3255,484 @@ pub mod pallet {
 				assert_eq!(pool.owner_reward, 250 * DOLLARS);
 			});
 		}
+
 		#[test]
-		fn test_divided_claim_rewards() {
+		fn test_auto_compound() {
 			use crate::mining::pallet::OnReward;
 			new_test_ext().execute_with(|| {
 				set_block_1();
 				setup_workers(1);
 				setup_pool_with_workers(1, &[1]); // pid = 0
-				assert_ok!(PhalaStakePool::set_payout_pref(
-					Origin::signed(1),
-					0,
-					Permill::from_percent(50)
-				));
+				let reward = |payout: u32| {
+					PhalaStakePool::on_reward(&vec![SettleInfo {
+						pubkey: worker_pubkey(1),
+						v: FixedPoint::from_num(1u32).to_bits(),
+						payout: FixedPoint::from_num(payout).to_bits(),
+						treasury: 0,
+					}]);
+				};
+				let one_staker = Weight::from_ref_time(AUTO_COMPOUND_BASE_WEIGHT);
+
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					400 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(3),
+					0,
+					100 * DOLLARS
+				));
+				assert_noop!(
+					PhalaStakePool::set_auto_compound(Origin::signed(1), 0, true),
+					Error::<Test>::PoolStakeNotFound
+				);
+				// Opting out doesn't require a stake
+				assert_ok!(PhalaStakePool::set_auto_compound(
+					Origin::signed(1),
+					0,
+					false
+				));
+				assert_ok!(PhalaStakePool::set_auto_compound(
+					Origin::signed(2),
+					0,
+					true
+				));
+				// Mined 500 PHA, only staker2 compounds the 400 PHA reward
+				reward(500);
+				let _ = take_events();
+				assert_eq!(PhalaStakePool::on_idle(1, one_staker), one_staker);
+				assert_eq!(
+					take_events().as_slice(),
+					[
+						TestEvent::Balances(pallet_balances::Event::<Test>::Transfer {
+							from: PhalaMining::account_id(),
+							to: 2,
+							amount: 400 * DOLLARS
+						}),
+						TestEvent::PhalaStakePool(Event::RewardsCompounded {
+							pid: 0,
+							user: 2,
+							amount: 400 * DOLLARS,
+							shares: 400 * DOLLARS
+						})
+					]
+				);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(pool.total_stake, 900 * DOLLARS);
+				assert_eq!(pool.free_stake, 900 * DOLLARS);
+				assert_eq!(staker2.shares, 800 * DOLLARS);
+				assert_eq!(staker2.locked, 800 * DOLLARS);
+				assert_eq!(staker2.available_rewards, 0);
+				assert_eq!(pool.pending_reward(&staker2), 0);
+				assert_eq!(Balances::locks(2), vec![the_lock(800 * DOLLARS)]);
+				assert_eq!(staker3.shares, 100 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker3), 100 * DOLLARS);
+				// Nothing to compound
+				PhalaStakePool::on_idle(1, Weight::from_ref_time(u64::MAX));
+				assert_eq!(
+					PhalaStakePool::pool_stakers((0, 2)).unwrap().shares,
+					800 * DOLLARS
+				);
+
+				// Each sweep only compounds one staker with the weight limit
+				assert_ok!(PhalaStakePool::set_auto_compound(
+					Origin::signed(3),
+					0,
+					true
+				));
+				reward(900);
+				let _ = take_events();
+				PhalaStakePool::on_idle(1, one_staker);
+				assert_eq!(take_events().len(), 2);
+				PhalaStakePool::on_idle(1, one_staker);
+				assert_eq!(take_events().len(), 2);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(pool.total_stake, 1900 * DOLLARS);
+				assert_eq!(staker2.locked, 1600 * DOLLARS);
+				assert_eq!(staker3.locked, 300 * DOLLARS);
+
+				// The compounded rewards respect the pool cap
+				assert_ok!(PhalaStakePool::set_cap(
+					Origin::signed(1),
+					0,
+					1950 * DOLLARS
+				));
+				reward(1900);
+				PhalaStakePool::on_idle(1, Weight::from_ref_time(u64::MAX));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.total_stake, 1950 * DOLLARS);
+
+				// Opt out
+				assert_ok!(PhalaStakePool::set_auto_compound(
+					Origin::signed(2),
+					0,
+					false
+				));
+				assert_ok!(PhalaStakePool::set_auto_compound(
+					Origin::signed(3),
+					0,
+					false
+				));
+				assert_eq!(
+					PhalaStakePool::on_idle(1, Weight::from_ref_time(u64::MAX)),
+					Weight::zero()
+				);
+			});
+		}
+
+		#[test]
+		fn test_auto_compound_cleanup() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				for staker in [2, 3] {
+					assert_ok!(PhalaStakePool::contribute(
+						Origin::signed(staker),
+						0,
+						100 * DOLLARS
+					));
+					assert_ok!(PhalaStakePool::set_auto_compound(
+						Origin::signed(staker),
+						0,
+						true
+					));
+				}
+				// Dropped after withdrawing all the stake
+				assert_ok!(PhalaStakePool::withdraw(Origin::signed(2), 0, 50 * DOLLARS));
+				assert!(AutoCompoundStakers::<Test>::contains_key(0, 2));
+				assert_ok!(PhalaStakePool::withdraw(Origin::signed(2), 0, 50 * DOLLARS));
+				assert!(!AutoCompoundStakers::<Test>::contains_key(0, 2));
+				// Dropped after transferring all the shares
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(3),
+					0,
+					1,
+					100 * DOLLARS
+				));
+				assert!(!AutoCompoundStakers::<Test>::contains_key(0, 3));
+				// Stale entries are dropped by the sweep
+				AutoCompoundStakers::<Test>::insert(0, 99, ());
+				AutoCompoundStakers::<Test>::insert(1, 2, ());
+				PhalaStakePool::on_idle(1, Weight::from_ref_time(u64::MAX));
+				assert_eq!(AutoCompoundStakers::<Test>::iter().count(), 0);
+			});
+		}
+
+		#[test]
+		fn test_auto_compound_weight_limit() {
+			use crate::mining::pallet::OnReward;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				set_db_weight(1_000, 10_000);
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				for staker in [1, 2, 3] {
+					assert_ok!(PhalaStakePool::contribute(
+						Origin::signed(staker),
+						0,
+						100 * DOLLARS
+					));
+					assert_ok!(PhalaStakePool::set_auto_compound(
+						Origin::signed(staker),
+						0,
+						true
+					));
+				}
+				PhalaStakePool::on_reward(&vec![SettleInfo {
+					pubkey: worker_pubkey(1),
+					v: FixedPoint::from_num(1u32).to_bits(),
+					payout: FixedPoint::from_num(300u32).to_bits(),
+					treasury: 0,
+				}]);
+				let _ = take_events();
+				let db_weight = <Test as frame_system::Config>::DbWeight::get();
+				let overhead = db_weight.reads_writes(1, 1);
+				let one_staker =
+					Weight::from_ref_time(AUTO_COMPOUND_BASE_WEIGHT) + db_weight.reads_writes(5, 5);
+				let compounded = || {
+					take_events()
+						.into_iter()
+						.filter(|e| {
+							matches!(
+								e,
+								TestEvent::PhalaStakePool(Event::RewardsCompounded { .. })
+							)
+						})
+						.count()
+				};
+
+				// Not enough for a single staker
+				assert_eq!(
+					PhalaStakePool::on_idle(1, overhead + one_staker - Weight::from_ref_time(1)),
+					Weight::zero()
+				);
+				assert_eq!(compounded(), 0);
+				// Exactly two stakers
+				assert_eq!(
+					PhalaStakePool::on_idle(1, overhead + one_staker * 2),
+					overhead + one_staker * 2
+				);
+				assert_eq!(compounded(), 2);
+				assert!(AutoCompoundCursor::<Test>::get().is_some());
+				// One short of two stakers, resumes from the third one
+				assert_eq!(
+					PhalaStakePool::on_idle(
+						1,
+						overhead + one_staker * 2 - Weight::from_ref_time(1)
+					),
+					overhead + one_staker
+				);
+				assert_eq!(compounded(), 1);
+				// Reaches the end and starts over in the next sweep
+				assert_eq!(PhalaStakePool::on_idle(1, overhead + one_staker), overhead);
+				assert_eq!(AutoCompoundCursor::<Test>::get(), None);
+				assert_eq!(compounded(), 0);
+				for staker in [1, 2, 3] {
+					let staker = PhalaStakePool::pool_stakers((0, staker)).unwrap();
+					assert_eq!(staker.locked, 200 * DOLLARS);
+				}
+			});
+		}
+
+		#[test]
+		fn test_commission_increase_delay() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+
+				// Applied immediately when there's no staker
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(10)
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(10)));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					100 * DOLLARS
+				));
+				// Increase is scheduled
+				let delay = <Test as Config>::CommissionIncreaseDelay::get();
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(50)
+				));
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaStakePool(
+						Event::PoolCommissionIncreaseScheduled {
+							pid: 0,
+							commission: 1000_000u32 * 50 / 100,
+							effective_at: delay,
+						}
+					)]
+				);
+				assert_eq!(
+					PhalaStakePool::pending_commissions(0),
+					Some((Permill::from_percent(50), delay))
+				);
+				elapse_seconds(delay - 1);
+				teleport_to_block(2);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(10)));
+				// Takes effect after the delay
+				elapse_seconds(1);
+				let _ = take_events();
+				teleport_to_block(3);
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaStakePool(Event::PoolCommissionSet {
+						pid: 0,
+						commission: 1000_000u32 * 50 / 100
+					})]
+				);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(50)));
+				assert_eq!(PhalaStakePool::pending_commissions(0), None);
+				// Decrease is applied immediately, cancelling the scheduled increase
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(80)
+				));
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(20)
+				));
+				assert_eq!(PhalaStakePool::pending_commissions(0), None);
+				elapse_seconds(delay);
+				teleport_to_block(4);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(20)));
+			});
+		}
+
+		#[test]
+		fn test_commission_increase_queue() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				setup_pool_with_workers(1, &[2]); // pid = 1
+				for pid in 0..2 {
+					assert_ok!(PhalaStakePool::contribute(
+						Origin::signed(2),
+						pid,
+						100 * DOLLARS
+					));
+					assert_ok!(PhalaStakePool::set_payout_pref(
+						Origin::signed(1),
+						pid,
+						Permill::from_percent(50)
+					));
+				}
+				let delay = <Test as Config>::CommissionIncreaseDelay::get();
+				assert_eq!(CommissionTimestamps::<Test>::get(), vec![delay]);
+				assert_eq!(CommissionQueuedPools::<Test>::get(delay), Some(vec![0, 1]));
+				// Pool 0 reschedules a larger increase later, pool 1 cancels it
+				elapse_seconds(10);
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(60)
+				));
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					1,
+					Permill::from_percent(0)
+				));
+				assert_eq!(CommissionTimestamps::<Test>::get(), vec![delay, delay + 10]);
+				// The stale entries are skipped
+				elapse_seconds(delay - 10);
+				let _ = take_events();
+				teleport_to_block(2);
+				assert_eq!(take_events(), vec![]);
+				assert_eq!(CommissionTimestamps::<Test>::get(), vec![delay + 10]);
+				assert_eq!(CommissionQueuedPools::<Test>::get(delay), None);
+				elapse_seconds(10);
+				teleport_to_block(3);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaStakePool(Event::PoolCommissionSet {
+						pid: 0,
+						commission: 1000_000u32 * 60 / 100
+					})]
+				);
+				assert!(CommissionTimestamps::<Test>::get().is_empty());
+				assert_eq!(PhalaStakePool::pending_commissions(0), None);
+				let pool = PhalaStakePool::stake_pools(1).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(0)));
+			});
+		}
+
+		#[test]
+		fn test_pool_ownership_transfer() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(50)
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					100 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::force_assign_reward(
+					Origin::root(),
+					vec![(0, 100 * DOLLARS)]
+				));
+
+				// Only the owner can propose, and only the proposed owner can accept
+				assert_noop!(
+					PhalaStakePool::transfer_pool_ownership(Origin::signed(2), 0, 3),
+					Error::<Test>::UnauthorizedPoolOwner
+				);
+				assert_noop!(
+					PhalaStakePool::accept_pool_ownership(Origin::signed(3), 0),
+					Error::<Test>::NotProposedOwner
+				);
+				assert_ok!(PhalaStakePool::transfer_pool_ownership(
+					Origin::signed(1),
+					0,
+					3
+				));
+				assert_noop!(
+					PhalaStakePool::accept_pool_ownership(Origin::signed(2), 0),
+					Error::<Test>::NotProposedOwner
+				);
+				// The old owner keeps the control until accepted
+				assert_ok!(PhalaStakePool::set_cap(
+					Origin::signed(1),
+					0,
+					1000 * DOLLARS
+				));
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::accept_pool_ownership(Origin::signed(3), 0));
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaStakePool(Event::PoolOwnerTransferred {
+						pid: 0,
+						old_owner: 1,
+						new_owner: 3,
+					})]
+				);
+				assert_eq!(PhalaStakePool::pending_owners(0), None);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.owner, 3);
+				// The new owner manages the pool and its workers
+				assert_noop!(
+					PhalaStakePool::set_cap(Origin::signed(1), 0, 2000 * DOLLARS),
+					Error::<Test>::UnauthorizedPoolOwner
+				);
+				assert_ok!(PhalaStakePool::set_cap(
+					Origin::signed(3),
+					0,
+					2000 * DOLLARS
+				));
+				// The unclaimed owner reward goes to the new owner
+				assert_noop!(
+					PhalaStakePool::claim_owner_rewards(Origin::signed(1), 0, 1),
+					Error::<Test>::UnauthorizedPoolOwner
+				);
+				let free3 = Balances::free_balance(3);
+				assert_ok!(PhalaStakePool::claim_owner_rewards(Origin::signed(3), 0, 3));
+				assert_eq!(Balances::free_balance(3), free3 + 50 * DOLLARS);
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(3),
+					0,
+					worker_pubkey(1),
+					100 * DOLLARS
+				));
+			});
+		}
+		#[test]
+		fn test_divided_claim_rewards() {
+			use crate::mining::pallet::OnReward;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(50)
+				));
 				assert_ok!(PhalaStakePool::contribute(
 					Origin::signed(1),
 					0,
@@ -3378,6 +4455,223 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_projections() {
+			use crate::mining::pallet::OnReward;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_eq!(PhalaStakePool::staker_projection(0, 2), None);
+				assert_eq!(PhalaStakePool::releasing_projection(1), None);
+
+				// Stake 1000 PHA, start a miner with 900 PHA, and queue a withdrawal of 800 PHA
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					900 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(3),
+					0,
+					100 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1),
+					900 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::withdraw(
+					Origin::signed(2),
+					0,
+					900 * DOLLARS
+				));
+				// Mined 900 PHA, 800 to staker2 and 100 to staker3
+				PhalaStakePool::on_reward(&vec![SettleInfo {
+					pubkey: worker_pubkey(1),
+					v: FixedPoint::from_num(1u32).to_bits(),
+					payout: FixedPoint::from_num(900u32).to_bits(),
+					treasury: 0,
+				}]);
+				let grace_period = <Test as Config>::GracePeriod::get();
+				let mut projection = StakerProjection {
+					shares: 800 * DOLLARS,
+					locked: 800 * DOLLARS,
+					shares_value: 800 * DOLLARS,
+					available_rewards: 0,
+					pending_rewards: 800 * DOLLARS,
+					withdrawal: Some(WithdrawalProjection {
+						position: 0,
+						shares: 800 * DOLLARS,
+						amount: 800 * DOLLARS,
+						queued_ahead: 0,
+						start_time: 0,
+						expires_at: grace_period,
+						fulfilled_at: None,
+					}),
+				};
+				assert_eq!(
+					PhalaStakePool::staker_projection(0, 2),
+					Some(projection.clone())
+				);
+				let staker3 = PhalaStakePool::staker_projection(0, 3).unwrap();
+				assert_eq!(staker3.pending_rewards, 100 * DOLLARS);
+				assert_eq!(staker3.withdrawal, None);
+				assert_eq!(
+					PhalaStakePool::releasing_projection(0),
+					Some(ReleasingProjection {
+						free_stake: 0,
+						releasing_stake: 0,
+						queued_withdrawals: 800 * DOLLARS,
+						timeline: vec![],
+					})
+				);
+				// Stopping the miner releases the stake after the cool down period
+				assert_ok!(PhalaStakePool::stop_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1)
+				));
+				let cool_down_end = PhalaMining::cool_down_period();
+				projection.withdrawal.as_mut().unwrap().fulfilled_at = Some(cool_down_end);
+				assert_eq!(PhalaStakePool::staker_projection(0, 2), Some(projection));
+				assert_eq!(
+					PhalaStakePool::releasing_projection(0),
+					Some(ReleasingProjection {
+						free_stake: 0,
+						releasing_stake: 900 * DOLLARS,
+						queued_withdrawals: 800 * DOLLARS,
+						timeline: vec![(cool_down_end, 900 * DOLLARS)],
+					})
+				);
+			});
+		}
+
+		#[test]
+		fn test_transfer_shares() {
+			use crate::mining::pallet::OnReward;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+
+				// Stake 500 PHA, and start a miner with 400 PHA as stake
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					500 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1),
+					400 * DOLLARS
+				));
+				// Mined 500 PHA, all to staker2
+				PhalaStakePool::on_reward(&vec![SettleInfo {
+					pubkey: worker_pubkey(1),
+					v: FixedPoint::from_num(1u32).to_bits(),
+					payout: FixedPoint::from_num(500u32).to_bits(),
+					treasury: 0,
+				}]);
+				// Bad cases
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 2, 100 * DOLLARS),
+					Error::<Test>::SelfTransfer
+				);
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 0),
+					Error::<Test>::InvalidTransferAmount
+				);
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 501 * DOLLARS),
+					Error::<Test>::InvalidTransferAmount
+				);
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(3), 0, 2, 100 * DOLLARS),
+					Error::<Test>::PoolStakeNotFound
+				);
+				// Transfer 200 shares to staker3. The reward stays with staker2.
+				let free3 = Balances::free_balance(3);
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					3,
+					200 * DOLLARS
+				));
+				assert_eq!(
+					take_events().as_slice(),
+					[
+						TestEvent::Balances(pallet_balances::Event::<Test>::Transfer {
+							from: 2,
+							to: 3,
+							amount: 200 * DOLLARS
+						}),
+						TestEvent::PhalaStakePool(Event::SharesTransferred {
+							pid: 0,
+							from: 2,
+							to: 3,
+							shares: 200 * DOLLARS,
+							amount: 200 * DOLLARS
+						})
+					]
+				);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(pool.total_shares, 500 * DOLLARS);
+				assert_eq!(pool.total_stake, 500 * DOLLARS);
+				assert_eq!(staker2.shares, 300 * DOLLARS);
+				assert_eq!(staker2.locked, 300 * DOLLARS);
+				assert_eq!(staker2.available_rewards, 500 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker2), 0);
+				assert_eq!(staker3.shares, 200 * DOLLARS);
+				assert_eq!(staker3.locked, 200 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker3), 0);
+				assert_eq!(Balances::free_balance(3), free3 + 200 * DOLLARS);
+				assert_eq!(Balances::locks(2), vec![the_lock(300 * DOLLARS)]);
+				assert_eq!(Balances::locks(3), vec![the_lock(200 * DOLLARS)]);
+				// Withdraw 250 shares, queuing 150 shares. The queued shares can't be transferred.
+				assert_ok!(PhalaStakePool::withdraw(
+					Origin::signed(2),
+					0,
+					250 * DOLLARS
+				));
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 100 * DOLLARS),
+					Error::<Test>::InvalidTransferAmount
+				);
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					3,
+					50 * DOLLARS
+				));
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(staker2.shares, 150 * DOLLARS);
+				assert_eq!(staker3.shares, 250 * DOLLARS);
+				// The receiver must be in the whitelist if there's one
+				assert_ok!(PhalaStakePool::add_staker_to_whitelist(
+					Origin::signed(1),
+					0,
+					2,
+				));
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(3), 0, 99, 100 * DOLLARS),
+					Error::<Test>::NotInContributeWhitelist
+				);
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(3),
+					0,
+					2,
+					100 * DOLLARS
+				));
+			});
+		}
+
 		#[test]
 		fn double_withdraw_cancel_the_first() {
 			new_test_ext().execute_with(|| {
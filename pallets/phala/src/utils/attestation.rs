@@ -1,4 +1,5 @@
 use crate::constants::*;
+use crate::dcap::validate_dcap_quote;
 
 use codec::{Decode, Encode};
 use scale_info::TypeInfo;
@@ -15,6 +16,31 @@ pub enum Attestation {
 		signature: Vec<u8>,
 		raw_signing_cert: Vec<u8>,
 	},
+	SgxDcap {
+		quote: Vec<u8>,
+		collateral: SgxQuoteCollateral,
+	},
+}
+
+/// The collateral to verify a DCAP quote, as served by Intel PCS or a PCCS
+#[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
+pub struct SgxQuoteCollateral {
+	/// DER encoded CRL of the Intel SGX Root CA
+	pub root_ca_crl: Vec<u8>,
+	/// DER encoded CRL of the PCK CA which issued the PCK certificate in the quote
+	pub pck_crl: Vec<u8>,
+	/// PEM encoded certificate chain of the TCB info signing key
+	pub tcb_info_issuer_chain: Vec<u8>,
+	/// The raw `tcbInfo` JSON object of the platform
+	pub tcb_info: Vec<u8>,
+	/// Raw ECDSA signature (`r || s`) of `tcb_info`
+	pub tcb_info_signature: Vec<u8>,
+	/// PEM encoded certificate chain of the QE identity signing key
+	pub qe_identity_issuer_chain: Vec<u8>,
+	/// The raw `enclaveIdentity` JSON object of the Quoting Enclave
+	pub qe_identity: Vec<u8>,
+	/// Raw ECDSA signature (`r || s`) of `qe_identity`
+	pub qe_identity_signature: Vec<u8>,
 }
 
 pub trait AttestationValidator {
@@ -25,6 +51,7 @@ pub trait AttestationValidator {
 		now: u64,
 		verify_pruntime_hash: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
+		dcap_extra_root_cas: Vec<Vec<u8>>,
 	) -> Result<IasFields, Error>;
 }
 
@@ -38,6 +65,12 @@ pub enum Error {
 	OutdatedIASReport,
 	UnknownQuoteBodyFormat,
 	InvalidUserDataHash,
+	// DCAP related
+	InvalidDcapQuote,
+	InvalidPckCertChain,
+	InvalidDcapCollateral,
+	OutdatedDcapCollateral,
+	RevokedCertificate,
 }
 
 #[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
@@ -128,7 +161,7 @@ impl IasFields {
 	}
 }
 
-/// Attestation validator implementation for IAS
+/// Attestation validator implementation for IAS reports and DCAP quotes
 pub struct IasValidator;
 impl AttestationValidator for IasValidator {
 	fn validate(
@@ -137,6 +170,7 @@ impl AttestationValidator for IasValidator {
 		now: u64,
 		verify_pruntime: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
+		dcap_extra_root_cas: Vec<Vec<u8>>,
 	) -> Result<IasFields, Error> {
 		let fields = match attestation {
 			Attestation::SgxIas {
@@ -151,6 +185,14 @@ impl AttestationValidator for IasValidator {
 				verify_pruntime,
 				pruntime_allowlist,
 			),
+			Attestation::SgxDcap { quote, collateral } => validate_dcap_quote(
+				quote,
+				collateral,
+				now,
+				verify_pruntime,
+				pruntime_allowlist,
+				&dcap_extra_root_cas,
+			),
 		}?;
 		let commit = &fields.report_data[..32];
 		if commit != user_data_hash {
@@ -14,6 +14,13 @@ pub const IAS_QUOTE_ADVISORY_ID_WHITELIST: &[&str] = &[
 	"INTEL-SA-00381",
 	"INTEL-SA-00389",
 ];
+
+pub const DCAP_TCB_STATUS_LEVEL_1: &[&str] = &["UpToDate"];
+pub const DCAP_TCB_STATUS_LEVEL_2: &[&str] = &["SWHardeningNeeded"];
+pub const DCAP_TCB_STATUS_LEVEL_3: &[&str] =
+	&["ConfigurationNeeded", "ConfigurationAndSWHardeningNeeded"];
+pub const DCAP_TCB_STATUS_LEVEL_5: &[&str] = &["OutOfDate", "OutOfDateConfigurationNeeded"];
+
 pub type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
 pub static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 	// &webpki::ECDSA_P256_SHA256,
@@ -26,6 +33,59 @@ pub static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 	&webpki::RSA_PKCS1_3072_8192_SHA384,
 ];
 
+/// The PCK, TCB signing and root certificates of DCAP are all ECDSA-P256 keys
+pub static DCAP_SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[&webpki::ECDSA_P256_SHA256];
+
+/// The DER encoded Intel SGX Root CA certificate, the root of trust of DCAP attestations
+///
+/// Published by Intel at <https://certificates.trustedservices.intel.com/IntelSGXRootCA.der>, with
+/// the SHA-256 fingerprint
+/// `44:A0:19:6B:2B:99:F8:89:B8:E1:49:E9:5B:80:7A:35:0E:74:24:96:43:99:E8:85:A7:CB:B8:CC:FA:B6:74:D3`.
+///
+/// ```text
+/// -----BEGIN CERTIFICATE-----
+/// MIICjzCCAjSgAwIBAgIUImUM1lqdNInzg7SVUr9QGzknBqwwCgYIKoZIzj0EAwIw
+/// aDEaMBgGA1UEAwwRSW50ZWwgU0dYIFJvb3QgQ0ExGjAYBgNVBAoMEUludGVsIENv
+/// cnBvcmF0aW9uMRQwEgYDVQQHDAtTYW50YSBDbGFyYTELMAkGA1UECAwCQ0ExCzAJ
+/// BgNVBAYTAlVTMB4XDTE4MDUyMTEwNDUxMFoXDTQ5MTIzMTIzNTk1OVowaDEaMBgG
+/// A1UEAwwRSW50ZWwgU0dYIFJvb3QgQ0ExGjAYBgNVBAoMEUludGVsIENvcnBvcmF0
+/// aW9uMRQwEgYDVQQHDAtTYW50YSBDbGFyYTELMAkGA1UECAwCQ0ExCzAJBgNVBAYT
+/// AlVTMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEC6nEwMDIYZOj/iPWsCzaEKi7
+/// 1OiOSLRFhWGjbnBVJfVnkY4u3IjkDYYL0MxO4mqsyYjlBalTVYxFP2sJBK5zlKOB
+/// uzCBuDAfBgNVHSMEGDAWgBQiZQzWWp00ifODtJVSv1AbOScGrDBSBgNVHR8ESzBJ
+/// MEegRaBDhkFodHRwczovL2NlcnRpZmljYXRlcy50cnVzdGVkc2VydmljZXMuaW50
+/// ZWwuY29tL0ludGVsU0dYUm9vdENBLmRlcjAdBgNVHQ4EFgQUImUM1lqdNInzg7SV
+/// Ur9QGzknBqwwDgYDVR0PAQH/BAQDAgEGMBIGA1UdEwEB/wQIMAYBAf8CAQEwCgYI
+/// KoZIzj0EAwIDSQAwRgIhAOW/5QkR+S9CiSDcNoowLuPRLsWGf/Yi7GSX94BgwTwg
+/// AiEA4J0lrHoMs+Xo5o/sX6O9QWxHRAvZUGOdRQ7cvqRXaqI=
+/// -----END CERTIFICATE-----
+/// ```
+pub const INTEL_SGX_ROOT_CA: &[u8] = &hex_literal::hex!(
+	"
+		3082028f30820234a003020102021422650cd65a9d3489f383b49552bf501b39
+		2706ac300a06082a8648ce3d0403023068311a301806035504030c11496e7465
+		6c2053475820526f6f74204341311a3018060355040a0c11496e74656c20436f
+		72706f726174696f6e3114301206035504070c0b53616e746120436c61726131
+		0b300906035504080c024341310b3009060355040613025553301e170d313830
+		3532313130343531305a170d3439313233313233353935395a3068311a301806
+		035504030c11496e74656c2053475820526f6f74204341311a3018060355040a
+		0c11496e74656c20436f72706f726174696f6e3114301206035504070c0b5361
+		6e746120436c617261310b300906035504080c024341310b3009060355040613
+		0255533059301306072a8648ce3d020106082a8648ce3d030107034200040ba9
+		c4c0c0c86193a3fe23d6b02cda10a8bbd4e88e48b4458561a36e705525f56791
+		8e2edc88e40d860bd0cc4ee26aacc988e505a953558c453f6b0904ae7394a381
+		bb3081b8301f0603551d2304183016801422650cd65a9d3489f383b49552bf50
+		1b392706ac30520603551d1f044b30493047a045a043864168747470733a2f2f
+		6365727469666963617465732e7472757374656473657276696365732e696e74
+		656c2e636f6d2f496e74656c534758526f6f7443412e646572301d0603551d0e
+		0416041422650cd65a9d3489f383b49552bf501b392706ac300e0603551d0f01
+		01ff04040302010630120603551d130101ff040830060101ff020101300a0608
+		2a8648ce3d0403020349003046022100e5bfe50911f92f428920dc368a302ee3
+		d12ec5867ff622ec6497f78060c13c20022100e09d25ac7a0cb3e5e8e68fec5f
+		a3bd416c47440bd950639d450edcbea4576aa2
+	"
+);
+
 pub static IAS_SERVER_ROOTS: webpki::TlsServerTrustAnchors = webpki::TlsServerTrustAnchors(&[
     /*
      * -----BEGIN CERTIFICATE-----
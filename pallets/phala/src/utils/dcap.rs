@@ -0,0 +1,915 @@
+//! Verification of SGX DCAP (ECDSA) quotes against the collateral published by Intel.
+
+use crate::attestation::{Error, IasFields, SgxQuoteCollateral};
+use crate::constants::*;
+
+use core::convert::{TryFrom, TryInto};
+use sp_std::vec::Vec;
+
+const QUOTE_VERSION_3: u16 = 3;
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+const CERTIFICATION_DATA_PCK_CERT_CHAIN: u16 = 5;
+
+const QUOTE_HEADER_LEN: usize = 48;
+const REPORT_BODY_LEN: usize = 384;
+const ECDSA_SIGNATURE_LEN: usize = 64;
+const ECDSA_PUBKEY_LEN: usize = 64;
+
+/// OID 1.2.840.113741.1.13.1, the SGX extension of PCK certificates
+const SGX_EXTENSION_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01];
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_BOOLEAN: u8 = 0x01;
+
+/// An enclave report body, either of the attested enclave or of the Quoting Enclave
+struct ReportBody<'a>(&'a [u8]);
+
+impl<'a> ReportBody<'a> {
+	fn miscselect(&self) -> &'a [u8] {
+		&self.0[16..20]
+	}
+	fn attributes(&self) -> &'a [u8] {
+		&self.0[48..64]
+	}
+	fn mr_enclave(&self) -> &'a [u8] {
+		&self.0[64..96]
+	}
+	fn mr_signer(&self) -> &'a [u8] {
+		&self.0[128..160]
+	}
+	fn isv_prod_id(&self) -> &'a [u8] {
+		&self.0[256..258]
+	}
+	fn isv_svn(&self) -> &'a [u8] {
+		&self.0[258..260]
+	}
+	fn report_data(&self) -> &'a [u8] {
+		&self.0[320..384]
+	}
+}
+
+/// A parsed ECDSA-P256 quote of version 3
+struct Quote<'a> {
+	/// The quote header and the report body, which are signed by the attestation key
+	signed_data: &'a [u8],
+	report: ReportBody<'a>,
+	signature: &'a [u8],
+	attestation_key: &'a [u8],
+	qe_report: ReportBody<'a>,
+	qe_report_signature: &'a [u8],
+	qe_auth_data: &'a [u8],
+	/// DER encoded PCK certificate chain, leaf first
+	pck_chain: Vec<Vec<u8>>,
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.0.len() < len {
+			return Err(Error::InvalidDcapQuote);
+		}
+		let (head, tail) = self.0.split_at(len);
+		self.0 = tail;
+		Ok(head)
+	}
+
+	fn u16(&mut self) -> Result<u16, Error> {
+		Ok(u16::from_le_bytes(
+			self.take(2)?.try_into().expect("2 bytes; qed."),
+		))
+	}
+
+	fn u32(&mut self) -> Result<u32, Error> {
+		Ok(u32::from_le_bytes(
+			self.take(4)?.try_into().expect("4 bytes; qed."),
+		))
+	}
+}
+
+impl<'a> Quote<'a> {
+	fn parse(quote: &'a [u8]) -> Result<Self, Error> {
+		let mut reader = Reader(quote);
+		let header = reader.take(QUOTE_HEADER_LEN)?;
+		let mut header_reader = Reader(header);
+		if header_reader.u16()? != QUOTE_VERSION_3
+			|| header_reader.u16()? != ATTESTATION_KEY_TYPE_ECDSA_P256
+		{
+			return Err(Error::InvalidDcapQuote);
+		}
+		let report = ReportBody(reader.take(REPORT_BODY_LEN)?);
+		let signed_data = &quote[..QUOTE_HEADER_LEN + REPORT_BODY_LEN];
+
+		let sig_data_len = reader.u32()? as usize;
+		let mut reader = Reader(reader.take(sig_data_len)?);
+		let signature = reader.take(ECDSA_SIGNATURE_LEN)?;
+		let attestation_key = reader.take(ECDSA_PUBKEY_LEN)?;
+		let qe_report = ReportBody(reader.take(REPORT_BODY_LEN)?);
+		let qe_report_signature = reader.take(ECDSA_SIGNATURE_LEN)?;
+		let qe_auth_data_len = reader.u16()? as usize;
+		let qe_auth_data = reader.take(qe_auth_data_len)?;
+		if reader.u16()? != CERTIFICATION_DATA_PCK_CERT_CHAIN {
+			return Err(Error::InvalidDcapQuote);
+		}
+		let cert_data_len = reader.u32()? as usize;
+		let pck_chain =
+			parse_pem_chain(reader.take(cert_data_len)?).ok_or(Error::InvalidPckCertChain)?;
+
+		Ok(Quote {
+			signed_data,
+			report,
+			signature,
+			attestation_key,
+			qe_report,
+			qe_report_signature,
+			qe_auth_data,
+			pck_chain,
+		})
+	}
+}
+
+/// The trusted root CA certificates with their trust anchors, the Intel SGX Root CA first
+///
+/// The extra roots which are not valid certificates are skipped.
+fn trusted_roots<'a>(
+	extra_root_cas: &'a [Vec<u8>],
+) -> impl Iterator<Item = (&'a [u8], webpki::TrustAnchor<'a>)> {
+	core::iter::once(INTEL_SGX_ROOT_CA)
+		.chain(extra_root_cas.iter().map(|root_ca| root_ca.as_slice()))
+		.filter_map(|root_ca| {
+			let anchor = webpki::TrustAnchor::try_from_cert_der(root_ca).ok()?;
+			Some((root_ca, anchor))
+		})
+}
+
+/// Validates a DCAP quote against its collateral
+///
+/// The certificate chains must lead to the Intel SGX Root CA, or one of `extra_root_cas` (DER
+/// encoded) trusted in addition to it.
+pub fn validate_dcap_quote(
+	quote: &[u8],
+	collateral: &SgxQuoteCollateral,
+	now: u64,
+	verify_pruntime: bool,
+	pruntime_allowlist: Vec<Vec<u8>>,
+	extra_root_cas: &[Vec<u8>],
+) -> Result<IasFields, Error> {
+	let quote = Quote::parse(quote)?;
+	let time_now = webpki::Time::from_seconds_since_unix_epoch(now);
+
+	// Validate the PCK certificate chain
+	let (pck_cert, pck_issuers) = quote
+		.pck_chain
+		.split_first()
+		.ok_or(Error::InvalidPckCertChain)?;
+	let pck_ca = pck_issuers.first().ok_or(Error::InvalidPckCertChain)?;
+	let pck_issuers: Vec<&[u8]> = pck_issuers.iter().map(|cert| cert.as_slice()).collect();
+	let pck =
+		webpki::EndEntityCert::try_from(pck_cert.as_slice()).or(Err(Error::InvalidPckCertChain))?;
+	// The collateral must be issued under the same root as the PCK certificate
+	let (root_ca, root_anchor) = trusted_roots(extra_root_cas)
+		.find(|(_, anchor)| {
+			pck.verify_is_valid_tls_client_cert(
+				DCAP_SUPPORTED_SIG_ALGS,
+				&webpki::TlsClientTrustAnchors(core::slice::from_ref(anchor)),
+				&pck_issuers,
+				time_now,
+			)
+			.is_ok()
+		})
+		.ok_or(Error::InvalidPckCertChain)?;
+	let root_anchor = [root_anchor];
+	let trust_anchors = webpki::TlsClientTrustAnchors(&root_anchor);
+	let root_ca_crl = Crl::parse(&collateral.root_ca_crl, root_ca, now)?;
+	let pck_crl = Crl::parse(&collateral.pck_crl, pck_ca, now)?;
+	root_ca_crl.ensure_not_revoked(pck_ca)?;
+	pck_crl.ensure_not_revoked(pck_cert)?;
+
+	// Validate the QE report, which vouches for the attestation key
+	verify_raw_signature(&pck, quote.qe_report.0, quote.qe_report_signature)
+		.or(Err(Error::InvalidDcapQuote))?;
+	let mut key_and_auth_data = Vec::new();
+	key_and_auth_data.extend_from_slice(quote.attestation_key);
+	key_and_auth_data.extend_from_slice(quote.qe_auth_data);
+	let report_data = quote.qe_report.report_data();
+	if report_data[..32] != crate::hashing::sha2_256(&key_and_auth_data)
+		|| report_data[32..].iter().any(|b| *b != 0)
+	{
+		return Err(Error::InvalidDcapQuote);
+	}
+
+	// Validate the quote signature
+	let mut attestation_key = [0u8; 1 + ECDSA_PUBKEY_LEN];
+	attestation_key[0] = 0x04; // Uncompressed point
+	attestation_key[1..].copy_from_slice(quote.attestation_key);
+	ring::signature::UnparsedPublicKey::new(
+		&ring::signature::ECDSA_P256_SHA256_FIXED,
+		&attestation_key[..],
+	)
+	.verify(quote.signed_data, quote.signature)
+	.or(Err(Error::InvalidDcapQuote))?;
+
+	// Validate the Quoting Enclave against its identity
+	let qe_identity = verify_signed_json(
+		&collateral.qe_identity,
+		&collateral.qe_identity_signature,
+		&collateral.qe_identity_issuer_chain,
+		&trust_anchors,
+		&root_ca_crl,
+		now,
+	)?;
+	let qe_level = check_qe_identity(&qe_identity, &quote.qe_report)?;
+
+	// Evaluate the TCB level of the platform
+	let tcb_info = verify_signed_json(
+		&collateral.tcb_info,
+		&collateral.tcb_info_signature,
+		&collateral.tcb_info_issuer_chain,
+		&trust_anchors,
+		&root_ca_crl,
+		now,
+	)?;
+	let pck_extension = PckExtension::parse(pck_cert).ok_or(Error::InvalidPckCertChain)?;
+	let (tcb_level, advisory_ids) = check_tcb_info(&tcb_info, &pck_extension)?;
+
+	let mut confidence_level = tcb_level.max(qe_level);
+	if confidence_level < 5 {
+		for advisory_id in advisory_ids {
+			let advisory_id = advisory_id.as_str().ok_or(Error::InvalidDcapCollateral)?;
+			if !IAS_QUOTE_ADVISORY_ID_WHITELIST.contains(&advisory_id) {
+				confidence_level = 4;
+			}
+		}
+	}
+
+	let fields = IasFields {
+		mr_enclave: quote
+			.report
+			.mr_enclave()
+			.try_into()
+			.expect("32 bytes; qed."),
+		mr_signer: quote.report.mr_signer().try_into().expect("32 bytes; qed."),
+		isv_prod_id: quote
+			.report
+			.isv_prod_id()
+			.try_into()
+			.expect("2 bytes; qed."),
+		isv_svn: quote.report.isv_svn().try_into().expect("2 bytes; qed."),
+		report_data: quote
+			.report
+			.report_data()
+			.try_into()
+			.expect("64 bytes; qed."),
+		confidence_level,
+	};
+
+	// Validate PRuntime
+	if verify_pruntime && !pruntime_allowlist.contains(&fields.extend_mrenclave()) {
+		return Err(Error::PRuntimeRejected);
+	}
+
+	Ok(fields)
+}
+
+/// Verifies the signing chain and the signature of a TCB info or QE identity, and returns the
+/// parsed JSON body if it is not expired.
+fn verify_signed_json(
+	body: &[u8],
+	signature: &[u8],
+	issuer_chain: &[u8],
+	trust_anchors: &webpki::TlsClientTrustAnchors,
+	root_ca_crl: &Crl,
+	now: u64,
+) -> Result<serde_json::Value, Error> {
+	let chain = parse_pem_chain(issuer_chain).ok_or(Error::InvalidDcapCollateral)?;
+	let (signer_cert, issuers) = chain.split_first().ok_or(Error::InvalidDcapCollateral)?;
+	let issuers: Vec<&[u8]> = issuers.iter().map(|cert| cert.as_slice()).collect();
+	let signer = webpki::EndEntityCert::try_from(signer_cert.as_slice())
+		.or(Err(Error::InvalidDcapCollateral))?;
+	signer
+		.verify_is_valid_tls_client_cert(
+			DCAP_SUPPORTED_SIG_ALGS,
+			trust_anchors,
+			&issuers,
+			webpki::Time::from_seconds_since_unix_epoch(now),
+		)
+		.or(Err(Error::InvalidDcapCollateral))?;
+	root_ca_crl.ensure_not_revoked(signer_cert)?;
+	verify_raw_signature(&signer, body, signature).or(Err(Error::InvalidDcapCollateral))?;
+
+	let parsed: serde_json::Value =
+		serde_json::from_slice(body).or(Err(Error::InvalidDcapCollateral))?;
+	let next_update = parsed["nextUpdate"]
+		.as_str()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	let next_update = chrono::DateTime::parse_from_rfc3339(next_update)
+		.or(Err(Error::InvalidDcapCollateral))?
+		.timestamp();
+	if (now as i64) >= next_update {
+		return Err(Error::OutdatedDcapCollateral);
+	}
+	Ok(parsed)
+}
+
+/// Checks the QE report against the QE identity and returns the confidence level of its TCB
+fn check_qe_identity(identity: &serde_json::Value, qe_report: &ReportBody) -> Result<u8, Error> {
+	let mr_signer: [u8; 32] = decode_hex_field(&identity["mrsigner"])?;
+	let miscselect: [u8; 4] = decode_hex_field(&identity["miscselect"])?;
+	let miscselect_mask: [u8; 4] = decode_hex_field(&identity["miscselectMask"])?;
+	let attributes: [u8; 16] = decode_hex_field(&identity["attributes"])?;
+	let attributes_mask: [u8; 16] = decode_hex_field(&identity["attributesMask"])?;
+	let isv_prod_id = identity["isvprodid"]
+		.as_u64()
+		.ok_or(Error::InvalidDcapCollateral)?;
+
+	let qe_isv_prod_id =
+		u16::from_le_bytes(qe_report.isv_prod_id().try_into().expect("2 bytes; qed."));
+	// The miscselect in the identity is a big-endian hex while the report is little-endian
+	let miscselect_matches = masked_eq(
+		qe_report.miscselect().iter().rev(),
+		&miscselect,
+		&miscselect_mask,
+	);
+	let attributes_matches =
+		masked_eq(qe_report.attributes().iter(), &attributes, &attributes_mask);
+	if qe_report.mr_signer() != mr_signer
+		|| qe_isv_prod_id as u64 != isv_prod_id
+		|| !miscselect_matches
+		|| !attributes_matches
+	{
+		return Err(Error::InvalidDcapQuote);
+	}
+
+	let isv_svn = u16::from_le_bytes(qe_report.isv_svn().try_into().expect("2 bytes; qed."));
+	let tcb_levels = identity["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	for level in tcb_levels {
+		let level_svn = level["tcb"]["isvsvn"]
+			.as_u64()
+			.ok_or(Error::InvalidDcapCollateral)?;
+		if isv_svn as u64 >= level_svn {
+			let status = level["tcbStatus"]
+				.as_str()
+				.ok_or(Error::InvalidDcapCollateral)?;
+			return tcb_status_level(status);
+		}
+	}
+	Err(Error::InvalidQuoteStatus)
+}
+
+/// Finds the TCB level of the platform and returns its confidence level with the advisories
+fn check_tcb_info<'a>(
+	tcb_info: &'a serde_json::Value,
+	pck_extension: &PckExtension,
+) -> Result<(u8, &'a [serde_json::Value]), Error> {
+	let fmspc: [u8; 6] = decode_hex_field(&tcb_info["fmspc"])?;
+	let pce_id: [u8; 2] = decode_hex_field(&tcb_info["pceId"])?;
+	if fmspc != pck_extension.fmspc || pce_id != pck_extension.pce_id {
+		return Err(Error::InvalidDcapCollateral);
+	}
+
+	let tcb_levels = tcb_info["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	for level in tcb_levels {
+		let tcb = &level["tcb"];
+		let components = tcb["sgxtcbcomponents"]
+			.as_array()
+			.ok_or(Error::InvalidDcapCollateral)?;
+		if components.len() != pck_extension.cpu_svn.len() {
+			return Err(Error::InvalidDcapCollateral);
+		}
+		let mut matched = true;
+		for (component, svn) in components.iter().zip(pck_extension.cpu_svn.iter()) {
+			let level_svn = component["svn"]
+				.as_u64()
+				.ok_or(Error::InvalidDcapCollateral)?;
+			if (*svn as u64) < level_svn {
+				matched = false;
+			}
+		}
+		let level_pce_svn = tcb["pcesvn"].as_u64().ok_or(Error::InvalidDcapCollateral)?;
+		if matched && pck_extension.pce_svn as u64 >= level_pce_svn {
+			let status = level["tcbStatus"]
+				.as_str()
+				.ok_or(Error::InvalidDcapCollateral)?;
+			let advisory_ids = level["advisoryIDs"]
+				.as_array()
+				.map(Vec::as_slice)
+				.unwrap_or(&[]);
+			return Ok((tcb_status_level(status)?, advisory_ids));
+		}
+	}
+	Err(Error::InvalidQuoteStatus)
+}
+
+fn masked_eq<'a>(value: impl Iterator<Item = &'a u8>, expected: &[u8], mask: &[u8]) -> bool {
+	value
+		.zip(expected.iter().zip(mask.iter()))
+		.all(|(v, (e, m))| v & m == *e)
+}
+
+fn tcb_status_level(status: &str) -> Result<u8, Error> {
+	if DCAP_TCB_STATUS_LEVEL_1.contains(&status) {
+		Ok(1)
+	} else if DCAP_TCB_STATUS_LEVEL_2.contains(&status) {
+		Ok(2)
+	} else if DCAP_TCB_STATUS_LEVEL_3.contains(&status) {
+		Ok(3)
+	} else if DCAP_TCB_STATUS_LEVEL_5.contains(&status) {
+		Ok(5)
+	} else {
+		// Revoked or unknown
+		Err(Error::InvalidQuoteStatus)
+	}
+}
+
+fn decode_hex_field<const N: usize>(value: &serde_json::Value) -> Result<[u8; N], Error> {
+	let mut buf = [0u8; N];
+	let value = value.as_str().ok_or(Error::InvalidDcapCollateral)?;
+	hex::decode_to_slice(value, &mut buf).or(Err(Error::InvalidDcapCollateral))?;
+	Ok(buf)
+}
+
+/// Verifies a raw `r || s` ECDSA-P256 signature with the key of the certificate
+fn verify_raw_signature(
+	cert: &webpki::EndEntityCert,
+	msg: &[u8],
+	signature: &[u8],
+) -> Result<(), ()> {
+	let signature = ecdsa_signature_to_der(signature).ok_or(())?;
+	cert.verify_signature(&webpki::ECDSA_P256_SHA256, msg, &signature)
+		.or(Err(()))
+}
+
+fn ecdsa_signature_to_der(signature: &[u8]) -> Option<Vec<u8>> {
+	if signature.len() != ECDSA_SIGNATURE_LEN {
+		return None;
+	}
+	let mut content = Vec::new();
+	for half in signature.chunks(ECDSA_SIGNATURE_LEN / 2) {
+		let first_nonzero = half.iter().position(|b| *b != 0).unwrap_or(half.len() - 1);
+		let half = &half[first_nonzero..];
+		content.push(TAG_INTEGER);
+		if half[0] & 0x80 != 0 {
+			content.push(half.len() as u8 + 1);
+			content.push(0);
+		} else {
+			content.push(half.len() as u8);
+		}
+		content.extend_from_slice(half);
+	}
+	let mut der = sp_std::vec![TAG_SEQUENCE, content.len() as u8];
+	der.extend(content);
+	Some(der)
+}
+
+fn parse_pem_chain(pem: &[u8]) -> Option<Vec<Vec<u8>>> {
+	const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+	const END: &str = "-----END CERTIFICATE-----";
+
+	let mut rest = core::str::from_utf8(pem).ok()?;
+	let mut certs = Vec::new();
+	while let Some(start) = rest.find(BEGIN) {
+		let body = &rest[start + BEGIN.len()..];
+		let end = body.find(END)?;
+		let encoded: Vec<u8> = body[..end]
+			.bytes()
+			.filter(|b| !b.is_ascii_whitespace())
+			.collect();
+		certs.push(base64::decode(&encoded).ok()?);
+		rest = &body[end + END.len()..];
+	}
+	if certs.is_empty() {
+		None
+	} else {
+		Some(certs)
+	}
+}
+
+/// A minimal DER reader, just enough to walk through certificates and CRLs
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+	fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	fn peek_tag(&self) -> Option<u8> {
+		self.0.first().cloned()
+	}
+
+	/// Reads the next element, returning its tag, its content and its whole encoding
+	fn read_any(&mut self) -> Option<(u8, &'a [u8], &'a [u8])> {
+		let tag = *self.0.first()?;
+		let first_len = *self.0.get(1)? as usize;
+		let (header_len, len) = if first_len < 0x80 {
+			(2, first_len)
+		} else {
+			let num_bytes = first_len & 0x7F;
+			if num_bytes == 0 || num_bytes > 4 {
+				return None;
+			}
+			let len = self
+				.0
+				.get(2..2 + num_bytes)?
+				.iter()
+				.fold(0usize, |acc, b| (acc << 8) | *b as usize);
+			(2 + num_bytes, len)
+		};
+		let total = header_len.checked_add(len)?;
+		let whole = self.0.get(..total)?;
+		self.0 = &self.0[total..];
+		Some((tag, &whole[header_len..], whole))
+	}
+
+	fn read(&mut self, tag: u8) -> Option<&'a [u8]> {
+		match self.read_any()? {
+			(t, content, _) if t == tag => Some(content),
+			_ => None,
+		}
+	}
+
+	fn read_optional(&mut self, tag: u8) -> Option<&'a [u8]> {
+		if self.peek_tag() == Some(tag) {
+			self.read(tag)
+		} else {
+			None
+		}
+	}
+
+	fn read_time(&mut self) -> Option<u64> {
+		let (tag, content, _) = self.read_any()?;
+		parse_der_time(tag, content)
+	}
+}
+
+fn parse_der_time(tag: u8, content: &[u8]) -> Option<u64> {
+	let time = core::str::from_utf8(content).ok()?;
+	let (year, rest) = match tag {
+		TAG_UTC_TIME => {
+			let year: i32 = time.get(..2)?.parse().ok()?;
+			(
+				if year >= 50 { 1900 + year } else { 2000 + year },
+				time.get(2..)?,
+			)
+		}
+		TAG_GENERALIZED_TIME => (time.get(..4)?.parse().ok()?, time.get(4..)?),
+		_ => return None,
+	};
+	// MMDDHHMMSSZ
+	if rest.len() != 11 || !rest.ends_with('Z') {
+		return None;
+	}
+	let field = |i: usize| -> Option<u32> { rest.get(i..i + 2)?.parse().ok() };
+	let datetime = chrono::NaiveDate::from_ymd_opt(year, field(0)?, field(2)?)?.and_hms_opt(
+		field(4)?,
+		field(6)?,
+		field(8)?,
+	)?;
+	u64::try_from(datetime.timestamp()).ok()
+}
+
+/// Returns the TBSCertificate of a certificate, positioned after the serial number, along with
+/// the serial number
+fn tbs_certificate(cert: &[u8]) -> Option<(Der<'_>, &[u8])> {
+	let mut cert = Der(Der(cert).read(TAG_SEQUENCE)?);
+	let mut tbs = Der(cert.read(TAG_SEQUENCE)?);
+	// version [0] EXPLICIT
+	tbs.read_optional(0xA0);
+	let serial = tbs.read(TAG_INTEGER)?;
+	Some((tbs, serial))
+}
+
+fn certificate_serial(cert: &[u8]) -> Option<&[u8]> {
+	tbs_certificate(cert).map(|(_, serial)| serial)
+}
+
+/// A certificate revocation list whose signature has been verified
+struct Crl<'a> {
+	revoked_serials: Vec<&'a [u8]>,
+}
+
+impl<'a> Crl<'a> {
+	/// Parses a DER encoded CRL, verifying it is signed by `issuer` and not expired
+	fn parse(crl: &'a [u8], issuer: &[u8], now: u64) -> Result<Self, Error> {
+		let (crl, next_update) =
+			Self::parse_signed(crl, issuer).ok_or(Error::InvalidDcapCollateral)?;
+		match next_update {
+			Some(next_update) if now >= next_update => Err(Error::OutdatedDcapCollateral),
+			_ => Ok(crl),
+		}
+	}
+
+	fn parse_signed(crl: &'a [u8], issuer: &[u8]) -> Option<(Self, Option<u64>)> {
+		let mut crl = Der(Der(crl).read(TAG_SEQUENCE)?);
+		let (tag, tbs_content, tbs) = crl.read_any()?;
+		if tag != TAG_SEQUENCE {
+			return None;
+		}
+		let _signature_algorithm = crl.read(TAG_SEQUENCE)?;
+		let signature = match crl.read(TAG_BIT_STRING)? {
+			[0, signature @ ..] => signature,
+			_ => return None,
+		};
+		let issuer = webpki::EndEntityCert::try_from(issuer).ok()?;
+		issuer
+			.verify_signature(&webpki::ECDSA_P256_SHA256, tbs, signature)
+			.ok()?;
+
+		let mut tbs = Der(tbs_content);
+		tbs.read_optional(TAG_INTEGER);
+		let _signature_algorithm = tbs.read(TAG_SEQUENCE)?;
+		let _issuer = tbs.read(TAG_SEQUENCE)?;
+		let _this_update = tbs.read_time()?;
+		let next_update = match tbs.peek_tag() {
+			Some(TAG_UTC_TIME) | Some(TAG_GENERALIZED_TIME) => Some(tbs.read_time()?),
+			_ => None,
+		};
+		let mut revoked_serials = Vec::new();
+		if let Some(revoked) = tbs.read_optional(TAG_SEQUENCE) {
+			let mut revoked = Der(revoked);
+			while !revoked.is_empty() {
+				let mut entry = Der(revoked.read(TAG_SEQUENCE)?);
+				revoked_serials.push(entry.read(TAG_INTEGER)?);
+			}
+		}
+		Some((Crl { revoked_serials }, next_update))
+	}
+
+	fn ensure_not_revoked(&self, cert: &[u8]) -> Result<(), Error> {
+		let serial = certificate_serial(cert).ok_or(Error::InvalidPckCertChain)?;
+		if self.revoked_serials.contains(&serial) {
+			Err(Error::RevokedCertificate)
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// The platform TCB and identity recorded in the SGX extension of a PCK certificate
+struct PckExtension {
+	cpu_svn: [u8; 16],
+	pce_svn: u16,
+	pce_id: [u8; 2],
+	fmspc: [u8; 6],
+}
+
+impl PckExtension {
+	fn parse(cert: &[u8]) -> Option<Self> {
+		let (mut tbs, _serial) = tbs_certificate(cert)?;
+		let _signature_algorithm = tbs.read(TAG_SEQUENCE)?;
+		let _issuer = tbs.read(TAG_SEQUENCE)?;
+		let _validity = tbs.read(TAG_SEQUENCE)?;
+		let _subject = tbs.read(TAG_SEQUENCE)?;
+		let _spki = tbs.read(TAG_SEQUENCE)?;
+		// issuerUniqueID [1] and subjectUniqueID [2]
+		tbs.read_optional(0x81);
+		tbs.read_optional(0x82);
+		let mut extensions = Der(Der(tbs.read(0xA3)?).read(TAG_SEQUENCE)?);
+		let sgx_extension = loop {
+			let mut extension = Der(extensions.read(TAG_SEQUENCE)?);
+			let oid = extension.read(TAG_OID)?;
+			extension.read_optional(TAG_BOOLEAN);
+			let value = extension.read(TAG_OCTET_STRING)?;
+			if oid == SGX_EXTENSION_OID {
+				break value;
+			}
+		};
+
+		let mut cpu_svn = None;
+		let mut pce_svn = None;
+		let mut pce_id = None;
+		let mut fmspc = None;
+		let mut entries = Der(Der(sgx_extension).read(TAG_SEQUENCE)?);
+		while !entries.is_empty() {
+			let mut entry = Der(entries.read(TAG_SEQUENCE)?);
+			let oid = entry.read(TAG_OID)?;
+			match oid.strip_prefix(SGX_EXTENSION_OID)? {
+				// TCB
+				[2] => {
+					let mut components = [0u8; 16];
+					let mut tcb = Der(entry.read(TAG_SEQUENCE)?);
+					while !tcb.is_empty() {
+						let mut component = Der(tcb.read(TAG_SEQUENCE)?);
+						let oid = component.read(TAG_OID)?;
+						match oid.strip_prefix(SGX_EXTENSION_OID)? {
+							[2, n @ 1..=16] => {
+								components[*n as usize - 1] =
+									parse_der_uint(component.read(TAG_INTEGER)?)?
+										.try_into()
+										.ok()?;
+							}
+							[2, 17] => {
+								pce_svn = Some(
+									parse_der_uint(component.read(TAG_INTEGER)?)?
+										.try_into()
+										.ok()?,
+								);
+							}
+							_ => {}
+						}
+					}
+					cpu_svn = Some(components);
+				}
+				// PCE-ID
+				[3] => pce_id = entry.read(TAG_OCTET_STRING)?.try_into().ok(),
+				// FMSPC
+				[4] => fmspc = entry.read(TAG_OCTET_STRING)?.try_into().ok(),
+				_ => {}
+			}
+		}
+		Some(PckExtension {
+			cpu_svn: cpu_svn?,
+			pce_svn: pce_svn?,
+			pce_id: pce_id?,
+			fmspc: fmspc?,
+		})
+	}
+}
+
+fn parse_der_uint(content: &[u8]) -> Option<u32> {
+	if content.is_empty() || content.len() > 5 || content[0] & 0x80 != 0 {
+		return None;
+	}
+	content
+		.iter()
+		.try_fold(0u64, |acc, b| Some((acc << 8) | *b as u64))
+		.and_then(|v| u32::try_from(v).ok())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use frame_support::assert_ok;
+
+	pub const DCAP_ATTESTATION_SAMPLE: &[u8] = include_bytes!("../../sample/dcap_attestation.json");
+	// Signed by a test root CA, the collateral is valid from 2022-10-01 to 2030-01-01
+	//
+	// TODO: add a quote recorded on a real SGX platform together with its PCK chain and the
+	// collateral from Intel PCS, so that the chain to `INTEL_SGX_ROOT_CA` is covered as well.
+	pub const DCAP_ATTESTATION_TIMESTAMP: u64 = 1666000000; // 2022-10-17T09:46:40Z
+
+	struct Sample {
+		quote: Vec<u8>,
+		collateral: SgxQuoteCollateral,
+		test_root_ca: Vec<u8>,
+		user_data_hash: Vec<u8>,
+		pruntime_hash: Vec<u8>,
+	}
+
+	fn load_sample() -> Sample {
+		let sample: serde_json::Value = serde_json::from_slice(DCAP_ATTESTATION_SAMPLE).unwrap();
+		let hex_field = |name: &str| hex::decode(sample[name].as_str().unwrap()).unwrap();
+		let raw_field = |name: &str| sample[name].as_str().unwrap().as_bytes().to_vec();
+		Sample {
+			quote: hex_field("quote"),
+			collateral: SgxQuoteCollateral {
+				root_ca_crl: hex_field("rootCaCrl"),
+				pck_crl: hex_field("pckCrl"),
+				tcb_info_issuer_chain: raw_field("tcbInfoIssuerChain"),
+				tcb_info: raw_field("tcbInfo"),
+				tcb_info_signature: hex_field("tcbInfoSignature"),
+				qe_identity_issuer_chain: raw_field("qeIdentityIssuerChain"),
+				qe_identity: raw_field("qeIdentity"),
+				qe_identity_signature: hex_field("qeIdentitySignature"),
+			},
+			test_root_ca: hex_field("rootCa"),
+			user_data_hash: hex_field("userDataHash"),
+			pruntime_hash: hex_field("pruntimeHash"),
+		}
+	}
+
+	#[test]
+	fn test_dcap_validator() {
+		let sample = load_sample();
+		let validate = |collateral: &SgxQuoteCollateral, now, allowlist| {
+			validate_dcap_quote(
+				&sample.quote,
+				collateral,
+				now,
+				true,
+				allowlist,
+				&[sample.test_root_ca.clone()],
+			)
+		};
+
+		assert_eq!(
+			validate(&sample.collateral, DCAP_ATTESTATION_TIMESTAMP, vec![]),
+			Err(Error::PRuntimeRejected)
+		);
+		// 2030-01-01, the collateral is expired
+		assert_eq!(
+			validate(
+				&sample.collateral,
+				1893456000,
+				vec![sample.pruntime_hash.clone()]
+			),
+			Err(Error::OutdatedDcapCollateral)
+		);
+
+		let mut tampered = sample.collateral.clone();
+		tampered.tcb_info[10] ^= 1;
+		assert_eq!(
+			validate(
+				&tampered,
+				DCAP_ATTESTATION_TIMESTAMP,
+				vec![sample.pruntime_hash.clone()]
+			),
+			Err(Error::InvalidDcapCollateral)
+		);
+
+		let fields = validate(
+			&sample.collateral,
+			DCAP_ATTESTATION_TIMESTAMP,
+			vec![sample.pruntime_hash.clone()],
+		)
+		.unwrap();
+		assert_eq!(fields.extend_mrenclave(), sample.pruntime_hash);
+		assert_eq!(&fields.report_data[..32], &sample.user_data_hash[..]);
+		// SWHardeningNeeded with an advisory out of the whitelist
+		assert_eq!(fields.confidence_level, 4);
+	}
+
+	#[test]
+	fn test_dcap_validator_rejects_tampered_quote() {
+		let sample = load_sample();
+		let mut quote = sample.quote.clone();
+		// Flip a bit of the report data
+		quote[48 + 320] ^= 1;
+		assert_eq!(
+			validate_dcap_quote(
+				&quote,
+				&sample.collateral,
+				DCAP_ATTESTATION_TIMESTAMP,
+				false,
+				vec![],
+				&[sample.test_root_ca.clone()]
+			),
+			Err(Error::InvalidDcapQuote)
+		);
+		// The TCB signing certificate is not a trusted root
+		let untrusted_root = parse_pem_chain(&sample.collateral.tcb_info_issuer_chain).unwrap();
+		assert_eq!(
+			validate_dcap_quote(
+				&sample.quote,
+				&sample.collateral,
+				DCAP_ATTESTATION_TIMESTAMP,
+				false,
+				vec![],
+				&[untrusted_root[0].clone()]
+			),
+			Err(Error::InvalidPckCertChain)
+		);
+		assert_ok!(validate_dcap_quote(
+			&sample.quote,
+			&sample.collateral,
+			DCAP_ATTESTATION_TIMESTAMP,
+			false,
+			vec![],
+			&[sample.test_root_ca.clone()]
+		));
+	}
+
+	#[test]
+	fn test_intel_sgx_root_ca() {
+		assert_eq!(
+			crate::hashing::sha2_256(INTEL_SGX_ROOT_CA),
+			hex_literal::hex!("44a0196b2b99f889b8e149e95b807a350e7424964399e885a7cbb8ccfab674d3")
+		);
+		assert!(webpki::TrustAnchor::try_from_cert_der(INTEL_SGX_ROOT_CA).is_ok());
+		// Always trusted, and before the extra roots
+		let roots: Vec<_> = trusted_roots(&[vec![1, 2, 3]]).collect();
+		assert_eq!(roots.len(), 1);
+		assert_eq!(roots[0].0, INTEL_SGX_ROOT_CA);
+
+		// The sample is issued by a test root, which is only trusted once it is added
+		let sample = load_sample();
+		assert_eq!(
+			validate_dcap_quote(
+				&sample.quote,
+				&sample.collateral,
+				DCAP_ATTESTATION_TIMESTAMP,
+				false,
+				vec![],
+				&[]
+			),
+			Err(Error::InvalidPckCertChain)
+		);
+		assert_ok!(validate_dcap_quote(
+			&sample.quote,
+			&sample.collateral,
+			DCAP_ATTESTATION_TIMESTAMP,
+			false,
+			vec![],
+			&[vec![1, 2, 3], sample.test_root_ca.clone()]
+		));
+	}
+}
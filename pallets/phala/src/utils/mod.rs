@@ -2,4 +2,5 @@ pub mod accumulator;
 pub(crate) mod attestation;
 pub(crate) mod balance_convert;
 pub mod constants;
+pub(crate) mod dcap;
 pub(crate) mod fixed_point;
@@ -0,0 +1,78 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Codec, Decode, Encode};
+use scale_info::TypeInfo;
+use sp_std::vec::Vec;
+
+/// The projection of a staker's position in a stake pool
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct StakerProjection<Balance> {
+	/// The shares the staker holds
+	pub shares: Balance,
+	/// The stake locked in the pool
+	pub locked: Balance,
+	/// The current value of the shares
+	///
+	/// It's less than `locked` if the pool has some slash not yet settled to the staker.
+	pub shares_value: Balance,
+	/// The rewards already settled and ready to claim
+	pub available_rewards: Balance,
+	/// The rewards accumulated since the last settlement
+	pub pending_rewards: Balance,
+	/// The withdraw request of the staker in the withdraw queue, if any
+	pub withdrawal: Option<WithdrawalProjection<Balance>>,
+}
+
+/// The projection of a queued withdraw request
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct WithdrawalProjection<Balance> {
+	/// The position in the withdraw queue, starting from 0
+	pub position: u32,
+	/// The shares to withdraw
+	pub shares: Balance,
+	/// The current value of the shares to withdraw
+	pub amount: Balance,
+	/// The total value of the requests ahead in the queue
+	pub queued_ahead: Balance,
+	/// The unix timestamp when the request was made
+	pub start_time: u64,
+	/// The unix timestamp after which the pool workers are forced to stop if the request is
+	/// still not fulfilled
+	pub expires_at: u64,
+	/// The unix timestamp when the free and releasing stake of the pool is expected to cover
+	/// the request
+	///
+	/// `None` if the releasing stake is not enough, in which case the request will be fulfilled
+	/// after the workers are forced to stop and cooled down.
+	pub fulfilled_at: Option<u64>,
+}
+
+/// The projection of the stake a pool is going to release
+#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ReleasingProjection<Balance> {
+	/// The stake not used by any worker
+	pub free_stake: Balance,
+	/// The stake of the cooling down workers
+	pub releasing_stake: Balance,
+	/// The total value of the queued withdraw requests
+	pub queued_withdrawals: Balance,
+	/// The stake of each cooling down worker and the unix timestamp it can be reclaimed, in
+	/// ascending order of time
+	pub timeline: Vec<(u64, Balance)>,
+}
+
+sp_api::decl_runtime_apis! {
+	pub trait StakePoolApi<AccountId, Balance>
+	where
+		AccountId: Codec,
+		Balance: Codec,
+	{
+		/// The position of `user` in the pool `pid`, or `None` if the user doesn't stake in it
+		fn staker_projection(pid: u64, user: AccountId) -> Option<StakerProjection<Balance>>;
+		/// The releasing stake timeline of the pool `pid`, or `None` if the pool doesn't exist
+		fn releasing_projection(pid: u64) -> Option<ReleasingProjection<Balance>>;
+	}
+}
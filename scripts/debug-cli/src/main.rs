@@ -354,6 +354,7 @@ fn decode_mq_payload(destination: &[u8], payload: &[u8]) {
         SystemEvent,
         MiningReportEvent,
         MiningInfoUpdateEvent<u32>,
+        WorkerReliabilityEvent<u32>,
         GatekeeperEvent,
         phala_pallets::registry::RegistryEvent,
     );
@@ -3,6 +3,7 @@ use crate::BlockNumber;
 use anyhow::Result;
 use rocksdb::DB;
 use std::mem::size_of;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +19,9 @@ pub struct Metadata {
     pub genesis: Vec<BlockNumber>,
     pub recent_imported: Counters,
     pub higest: Counters,
+    /// Missing in the databases created before it was recorded.
+    #[serde(default)]
+    pub lowest: Counters,
 }
 
 macro_rules! update_field {
@@ -25,6 +29,11 @@ macro_rules! update_field {
         $self.recent_imported.$field = Some($value);
         let higest = $self.higest.$field.unwrap_or_default().max($value);
         $self.higest.$field = Some(higest);
+        let lowest = $self
+            .lowest
+            .$field
+            .map_or($value, |lowest| lowest.min($value));
+        $self.lowest.$field = Some(lowest);
     }};
 }
 
@@ -48,7 +57,8 @@ impl Metadata {
     }
 }
 
-pub struct CacheDB(DB);
+#[derive(Clone)]
+pub struct CacheDB(Arc<DB>);
 
 fn mk_key(prefix: u8, block_number: BlockNumber) -> [u8; size_of::<BlockNumber>() + 1] {
     let mut key = [prefix; size_of::<BlockNumber>() + 1];
@@ -58,7 +68,7 @@ fn mk_key(prefix: u8, block_number: BlockNumber) -> [u8; size_of::<BlockNumber>(
 
 impl CacheDB {
     pub fn open(path: &str) -> Result<Self> {
-        Ok(CacheDB(DB::open_default(path)?))
+        Ok(CacheDB(Arc::new(DB::open_default(path)?)))
     }
 
     pub fn flush(&self) -> Result<()> {
@@ -0,0 +1,264 @@
+//! Keep the cache database up to date with the finalized blocks of the chains.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use log::{error, info, warn};
+use pherry::headers_cache as cache;
+use pherry::types::{subxt::rpc::Subscription, ChainApi, Header};
+use scale::Encode;
+
+use crate::db::{CacheDB, Metadata};
+use crate::BlockNumber;
+
+/// Max number of blocks of each kind to import before saving the progress.
+const MAX_BATCH: BlockNumber = 10000;
+/// Number of blocks requested in a single RPC while grabbing storage changes.
+const STORAGE_CHANGES_BATCH_SIZE: BlockNumber = 10;
+/// Time to wait before reconnecting when the chain is unavailable.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(crate) struct FollowConfig {
+    /// The relaychain RPC endpoint
+    pub relay_uri: String,
+    /// The parachain RPC endpoint
+    pub para_uri: String,
+    /// The first relaychain block to import if nothing has been imported
+    pub relay_start: Option<BlockNumber>,
+    /// The first parachain block to import if nothing has been imported
+    pub para_start: BlockNumber,
+    /// Prefered minimum number of blocks between justification
+    pub justification_interval: BlockNumber,
+    /// Time between two polls of the finalized heads if the nodes can't be subscribed
+    pub poll_interval: Duration,
+}
+
+/// Follow the chains forever, importing the newly finalized blocks into the database.
+///
+/// The import resumes from `Metadata::recent_imported`, so it can be stopped and restarted at
+/// any time.
+pub(crate) async fn follow(db: CacheDB, config: FollowConfig) {
+    // Kept across the batches and reconnections, otherwise the justifications would never be
+    // fetched when importing a few blocks per poll.
+    let mut countdown = cache::JustificationCountdown::new(config.justification_interval);
+    loop {
+        if let Err(err) = run(&db, &config, &mut countdown).await {
+            error!("Failed to follow the chain: {err:?}");
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+async fn run(
+    db: &CacheDB,
+    config: &FollowConfig,
+    countdown: &mut cache::JustificationCountdown,
+) -> Result<()> {
+    let api = pherry::subxt_connect(&config.relay_uri).await?;
+    let para_api = pherry::subxt_connect(&config.para_uri).await?;
+    info!(
+        "Following relaychain at {} and parachain at {}",
+        config.relay_uri, config.para_uri
+    );
+    let mut heads = FinalizedHeads::subscribe(&api, &para_api, config.poll_interval).await;
+    loop {
+        if import_batch(db, config, countdown, &api, &para_api).await? {
+            heads.next().await?;
+        }
+    }
+}
+
+/// Wakes up the import when either chain finalizes a new block.
+enum FinalizedHeads {
+    Subscribed(Subscription<Header>, Subscription<Header>),
+    /// Fallback for the nodes which don't support the subscriptions.
+    Polling(Duration),
+}
+
+impl FinalizedHeads {
+    async fn subscribe(api: &ChainApi, para_api: &ChainApi, poll_interval: Duration) -> Self {
+        let subscribe = async {
+            let relay_heads = api.rpc().subscribe_finalized_blocks().await?;
+            let para_heads = para_api.rpc().subscribe_finalized_blocks().await?;
+            Ok::<_, anyhow::Error>((relay_heads, para_heads))
+        };
+        match subscribe.await {
+            Ok((relay_heads, para_heads)) => FinalizedHeads::Subscribed(relay_heads, para_heads),
+            Err(err) => {
+                warn!("Failed to subscribe the finalized heads, polling instead: {err}");
+                FinalizedHeads::Polling(poll_interval)
+            }
+        }
+    }
+
+    /// Wait for the next finalized head. Fails if the subscription is closed, so that the chains
+    /// are reconnected.
+    async fn next(&mut self) -> Result<()> {
+        match self {
+            FinalizedHeads::Subscribed(relay_heads, para_heads) => {
+                let head = tokio::select! {
+                    head = relay_heads.next() => head,
+                    head = para_heads.next() => head,
+                };
+                head.ok_or_else(|| anyhow!("Finalized heads subscription closed"))??;
+                Ok(())
+            }
+            FinalizedHeads::Polling(interval) => {
+                tokio::time::sleep(*interval).await;
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn finalized_number(api: &ChainApi) -> Result<BlockNumber> {
+    let hash = api.rpc().finalized_head().await?;
+    let header = api
+        .rpc()
+        .header(Some(hash))
+        .await?
+        .ok_or_else(|| anyhow!("Finalized header not found"))?;
+    Ok(header.number)
+}
+
+/// The next block to import and the number of blocks to import in this batch.
+fn next_batch(
+    imported: Option<BlockNumber>,
+    start: BlockNumber,
+    finalized: BlockNumber,
+) -> Option<(BlockNumber, BlockNumber)> {
+    let next = imported.map_or(start, |n| n + 1);
+    if next > finalized {
+        return None;
+    }
+    Some((next, (finalized - next + 1).min(MAX_BATCH)))
+}
+
+/// The first relaychain block to import if nothing has been imported.
+fn relay_start(config_start: Option<BlockNumber>, metadata: &Metadata) -> BlockNumber {
+    config_start
+        .or_else(|| metadata.genesis.iter().max().map(|g| g + 1))
+        .unwrap_or(1)
+}
+
+/// Import a batch of each kind of data. Returns false if there might be more blocks to import.
+async fn import_batch(
+    db: &CacheDB,
+    config: &FollowConfig,
+    countdown: &mut cache::JustificationCountdown,
+    api: &ChainApi,
+    para_api: &ChainApi,
+) -> Result<bool> {
+    let relay_finalized = finalized_number(api).await?;
+    let para_finalized = finalized_number(para_api).await?;
+    let mut metadata = db.get_metadata()?.unwrap_or_default();
+    let mut caught_up = true;
+
+    let relay_start = relay_start(config.relay_start, &metadata);
+    let imported = metadata.recent_imported.header;
+    if let Some((from, count)) = next_batch(imported, relay_start, relay_finalized) {
+        let grabbed = cache::grab_headers_with(api, para_api, from, count, countdown, |info| {
+            if info.justification.is_some() {
+                info!("Got justification at {}", info.header.number);
+            }
+            db.put_header(info.header.number, &info.encode())?;
+            metadata.update_header(info.header.number);
+            Ok(())
+        })
+        .await?;
+        caught_up &= grabbed < MAX_BATCH;
+        save_progress(db, &metadata, "headers", from, grabbed)?;
+    }
+
+    let imported = metadata.recent_imported.para_header;
+    if let Some((from, count)) = next_batch(imported, config.para_start, para_finalized) {
+        let grabbed = cache::grab_para_headers(para_api, from, count, |header| {
+            db.put_para_header(header.number, &header.encode())?;
+            metadata.update_para_header(header.number);
+            Ok(())
+        })
+        .await?;
+        caught_up &= grabbed < MAX_BATCH;
+        save_progress(db, &metadata, "parachain headers", from, grabbed)?;
+    }
+
+    let imported = metadata.recent_imported.storage_changes;
+    if let Some((from, count)) = next_batch(imported, config.para_start, para_finalized) {
+        let grabbed = cache::grab_storage_changes(
+            para_api,
+            from,
+            count,
+            STORAGE_CHANGES_BATCH_SIZE,
+            |changes| {
+                let number = changes.block_header.number;
+                db.put_storage_changes(number, &changes.encode())?;
+                metadata.update_storage_changes(number);
+                Ok(())
+            },
+        )
+        .await?;
+        caught_up &= grabbed < MAX_BATCH;
+        save_progress(db, &metadata, "storage changes", from, grabbed)?;
+    }
+
+    Ok(caught_up)
+}
+
+fn save_progress(
+    db: &CacheDB,
+    metadata: &Metadata,
+    what: &str,
+    from: BlockNumber,
+    count: BlockNumber,
+) -> Result<()> {
+    db.put_metadata(metadata.clone())?;
+    db.flush()?;
+    if count > 0 {
+        info!("Imported {count} {what} from {from}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_batch_works() {
+        // Nothing imported yet
+        assert_eq!(next_batch(None, 5, 4), None);
+        assert_eq!(next_batch(None, 5, 5), Some((5, 1)));
+        assert_eq!(next_batch(None, 1, 100), Some((1, 100)));
+        // Resume from the block next to the imported one
+        assert_eq!(next_batch(Some(10), 1, 10), None);
+        assert_eq!(next_batch(Some(10), 1, 12), Some((11, 2)));
+        // At most MAX_BATCH blocks at once
+        assert_eq!(next_batch(Some(10), 1, 100000), Some((11, MAX_BATCH)));
+    }
+
+    #[test]
+    fn relay_start_works() {
+        let mut metadata = Metadata::default();
+        assert_eq!(relay_start(None, &metadata), 1);
+        metadata.genesis = vec![100, 50];
+        assert_eq!(relay_start(None, &metadata), 101);
+        assert_eq!(relay_start(Some(7), &metadata), 7);
+    }
+
+    #[test]
+    fn justification_countdown_continues_across_batches() {
+        let mut countdown = cache::JustificationCountdown::new(3);
+        // Import one block per poll
+        for _ in 0..3 {
+            assert!(!countdown.due());
+            countdown.on_block(false);
+        }
+        // Keep fetching until a justification is found
+        assert!(countdown.due());
+        countdown.on_block(false);
+        assert!(countdown.due());
+        countdown.on_block(true);
+        assert!(!countdown.due());
+    }
+}
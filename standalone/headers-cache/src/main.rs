@@ -9,6 +9,8 @@ use clap::{AppSettings, Parser, Subcommand};
 use pherry::headers_cache as cache;
 
 mod db;
+mod follow;
+mod verify;
 mod web_api;
 
 type BlockNumber = u32;
@@ -140,6 +142,47 @@ enum Action {
         /// The database file to use
         #[clap(long, default_value = "cache.db")]
         db: String,
+        /// Keep importing the finalized blocks from the given relaychain and parachain RPC
+        /// endpoints while serving
+        #[clap(long, number_of_values = 2, value_names = &["RELAY_URI", "PARA_URI"])]
+        follow: Vec<String>,
+        /// The relaychain block to start following at if no header is imported.
+        /// Defaults to the block next to the imported genesis
+        #[clap(long)]
+        follow_relay_start: Option<BlockNumber>,
+        /// The parachain block to start following at if no parachain data is imported
+        #[clap(long, default_value_t = 0)]
+        follow_para_start: BlockNumber,
+        /// Prefered minimum number of blocks between justification while following
+        #[clap(long, default_value_t = 1000)]
+        justification_interval: BlockNumber,
+        /// Seconds between two polls of the finalized heads while following, if the nodes don't
+        /// support subscribing them
+        #[clap(long, default_value_t = 6)]
+        poll_interval: u64,
+    },
+    /// Verify the cached data with the GRANDPA light client and report the gaps
+    Verify {
+        /// The database file to use
+        #[clap(long, default_value = "cache.db")]
+        db: String,
+        /// The parachain id
+        #[clap(long)]
+        para_id: u32,
+        /// The genesis block to start at. Defaults to the lowest imported one
+        #[clap(long)]
+        genesis: Option<BlockNumber>,
+        /// The parachain storage at block 0, SCALE encoded as saved by
+        /// `replay --save-genesis-state`. The storage changes are verified only if given
+        #[clap(long)]
+        para_genesis_state: Option<String>,
+        /// Re-fetch the missing or broken blocks from the given relaychain and parachain RPC
+        /// endpoints
+        #[clap(long, number_of_values = 2, value_names = &["RELAY_URI", "PARA_URI"])]
+        repair: Vec<String>,
+        /// Prefered minimum number of blocks between justification while repairing
+        #[clap(long, default_value_t = 1000)]
+        justification_interval: BlockNumber,
     },
     /// Split given grabbed headers file into chunks
     Split {
@@ -316,8 +359,57 @@ async fn main() -> anyhow::Result<()> {
             }
             cache.flush()?;
         }
-        Action::Serve { db } => {
-            web_api::serve(&db).await?;
+        Action::Serve {
+            db,
+            follow,
+            follow_relay_start,
+            follow_para_start,
+            justification_interval,
+            poll_interval,
+        } => {
+            let db = db::CacheDB::open(&db)?;
+            if let [relay_uri, para_uri] = &follow[..] {
+                let config = follow::FollowConfig {
+                    relay_uri: relay_uri.clone(),
+                    para_uri: para_uri.clone(),
+                    relay_start: follow_relay_start,
+                    para_start: follow_para_start,
+                    justification_interval,
+                    poll_interval: std::time::Duration::from_secs(poll_interval),
+                };
+                tokio::spawn(follow::follow(db.clone(), config));
+            }
+            web_api::serve(db).await?;
+        }
+        Action::Verify {
+            db,
+            para_id,
+            genesis,
+            para_genesis_state,
+            repair,
+            justification_interval,
+        } => {
+            let para_genesis_state = match para_genesis_state {
+                Some(filename) => {
+                    let data = std::fs::read(&filename)
+                        .with_context(|| format!("Failed to read {filename}"))?;
+                    Some(Decode::decode(&mut &data[..]).context("Failed to decode the state")?)
+                }
+                None => None,
+            };
+            let repair = match &repair[..] {
+                [relay_uri, para_uri] => Some((relay_uri.clone(), para_uri.clone())),
+                _ => None,
+            };
+            let config = verify::VerifyConfig {
+                para_id,
+                genesis,
+                para_genesis_state,
+                repair,
+                justification_interval,
+            };
+            let db = db::CacheDB::open(&db)?;
+            verify::verify(&db, config).await?;
         }
         Action::ShowSetId { uri, block } => {
             let api = pherry::subxt_connect(&uri).await?;
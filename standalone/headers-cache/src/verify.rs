@@ -0,0 +1,460 @@
+//! Verify the integrity of the cached data before distributing it.
+//!
+//! The relaychain headers are replayed through the same GRANDPA light client as pRuntime uses,
+//! starting from the imported genesis. The parachain headers are checked against the `Paras.Heads`
+//! proofs carried by the justified relaychain headers. If the parachain genesis state is given,
+//! the storage changes are applied on top of it and the resulting state roots are checked against
+//! the parachain headers.
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use phactory::light_validation::{utils::storage_map_prefix_twox_64_concat, LightValidation};
+use phactory::{RelaychainValidator, Storage};
+use phactory_api::blocks::HeaderToSync;
+use phactory_api::storage_sync::{BlockValidator, StorageSynchronizer, Synchronizer};
+use pherry::headers_cache::{self as cache, BlockHeaderWithChanges, BlockInfo, GenesisBlockInfo};
+use pherry::types::{ChainApi, Header};
+use scale::{Decode, Encode};
+
+use crate::db::{CacheDB, Metadata};
+use crate::BlockNumber;
+
+/// Max number of blocks to re-fetch at once while repairing a gap.
+const MAX_REPAIR_LEN: BlockNumber = 10000;
+/// Number of blocks requested in a single RPC while re-fetching storage changes.
+const STORAGE_CHANGES_BATCH_SIZE: BlockNumber = 10;
+
+pub(crate) struct VerifyConfig {
+    /// The parachain id, to locate the parachain heads in the relaychain storage proofs
+    pub para_id: u32,
+    /// The genesis block to start at. Defaults to the lowest imported one
+    pub genesis: Option<BlockNumber>,
+    /// The parachain storage at block 0, to verify the state roots of the storage changes
+    pub para_genesis_state: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+    /// The relaychain and parachain RPC endpoints to re-fetch the missing data from
+    pub repair: Option<(String, String)>,
+    /// Prefered minimum number of blocks between justification while repairing
+    pub justification_interval: BlockNumber,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Header,
+    ParaHeader,
+    StorageChanges,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Header => "relaychain header",
+            Kind::ParaHeader => "parachain header",
+            Kind::StorageChanges => "storage changes",
+        }
+    }
+
+    fn get(&self, db: &CacheDB, block: BlockNumber) -> Option<Vec<u8>> {
+        match self {
+            Kind::Header => db.get_header(block),
+            Kind::ParaHeader => db.get_para_header(block),
+            Kind::StorageChanges => db.get_storage_changes(block),
+        }
+    }
+
+    fn load<T: Decode>(&self, db: &CacheDB, block: BlockNumber) -> Option<T> {
+        let data = self.get(db, block)?;
+        T::decode(&mut &data[..]).ok()
+    }
+
+    /// Whether the cached item of the block exists and decodes to the right block number.
+    fn is_valid(&self, db: &CacheDB, block: BlockNumber) -> bool {
+        let data = match self.get(db, block) {
+            Some(data) => data,
+            None => return false,
+        };
+        let number = match self {
+            Kind::Header => BlockInfo::decode(&mut &data[..]).map(|info| info.header.number),
+            Kind::ParaHeader => Header::decode(&mut &data[..]).map(|header| header.number),
+            Kind::StorageChanges => BlockHeaderWithChanges::decode(&mut &data[..])
+                .map(|changes| changes.block_header.number),
+        };
+        number.ok() == Some(block)
+    }
+}
+
+struct Verifier<'a> {
+    db: &'a CacheDB,
+    config: &'a VerifyConfig,
+    metadata: Metadata,
+    /// The relaychain and parachain API, if repairing is enabled
+    apis: Option<(ChainApi, ChainApi)>,
+    synchronizer: Synchronizer<RelaychainValidator>,
+    storage: Option<Storage>,
+    /// The `Paras.Heads` storage key of the parachain
+    para_heads_key: Vec<u8>,
+    problems: Vec<String>,
+}
+
+/// Verify the cached data, re-fetching the missing blocks if `config.repair` is given.
+///
+/// Fails if any problem remains.
+pub(crate) async fn verify(db: &CacheDB, mut config: VerifyConfig) -> Result<()> {
+    let metadata = db.get_metadata()?.unwrap_or_default();
+    let genesis_block = config
+        .genesis
+        .or_else(|| metadata.genesis.iter().min().cloned())
+        .ok_or_else(|| anyhow!("No genesis imported"))?;
+    let genesis = db
+        .get_genesis(genesis_block)
+        .ok_or_else(|| anyhow!("Genesis at {genesis_block} not found"))?;
+    let genesis =
+        GenesisBlockInfo::decode(&mut &genesis[..]).context("Failed to decode the genesis")?;
+    let mut light_client = LightValidation::new();
+    let bridge_id = light_client
+        .initialize_bridge(genesis.block_header, genesis.authority_set, genesis.proof)
+        .context("Invalid genesis")?;
+
+    let apis = match &config.repair {
+        Some((relay_uri, para_uri)) => Some((
+            pherry::subxt_connect(relay_uri).await?,
+            pherry::subxt_connect(para_uri).await?,
+        )),
+        None => None,
+    };
+    let para_start = lowest(&metadata, Kind::ParaHeader);
+    let mut storage = config.para_genesis_state.take().map(|state| {
+        let mut storage = Storage::default();
+        storage.load(state.into_iter());
+        storage
+    });
+    if storage.is_none() {
+        warn!("No parachain genesis state given, the storage changes will not be verified");
+    } else if para_start > 1 {
+        warn!("The cache starts at parachain block {para_start}, the storage changes can not be verified against the genesis state");
+        storage = None;
+    }
+
+    let mut verifier = Verifier {
+        db,
+        config: &config,
+        metadata,
+        apis,
+        synchronizer: new_synchronizer(light_client, bridge_id, genesis_block, para_start),
+        storage,
+        para_heads_key: storage_map_prefix_twox_64_concat(b"Paras", b"Heads", &config.para_id),
+        problems: vec![],
+    };
+    verifier.scan(Kind::Header, genesis_block + 1).await?;
+    verifier.scan(Kind::ParaHeader, para_start).await?;
+    let changes_start = verifier.lowest(Kind::StorageChanges);
+    verifier.scan(Kind::StorageChanges, changes_start).await?;
+    verifier.validate();
+    verifier.report()
+}
+
+/// The lowest imported block of the given kind. The blocks below it are not expected in the
+/// cache, e.g. when the import started from `--follow-para-start`.
+///
+/// Falls back to block 1 for the databases which didn't record it.
+fn lowest(metadata: &Metadata, kind: Kind) -> BlockNumber {
+    let lowest = match kind {
+        Kind::Header => metadata.lowest.header,
+        Kind::ParaHeader => metadata.lowest.para_header,
+        Kind::StorageChanges => metadata.lowest.storage_changes,
+    };
+    lowest.unwrap_or(1)
+}
+
+/// Replay the relaychain from the block next to the genesis, and the parachain from the lowest
+/// cached block, whose ancestors can't be verified without the cache.
+fn new_synchronizer<V: BlockValidator>(
+    validator: V,
+    bridge_id: u64,
+    genesis_block: BlockNumber,
+    para_start: BlockNumber,
+) -> Synchronizer<V> {
+    Synchronizer::new_parachain_at(validator, bridge_id, genesis_block + 1, para_start)
+}
+
+impl Verifier<'_> {
+    fn highest(&self, kind: Kind) -> Option<BlockNumber> {
+        match kind {
+            Kind::Header => self.metadata.higest.header,
+            Kind::ParaHeader => self.metadata.higest.para_header,
+            Kind::StorageChanges => self.metadata.higest.storage_changes,
+        }
+    }
+
+    fn lowest(&self, kind: Kind) -> BlockNumber {
+        lowest(&self.metadata, kind)
+    }
+
+    /// Report a block required by the verification but missing in the cache. Only the blocks
+    /// below the lowest imported one are reported, the others have been reported by the scan.
+    fn report_uncached(&mut self, kind: Kind, block: BlockNumber) {
+        let lowest = self.lowest(kind);
+        if block < lowest {
+            self.problems.push(format!(
+                "{} {block} is required to verify the cache, which starts at {lowest}",
+                kind.name()
+            ));
+        }
+    }
+
+    /// Look for the missing or broken items of the given kind, and repair them if possible.
+    async fn scan(&mut self, kind: Kind, start: BlockNumber) -> Result<()> {
+        let highest = match self.highest(kind) {
+            Some(highest) => highest,
+            None => {
+                self.problems.push(format!("No {} imported", kind.name()));
+                return Ok(());
+            }
+        };
+        info!("Scanning {} from {start} to {highest}", kind.name());
+        let mut block = start;
+        while block <= highest {
+            if kind.is_valid(self.db, block) {
+                block += 1;
+                continue;
+            }
+            let end = (block..=highest.min(block.saturating_add(MAX_REPAIR_LEN - 1)))
+                .take_while(|&b| !kind.is_valid(self.db, b))
+                .last()
+                .unwrap_or(block);
+            if self.apis.is_some() {
+                self.repair(kind, block, end - block + 1).await?;
+            }
+            let remaining = (block..=end)
+                .filter(|&b| !kind.is_valid(self.db, b))
+                .count();
+            if remaining > 0 {
+                self.problems.push(format!(
+                    "Missing or broken {}: {remaining} blocks in {block}..={end}",
+                    kind.name()
+                ));
+            }
+            block = end + 1;
+        }
+        Ok(())
+    }
+
+    /// Re-fetch the items from the chain. The import progress in the metadata is left untouched,
+    /// since the repaired blocks are always below the highest imported ones.
+    async fn repair(&self, kind: Kind, from: BlockNumber, count: BlockNumber) -> Result<()> {
+        let (api, para_api) = self.apis.as_ref().expect("Only called when repairing");
+        info!("Re-fetching {count} {} from {from}", kind.name());
+        let db = self.db;
+        let grabbed = match kind {
+            Kind::Header => {
+                cache::grab_headers(
+                    api,
+                    para_api,
+                    from,
+                    count,
+                    self.config.justification_interval,
+                    |info| db.put_header(info.header.number, &info.encode()),
+                )
+                .await?
+            }
+            Kind::ParaHeader => {
+                cache::grab_para_headers(para_api, from, count, |header| {
+                    db.put_para_header(header.number, &header.encode())
+                })
+                .await?
+            }
+            Kind::StorageChanges => {
+                cache::grab_storage_changes(
+                    para_api,
+                    from,
+                    count,
+                    STORAGE_CHANGES_BATCH_SIZE,
+                    |changes| {
+                        db.put_storage_changes(changes.block_header.number, &changes.encode())
+                    },
+                )
+                .await?
+            }
+        };
+        db.flush()?;
+        info!("Repaired {grabbed} {}", kind.name());
+        Ok(())
+    }
+
+    /// Replay the cached data through the light client until the first failure.
+    fn validate(&mut self) {
+        let next = self.synchronizer.counters().next_header_number;
+        let highest = self.highest(Kind::Header).unwrap_or_default();
+        let mut batch: Vec<BlockInfo> = vec![];
+        for block in next..=highest {
+            // The missing ones have been reported by the scan.
+            let info: BlockInfo = match Kind::Header.load(self.db, block) {
+                Some(info) => info,
+                None => break,
+            };
+            let justified = info.justification.is_some();
+            batch.push(info);
+            if justified && !self.sync_headers(std::mem::take(&mut batch)) {
+                return;
+            }
+        }
+        if !batch.is_empty() {
+            warn!(
+                "{} relaychain headers after the last justification can not be verified",
+                batch.len()
+            );
+        }
+    }
+
+    /// Submit the relaychain headers ending with a justification, and the parachain data it
+    /// finalizes. Returns false if the verification can not go on.
+    fn sync_headers(&mut self, mut batch: Vec<BlockInfo>) -> bool {
+        let from = batch[0].header.number;
+        let last = batch.last_mut().expect("Never called with an empty batch");
+        let to = last.header.number;
+        let authority_set_change = last.authority_set_change.take();
+        let para_header = last.para_header.take();
+        let headers = batch
+            .into_iter()
+            .map(|info| HeaderToSync {
+                header: info.header,
+                justification: info.justification,
+            })
+            .collect();
+        if let Err(err) = self.synchronizer.sync_header(headers, authority_set_change) {
+            self.problems.push(format!(
+                "Relaychain headers {from}..={to} failed to verify: {err}"
+            ));
+            return false;
+        }
+
+        let para_header = match para_header {
+            Some(para_header) => para_header,
+            None => return true,
+        };
+        let next = self.synchronizer.counters().next_para_header_number;
+        let mut headers = vec![];
+        for block in next..=para_header.fin_header_num {
+            match Kind::ParaHeader.load(self.db, block) {
+                Some(header) => headers.push(header),
+                None => {
+                    self.report_uncached(Kind::ParaHeader, block);
+                    return false;
+                }
+            }
+        }
+        if let Err(err) = self.synchronizer.sync_parachain_header(
+            headers,
+            para_header.proof,
+            &self.para_heads_key,
+        ) {
+            self.problems.push(format!(
+                "Parachain headers {next}..={} failed to verify against relaychain block {to}: {err}",
+                para_header.fin_header_num
+            ));
+            return false;
+        }
+        self.feed_storage_changes(para_header.fin_header_num)
+    }
+
+    fn feed_storage_changes(&mut self, to: BlockNumber) -> bool {
+        let storage = match &mut self.storage {
+            Some(storage) => storage,
+            None => return true,
+        };
+        let next = self.synchronizer.counters().next_block_number;
+        let to = to.min(self.metadata.higest.storage_changes.unwrap_or_default());
+        for block in next..=to {
+            let changes: BlockHeaderWithChanges = match Kind::StorageChanges.load(self.db, block) {
+                Some(changes) => changes,
+                None => {
+                    self.report_uncached(Kind::StorageChanges, block);
+                    return false;
+                }
+            };
+            if let Err(err) = self.synchronizer.feed_block(&changes, storage) {
+                self.problems.push(format!(
+                    "Storage changes of {block} failed to verify: {err}"
+                ));
+                return false;
+            }
+        }
+        true
+    }
+
+    fn report(&self) -> Result<()> {
+        let counters = self.synchronizer.counters();
+        println!(
+            "relaychain headers verified to {}",
+            counters.next_header_number - 1
+        );
+        println!(
+            "parachain headers verified to {}",
+            counters.next_para_header_number - 1
+        );
+        if self.storage.is_some() {
+            println!(
+                "storage changes verified to {}",
+                counters.next_block_number - 1
+            );
+        }
+        if self.problems.is_empty() {
+            println!("no problem found");
+            return Ok(());
+        }
+        for problem in &self.problems {
+            println!("ERROR: {problem}");
+        }
+        bail!("{} problems found", self.problems.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phactory_api::blocks::{AuthoritySetChange, StorageProof};
+    use phactory_api::storage_sync::Result;
+    use pherry::types::Hash;
+
+    struct AcceptAll;
+
+    impl BlockValidator for AcceptAll {
+        fn submit_finalized_headers(
+            &mut self,
+            _bridge_id: u64,
+            _header: Header,
+            _ancestry_proof: Vec<Header>,
+            _grandpa_proof: Vec<u8>,
+            _authority_set_change: Option<AuthoritySetChange>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn validate_storage_proof(
+            &self,
+            _state_root: Hash,
+            _proof: StorageProof,
+            _items: &[(&[u8], &[u8])],
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn synchronizer_starts_at_lowest_para_header() {
+        let mut metadata = Metadata::default();
+        // Imported before the lowest blocks were recorded
+        let para_start = lowest(&metadata, Kind::ParaHeader);
+        let counters = new_synchronizer(AcceptAll, 0, 100, para_start).counters();
+        assert_eq!(counters.next_header_number, 101);
+        assert_eq!(counters.next_para_header_number, 1);
+
+        // Imported with `--follow-para-start`
+        metadata.lowest.header = Some(101);
+        metadata.lowest.para_header = Some(5000);
+        let para_start = lowest(&metadata, Kind::ParaHeader);
+        let counters = new_synchronizer(AcceptAll, 0, 100, para_start).counters();
+        assert_eq!(counters.next_header_number, 101);
+        assert_eq!(counters.next_para_header_number, 5000);
+        assert_eq!(counters.next_block_number, 5000);
+    }
+}
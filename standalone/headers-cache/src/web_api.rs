@@ -1,116 +1,421 @@
-use rocket::response::status::NotFound;
-use rocket::State;
-use rocket::{get, routes};
+use std::convert::Infallible;
+use std::io::{Cursor, Write};
+use std::ops::{Range, RangeInclusive};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::{get, routes, Request, State};
 
 use scale::{Decode, Encode};
 
 use crate::db::CacheDB;
 use crate::BlockNumber;
 
+/// Max number of blocks can be requested in a range query.
+const MAX_RANGE_LEN: BlockNumber = 10000;
+
+/// Don't bother compressing the small responses.
+const MIN_COMPRESS_SIZE: usize = 1024;
+
 struct App {
     db: CacheDB,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Pick the preferred encoding from the `Accept-Encoding` header.
+    fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let accepted = |name: &str| {
+            accept_encoding.split(',').any(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                parts.next() == Some(name) && !parts.any(|p| p == "q=0" || p == "q=0.0")
+            })
+        };
+        if accepted("zstd") {
+            Some(Encoding::Zstd)
+        } else if accepted("gzip") {
+            Some(Encoding::Gzip)
+        } else {
+            None
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Zstd => zstd::stream::encode_all(data, 0),
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The encoding and the cached ETags of a request, parsed before loading any data.
+struct Negotiation {
+    encoding: Option<Encoding>,
+    if_none_match: Vec<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Negotiation {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        Outcome::Success(Negotiation {
+            encoding: headers
+                .get_one("Accept-Encoding")
+                .and_then(Encoding::negotiate),
+            if_none_match: headers
+                .get("If-None-Match")
+                .flat_map(|value| value.split(','))
+                .map(|tag| tag.trim().to_string())
+                .collect(),
+        })
+    }
+}
+
+impl Negotiation {
+    /// Respond with the data loaded by `load`, identified by `key`.
+    ///
+    /// The cached data never changes once imported, so the ETag is derived from the request
+    /// rather than the data. A client revalidating with `If-None-Match` gets a `304 Not Modified`
+    /// without the data being loaded. Otherwise the data is loaded and compressed on a blocking
+    /// thread to keep the large ranges off the async workers.
+    async fn respond(
+        self,
+        key: String,
+        db: CacheDB,
+        load: impl FnOnce(&CacheDB) -> Result<Vec<u8>, ApiError> + Send + 'static,
+    ) -> Result<Encoded, ApiError> {
+        let etag = match self.encoding {
+            Some(encoding) => format!("\"{}-{}\"", key, encoding.name()),
+            None => format!("\"{}\"", key),
+        };
+        if self.if_none_match.contains(&etag) {
+            return Ok(Encoded::NotModified { etag });
+        }
+        let encoding = self.encoding;
+        tokio::task::spawn_blocking(move || {
+            let data = load(&db)?;
+            let encoding = encoding.filter(|_| data.len() >= MIN_COMPRESS_SIZE);
+            let body = match encoding {
+                Some(encoding) => encoding.encode(&data).map_err(|err| {
+                    log::error!("Failed to compress the response: {}", err);
+                    (Status::InternalServerError, "Compression error".into())
+                })?,
+                None => data,
+            };
+            Ok(Encoded::Data {
+                etag,
+                encoding,
+                body,
+            })
+        })
+        .await
+        .map_err(|err| {
+            log::error!("Failed to load the response: {}", err);
+            (Status::InternalServerError, "Internal error".into())
+        })?
+    }
+}
+
+/// A SCALE encoded response, compressed as the client accepts and tagged with an ETag.
+enum Encoded {
+    NotModified {
+        etag: String,
+    },
+    Data {
+        etag: String,
+        encoding: Option<Encoding>,
+        body: Vec<u8>,
+    },
+}
+
+impl<'r> Responder<'r, 'static> for Encoded {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response.header(Header::new("Vary", "Accept-Encoding"));
+        match self {
+            Encoded::NotModified { etag } => response
+                .header(Header::new("ETag", etag))
+                .status(Status::NotModified)
+                .ok(),
+            Encoded::Data {
+                etag,
+                encoding,
+                body,
+            } => {
+                if let Some(encoding) = encoding {
+                    response.header(Header::new("Content-Encoding", encoding.name()));
+                }
+                response
+                    .header(Header::new("ETag", etag))
+                    .header(ContentType::Binary)
+                    .sized_body(body.len(), Cursor::new(body))
+                    .ok()
+            }
+        }
+    }
+}
+
+type ApiError = (Status, String);
+
+fn codec_error(what: &str, block: BlockNumber) -> ApiError {
+    log::error!("Failed to decode {} at {}", what, block);
+    (Status::InternalServerError, "Codec error".into())
+}
+
+fn not_found(what: &str, block: BlockNumber) -> ApiError {
+    log::warn!("{} at {} not found", what, block);
+    (Status::NotFound, format!("{} not found", what))
+}
+
+fn too_many_blocks() -> ApiError {
+    (
+        Status::BadRequest,
+        format!("at most {} blocks can be requested at once", MAX_RANGE_LEN),
+    )
+}
+
+/// Resolve the blocks requested by the inclusive range `/range/<from>/<to>`.
+fn inclusive_range(
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<RangeInclusive<BlockNumber>, ApiError> {
+    if to < from {
+        return Err((Status::BadRequest, "empty range".into()));
+    }
+    if to - from >= MAX_RANGE_LEN {
+        return Err(too_many_blocks());
+    }
+    Ok(from..=to)
+}
+
+/// Resolve the blocks requested by `/<start>/<count>`.
+fn counted_range(start: BlockNumber, count: BlockNumber) -> Result<Range<BlockNumber>, ApiError> {
+    if count > MAX_RANGE_LEN {
+        return Err(too_many_blocks());
+    }
+    Ok(start..start.saturating_add(count))
+}
+
+/// Collect and encode the items of the blocks. Fails if any of the blocks is missing.
+fn collect_blocks<T: Decode + Encode>(
+    blocks: impl Iterator<Item = BlockNumber>,
+    what: &str,
+    get: impl Fn(BlockNumber) -> Option<Vec<u8>>,
+) -> Result<Vec<u8>, ApiError> {
+    let mut items = vec![];
+    for block in blocks {
+        let data = get(block).ok_or_else(|| not_found(what, block))?;
+        let item = T::decode(&mut &data[..]).map_err(|_| codec_error(what, block))?;
+        items.push(item);
+    }
+    log::info!("Got {} {}s", items.len(), what);
+    Ok(items.encode())
+}
+
 #[get("/genesis/<block_number>")]
-fn get_genesis(app: &State<App>, block_number: BlockNumber) -> Result<Vec<u8>, NotFound<String>> {
-    app.db
-        .get_genesis(block_number)
-        .ok_or(NotFound(format!("genesis not found")))
+async fn get_genesis(
+    app: &State<App>,
+    negotiation: Negotiation,
+    block_number: BlockNumber,
+) -> Result<Encoded, ApiError> {
+    let key = format!("genesis-{}", block_number);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            db.get_genesis(block_number)
+                .ok_or((Status::NotFound, "genesis not found".into()))
+        })
+        .await
 }
 
 #[get("/header/<block_number>")]
-fn get_header(app: &State<App>, block_number: BlockNumber) -> Result<Vec<u8>, NotFound<String>> {
-    app.db
-        .get_header(block_number)
-        .ok_or(NotFound(format!("header not found")))
+async fn get_header(
+    app: &State<App>,
+    negotiation: Negotiation,
+    block_number: BlockNumber,
+) -> Result<Encoded, ApiError> {
+    let key = format!("header-{}", block_number);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            db.get_header(block_number)
+                .ok_or((Status::NotFound, "header not found".into()))
+        })
+        .await
 }
 
 #[get("/headers/<start>")]
-fn get_headers(app: &State<App>, start: BlockNumber) -> Result<Vec<u8>, NotFound<String>> {
-    let mut headers = vec![];
-    for block in start..start + 10000 {
-        match app.db.get_header(block) {
-            Some(data) => {
-                let info = crate::cache::BlockInfo::decode(&mut &data[..])
-                    .or(Err(NotFound("Codec error".into())))?;
-                let end = info.justification.is_some();
-                headers.push(info);
-                if end {
-                    break;
+async fn get_headers(
+    app: &State<App>,
+    negotiation: Negotiation,
+    start: BlockNumber,
+) -> Result<Encoded, ApiError> {
+    let key = format!("headers-{}", start);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            let mut headers = vec![];
+            for block in start..start.saturating_add(MAX_RANGE_LEN) {
+                match db.get_header(block) {
+                    Some(data) => {
+                        let info = crate::cache::BlockInfo::decode(&mut &data[..])
+                            .map_err(|_| codec_error("header", block))?;
+                        let end = info.justification.is_some();
+                        headers.push(info);
+                        if end {
+                            break;
+                        }
+                    }
+                    None => return Err(not_found("header", block)),
                 }
             }
-            None => {
-                log::warn!("{} not found", block);
-                return Err(NotFound("header not found".into()));
-            }
-        }
-    }
-    log::info!("Got {} headers", headers.len());
-    Ok(headers.encode())
+            log::info!("Got {} headers", headers.len());
+            Ok(headers.encode())
+        })
+        .await
+}
+
+#[get("/headers/range/<from>/<to>")]
+async fn get_headers_range(
+    app: &State<App>,
+    negotiation: Negotiation,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<Encoded, ApiError> {
+    let blocks = inclusive_range(from, to)?;
+    let key = format!("headers-{}-{}", from, to);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            collect_blocks::<crate::cache::BlockInfo>(blocks, "header", |block| {
+                db.get_header(block)
+            })
+        })
+        .await
 }
 
 #[get("/parachain-headers/<start>/<count>")]
-fn get_parachain_headers(
+async fn get_parachain_headers(
     app: &State<App>,
+    negotiation: Negotiation,
     start: BlockNumber,
     count: BlockNumber,
-) -> Result<Vec<u8>, NotFound<String>> {
-    let mut headers = vec![];
-    for block in start..start + count {
-        match app.db.get_para_header(block) {
-            Some(data) => {
-                use pherry::types::Header;
-                let header =
-                    Header::decode(&mut &data[..]).or(Err(NotFound("Codec error".into())))?;
-                headers.push(header);
-            }
-            None => {
-                log::warn!("header at {} not found", block);
-                return Err(NotFound("header not found".into()));
-            }
-        }
-    }
-    log::info!("Got {} parachain headers", headers.len());
-    Ok(headers.encode())
+) -> Result<Encoded, ApiError> {
+    let blocks = counted_range(start, count)?;
+    let key = format!("parachain-headers-{}+{}", start, count);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            collect_blocks::<pherry::types::Header>(blocks, "parachain header", |block| {
+                db.get_para_header(block)
+            })
+        })
+        .await
+}
+
+#[get("/parachain-headers/range/<from>/<to>")]
+async fn get_parachain_headers_range(
+    app: &State<App>,
+    negotiation: Negotiation,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<Encoded, ApiError> {
+    let blocks = inclusive_range(from, to)?;
+    let key = format!("parachain-headers-{}-{}", from, to);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            collect_blocks::<pherry::types::Header>(blocks, "parachain header", |block| {
+                db.get_para_header(block)
+            })
+        })
+        .await
 }
 
 #[get("/storage-changes/<start>/<count>")]
-fn get_storage_changes(
+async fn get_storage_changes(
     app: &State<App>,
+    negotiation: Negotiation,
     start: BlockNumber,
     count: BlockNumber,
-) -> Result<Vec<u8>, NotFound<String>> {
-    let mut changes = vec![];
-    for block in start..start + count {
-        match app.db.get_storage_changes(block) {
-            Some(data) => {
-                let header = crate::cache::BlockHeaderWithChanges::decode(&mut &data[..])
-                    .or(Err(NotFound("Codec error".into())))?;
-                changes.push(header);
-            }
-            None => {
-                log::warn!("changes at {} not found", block);
-                return Err(NotFound("header not found".into()));
-            }
-        }
-    }
-    log::info!("Got {} storage changes", changes.len());
-    Ok(changes.encode())
+) -> Result<Encoded, ApiError> {
+    let blocks = counted_range(start, count)?;
+    let key = format!("storage-changes-{}+{}", start, count);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            collect_blocks::<crate::cache::BlockHeaderWithChanges>(
+                blocks,
+                "storage change",
+                |block| db.get_storage_changes(block),
+            )
+        })
+        .await
 }
 
-pub(crate) async fn serve(db: &str) -> anyhow::Result<()> {
-    let _rocket = rocket::build()
-        .manage(App {
-            db: CacheDB::open(db)?,
+#[get("/storage-changes/range/<from>/<to>")]
+async fn get_storage_changes_range(
+    app: &State<App>,
+    negotiation: Negotiation,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<Encoded, ApiError> {
+    let blocks = inclusive_range(from, to)?;
+    let key = format!("storage-changes-{}-{}", from, to);
+    negotiation
+        .respond(key, app.db.clone(), move |db| {
+            collect_blocks::<crate::cache::BlockHeaderWithChanges>(
+                blocks,
+                "storage change",
+                |block| db.get_storage_changes(block),
+            )
         })
+        .await
+}
+
+/// The import progress of the cached data.
+#[get("/status")]
+fn get_status(app: &State<App>) -> Result<(ContentType, String), Status> {
+    let metadata = app
+        .db
+        .get_metadata()
+        .map_err(|err| {
+            log::error!("Failed to read metadata: {}", err);
+            Status::InternalServerError
+        })?
+        .unwrap_or_default();
+    let body = serde_json::to_string(&metadata).or(Err(Status::InternalServerError))?;
+    Ok((ContentType::JSON, body))
+}
+
+pub(crate) async fn serve(db: CacheDB) -> anyhow::Result<()> {
+    let _rocket = rocket::build()
+        .manage(App { db })
         .mount(
             "/",
             routes![
                 get_genesis,
                 get_header,
                 get_headers,
+                get_headers_range,
                 get_parachain_headers,
+                get_parachain_headers_range,
                 get_storage_changes,
+                get_storage_changes_range,
+                get_status,
             ],
         )
         .attach(phala_rocket_middleware::TimeMeter)
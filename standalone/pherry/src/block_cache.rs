@@ -0,0 +1,155 @@
+//! In-memory cache of the chain data shared by the workers in multi-worker mode.
+//!
+//! The workers driven by the same pherry process are usually syncing the same blocks at the same
+//! time. Each item is fetched from the nodes only once, and the concurrent requests of the same
+//! item wait for the first one to finish. The cache is disabled unless `enable` is called.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use phactory_api::blocks::BlockHeaderWithChanges;
+use tokio::sync::OnceCell;
+
+use crate::types::{Block, BlockNumber, Header};
+
+pub static BLOCK_CACHE: Lazy<BlockCache> = Lazy::new(BlockCache::default);
+
+#[derive(Default)]
+pub struct BlockCache {
+    /// Max number of items of each kind to keep, 0 to disable the cache
+    capacity: AtomicUsize,
+    blocks: Table<Block>,
+    para_headers: Table<Header>,
+    storage_changes: Table<BlockHeaderWithChanges>,
+}
+
+struct Table<T> {
+    items: Mutex<BTreeMap<BlockNumber, Arc<OnceCell<T>>>>,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            items: Default::default(),
+        }
+    }
+}
+
+impl<T: Clone> Table<T> {
+    /// The cell of the given block. The lowest blocks are evicted when the table is full.
+    fn cell(&self, number: BlockNumber, capacity: usize) -> Arc<OnceCell<T>> {
+        let mut items = self.items.lock().unwrap();
+        let cell = items.entry(number).or_default().clone();
+        while items.len() > capacity {
+            let lowest = *items.keys().next().expect("Never empty here");
+            items.remove(&lowest);
+        }
+        cell
+    }
+
+    /// Get the item from the cache, or fetch it if missing. A `None` fetched is not cached.
+    async fn get_or_fetch<Fut>(
+        &self,
+        number: BlockNumber,
+        capacity: usize,
+        fetch: Fut,
+    ) -> Result<Option<T>>
+    where
+        Fut: Future<Output = Result<Option<T>>>,
+    {
+        if capacity == 0 {
+            return fetch.await;
+        }
+        let cell = self.cell(number, capacity);
+        let result = cell
+            .get_or_try_init(|| async {
+                match fetch.await {
+                    Ok(Some(item)) => Ok(item),
+                    Ok(None) => Err(None),
+                    Err(err) => Err(Some(err)),
+                }
+            })
+            .await;
+        match result {
+            Ok(item) => Ok(Some(item.clone())),
+            Err(None) => Ok(None),
+            Err(Some(err)) => Err(err),
+        }
+    }
+}
+
+impl BlockCache {
+    /// Enable the cache, keeping at most `capacity` items of each kind.
+    pub fn enable(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    pub async fn block<Fut>(&self, number: BlockNumber, fetch: Fut) -> Result<Block>
+    where
+        Fut: Future<Output = Result<Block>>,
+    {
+        let block = self
+            .blocks
+            .get_or_fetch(number, self.capacity(), async { fetch.await.map(Some) })
+            .await?;
+        block.ok_or_else(|| anyhow!("Block {number} not fetched"))
+    }
+
+    /// Get the parachain header. The fetcher returns `None` if the header is not yet available.
+    pub async fn para_header<Fut>(&self, number: BlockNumber, fetch: Fut) -> Result<Option<Header>>
+    where
+        Fut: Future<Output = Result<Option<Header>>>,
+    {
+        self.para_headers
+            .get_or_fetch(number, self.capacity(), fetch)
+            .await
+    }
+
+    /// Get the storage changes of blocks `from..=to`.
+    ///
+    /// On a cache miss, the fetcher is called with the range from the missing block to `to`, and
+    /// all the fetched blocks are cached.
+    pub async fn storage_changes<F, Fut>(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+        fetch: F,
+    ) -> Result<Vec<BlockHeaderWithChanges>>
+    where
+        F: Fn(BlockNumber, BlockNumber) -> Fut,
+        Fut: Future<Output = Result<Vec<BlockHeaderWithChanges>>>,
+    {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return fetch(from, to).await;
+        }
+        let mut changes = vec![];
+        for number in from..=to {
+            let table = &self.storage_changes;
+            let fetch_rest = async {
+                let mut fetched = fetch(number, to).await?.into_iter();
+                let first = fetched.next();
+                for (offset, item) in fetched.enumerate() {
+                    let _ = table
+                        .cell(number + 1 + offset as BlockNumber, capacity)
+                        .set(item);
+                }
+                Ok::<_, anyhow::Error>(first)
+            };
+            let item = table
+                .get_or_fetch(number, capacity, fetch_rest)
+                .await?
+                .ok_or_else(|| anyhow!("Storage changes of {number} not fetched"))?;
+            changes.push(item);
+        }
+        Ok(changes)
+    }
+}
@@ -5,7 +5,9 @@ use phaxt::{
     subxt::{self, rpc::NumberOrHex},
     BlockNumber, Header, ParachainApi, RelaychainApi,
 };
+use std::collections::VecDeque;
 use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
 
 use log::{error, info, warn};
 
@@ -162,12 +164,61 @@ pub async fn get_set_id(api: &RelaychainApi, block: BlockNumber) -> Result<(u64,
     Ok((set_id, block.justifications.is_some()))
 }
 
-async fn grab_headers(
+/// Decides at which blocks the justifications are fetched while grabbing the headers.
+///
+/// Fetching a justification takes a full block request, so it is only done once the preferred
+/// interval has passed since the last justification, and then at each block until one is found.
+/// Keep the countdown across the calls of [`grab_headers_with`] when grabbing the headers batch
+/// by batch, otherwise it restarts for each batch.
+#[derive(Debug, Clone)]
+pub struct JustificationCountdown {
+    interval: BlockNumber,
+    remaining: BlockNumber,
+}
+
+impl JustificationCountdown {
+    pub fn new(interval: BlockNumber) -> Self {
+        Self {
+            interval,
+            remaining: interval,
+        }
+    }
+
+    /// Whether to fetch the justification of the next block.
+    pub fn due(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Count down a grabbed block.
+    pub fn on_block(&mut self, has_justification: bool) {
+        if has_justification {
+            self.remaining = self.interval;
+        } else {
+            self.remaining = self.remaining.saturating_sub(1);
+        }
+    }
+}
+
+/// Grab relaychain headers with justifications and authority set changes from the chain.
+pub async fn grab_headers(
     api: &RelaychainApi,
     para_api: &ParachainApi,
     start_at: BlockNumber,
     count: BlockNumber,
     justification_interval: u32,
+    f: impl FnMut(BlockInfo) -> Result<()>,
+) -> Result<BlockNumber> {
+    let mut countdown = JustificationCountdown::new(justification_interval);
+    grab_headers_with(api, para_api, start_at, count, &mut countdown, f).await
+}
+
+/// Same as [`grab_headers`], but continues the justification countdown of the previous calls.
+pub async fn grab_headers_with(
+    api: &RelaychainApi,
+    para_api: &ParachainApi,
+    start_at: BlockNumber,
+    count: BlockNumber,
+    countdown: &mut JustificationCountdown,
     mut f: impl FnMut(BlockInfo) -> Result<()>,
 ) -> Result<BlockNumber> {
     if start_at == 0 {
@@ -179,7 +230,6 @@ async fn grab_headers(
 
     let header_hash = crate::get_header_hash(api, Some(start_at - 1)).await?;
     let mut last_set = api.current_set_id(Some(header_hash)).await?;
-    let mut skip_justitication = justification_interval;
     let mut grabbed = 0;
 
     let para_id = para_api.get_paraid(None).await?;
@@ -189,7 +239,7 @@ async fn grab_headers(
         let header;
         let justifications;
         let hash;
-        if skip_justitication == 0 {
+        if countdown.due() {
             let (block, header_hash) = match crate::get_block_at(api, Some(block_number)).await {
                 Ok(x) => x,
                 Err(e) => {
@@ -242,13 +292,12 @@ async fn grab_headers(
             .map(|v| v.into_justification(GRANDPA_ENGINE_ID))
             .flatten();
 
-        skip_justitication = skip_justitication.saturating_sub(1);
+        countdown.on_block(justification.is_some());
         last_set = set_id;
 
         let para_header = if justification.is_none() {
             None
         } else {
-            skip_justitication = justification_interval;
             crate::get_finalized_header_with_paraid(api, para_id, hash).await?
         };
 
@@ -271,7 +320,8 @@ async fn grab_headers(
     Ok(grabbed)
 }
 
-async fn grab_para_headers(
+/// Grab parachain headers from the chain.
+pub async fn grab_para_headers(
     api: &ParachainApi,
     start_at: BlockNumber,
     count: BlockNumber,
@@ -299,7 +349,8 @@ async fn grab_para_headers(
     Ok(grabbed)
 }
 
-async fn grab_storage_changes(
+/// Grab storage changes of parachain blocks from the chain.
+pub async fn grab_storage_changes(
     api: &ParachainApi,
     start_at: BlockNumber,
     count: BlockNumber,
@@ -348,35 +399,148 @@ pub async fn fetch_genesis_info(
     })
 }
 
+/// The headers-cache server responded with a non-success status.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: u16,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to fetch data from cache with status={}",
+            self.status
+        )
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Whether the error is caused by the requested data missing in the cache.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<HttpError>(),
+        Some(HttpError { status: 404 })
+    )
+}
+
+#[derive(serde::Deserialize, Default)]
+struct CacheStatus {
+    #[serde(default)]
+    higest: CacheCounters,
+}
+
+#[derive(serde::Deserialize, Default)]
+struct CacheCounters {
+    storage_changes: Option<BlockNumber>,
+}
+
+/// Max number of blocks the cache server returns in a range query.
+const MAX_RANGE_LEN: BlockNumber = 10000;
+
+/// Number of recent responses kept to revalidate with their ETags.
+const ETAG_CACHE_SIZE: usize = 8;
+
+/// A response body kept to be reused when the server replies `304 Not Modified`.
+struct CachedResponse {
+    url: String,
+    etag: reqwest::header::HeaderValue,
+    zstd_encoded: bool,
+    body: Vec<u8>,
+}
+
 #[derive(Clone)]
 pub struct Client {
     base_uri: String,
+    etag_cache: Arc<Mutex<VecDeque<CachedResponse>>>,
 }
 
 impl Client {
     pub fn new(uri: &str) -> Self {
         Self {
             base_uri: uri.to_string(),
+            etag_cache: Default::default(),
         }
     }
 
+    fn cached_response(&self, url: &str) -> Option<(reqwest::header::HeaderValue, bool, Vec<u8>)> {
+        let cache = self.etag_cache.lock().unwrap();
+        cache.iter().find(|cached| cached.url == url).map(|cached| {
+            (
+                cached.etag.clone(),
+                cached.zstd_encoded,
+                cached.body.clone(),
+            )
+        })
+    }
+
+    fn cache_response(&self, response: CachedResponse) {
+        let mut cache = self.etag_cache.lock().unwrap();
+        cache.retain(|cached| cached.url != response.url);
+        if cache.len() >= ETAG_CACHE_SIZE {
+            cache.pop_front();
+        }
+        cache.push_back(response);
+    }
+
     async fn request<T: Decode>(&self, url: &str) -> Result<T> {
-        let response = reqwest::get(url).await.map_err(|err| {
+        let cached = self.cached_response(url);
+        let mut request = reqwest::Client::new()
+            .get(url)
+            .header(reqwest::header::ACCEPT_ENCODING, "zstd");
+        if let Some((etag, _, _)) = &cached {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag.clone());
+        }
+        let response = request.send().await.map_err(|err| {
             warn!("Failed to fetch data from cache: {err}");
             err
         })?;
         let status = response.status();
         info!("Requested cache from {url} ({})", status.as_u16());
-        if !status.is_success() {
-            anyhow::bail!(
-                "Failed to fetch data from cache with status={}",
-                status.as_u16()
-            );
-        }
-        let body = response.bytes().await.map_err(|err| {
-            error!("Failed to read cache response: {err}");
-            err
-        })?;
+        let (zstd_encoded, body) = match cached {
+            Some((_, zstd_encoded, body)) if status == reqwest::StatusCode::NOT_MODIFIED => {
+                (zstd_encoded, body)
+            }
+            _ => {
+                if !status.is_success() {
+                    return Err(HttpError {
+                        status: status.as_u16(),
+                    }
+                    .into());
+                }
+                let zstd_encoded = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_ENCODING)
+                    .map_or(false, |encoding| encoding == "zstd");
+                let etag = response.headers().get(reqwest::header::ETAG).cloned();
+                let body = response
+                    .bytes()
+                    .await
+                    .map_err(|err| {
+                        error!("Failed to read cache response: {err}");
+                        err
+                    })?
+                    .to_vec();
+                if let Some(etag) = etag {
+                    self.cache_response(CachedResponse {
+                        url: url.to_string(),
+                        etag,
+                        zstd_encoded,
+                        body: body.clone(),
+                    });
+                }
+                (zstd_encoded, body)
+            }
+        };
+        let body = if zstd_encoded {
+            zstd::stream::decode_all(&body[..]).map_err(|err| {
+                error!("Failed to decompress cache response: {err}");
+                err
+            })?
+        } else {
+            body
+        };
         let decoded = T::decode(&mut &body[..]).map_err(|err| {
             error!("Failed to decode cache response: {err}");
             err
@@ -384,33 +548,63 @@ impl Client {
         Ok(decoded)
     }
 
+    /// Request the items of the blocks `from..=to`, split into ranges the server accepts.
+    async fn request_range<T: Decode>(
+        &self,
+        path: &str,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<T>> {
+        let mut items = vec![];
+        let mut start = from;
+        while start <= to {
+            let end = to.min(start.saturating_add(MAX_RANGE_LEN - 1));
+            let url = format!("{}/{}/range/{}/{}", self.base_uri, path, start, end);
+            items.extend(self.request::<Vec<T>>(&url).await?);
+            if end == BlockNumber::MAX {
+                break;
+            }
+            start = end + 1;
+        }
+        Ok(items)
+    }
+
     pub async fn get_headers(&self, block_number: BlockNumber) -> Result<Vec<BlockInfo>> {
         let url = format!("{}/headers/{}", self.base_uri, block_number);
         self.request(&url).await
     }
 
+    /// Get the parachain headers of the blocks `from..=to`.
     pub async fn get_parachain_headers(
         &self,
-        start_number: BlockNumber,
-        count: BlockNumber,
+        from: BlockNumber,
+        to: BlockNumber,
     ) -> Result<Vec<Header>> {
-        let url = format!(
-            "{}/parachain-headers/{}/{}",
-            self.base_uri, start_number, count
-        );
-        self.request(&url).await
+        self.request_range("parachain-headers", from, to).await
     }
 
+    /// Get the storage changes of the blocks `from..=to`.
     pub async fn get_storage_changes(
         &self,
-        start_number: BlockNumber,
-        count: BlockNumber,
+        from: BlockNumber,
+        to: BlockNumber,
     ) -> Result<Vec<BlockHeaderWithChanges>> {
-        let url = format!(
-            "{}/storage-changes/{}/{}",
-            self.base_uri, start_number, count
-        );
-        self.request(&url).await
+        self.request_range("storage-changes", from, to).await
+    }
+
+    /// The highest block whose storage changes have been imported into the cache.
+    pub async fn get_highest_storage_changes(&self) -> Result<Option<BlockNumber>> {
+        let url = format!("{}/status", self.base_uri);
+        let response = reqwest::get(&url).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(HttpError {
+                status: status.as_u16(),
+            }
+            .into());
+        }
+        let status: CacheStatus = serde_json::from_slice(&response.bytes().await?)?;
+        Ok(status.higest.storage_changes)
     }
 
     pub async fn get_genesis(&self, block_number: BlockNumber) -> Result<GenesisBlockInfo> {
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use log::{debug, error, info, warn};
-use phala_pallets::pallet_registry::Attestation;
+use phala_pallets::pallet_registry::{Attestation, SgxQuoteCollateral};
 use sp_core::crypto::AccountId32;
 use sp_runtime::generic::Era;
 use std::cmp;
@@ -14,11 +14,14 @@ use phaxt::{subxt, RpcClient};
 use sp_core::{crypto::Pair, sr25519};
 use sp_finality_grandpa::{AuthorityList, SetId, VersionedAuthorityList, GRANDPA_AUTHORITIES_KEY};
 
+mod block_cache;
 mod endpoint;
 mod error;
+mod metrics;
 mod msg_sync;
 mod notify_client;
 mod prefetcher;
+mod verify;
 
 pub mod chain_client;
 pub mod headers_cache;
@@ -43,7 +46,7 @@ use notify_client::NotifyClient;
 
 pub use phaxt::connect as subxt_connect;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(
     about = "Sync messages between pruntime and the blockchain.",
     version,
@@ -98,21 +101,23 @@ pub struct Args {
     #[clap(
         default_value = "ws://localhost:9944",
         long,
-        help = "Substrate rpc websocket endpoint"
+        value_delimiter = ',',
+        help = "Substrate rpc websocket endpoint. Multiple comma separated endpoints of the same chain can be given to fail over between them"
     )]
-    substrate_ws_endpoint: String,
+    substrate_ws_endpoint: Vec<String>,
 
     #[clap(
         default_value = "ws://localhost:9977",
         long,
-        help = "Parachain collator rpc websocket endpoint"
+        value_delimiter = ',',
+        help = "Parachain collator rpc websocket endpoint. Multiple comma separated endpoints of the same chain can be given to fail over between them"
     )]
-    collator_ws_endpoint: String,
+    collator_ws_endpoint: Vec<String>,
 
     #[clap(
         default_value = "http://localhost:8000",
         long,
-        help = "pRuntime http endpoint"
+        help = "pRuntime http endpoint. Multiple comma separated endpoints can be given to drive several workers in one process, sharing the blocks fetched from the chain"
     )]
     pruntime_endpoint: String,
 
@@ -129,7 +134,7 @@ pub struct Args {
         default_value = "//Alice",
         short = 'm',
         long = "mnemonic",
-        help = "Controller SR25519 private key mnemonic, private key seed, or derive path"
+        help = "Controller SR25519 private key mnemonic, private key seed, or derive path. In multi-worker mode, comma separated keys must be given for each of the pRuntime endpoints"
     )]
     mnemonic: String,
 
@@ -190,6 +195,18 @@ pub struct Args {
         help = "Max number of messages to be submitted per-round"
     )]
     max_sync_msgs_per_round: u64,
+    #[clap(
+        default_value = "1",
+        long,
+        help = "Max number of messages to be batched in a single extrinsic, at most 100 are accepted by the chain. Messages are submitted one by one if set to 1"
+    )]
+    max_msgs_per_tx: u64,
+    #[clap(
+        default_value = "65536",
+        long,
+        help = "Max total encoded size of the messages batched in a single extrinsic, unit: byte"
+    )]
+    max_msgs_bytes_per_tx: usize,
 
     #[clap(long, help = "Auto restart self after an error occurred")]
     auto_restart: bool,
@@ -217,6 +234,25 @@ pub struct Args {
         help = "Disable syncing waiting parachain blocks in the beginning of each round"
     )]
     disable_sync_waiting_paraheaders: bool,
+
+    #[clap(
+        long,
+        help = "Serve the Prometheus metrics at http://<METRICS_ADDR>/metrics, e.g. 0.0.0.0:9615"
+    )]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    #[clap(
+        default_value = "4000",
+        long,
+        help = "Max number of blocks of each kind kept in memory to share between the workers. Only used in multi-worker mode"
+    )]
+    shared_cache_size: usize,
+
+    #[clap(
+        long,
+        help = "Feed the blocks to a pRuntime restored from a checkpoint without submitting anything, and compare its egress messages with the ones submitted on chain by the production worker. Use with --to-block to verify a block range"
+    )]
+    verify_only: bool,
 }
 
 struct RunningFlags {
@@ -276,9 +312,15 @@ pub async fn get_header_at<T: subxt::Config>(
 }
 
 async fn get_block_without_storage_changes(api: &RelaychainApi, h: Option<u32>) -> Result<Block> {
-    let (block, hash) = get_block_at(api, h).await?;
-    info!("get_block: Got block {:?} hash {}", h, hash.to_string());
-    return Ok(block);
+    let fetch = async {
+        let (block, hash) = get_block_at(api, h).await?;
+        info!("get_block: Got block {:?} hash {}", h, hash.to_string());
+        Ok::<_, anyhow::Error>(block)
+    };
+    match h {
+        Some(number) => block_cache::BLOCK_CACHE.block(number, fetch).await,
+        None => fetch.await,
+    }
 }
 
 pub async fn fetch_storage_changes(
@@ -287,13 +329,25 @@ pub async fn fetch_storage_changes(
     from: BlockNumber,
     to: BlockNumber,
 ) -> Result<Vec<BlockHeaderWithChanges>> {
-    log::info!("fetch_storage_changes ({from}-{to})");
     if to < from {
         return Ok(vec![]);
     }
+    block_cache::BLOCK_CACHE
+        .storage_changes(from, to, |from, to| {
+            fetch_storage_changes_uncached(client, cache, from, to)
+        })
+        .await
+}
+
+async fn fetch_storage_changes_uncached(
+    client: &RpcClient,
+    cache: Option<&CacheClient>,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<Vec<BlockHeaderWithChanges>> {
+    log::info!("fetch_storage_changes ({from}-{to})");
     if let Some(cache) = cache {
-        let count = to + 1 - from;
-        if let Ok(changes) = cache.get_storage_changes(from, count).await {
+        if let Ok(changes) = cache.get_storage_changes(from, to).await {
             log::info!(
                 "Got {} storage changes from cache server ({from}-{to})",
                 changes.len()
@@ -344,6 +398,7 @@ pub async fn batch_sync_storage_changes(
         let storage_changes = fetcher.fetch_storage_changes(api, cache, from, to).await?;
         let r = req_dispatch_block(pr, storage_changes).await?;
         log::debug!("  ..dispatch_block: {:?}", r);
+        verify::check_egress(pr, api, r.synced_to).await?;
     }
     Ok(())
 }
@@ -445,7 +500,9 @@ async fn req_dispatch_block(
     pr: &PrClient,
     blocks: Vec<BlockHeaderWithChanges>,
 ) -> Result<prpc::SyncedTo> {
+    let count = blocks.len();
     let resp = pr.dispatch_blocks(prpc::Blocks::new(blocks)).await?;
+    metrics::METRICS.blocks_dispatched.inc_by(count as _);
     Ok(resp)
 }
 
@@ -751,9 +808,8 @@ async fn sync_parachain_header(
         return Ok(next_headernum - 1);
     }
     let mut para_headers = if let Some(cache) = cache {
-        let count = para_fin_block_number - next_headernum + 1;
         cache
-            .get_parachain_headers(next_headernum, count)
+            .get_parachain_headers(next_headernum, para_fin_block_number)
             .await
             .unwrap_or_default()
     } else {
@@ -762,22 +818,27 @@ async fn sync_parachain_header(
     if para_headers.is_empty() {
         info!("parachain headers not found in cache");
         for b in next_headernum..=para_fin_block_number {
-            info!("fetching parachain header {}", b);
-            let num = subxt::rpc::BlockNumber::from(NumberOrHex::Number(b.into()));
-            let hash = para_api.rpc().block_hash(Some(num)).await?;
-            let hash = match hash {
-                Some(hash) => hash,
+            let fetch = async {
+                info!("fetching parachain header {}", b);
+                let num = subxt::rpc::BlockNumber::from(NumberOrHex::Number(b.into()));
+                let hash = match para_api.rpc().block_hash(Some(num)).await? {
+                    Some(hash) => hash,
+                    None => return Ok(None),
+                };
+                let header = para_api
+                    .rpc()
+                    .header(Some(hash))
+                    .await?
+                    .ok_or(Error::BlockNotFound)?;
+                Ok::<_, anyhow::Error>(Some(header))
+            };
+            match block_cache::BLOCK_CACHE.para_header(b, fetch).await? {
+                Some(header) => para_headers.push(header),
                 None => {
                     info!("Hash not found for block {}, fetch it next turn", b);
                     return Ok(next_headernum - 1);
                 }
-            };
-            let header = para_api
-                .rpc()
-                .header(Some(hash))
-                .await?
-                .ok_or(Error::BlockNotFound)?;
-            para_headers.push(header);
+            }
         }
     } else {
         info!("Got {} parachain headers from cache", para_headers.len());
@@ -880,10 +941,17 @@ async fn register_worker(
     let payload = attestation
         .payload
         .ok_or(anyhow!("Missing attestation payload"))?;
-    let attestation = Attestation::SgxIas {
-        ra_report: payload.report.as_bytes().to_vec(),
-        signature: payload.signature,
-        raw_signing_cert: payload.signing_cert,
+    let attestation = match attestation.provider.as_str() {
+        "SGX-DCAP" => Attestation::SgxDcap {
+            quote: payload.signature,
+            collateral: SgxQuoteCollateral::decode(&mut &payload.signing_cert[..])
+                .map_err(|_| anyhow!("Invalid DCAP collateral"))?,
+        },
+        _ => Attestation::SgxIas {
+            ra_report: payload.report.as_bytes().to_vec(),
+            signature: payload.signature,
+            raw_signing_cert: payload.signing_cert,
+        },
     };
     chain_client::update_signer_nonce(para_api, signer).await?;
     let params = mk_params(para_api, args.longevity, args.tip).await?;
@@ -949,19 +1017,19 @@ async fn bridge(
 ) -> Result<()> {
     // Connect to substrate
 
-    let api: RelaychainApi = subxt_connect(&args.substrate_ws_endpoint).await?;
-    info!("Connected to relaychain at: {}", args.substrate_ws_endpoint);
+    let api: RelaychainApi = phaxt::connect_endpoints(&args.substrate_ws_endpoint).await?;
+    info!(
+        "Connected to relaychain at: {:?}",
+        args.substrate_ws_endpoint
+    );
 
-    let para_uri: &str = if args.parachain {
+    let para_uris = if args.parachain {
         &args.collator_ws_endpoint
     } else {
         &args.substrate_ws_endpoint
     };
-    let para_api: ParachainApi = subxt_connect(para_uri).await?;
-    info!(
-        "Connected to parachain node at: {}",
-        args.collator_ws_endpoint
-    );
+    let para_api: ParachainApi = phaxt::connect_endpoints(para_uris).await?;
+    info!("Connected to parachain node at: {:?}", para_uris);
 
     if !args.no_wait {
         // Don't start our worker until the substrate node is synced
@@ -997,6 +1065,15 @@ async fn bridge(
             Some(parsed_operator)
         }
     };
+    if args.verify_only {
+        if !info.initialized {
+            return Err(anyhow!(
+                "The pRuntime to verify must be restored from a checkpoint"
+            ));
+        }
+        let worker = info.public_key.as_deref().and_then(verify::worker_origin);
+        verify::enable(&para_api, info.blocknum - 1, worker).await?;
+    }
     if !args.no_init {
         if !info.initialized {
             warn!("pRuntime not initialized. Requesting init...");
@@ -1079,9 +1156,15 @@ async fn bridge(
         // update the latest pRuntime state
         let info = pr.get_info(()).await?;
         info!("pRuntime get_info response: {:#?}", info);
+        metrics::METRICS.observe_sync_progress(info.headernum, info.para_headernum, info.blocknum);
+        if args.metrics_addr.is_some() {
+            if let Err(err) = update_chain_tips(&api, &para_api).await {
+                warn!("Failed to update the chain tips: {:?}", err);
+            }
+        }
         if info.blocknum >= args.to_block {
             info!("Reached target block: {}", args.to_block);
-            return Ok(());
+            return verify::finish().await;
         }
 
         // STATUS: header_synced = info.headernum
@@ -1266,7 +1349,11 @@ async fn bridge(
                     &mut signer,
                     args.tip,
                     args.longevity,
-                    args.max_sync_msgs_per_round,
+                    &msg_sync::SyncLimits {
+                        max_msgs_per_round: args.max_sync_msgs_per_round,
+                        max_msgs_per_tx: args.max_msgs_per_tx,
+                        max_bytes_per_tx: args.max_msgs_bytes_per_tx,
+                    },
                     err_report.clone(),
                 )
                 .await?;
@@ -1289,12 +1376,28 @@ async fn bridge(
     Ok(())
 }
 
+async fn update_chain_tips(api: &RelaychainApi, para_api: &ParachainApi) -> Result<()> {
+    let relay_tip = get_header_at(api, None).await?.0.number;
+    let para_tip = get_header_at(para_api, None).await?.0.number;
+    metrics::METRICS.relaychain_finalized.set(relay_tip as _);
+    metrics::METRICS.parachain_finalized.set(para_tip as _);
+    Ok(())
+}
+
 fn preprocess_args(args: &mut Args) {
     if args.dev {
         args.ra = false;
         args.use_dev_key = true;
         args.mnemonic = String::from("//Alice");
     }
+    if args.verify_only {
+        args.no_register = true;
+        args.no_bind = true;
+        args.no_msg_submit = true;
+        args.auto_restart = false;
+        // Check the egress messages after each block, before they are dropped by pRuntime
+        args.sync_blocks = 1;
+    }
     if args.longevity > 0 {
         assert!(args.longevity >= 4, "Option --longevity must be 0 or >= 4.");
         assert_eq!(
@@ -1303,6 +1406,43 @@ fn preprocess_args(args: &mut Args) {
             "Option --longevity must be power of two."
         );
     }
+    let workers = args.pruntime_endpoint.split(',').count();
+    if workers > 1 {
+        // The workers submit transactions concurrently, so they can't share the nonce of a key.
+        assert_eq!(
+            args.mnemonic.split(',').count(),
+            workers,
+            "Option --mnemonic must give one key for each pRuntime endpoint."
+        );
+        assert!(
+            args.next_pruntime_endpoint.is_none(),
+            "Option --next-pruntime-endpoint can not be used with multiple pRuntime endpoints."
+        );
+        assert!(
+            !args.use_dev_key && args.inject_key.is_empty(),
+            "Can not inject the same key to multiple pRuntimes."
+        );
+        assert!(
+            !args.verify_only,
+            "Option --verify-only can not be used with multiple pRuntime endpoints."
+        );
+    }
+}
+
+/// Split the args of each worker in multi-worker mode.
+fn worker_args(args: &Args) -> Vec<Args> {
+    if !args.pruntime_endpoint.contains(',') {
+        return vec![args.clone()];
+    }
+    args.pruntime_endpoint
+        .split(',')
+        .zip(args.mnemonic.split(','))
+        .map(|(endpoint, mnemonic)| Args {
+            pruntime_endpoint: endpoint.trim().to_string(),
+            mnemonic: mnemonic.trim().to_string(),
+            ..args.clone()
+        })
+        .collect()
 }
 
 async fn collect_async_errors(
@@ -1311,7 +1451,11 @@ async fn collect_async_errors(
 ) {
     let threshold_bak = threshold.unwrap_or_default();
     loop {
-        match err_receiver.recv().await {
+        let error = err_receiver.recv().await;
+        if error.is_some() {
+            metrics::METRICS.rpc_errors.with_label_values(&["tx"]).inc();
+        }
+        match error {
             Some(error) => match error {
                 MsgSyncError::BadSignature => {
                     warn!("tx received bad signature, restarting...");
@@ -1383,7 +1527,47 @@ pub async fn pherry_main() {
 
     let mut args = Args::parse();
     preprocess_args(&mut args);
+    if let Some(addr) = args.metrics_addr {
+        metrics::serve(addr);
+    }
+
+    let mut workers = worker_args(&args);
+    if workers.len() == 1 {
+        let args = workers.remove(0);
+        let worker = args.pruntime_endpoint.clone();
+        if let Some(code) = metrics::WORKER.scope(worker, run_worker(args)).await {
+            std::process::exit(code);
+        }
+        return;
+    }
+
+    // Drive the workers concurrently. They share the blocks fetched from the chain, but run into
+    // errors and restart independently.
+    info!("Driving {} workers", workers.len());
+    block_cache::BLOCK_CACHE.enable(args.shared_cache_size);
+    let results = futures::future::join_all(workers.into_iter().map(|args| {
+        let worker = args.pruntime_endpoint.clone();
+        metrics::WORKER.scope(
+            worker.clone(),
+            async move { (worker, run_worker(args).await) },
+        )
+    }))
+    .await;
+    let mut failed = false;
+    for (worker, code) in results {
+        if let Some(code) = code {
+            error!("Worker {} stopped with exit code {}", worker, code);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
 
+/// Drive a pRuntime until it reaches `--to-block`, restarting on errors if `--auto-restart` is
+/// given. Returns the exit code if it gives up.
+async fn run_worker(args: Args) -> Option<i32> {
     let mut flags = RunningFlags {
         worker_registered: false,
         endpoint_registered: false,
@@ -1397,14 +1581,18 @@ pub async fn pherry_main() {
             res = bridge(&args, &mut flags, sender) => {
                 if let Err(err) = res {
                     info!("bridge() exited with error: {:?}", err);
+                    metrics::METRICS
+                        .rpc_errors
+                        .with_label_values(&["sync"])
+                        .inc();
                 } else {
-                    break;
+                    return None;
                 }
             }
             () = collect_async_errors(threshold, receiver) => ()
         };
         if !args.auto_restart || flags.restart_failure_count > args.max_restart_retries {
-            std::process::exit(if flags.worker_registered { 1 } else { 2 });
+            return Some(if flags.worker_registered { 1 } else { 2 });
         }
         flags.restart_failure_count += 1;
         sleep(Duration::from_secs(2)).await;
@@ -0,0 +1,194 @@
+//! Prometheus metrics of pherry, served at `/metrics` if `--metrics-addr` is given.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::{error, info};
+use once_cell::sync::Lazy;
+use prometheus_endpoint::{
+    register, Counter, CounterVec, Gauge, GaugeVec, Opts, PrometheusError, Registry, F64, U64,
+};
+
+use crate::types::BlockNumber;
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+tokio::task_local! {
+    /// The pRuntime endpoint of the worker driven by the current task, to label the metrics with.
+    pub static WORKER: String;
+}
+
+/// The label of the worker driven by the current task.
+pub fn worker() -> String {
+    WORKER.try_with(|worker| worker.clone()).unwrap_or_default()
+}
+
+pub static METRICS: Lazy<Metrics> =
+    Lazy::new(|| Metrics::register(&REGISTRY).expect("Failed to register the metrics"));
+
+pub struct Metrics {
+    pub relaychain_header_synced: GaugeVec<U64>,
+    pub parachain_header_synced: GaugeVec<U64>,
+    pub block_synced: GaugeVec<U64>,
+    pub relaychain_finalized: Gauge<U64>,
+    pub parachain_finalized: Gauge<U64>,
+    pub blocks_dispatched: Counter<U64>,
+    pub blocks_per_second: GaugeVec<F64>,
+    pub prefetch_hits: Counter<U64>,
+    pub prefetch_misses: Counter<U64>,
+    pending_egress_messages: GaugeVec<U64>,
+    pub tx_submitted: Counter<U64>,
+    pub tx_failed: Counter<U64>,
+    pub rpc_errors: CounterVec<U64>,
+    /// The last observed block number of each worker, to calculate the sync speed
+    last_block: Mutex<HashMap<String, (Instant, BlockNumber)>>,
+    /// The senders with pending egress messages of each worker, to clear the stale ones
+    egress_senders: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl Metrics {
+    fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        let gauge = |name: &str, help: &str| register(Gauge::new(name, help)?, registry);
+        let counter = |name: &str, help: &str| register(Counter::new(name, help)?, registry);
+        let worker_gauge = |name: &str, help: &str| {
+            register(GaugeVec::new(Opts::new(name, help), &["worker"])?, registry)
+        };
+        Ok(Self {
+            relaychain_header_synced: worker_gauge(
+                "pherry_relaychain_header_synced",
+                "The last relaychain header synced to pRuntime",
+            )?,
+            parachain_header_synced: worker_gauge(
+                "pherry_parachain_header_synced",
+                "The last parachain header synced to pRuntime",
+            )?,
+            block_synced: worker_gauge(
+                "pherry_block_synced",
+                "The last block whose storage changes are dispatched to pRuntime",
+            )?,
+            relaychain_finalized: gauge(
+                "pherry_relaychain_finalized",
+                "The finalized relaychain block number",
+            )?,
+            parachain_finalized: gauge(
+                "pherry_parachain_finalized",
+                "The finalized parachain block number",
+            )?,
+            blocks_dispatched: counter(
+                "pherry_blocks_dispatched_total",
+                "Number of blocks dispatched to pRuntime",
+            )?,
+            blocks_per_second: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "pherry_blocks_per_second",
+                        "Blocks dispatched per second since the last sync round",
+                    ),
+                    &["worker"],
+                )?,
+                registry,
+            )?,
+            prefetch_hits: counter(
+                "pherry_prefetch_hits_total",
+                "Number of storage changes requests served by the prefetcher",
+            )?,
+            prefetch_misses: counter(
+                "pherry_prefetch_misses_total",
+                "Number of storage changes requests missing the prefetcher",
+            )?,
+            pending_egress_messages: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "pherry_pending_egress_messages",
+                        "Number of egress messages not yet submitted, by sender",
+                    ),
+                    &["worker", "sender"],
+                )?,
+                registry,
+            )?,
+            tx_submitted: counter(
+                "pherry_tx_submitted_total",
+                "Number of egress message transactions accepted by the node",
+            )?,
+            tx_failed: counter(
+                "pherry_tx_failed_total",
+                "Number of egress message transactions failed or timed out",
+            )?,
+            rpc_errors: register(
+                CounterVec::new(
+                    Opts::new("pherry_rpc_errors_total", "Number of RPC errors, by source"),
+                    &["source"],
+                )?,
+                registry,
+            )?,
+            last_block: Default::default(),
+            egress_senders: Default::default(),
+        })
+    }
+
+    /// Update the sync progress of the current worker with the pRuntime info.
+    pub fn observe_sync_progress(
+        &self,
+        headernum: BlockNumber,
+        para_headernum: BlockNumber,
+        blocknum: BlockNumber,
+    ) {
+        let worker = worker();
+        let labels = [worker.as_str()];
+        // The pRuntime reports the next numbers to sync.
+        self.relaychain_header_synced
+            .with_label_values(&labels)
+            .set(headernum.saturating_sub(1) as _);
+        self.parachain_header_synced
+            .with_label_values(&labels)
+            .set(para_headernum.saturating_sub(1) as _);
+        self.block_synced
+            .with_label_values(&labels)
+            .set(blocknum.saturating_sub(1) as _);
+
+        let now = Instant::now();
+        let mut last_block = self.last_block.lock().unwrap();
+        if let Some((last_time, last_blocknum)) = last_block.insert(worker, (now, blocknum)) {
+            let elapsed = now.duration_since(last_time).as_secs_f64();
+            if elapsed > 0.0 {
+                let blocks = blocknum.saturating_sub(last_blocknum);
+                self.blocks_per_second
+                    .with_label_values(&labels)
+                    .set(blocks as f64 / elapsed);
+            }
+        }
+    }
+
+    /// Update the pending egress messages of the current worker, by sender.
+    pub fn observe_pending_egress(&self, pending: Vec<(String, usize)>) {
+        let worker = worker();
+        let mut egress_senders = self.egress_senders.lock().unwrap();
+        let senders = egress_senders.entry(worker.clone()).or_default();
+        for sender in senders.iter() {
+            let _ = self
+                .pending_egress_messages
+                .remove_label_values(&[worker.as_str(), sender.as_str()]);
+        }
+        senders.clear();
+        for (sender, count) in pending {
+            self.pending_egress_messages
+                .with_label_values(&[worker.as_str(), sender.as_str()])
+                .set(count as _);
+            senders.push(sender);
+        }
+    }
+}
+
+/// Serve the metrics in background.
+pub fn serve(addr: SocketAddr) {
+    // Make sure the metrics are registered before serving.
+    Lazy::force(&METRICS);
+    info!("Serving metrics at http://{addr}/metrics");
+    tokio::spawn(async move {
+        if let Err(err) = prometheus_endpoint::init_prometheus(addr, REGISTRY.clone()).await {
+            error!("Metrics server exited with error: {err}");
+        }
+    });
+}
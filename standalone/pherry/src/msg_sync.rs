@@ -1,11 +1,14 @@
 use anyhow::Result;
+use codec::Encode;
 use log::{error, info};
 use std::time::Duration;
 
 use crate::{
     chain_client::{mq_next_sequence, update_signer_nonce},
+    metrics::METRICS,
     types::{ParachainApi, PrClient, SrSigner},
 };
+use phala_types::messaging::SignedMessage;
 use phaxt::subxt::tx::Signer as _;
 
 pub use tokio::sync::mpsc::{channel, Receiver, Sender};
@@ -19,13 +22,45 @@ pub fn create_report_channel() -> (Sender<Error>, Receiver<Error>) {
     channel(1024)
 }
 
+/// Limits of the egress messages submitted by pherry.
+pub struct SyncLimits {
+    /// Max number of messages to be submitted per round
+    pub max_msgs_per_round: u64,
+    /// Max number of messages batched in a single extrinsic
+    pub max_msgs_per_tx: u64,
+    /// Max total encoded size of the messages batched in a single extrinsic
+    pub max_bytes_per_tx: usize,
+}
+
+/// The messages to be submitted in a single extrinsic.
+#[derive(Default)]
+struct Batch {
+    messages: Vec<SignedMessage>,
+    infos: Vec<String>,
+    size: usize,
+}
+
+impl Batch {
+    fn is_full(&self, limits: &SyncLimits, next_size: usize) -> bool {
+        !self.messages.is_empty()
+            && (self.messages.len() as u64 >= limits.max_msgs_per_tx
+                || self.size + next_size > limits.max_bytes_per_tx)
+    }
+
+    fn push(&mut self, message: SignedMessage, info: String, size: usize) {
+        self.messages.push(message);
+        self.infos.push(info);
+        self.size += size;
+    }
+}
+
 pub async fn maybe_sync_mq_egress(
     api: &ParachainApi,
     pr: &PrClient,
     signer: &mut SrSigner,
     tip: u128,
     longevity: u64,
-    max_sync_msgs_per_round: u64,
+    limits: &SyncLimits,
     err_report: Sender<Error>,
 ) -> Result<()> {
     // Send the query
@@ -33,12 +68,15 @@ pub async fn maybe_sync_mq_egress(
 
     // No pending message. We are done.
     if messages.is_empty() {
+        METRICS.observe_pending_egress(vec![]);
         return Ok(());
     }
 
     update_signer_nonce(api, signer).await?;
 
     let mut sync_msgs_count = 0;
+    let mut batch = Batch::default();
+    let mut pending_egress = vec![];
 
     'sync_outer: for (sender, messages) in messages {
         if messages.is_empty() {
@@ -47,6 +85,8 @@ pub async fn maybe_sync_mq_egress(
         let min_seq = mq_next_sequence(api, &sender).await?;
 
         info!("Next seq for {} is {}", sender, min_seq);
+        let pending = messages.iter().filter(|m| m.sequence >= min_seq).count();
+        pending_egress.push((sender.to_string(), pending));
 
         for message in messages {
             if message.sequence < min_seq {
@@ -54,63 +94,94 @@ pub async fn maybe_sync_mq_egress(
                 continue;
             }
             let msg_info = format!(
-                "sender={} seq={} dest={} nonce={:?}",
+                "sender={} seq={} dest={}",
                 sender,
                 message.sequence,
                 String::from_utf8_lossy(&message.message.destination.path()[..]),
-                signer.nonce()
             );
-            info!("Submitting message: {}", msg_info);
-
-            let params = crate::mk_params(api, longevity, tip).await?;
-            let tx = phaxt::dynamic::tx::sync_offchain_message(message);
-            let extrinsic = api.tx().create_signed(&tx, signer, params).await;
-            signer.increment_nonce();
-            match extrinsic {
-                Ok(extrinsic) => {
-                    let api = ParachainApi::from(api.clone());
-                    let err_report = err_report.clone();
-                    let extrinsic = crate::subxt::utils::Encoded(extrinsic.encoded().to_vec());
-                    tokio::spawn(async move {
-                        const TIMEOUT: u64 = 120;
-                        let fut = api.rpc().submit_extrinsic(extrinsic);
-                        let result = tokio::time::timeout(Duration::from_secs(TIMEOUT), fut).await;
-                        match result {
-                            Err(_) => {
-                                error!("Submit message timed out: {}", msg_info);
-                                let _ = err_report.send(Error::OtherRpcError).await;
-                            }
-                            Ok(Err(err)) => {
-                                error!("Error submitting message {}: {:?}", msg_info, err);
-                                use phaxt::subxt::{error::RpcError, Error as SubxtError};
-                                let report = match err {
-                                    SubxtError::Rpc(RpcError(err)) => {
-                                        if err.contains("bad signature") {
-                                            Error::BadSignature
-                                        } else {
-                                            Error::OtherRpcError
-                                        }
-                                    }
-                                    _ => Error::OtherRpcError,
-                                };
-                                let _ = err_report.send(report).await;
-                            }
-                            Ok(Ok(hash)) => {
-                                info!("Message submited: {} xt-hash={:?}", msg_info, hash);
-                            }
-                        }
-                    });
-                }
-                Err(err) => {
-                    panic!("Failed to sign the call: {:?}", err);
-                }
+            let size = message.encoded_size();
+            if batch.is_full(limits, size) {
+                let batch = std::mem::take(&mut batch);
+                submit_batch(api, signer, tip, longevity, batch, &err_report).await?;
             }
+            batch.push(message, msg_info, size);
             sync_msgs_count += 1;
-            if sync_msgs_count >= max_sync_msgs_per_round {
+            if sync_msgs_count >= limits.max_msgs_per_round {
                 info!("Synced {} messages, take a break", sync_msgs_count);
                 break 'sync_outer;
             }
         }
     }
+    METRICS.observe_pending_egress(pending_egress);
+    if !batch.messages.is_empty() {
+        submit_batch(api, signer, tip, longevity, batch, &err_report).await?;
+    }
+    Ok(())
+}
+
+/// Sign and submit the batch in background. A single message is submitted with
+/// `sync_offchain_message` to be compatible with the older runtimes.
+async fn submit_batch(
+    api: &ParachainApi,
+    signer: &mut SrSigner,
+    tip: u128,
+    longevity: u64,
+    mut batch: Batch,
+    err_report: &Sender<Error>,
+) -> Result<()> {
+    let msg_info = format!("{} nonce={:?}", batch.infos.join(", "), signer.nonce());
+    info!("Submitting message: {}", msg_info);
+
+    let params = crate::mk_params(api, longevity, tip).await?;
+    let extrinsic = if batch.messages.len() == 1 {
+        let tx = phaxt::dynamic::tx::sync_offchain_message(batch.messages.remove(0));
+        api.tx().create_signed(&tx, signer, params).await
+    } else {
+        let tx = phaxt::dynamic::tx::sync_offchain_messages(batch.messages);
+        api.tx().create_signed(&tx, signer, params).await
+    };
+    signer.increment_nonce();
+    match extrinsic {
+        Ok(extrinsic) => {
+            let api = ParachainApi::from(api.clone());
+            let err_report = err_report.clone();
+            let extrinsic = crate::subxt::utils::Encoded(extrinsic.encoded().to_vec());
+            tokio::spawn(async move {
+                const TIMEOUT: u64 = 120;
+                let fut = api.rpc().submit_extrinsic(extrinsic);
+                let result = tokio::time::timeout(Duration::from_secs(TIMEOUT), fut).await;
+                match result {
+                    Err(_) => {
+                        error!("Submit message timed out: {}", msg_info);
+                        METRICS.tx_failed.inc();
+                        let _ = err_report.send(Error::OtherRpcError).await;
+                    }
+                    Ok(Err(err)) => {
+                        error!("Error submitting message {}: {:?}", msg_info, err);
+                        METRICS.tx_failed.inc();
+                        use phaxt::subxt::{error::RpcError, Error as SubxtError};
+                        let report = match err {
+                            SubxtError::Rpc(RpcError(err)) => {
+                                if err.contains("bad signature") {
+                                    Error::BadSignature
+                                } else {
+                                    Error::OtherRpcError
+                                }
+                            }
+                            _ => Error::OtherRpcError,
+                        };
+                        let _ = err_report.send(report).await;
+                    }
+                    Ok(Ok(hash)) => {
+                        info!("Message submited: {} xt-hash={:?}", msg_info, hash);
+                        METRICS.tx_submitted.inc();
+                    }
+                }
+            });
+        }
+        Err(err) => {
+            panic!("Failed to sign the call: {:?}", err);
+        }
+    }
     Ok(())
 }
@@ -31,6 +31,7 @@ impl PrefetchClient {
         let result = if let Some(state) = self.prefetching_storage_changes.take() {
             if state.from == from && state.to == to {
                 log::info!("use prefetched storage changes ({from}-{to})",);
+                crate::metrics::METRICS.prefetch_hits.inc();
                 state.handle.await?.ok()
             } else {
                 log::info!(
@@ -48,6 +49,7 @@ impl PrefetchClient {
         let result = if let Some(result) = result {
             result
         } else {
+            crate::metrics::METRICS.prefetch_misses.inc();
             crate::fetch_storage_changes(client, cache, from, to).await?
         };
         let next_from = from + count;
@@ -0,0 +1,334 @@
+//! The `--verify-only` mode: check a pRuntime build against the production workers.
+//!
+//! The pRuntime restored from a checkpoint is fed with the same blocks as in normal mode, one block
+//! at a time. After each block, its egress messages are compared with the ones the production
+//! worker submitted on chain, which are picked from the `PhalaMq` extrinsics of the parachain
+//! blocks. The state roots are checked by pRuntime itself while dispatching the blocks, so a
+//! diverged state stops the sync with an error.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use codec::{Decode, Encode};
+use log::{error, info};
+use once_cell::sync::Lazy;
+use phala_types::messaging::{Message, MessageOrigin, SignedMessage};
+use phaxt::subxt::{ext::scale_value::scale::decode_as_type, Metadata};
+use sp_runtime::OpaqueExtrinsic;
+use tokio::sync::Mutex;
+
+use crate::types::{BlockNumber, Hash, ParachainApi, PrClient};
+
+/// Number of blocks to wait for a message to be submitted on chain before reporting it missing.
+const MAX_SUBMIT_DELAY: BlockNumber = 100;
+
+static VERIFIER: Lazy<Mutex<Option<Verifier>>> = Lazy::new(Default::default);
+
+/// The types of the signed part of the extrinsics, resolved from the runtime metadata.
+struct SignedPartTypes {
+    address: u32,
+    signature: u32,
+    /// The signed extensions, in the order they are encoded in the extra
+    extra: Vec<u32>,
+}
+
+impl SignedPartTypes {
+    fn resolve(metadata: &Metadata) -> Result<Self> {
+        let runtime = metadata.runtime_metadata();
+        let extrinsic = &runtime.extrinsic;
+        if extrinsic.version != 4 {
+            bail!("Unsupported extrinsic version {}", extrinsic.version);
+        }
+        let ty = runtime
+            .types
+            .resolve(extrinsic.ty.id())
+            .ok_or_else(|| anyhow!("Extrinsic type not found in the metadata"))?;
+        let param = |name: &str| {
+            ty.type_params()
+                .iter()
+                .find(|param| param.name() == name)
+                .and_then(|param| param.ty())
+                .map(|ty| ty.id())
+                .ok_or_else(|| anyhow!("No {} in the extrinsic type", name))
+        };
+        Ok(Self {
+            address: param("Address")?,
+            signature: param("Signature")?,
+            extra: extrinsic
+                .signed_extensions
+                .iter()
+                .map(|ext| ext.ty.id())
+                .collect(),
+        })
+    }
+}
+
+struct Verifier {
+    /// The runtime metadata to decode the extrinsics with
+    metadata: Metadata,
+    signed_part: SignedPartTypes,
+    /// The index of the `PhalaMq` pallet and its `sync_offchain_message(s)` calls
+    pallet_index: u8,
+    sync_call_index: u8,
+    batch_call_index: Option<u8>,
+    /// The last block dispatched before the verification, where the sequences start
+    start_hash: Hash,
+    /// The first sequence to verify of each tracked sender
+    start_seq: BTreeMap<MessageOrigin, u64>,
+    /// The next parachain block to look for the submitted messages
+    next_scan: BlockNumber,
+    /// The messages produced by pRuntime but not yet matched, with the block producing them
+    produced: BTreeMap<(MessageOrigin, u64), (BlockNumber, Message)>,
+    /// The messages submitted on chain but not yet matched, with the block including them
+    submitted: BTreeMap<(MessageOrigin, u64), (BlockNumber, Message)>,
+    matched: u64,
+    divergences: u64,
+}
+
+/// Start verifying the egress messages of the blocks after `start`.
+///
+/// The messages of the worker itself are always tracked, the other senders are tracked once
+/// pRuntime produces messages from them.
+pub async fn enable(
+    para_api: &ParachainApi,
+    start: BlockNumber,
+    worker: Option<MessageOrigin>,
+) -> Result<()> {
+    let metadata = para_api.metadata();
+    let pallet = metadata.pallet("PhalaMq")?;
+    let mut verifier = Verifier {
+        signed_part: SignedPartTypes::resolve(&metadata)?,
+        pallet_index: pallet.index(),
+        sync_call_index: pallet.call_index("sync_offchain_message")?,
+        batch_call_index: pallet.call_index("sync_offchain_messages").ok(),
+        start_hash: crate::get_header_hash(para_api, Some(start)).await?,
+        start_seq: Default::default(),
+        next_scan: start + 1,
+        produced: Default::default(),
+        submitted: Default::default(),
+        matched: 0,
+        divergences: 0,
+        metadata: metadata.clone(),
+    };
+    if let Some(worker) = worker {
+        verifier.track(para_api, worker).await?;
+    }
+    info!("Verifying the egress messages from block {}", start + 1);
+    *VERIFIER.lock().await = Some(verifier);
+    Ok(())
+}
+
+/// Compare the egress messages after pRuntime dispatched the blocks up to `synced_to`.
+///
+/// Does nothing unless the verification is enabled.
+pub async fn check_egress(
+    pr: &PrClient,
+    para_api: &ParachainApi,
+    synced_to: BlockNumber,
+) -> Result<()> {
+    let mut verifier = VERIFIER.lock().await;
+    match verifier.as_mut() {
+        Some(verifier) => verifier.check(pr, para_api, synced_to).await,
+        None => Ok(()),
+    }
+}
+
+/// Report the result of the verification. Fails if any divergence was found.
+pub async fn finish() -> Result<()> {
+    let verifier = VERIFIER.lock().await;
+    let verifier = match verifier.as_ref() {
+        Some(verifier) => verifier,
+        None => return Ok(()),
+    };
+    info!(
+        "Verification finished: {} messages matched, {} divergences, {} produced and {} submitted messages left unverified",
+        verifier.matched,
+        verifier.divergences,
+        verifier.produced.len(),
+        verifier.submitted.len(),
+    );
+    if verifier.divergences > 0 {
+        bail!("{} divergences found", verifier.divergences);
+    }
+    Ok(())
+}
+
+impl Verifier {
+    async fn track(&mut self, para_api: &ParachainApi, sender: MessageOrigin) -> Result<()> {
+        if self.start_seq.contains_key(&sender) {
+            return Ok(());
+        }
+        let key = para_api.storage_key("PhalaMq", "OffchainIngress", &sender)?;
+        let seq = match para_api.rpc().storage(&key, Some(self.start_hash)).await? {
+            Some(data) => u64::decode(&mut &data.0[..])?,
+            None => 0,
+        };
+        info!("Tracking messages of {} from seq {}", sender, seq);
+        // Only the messages submitted by the tracked senders are verified.
+        self.submitted.retain(|(s, n), _| s != &sender || *n >= seq);
+        self.start_seq.insert(sender, seq);
+        Ok(())
+    }
+
+    fn is_tracked(&self, sender: &MessageOrigin, seq: u64) -> bool {
+        self.start_seq
+            .get(sender)
+            .map_or(false, |start_seq| seq >= *start_seq)
+    }
+
+    async fn check(
+        &mut self,
+        pr: &PrClient,
+        para_api: &ParachainApi,
+        synced_to: BlockNumber,
+    ) -> Result<()> {
+        let egress = pr.get_egress_messages(()).await?.decode_messages()?;
+        for (sender, messages) in egress {
+            if messages.is_empty() {
+                continue;
+            }
+            self.track(para_api, sender.clone()).await?;
+            for message in messages {
+                if self.is_tracked(&sender, message.sequence) {
+                    self.produced
+                        .entry((sender.clone(), message.sequence))
+                        .or_insert((synced_to, message.message));
+                }
+            }
+        }
+
+        let finalized = crate::get_header_at(para_api, None).await?.0.number;
+        self.scan(para_api, finalized.min(synced_to + MAX_SUBMIT_DELAY))
+            .await?;
+
+        // Match the messages on both sides
+        let keys: Vec<_> = self
+            .produced
+            .keys()
+            .filter(|key| self.submitted.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in keys {
+            let (_, produced) = self.produced.remove(&key).expect("Key exists");
+            let (block, submitted) = self.submitted.remove(&key).expect("Key exists");
+            if produced == submitted {
+                self.matched += 1;
+            } else {
+                self.diverge(format!(
+                    "message {} seq={} differs from the one submitted at block {}",
+                    key.0, key.1, block
+                ));
+            }
+        }
+
+        // The messages should have been submitted in time
+        let scanned_to = self.next_scan - 1;
+        let late: Vec<_> = self
+            .produced
+            .iter()
+            .filter(|(_, (block, _))| block + MAX_SUBMIT_DELAY <= scanned_to)
+            .map(|(key, (block, _))| (key.clone(), *block))
+            .collect();
+        for (key, block) in late {
+            self.produced.remove(&key);
+            self.diverge(format!(
+                "message {} seq={} produced at block {} is not submitted on chain",
+                key.0, key.1, block
+            ));
+        }
+
+        // The submitted messages should have been produced before pRuntime dispatched the
+        // blocks including them, since pRuntime drops the egress messages seen on chain.
+        let missing: Vec<_> = self
+            .submitted
+            .iter()
+            .filter(|(_, (block, _))| *block <= synced_to)
+            .map(|(key, (block, _))| (key.clone(), *block))
+            .collect();
+        for (key, block) in missing {
+            self.submitted.remove(&key);
+            if self.start_seq.contains_key(&key.0) {
+                self.diverge(format!(
+                    "message {} seq={} submitted at block {} is not produced",
+                    key.0, key.1, block
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect the messages submitted in the parachain blocks up to `to`.
+    async fn scan(&mut self, para_api: &ParachainApi, to: BlockNumber) -> Result<()> {
+        while self.next_scan <= to {
+            let number = self.next_scan;
+            let (block, _) = crate::get_block_at(para_api, Some(number)).await?;
+            for (index, xt) in block.block.extrinsics.iter().enumerate() {
+                let messages = self
+                    .decode_synced_messages(xt)
+                    .with_context(|| format!("Failed to decode extrinsic {number}-{index}"))?;
+                for signed_message in messages.unwrap_or_default() {
+                    let sender = signed_message.message.sender.clone();
+                    let seq = signed_message.sequence;
+                    // Untracked senders are kept until pRuntime dispatches the block, in case it
+                    // starts producing messages from them.
+                    if self.is_tracked(&sender, seq) || !self.start_seq.contains_key(&sender) {
+                        self.submitted
+                            .insert((sender, seq), (number, signed_message.message));
+                    }
+                }
+            }
+            self.next_scan += 1;
+        }
+        Ok(())
+    }
+
+    /// Decode the messages carried by a `sync_offchain_message(s)` extrinsic. Returns None for
+    /// the other extrinsics.
+    fn decode_synced_messages(&self, xt: &OpaqueExtrinsic) -> Result<Option<Vec<SignedMessage>>> {
+        let encoded = xt.encode();
+        let xt = Vec::<u8>::decode(&mut &encoded[..])?;
+        let input = &mut &xt[..];
+        let version = u8::decode(input)?;
+        if version & 0b0111_1111 != 4 {
+            bail!("Unsupported extrinsic version {}", version & 0b0111_1111);
+        }
+        if version & 0b1000_0000 != 0 {
+            let types = &self.metadata.runtime_metadata().types;
+            let signed_part = &self.signed_part;
+            for ty in [signed_part.address, signed_part.signature]
+                .iter()
+                .chain(&signed_part.extra)
+            {
+                decode_as_type(input, *ty, types)
+                    .map_err(|err| anyhow!("Failed to decode the signed part: {:?}", err))?;
+            }
+        }
+        let (pallet_index, call_index) = <(u8, u8)>::decode(input)?;
+        if pallet_index != self.pallet_index {
+            return Ok(None);
+        }
+        let messages = if call_index == self.sync_call_index {
+            vec![SignedMessage::decode(input).context("Invalid sync_offchain_message")?]
+        } else if Some(call_index) == self.batch_call_index {
+            Vec::<SignedMessage>::decode(input).context("Invalid sync_offchain_messages")?
+        } else {
+            return Ok(None);
+        };
+        if !input.is_empty() {
+            bail!("{} trailing bytes in the PhalaMq extrinsic", input.len());
+        }
+        Ok(Some(messages))
+    }
+
+    fn diverge(&mut self, reason: String) {
+        error!("Divergence: {}", reason);
+        self.divergences += 1;
+    }
+}
+
+/// The message origin of the worker, from its hex encoded public key.
+pub fn worker_origin(public_key: &str) -> Option<MessageOrigin> {
+    let key = hex::decode(public_key.trim_start_matches("0x")).ok()?;
+    let key = sp_core::sr25519::Public::decode(&mut &key[..]).ok()?;
+    Some(MessageOrigin::Worker(key))
+}
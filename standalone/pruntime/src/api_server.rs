@@ -290,6 +290,16 @@ pub(super) fn rocket(args: &super::Args) -> rocket::Rocket<impl Phase> {
                     sync_combined_headers,
                     actions::BIN_ACTION_SYNC_COMBINED_HEADERS
                 ),
+                (
+                    "/export_cluster_state",
+                    export_cluster_state,
+                    actions::BIN_ACTION_EXPORT_CLUSTER_STATE
+                ),
+                (
+                    "/import_cluster_state",
+                    import_cluster_state,
+                    actions::BIN_ACTION_IMPORT_CLUSTER_STATE
+                ),
             ],
         )
         .mount("/", routes![getinfo, get_contract_info, get_cluster_info]);
@@ -69,6 +69,15 @@ struct Args {
     #[clap(long)]
     #[clap(default_value_t = 100)]
     gc_interval: BlockNumber,
+
+    /// Listening address of the TLS ingress shared by sidevm programs, e.g. 0.0.0.0:443
+    #[clap(long)]
+    sidevm_ingress: Option<String>,
+
+    /// Attest with DCAP quotes instead of IAS reports when registering the worker. The
+    /// collateral is fetched from the PCCS given by the env PCCS_URL.
+    #[clap(long)]
+    dcap: bool,
 }
 
 #[rocket::main]
@@ -133,6 +142,8 @@ async fn main() -> Result<(), rocket::Error> {
             gc_interval: args.gc_interval,
             cores,
             public_port: args.public_port,
+            sidevm_ingress: args.sidevm_ingress,
+            dcap_attestation: args.dcap,
         }
     };
     info!("init_args: {:#?}", init_args);
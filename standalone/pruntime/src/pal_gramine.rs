@@ -1,7 +1,9 @@
 use log::info;
 use std::alloc::System;
 
-use phactory_pal::{AppInfo, AppVersion, Machine, MemoryStats, MemoryUsage, Sealing, RA};
+use phactory_pal::{
+    AppInfo, AppVersion, DcapCollateral, Machine, MemoryStats, MemoryUsage, Sealing, RA,
+};
 use phala_allocator::StatSizeAllocator;
 use std::io::ErrorKind;
 use std::str::FromStr as _;
@@ -47,9 +49,18 @@ impl RA for GraminePlatform {
         ra::create_attestation_report(data, IAS_API_KEY_STR)
     }
 
+    fn create_dcap_quote(&self, data: &[u8]) -> Result<(Vec<u8>, DcapCollateral), Self::Error> {
+        ra::create_dcap_quote(data)
+    }
+
     fn quote_test(&self) -> Result<(), Self::Error> {
         ra::create_quote_vec(&[0u8; 64]).map(|_| ())
     }
+
+    fn measurement(&self) -> Option<Vec<u8>> {
+        let quote = ra::create_quote_vec(&[0u8; 64]).ok()?;
+        ra::parse_measurement(&quote)
+    }
 }
 
 impl Machine for GraminePlatform {
@@ -2,7 +2,13 @@ use anyhow::{anyhow, Context as _, Result};
 use log::{error, warn, info};
 use std::{fs, time::Duration};
 
+use phactory_pal::DcapCollateral;
 use reqwest_env_proxy::EnvProxyBuilder as _;
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::{oid, parse_der, Oid},
+    pem::parse_x509_pem,
+};
 
 pub const IAS_HOST: &str = env!("IAS_HOST");
 pub const IAS_REPORT_ENDPOINT: &str = env!("IAS_REPORT_ENDPOINT");
@@ -93,8 +99,208 @@ pub fn create_quote_vec(data: &[u8]) -> Result<Vec<u8>> {
     Ok(fs::read("/dev/attestation/quote")?)
 }
 
+/// Extracts the pRuntime hash (mr_enclave ++ isv_prod_id ++ isv_svn ++ mr_signer) from a quote.
+///
+/// Consistent with `IasFields::extend_mrenclave` used on chain.
+pub fn parse_measurement(quote: &[u8]) -> Option<Vec<u8>> {
+    if quote.len() < 308 {
+        return None;
+    }
+    let mr_enclave = &quote[112..144];
+    let mr_signer = &quote[176..208];
+    let isv_prod_id = &quote[304..306];
+    let isv_svn = &quote[306..308];
+    Some([mr_enclave, isv_prod_id, isv_svn, mr_signer].concat())
+}
+
 pub fn create_attestation_report(data: &[u8], ias_key: &str) -> Result<(String, String, String)> {
     let quote_vec = create_quote_vec(data)?;
     let (attn_report, sig, cert) = get_report_from_intel(&quote_vec, ias_key)?;
     Ok((attn_report, sig, cert))
 }
+
+/// Creates a DCAP quote of `data` and fetches the collateral to verify it from the PCCS given
+/// by the env `PCCS_URL` (defaults to Intel PCS).
+pub fn create_dcap_quote(data: &[u8]) -> Result<(Vec<u8>, DcapCollateral)> {
+    let quote = create_quote_vec(data)?;
+    let pccs_url = std::env::var("PCCS_URL")
+        .unwrap_or_else(|_| "https://api.trustedservices.intel.com/sgx".to_string());
+    let collateral = get_collateral(pccs_url.trim_end_matches('/'), &quote)?;
+    Ok((quote, collateral))
+}
+
+fn get_collateral(pccs_url: &str, quote: &[u8]) -> Result<DcapCollateral> {
+    // The first certificate of the chain is the PCK certificate
+    let (_, pem) = parse_x509_pem(pck_chain_from_quote(quote)?).context("Invalid PCK chain")?;
+    let pck_cert = pem.parse_x509().context("Invalid PCK certificate")?;
+    let fmspc = hex::encode_upper(find_fmspc(&pck_cert)?);
+    let ca = pck_ca(issuer_cn(&pck_cert).context("No CN in PCK certificate issuer")?)?;
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Some(Duration::from_secs(8)))
+        .env_proxy(reqwest::Url::parse(pccs_url)?.domain().unwrap_or_default())
+        .build()
+        .context("Failed to create http client, maybe invalid PCCS URI")?;
+    let get = |path: &str| -> Result<reqwest::blocking::Response> {
+        let url = format!("{}/certification/v4/{}", pccs_url, path);
+        info!("Getting DCAP collateral from {}", url);
+        let res = client
+            .get(&url)
+            .send()
+            .context("Failed to send http request")?;
+        if !res.status().is_success() {
+            return Err(anyhow!("Bad http status {} from {}", res.status(), url));
+        }
+        Ok(res)
+    };
+    let issuer_chain = |res: &reqwest::blocking::Response, header: &str| -> Result<Vec<u8>> {
+        let chain = res
+            .headers()
+            .get(header)
+            .with_context(|| format!("No header {}", header))?
+            .to_str()?;
+        Ok(urlencoding::decode(chain)?.into_owned().into_bytes())
+    };
+
+    let pck_crl = get(&format!("pckcrl?ca={}&encoding=der", ca))?
+        .bytes()?
+        .to_vec();
+    let root_ca_crl = get("rootcacrl")?.bytes()?.to_vec();
+    // PCCS serves the root CA CRL hex encoded while Intel PCS serves it in DER
+    let root_ca_crl = hex::decode(&root_ca_crl).unwrap_or(root_ca_crl);
+
+    let res = get(&format!("tcb?fmspc={}", fmspc))?;
+    let tcb_info_issuer_chain = issuer_chain(&res, "TCB-Info-Issuer-Chain")?;
+    let (tcb_info, tcb_info_signature) = split_signed_json(&res.text()?, "tcbInfo")?;
+
+    let res = get("qe/identity")?;
+    let qe_identity_issuer_chain = issuer_chain(&res, "SGX-Enclave-Identity-Issuer-Chain")?;
+    let (qe_identity, qe_identity_signature) = split_signed_json(&res.text()?, "enclaveIdentity")?;
+
+    Ok(DcapCollateral {
+        root_ca_crl,
+        pck_crl,
+        tcb_info_issuer_chain,
+        tcb_info,
+        tcb_info_signature,
+        qe_identity_issuer_chain,
+        qe_identity,
+        qe_identity_signature,
+    })
+}
+
+/// Extracts the PEM encoded PCK certificate chain from the certification data of a quote v3.
+fn pck_chain_from_quote(quote: &[u8]) -> Result<&[u8]> {
+    const SIG_DATA_OFFSET: usize = 436;
+    const QE_AUTH_OFFSET: usize = SIG_DATA_OFFSET + 64 + 64 + 384 + 64;
+    let read_u16 = |offset: usize| -> Result<usize> {
+        let bytes = quote.get(offset..offset + 2).context("Quote too short")?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    };
+    let cert_type_offset = QE_AUTH_OFFSET + 2 + read_u16(QE_AUTH_OFFSET)?;
+    if read_u16(cert_type_offset)? != 5 {
+        return Err(anyhow!("Unsupported certification data type"));
+    }
+    let len = quote
+        .get(cert_type_offset + 2..cert_type_offset + 6)
+        .context("Quote too short")?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    quote
+        .get(cert_type_offset + 6..cert_type_offset + 6 + len)
+        .context("Quote too short")
+}
+
+/// Finds the FMSPC in the SGX extensions of a PCK certificate.
+///
+/// The SGX extensions is a sequence of `(OID, value)` pairs, where the FMSPC is a 6 bytes octet
+/// string.
+fn find_fmspc(cert: &X509Certificate) -> Result<Vec<u8>> {
+    const SGX_EXTENSIONS_OID: Oid<'static> = oid!(1.2.840 .113741 .1 .13 .1);
+    const FMSPC_OID: Oid<'static> = oid!(1.2.840 .113741 .1 .13 .1 .4);
+
+    let ext = cert
+        .extensions()
+        .iter()
+        .find(|ext| ext.oid == SGX_EXTENSIONS_OID)
+        .context("No SGX extensions in PCK certificate")?;
+    let (_, sgx_extensions) = parse_der(ext.value).context("Invalid SGX extensions")?;
+    for item in sgx_extensions
+        .as_sequence()
+        .context("Invalid SGX extensions")?
+    {
+        if let Ok([oid, value]) = item.as_sequence().map(|pair| pair.as_slice()) {
+            if oid.as_oid().ok() != Some(&FMSPC_OID) {
+                continue;
+            }
+            return match value.as_slice() {
+                Ok(fmspc) if fmspc.len() == 6 => Ok(fmspc.to_vec()),
+                _ => Err(anyhow!("Invalid FMSPC in PCK certificate")),
+            };
+        }
+    }
+    Err(anyhow!("No FMSPC in PCK certificate"))
+}
+
+fn issuer_cn<'a>(cert: &'a X509Certificate) -> Option<&'a str> {
+    cert.issuer()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+}
+
+/// Maps the issuer of a PCK certificate to the `ca` param of the PCK CRL API.
+fn pck_ca(issuer_cn: &str) -> Result<&'static str> {
+    match issuer_cn {
+        "Intel SGX PCK Processor CA" => Ok("processor"),
+        "Intel SGX PCK Platform CA" => Ok("platform"),
+        _ => Err(anyhow!("Unknown PCK certificate issuer: {}", issuer_cn)),
+    }
+}
+
+/// Splits a PCS response like `{"tcbInfo":{...},"signature":"..."}` into the raw bytes of the
+/// signed body and the decoded signature.
+///
+/// The body is kept as served since the signature covers its exact bytes.
+fn split_signed_json(text: &str, key: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+    let prefix = format!("{{\"{}\":", key);
+    let body = text
+        .strip_prefix(&prefix)
+        .context("Unexpected collateral format")?;
+    let sig_pos = body
+        .rfind(",\"signature\":")
+        .context("No signature in collateral")?;
+    let json: serde_json::Value = serde_json::from_str(text).context("Invalid collateral json")?;
+    let signature = json["signature"]
+        .as_str()
+        .context("No signature in collateral")?;
+    Ok((body.as_bytes()[..sig_pos].to_vec(), hex::decode(signature)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Signed by a test CA, the PCK certificate has the same layout as the ones issued by Intel
+    const DCAP_ATTESTATION_SAMPLE: &[u8] =
+        include_bytes!("../../../pallets/phala/sample/dcap_attestation.json");
+
+    #[test]
+    fn parse_pck_certificate_in_quote() {
+        let sample: serde_json::Value = serde_json::from_slice(DCAP_ATTESTATION_SAMPLE).unwrap();
+        let quote = hex::decode(sample["quote"].as_str().unwrap()).unwrap();
+        let (_, pem) = parse_x509_pem(pck_chain_from_quote(&quote).unwrap()).unwrap();
+        let pck_cert = pem.parse_x509().unwrap();
+        assert_eq!(
+            hex::encode_upper(find_fmspc(&pck_cert).unwrap()),
+            "00906ED50000"
+        );
+        assert_eq!(issuer_cn(&pck_cert), Some("Test SGX PCK Platform CA"));
+    }
+
+    #[test]
+    fn pck_ca_from_issuer() {
+        assert_eq!(pck_ca("Intel SGX PCK Processor CA").unwrap(), "processor");
+        assert_eq!(pck_ca("Intel SGX PCK Platform CA").unwrap(), "platform");
+        assert!(pck_ca("Test SGX PCK Platform CA").is_err());
+    }
+}
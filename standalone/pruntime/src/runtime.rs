@@ -55,6 +55,10 @@ pub fn ecall_init(args: phactory_api::ecall_args::InitArgs) -> Result<()> {
         anyhow::bail!("Enclave already initialized.");
     }
 
+    if let Some(addr) = &args.sidevm_ingress {
+        phactory::serve_sidevm_ingress(addr.clone());
+    }
+
     if args.enable_checkpoint {
         match Phactory::restore_from_checkpoint(
             &GraminePlatform,
@@ -25,3 +25,18 @@ SELECT create_hypertable(
     'time',
     chunk_time_interval := interval '7 days'
 );
+
+-- The per-worker results of the tokenomic simulations (--simulate), with the payouts and slashes
+-- counted since the simulation started.
+DROP TABLE IF EXISTS "worker_simulation_deltas";
+CREATE TABLE "worker_simulation_deltas" (
+    "block" integer NOT NULL,
+    "pubkey" bytea NOT NULL,
+    "baseline_v" numeric NOT NULL,
+    "simulated_v" numeric NOT NULL,
+    "baseline_payout" numeric NOT NULL,
+    "simulated_payout" numeric NOT NULL,
+    "baseline_slash" numeric NOT NULL,
+    "simulated_slash" numeric NOT NULL,
+    PRIMARY KEY(block, pubkey)
+) WITH (oids = false);
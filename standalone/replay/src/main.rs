@@ -53,6 +53,62 @@ pub struct Args {
         help = "The checkpoint file to restore from. Default is to use the latest checkpoint."
     )]
     restore_from: Option<String>,
+
+    #[clap(
+        long,
+        help = "Stop replaying after the given block and take a final checkpoint."
+    )]
+    stop_at: Option<u32>,
+
+    #[clap(long, help = "Write the events to the given file in JSON lines.")]
+    events_output: Option<String>,
+
+    #[clap(
+        long,
+        help = "Save the genesis state fetched from the node to the given file for later offline replays."
+    )]
+    save_genesis_state: Option<String>,
+
+    #[clap(
+        long,
+        help = "Replay offline from the headers-cache server instead of the node, e.g. http://localhost:8002."
+    )]
+    cache_uri: Option<String>,
+
+    #[clap(
+        long,
+        multiple_values = true,
+        conflicts_with = "cache-uri",
+        help = "Replay offline from the files grabbed by `headers-cache grab storage-changes`."
+    )]
+    storage_changes: Vec<String>,
+
+    #[clap(
+        long,
+        multiple_values = true,
+        requires = "storage-changes",
+        help = "The files grabbed by `headers-cache grab para-headers` to verify the storage changes with."
+    )]
+    para_headers: Vec<String>,
+
+    #[clap(
+        long,
+        help = "The genesis state saved by --save-genesis-state, required by offline replays without a checkpoint."
+    )]
+    genesis_state: Option<String>,
+
+    #[clap(
+        long,
+        help = "The config file of the tokenomic parameters to simulate with. See simulation.rs for the format."
+    )]
+    simulate: Option<String>,
+
+    #[clap(
+        default_value = "simulation.csv",
+        long,
+        help = "The CSV file to write the per-worker simulation results to at each checkpoint."
+    )]
+    simulation_output: String,
 }
 
 #[tokio::main]
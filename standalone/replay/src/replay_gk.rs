@@ -1,5 +1,8 @@
 mod data_persist;
+mod events_file;
 mod httpserver;
+mod simulation;
+mod source;
 
 use std::{
     fs::File,
@@ -11,6 +14,7 @@ use std::{
 
 use anyhow::Error;
 use anyhow::Result;
+use parity_scale_codec::Encode;
 use phactory::{gk, BlockInfo, StorageExt};
 use phactory_api::blocks::BlockHeaderWithChanges;
 use phala_mq::Path as MqPath;
@@ -20,9 +24,13 @@ use phala_types::WorkerPublicKey;
 use phaxt::rpc::ExtraRpcExt as _;
 use pherry::types::{phaxt, subxt, BlockNumber, Hashing, NumberOrHex, ParachainApi, StorageKey};
 use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc, Mutex};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
 
 use crate::Args;
+use simulation::{Simulation, SimulationConfig};
 
 type RecordSender = mpsc::Sender<EventRecord>;
 
@@ -45,6 +53,8 @@ pub struct ReplayFactory {
     #[serde(default)]
     recv_mq: MessageDispatcher,
     gk: gk::MiningEconomics<ReplayMsgChannel>,
+    #[serde(skip)]
+    simulation: Option<Simulation>,
 }
 
 impl ReplayFactory {
@@ -59,6 +69,7 @@ impl ReplayFactory {
             storage,
             recv_mq,
             gk,
+            simulation: None,
         }
     }
 
@@ -66,6 +77,7 @@ impl ReplayFactory {
         &mut self,
         block: BlockHeaderWithChanges,
         event_tx: &Option<RecordSender>,
+        verify_state_root: bool,
     ) -> Result<(), &'static str> {
         let (state_root, transaction) = self.storage.calc_root_if_changes(
             &block.storage_changes.main_storage_changes,
@@ -73,7 +85,7 @@ impl ReplayFactory {
         );
         let header = &block.block_header;
 
-        if header.state_root != state_root {
+        if verify_state_root && header.state_root != state_root {
             return Err("State root mismatch");
         }
 
@@ -101,6 +113,18 @@ impl ReplayFactory {
             .timestamp_now()
             .ok_or_else(|| "No timestamp found in block")?;
 
+        let simulated_messages = match &mut self.simulation {
+            Some(simulation) => {
+                simulation.fork_if_due(block_number, &self.gk);
+                if simulation.started() {
+                    messages.clone()
+                } else {
+                    vec![]
+                }
+            }
+            None => vec![],
+        };
+
         let mut block = BlockInfo {
             block_number,
             now_ms,
@@ -134,6 +158,10 @@ impl ReplayFactory {
         }
         self.gk.did_process_block(&block, &mut event_handler);
 
+        if let Some(simulation) = &mut self.simulation {
+            simulation.process_block(&self.storage, block_number, now_ms, simulated_messages);
+        }
+
         if let Some(tx) = event_tx.as_ref() {
             for record in records {
                 match tx.send(record).await {
@@ -153,6 +181,37 @@ impl ReplayFactory {
         Ok(())
     }
 
+    fn start_simulation(&mut self, simulation: Simulation) -> Result<()> {
+        if simulation.at_block() <= self.current_block {
+            anyhow::bail!(
+                "Can not simulate from block {} since the replay is at block {}, restore from an earlier checkpoint",
+                simulation.at_block(),
+                self.current_block
+            );
+        }
+        self.simulation = Some(simulation);
+        Ok(())
+    }
+
+    async fn report_simulation(&self, output: &str, db_uri: &str) -> Result<()> {
+        let sim = match &self.simulation {
+            Some(sim) if sim.started() => sim,
+            _ => return Ok(()),
+        };
+        let deltas = sim.deltas(&self.gk);
+        log::info!(
+            "Writing the simulation report of {} workers to {}",
+            deltas.len(),
+            output
+        );
+        let file = File::create(output)?;
+        simulation::write_csv(&deltas, std::io::BufWriter::new(file))?;
+        if !db_uri.is_empty() {
+            data_persist::persist_simulation(db_uri, self.current_block, &deltas).await?;
+        }
+        Ok(())
+    }
+
     fn load(reader: impl Read) -> Self {
         let mut dispatcher = Default::default();
         let mut factory: Self =
@@ -228,9 +287,108 @@ async fn wait_for_block(
     }
 }
 
+fn start_event_sink(args: &Args) -> (Option<RecordSender>, Option<JoinHandle<()>>) {
+    let (event_tx, event_rx) = mpsc::channel(1024 * 5);
+    let task = if !args.persist_events_to.is_empty() {
+        if args.events_output.is_some() {
+            log::warn!("Events are persisted to the database, ignoring --events-output");
+        }
+        let db_uri = args.persist_events_to.clone();
+        tokio::spawn(async move { data_persist::run_persist(event_rx, &db_uri).await })
+    } else if let Some(filename) = args.events_output.clone() {
+        tokio::spawn(async move {
+            if let Err(err) = events_file::run_dump(event_rx, &filename).await {
+                log::error!("Failed to write events to {}: {}", filename, err);
+            }
+        })
+    } else {
+        return (None, None);
+    };
+    (Some(event_tx), Some(task))
+}
+
+fn load_factory(
+    args: &Args,
+    genesis_state: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+) -> Result<ReplayFactory> {
+    let mut factory = match get_checkpoint_path(&args.restore_from) {
+        Some(filename) => {
+            log::info!("Restoring from checkpoint: {}", filename);
+            ReplayFactory::load_from_file(&filename)
+        }
+        None => match genesis_state {
+            Some(genesis_state) => ReplayFactory::new(genesis_state),
+            None => anyhow::bail!("Neither a checkpoint nor the genesis state is available"),
+        },
+    };
+    if let Some(filename) = &args.simulate {
+        let config = SimulationConfig::load(filename)?;
+        log::info!("Simulating tokenomic changes at block {}", config.at_block);
+        factory.start_simulation(Simulation::new(config))?;
+    }
+    Ok(factory)
+}
+
+fn start_http_server(bind_addr: String, factory: Arc<Mutex<ReplayFactory>>) {
+    let _http_task = std::thread::spawn(move || {
+        let system = actix_rt::System::new();
+        system.block_on(httpserver::serve(bind_addr, factory))
+    });
+}
+
+/// Take a checkpoint and write out the simulation report if any.
+///
+/// Note that the simulated state is not included in the checkpoints.
+async fn save_progress(
+    factory: &ReplayFactory,
+    block_number: BlockNumber,
+    args: &Args,
+) -> Result<()> {
+    take_checkpoint(factory, block_number);
+    factory
+        .report_simulation(&args.simulation_output, &args.persist_events_to)
+        .await
+}
+
+fn take_checkpoint(factory: &ReplayFactory, block_number: BlockNumber) {
+    let filename = format!("checkpoint.{}", block_number);
+    log::info!("Taking checkpoint: {}", filename);
+    factory.dump_to_file(&filename);
+    let link = Path::new("checkpoint.latest");
+    if link.is_symlink() {
+        std::fs::remove_file(link).expect("Failed to remove the checkpoint symlink");
+    }
+    std::os::unix::fs::symlink(filename, link)
+        .expect("Failed to create symlink for latest checkpoint");
+}
+
+fn first_block(args: &Args, factory: &ReplayFactory) -> BlockNumber {
+    if factory.current_block == 0 {
+        args.start_at + 1
+    } else {
+        factory.current_block + 1
+    }
+}
+
 pub async fn replay(args: Args) -> Result<()> {
-    let db_uri = args.persist_events_to;
-    let bind_addr = args.bind_addr;
+    if let Some(uri) = &args.cache_uri {
+        log::info!("Replaying offline from headers cache at: {}", uri);
+        let source = source::OfflineSource::cache(uri);
+        return replay_offline(args, source).await;
+    }
+    if !args.storage_changes.is_empty() {
+        log::info!(
+            "Replaying offline from {} files",
+            args.storage_changes.len()
+        );
+        let source =
+            source::OfflineSource::files(args.storage_changes.clone(), args.para_headers.clone());
+        return replay_offline(args, source).await;
+    }
+    replay_online(args).await
+}
+
+async fn replay_online(args: Args) -> Result<()> {
     let assume_finalized = args.assume_finalized;
 
     let mut api: ParachainApi = pherry::subxt_connect(&args.node_uri)
@@ -240,41 +398,29 @@ pub async fn replay(args: Args) -> Result<()> {
     log::info!("Connected to substrate at: {}", args.node_uri);
 
     let genesis_state = fetch_genesis_storage(&api, args.start_at).await?;
-    let event_tx = if !db_uri.is_empty() {
-        let (event_tx, event_rx) = mpsc::channel(1024 * 5);
-        let _db_task =
-            tokio::spawn(async move { data_persist::run_persist(event_rx, &db_uri).await });
-        Some(event_tx)
-    } else {
-        None
-    };
+    if let Some(filename) = &args.save_genesis_state {
+        log::info!("Saving genesis state to {}", filename);
+        std::fs::write(filename, genesis_state.encode())?;
+    }
+    let (event_tx, _event_task) = start_event_sink(&args);
 
-    let factory = match get_checkpoint_path(&args.restore_from) {
-        Some(filename) => {
-            log::info!("Restoring from checkpoint: {}", filename);
-            ReplayFactory::load_from_file(&filename)
-        }
-        None => ReplayFactory::new(genesis_state),
-    };
+    let factory = load_factory(&args, Some(genesis_state))?;
     let mut last_checkpoint_block: BlockNumber = factory.current_block;
+    let mut block_number = first_block(&args, &factory);
     let factory = Arc::new(Mutex::new(factory));
 
-    let _http_task = std::thread::spawn({
-        let factory = factory.clone();
-        move || {
-            let system = actix_rt::System::new();
-            system.block_on(httpserver::serve(bind_addr, factory))
-        }
-    });
-
-    let mut block_number = if last_checkpoint_block == 0 {
-        args.start_at + 1
-    } else {
-        last_checkpoint_block + 1
-    };
+    start_http_server(args.bind_addr.clone(), factory.clone());
 
     loop {
         loop {
+            if matches!(args.stop_at, Some(stop_at) if block_number > stop_at) {
+                log::info!("Reached block {}, stopping", block_number - 1);
+                let factory = factory.lock().await;
+                if factory.current_block > last_checkpoint_block {
+                    save_progress(&factory, factory.current_block, &args).await?;
+                }
+                return Ok(());
+            }
             if let Err(err) = wait_for_block(&api, block_number, assume_finalized).await {
                 log::error!("{}", err);
                 if restart_required(&err) {
@@ -290,22 +436,13 @@ pub async fn replay(args: Args) -> Result<()> {
                     log::info!("Replaying block {}", block_number);
                     let mut factory = factory.lock().await;
                     factory
-                        .dispatch_block(block, &event_tx)
+                        .dispatch_block(block, &event_tx, true)
                         .await
                         .expect("Block is valid");
                     if args.checkpoint_interval > 0
                         && block_number >= args.checkpoint_interval + last_checkpoint_block
                     {
-                        let filename = format!("checkpoint.{}", block_number);
-                        log::info!("Taking checkpoint: {}", filename);
-                        factory.dump_to_file(&filename);
-                        let link = Path::new("checkpoint.latest");
-                        if link.is_symlink() {
-                            std::fs::remove_file(link)
-                                .expect("Failed to remove the checkpoint symlink");
-                        }
-                        std::os::unix::fs::symlink(filename, link)
-                            .expect("Failed to create symlink for latest checkpoint");
+                        save_progress(&factory, block_number, &args).await?;
                         last_checkpoint_block = block_number;
                     }
                     block_number += 1;
@@ -335,6 +472,63 @@ pub async fn replay(args: Args) -> Result<()> {
     }
 }
 
+/// Replay the blocks from a headers-cache source without connecting to any node.
+///
+/// The replay stops when reaching `--stop-at` or running out of blocks, then takes a final
+/// checkpoint, so the results of the same range can be compared between pRuntime versions.
+async fn replay_offline(args: Args, mut source: source::OfflineSource) -> Result<()> {
+    let genesis_state = match &args.genesis_state {
+        Some(filename) => Some(source::load_genesis_state(filename)?),
+        None => None,
+    };
+    let (event_tx, event_task) = start_event_sink(&args);
+
+    let factory = load_factory(&args, genesis_state)?;
+    let mut last_checkpoint_block: BlockNumber = factory.current_block;
+    let mut block_number = first_block(&args, &factory);
+    let factory = Arc::new(Mutex::new(factory));
+
+    start_http_server(args.bind_addr.clone(), factory.clone());
+
+    let verify_state_root = source.has_headers();
+    let stop_at = args.stop_at.unwrap_or(BlockNumber::MAX);
+    while block_number <= stop_at {
+        let block = match source.next_block(block_number).await? {
+            Some(block) => block,
+            None => {
+                log::info!("No more blocks after {}", block_number - 1);
+                break;
+            }
+        };
+        log::info!("Replaying block {}", block_number);
+        let mut factory = factory.lock().await;
+        factory
+            .dispatch_block(block, &event_tx, verify_state_root)
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to replay block {}: {}", block_number, err))?;
+        if args.checkpoint_interval > 0
+            && block_number >= args.checkpoint_interval + last_checkpoint_block
+        {
+            save_progress(&factory, block_number, &args).await?;
+            last_checkpoint_block = block_number;
+        }
+        block_number += 1;
+    }
+
+    let factory = factory.lock().await;
+    if factory.current_block > last_checkpoint_block {
+        save_progress(&factory, factory.current_block, &args).await?;
+    }
+    log::info!("Replayed to block {}", factory.current_block);
+
+    // Wait for the pending events to be flushed.
+    drop(event_tx);
+    if let Some(task) = event_task {
+        task.await?;
+    }
+    Ok(())
+}
+
 fn restart_required(error: &Error) -> bool {
     format!("{}", error).contains("restart required")
 }
@@ -1,7 +1,8 @@
-use super::EventRecord;
+use super::{simulation::WorkerDelta, EventRecord};
 use anyhow::Result;
 use chrono::TimeZone as _;
 use phactory::gk;
+use pherry::types::BlockNumber;
 use sqlx::types::Decimal;
 use sqlx::{postgres::PgPoolOptions, Row};
 use std::time::Duration;
@@ -152,3 +153,62 @@ async fn get_last_sequence(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<i64> {
             .await?;
     Ok(latest_row.map_or(0, |row| row.get(0)))
 }
+
+pub(super) async fn persist_simulation(
+    uri: &str,
+    block_number: BlockNumber,
+    deltas: &[WorkerDelta],
+) -> Result<()> {
+    let pool = PgPoolOptions::new().max_connections(1).connect(uri).await?;
+
+    let mut pubkeys = vec![];
+    let mut baseline_vs = vec![];
+    let mut simulated_vs = vec![];
+    let mut baseline_payouts = vec![];
+    let mut simulated_payouts = vec![];
+    let mut baseline_slashes = vec![];
+    let mut simulated_slashes = vec![];
+    for delta in deltas {
+        pubkeys.push(delta.pubkey.0.to_vec());
+        baseline_vs.push(cvt_fp(delta.baseline_v));
+        simulated_vs.push(cvt_fp(delta.simulated_v));
+        baseline_payouts.push(cvt_fp(delta.baseline_payout));
+        simulated_payouts.push(cvt_fp(delta.simulated_payout));
+        baseline_slashes.push(cvt_fp(delta.baseline_slash));
+        simulated_slashes.push(cvt_fp(delta.simulated_slash));
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO worker_simulation_deltas
+            (block, pubkey, baseline_v, simulated_v, baseline_payout, simulated_payout,
+             baseline_slash, simulated_slash)
+        SELECT $1, *
+        FROM UNNEST($2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (block, pubkey)
+        DO UPDATE
+        SET (baseline_v, simulated_v, baseline_payout, simulated_payout, baseline_slash,
+             simulated_slash) = (
+            EXCLUDED.baseline_v, EXCLUDED.simulated_v, EXCLUDED.baseline_payout,
+            EXCLUDED.simulated_payout, EXCLUDED.baseline_slash, EXCLUDED.simulated_slash
+        )
+        "#,
+    )
+    .bind(block_number as i32)
+    .bind(&pubkeys)
+    .bind(&baseline_vs)
+    .bind(&simulated_vs)
+    .bind(&baseline_payouts)
+    .bind(&simulated_payouts)
+    .bind(&baseline_slashes)
+    .bind(&simulated_slashes)
+    .execute(&pool)
+    .await?;
+
+    log::info!(
+        "Persisted the simulation deltas of {} workers at block {}",
+        deltas.len(),
+        block_number
+    );
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use super::EventRecord;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use tokio::sync::mpsc;
+
+/// Write the events to a JSON-lines file, which can be diffed between different replay runs.
+pub(super) async fn run_dump(mut rx: mpsc::Receiver<EventRecord>, filename: &str) -> Result<()> {
+    let file = File::create(filename).with_context(|| format!("Failed to create {}", filename))?;
+    let mut writer = BufWriter::new(file);
+    while let Some(rec) = rx.recv().await {
+        let line = serde_json::json!({
+            "sequence": rec.sequence,
+            "pubkey": hex::encode(rec.pubkey.0),
+            "block": rec.block_number,
+            "time_ms": rec.time_ms,
+            "event": rec.event.event_string(),
+            "v": rec.v.to_string(),
+            "p": rec.p.to_string(),
+            "payout": rec.event.payout().to_string(),
+        });
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! What-if simulation of tokenomic parameter changes.
+//!
+//! At the configured block, the gatekeeper state is forked and the overridden parameters are
+//! applied to the fork. From then on, both the baseline and the simulated gatekeeper process the
+//! same on-chain messages, so the difference of the worker rewards/V/slashes between them is the
+//! effect of the parameter change.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use phactory::{gk, BlockInfo, Storage};
+use phala_mq::{Message, MessageDispatcher};
+use phala_types::WorkerPublicKey;
+use pherry::types::BlockNumber;
+use serde::Deserialize;
+
+use super::ReplayMsgChannel;
+
+type MiningEconomics = gk::MiningEconomics<ReplayMsgChannel>;
+
+/// The simulation config file, e.g.:
+///
+/// ```json
+/// {
+///     "at_block": 1500000,
+///     "params": {
+///         "budget_per_block": "80",
+///         "treasury_ratio": "0.25"
+///     }
+/// }
+/// ```
+#[derive(Deserialize)]
+pub(super) struct SimulationConfig {
+    /// The block to apply the parameters at.
+    pub at_block: BlockNumber,
+    pub params: ParamsConfig,
+}
+
+/// The overridden parameters as decimal strings. See `TokenomicParameters` for the meanings.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(super) struct ParamsConfig {
+    rho: Option<String>,
+    slash_rate: Option<String>,
+    budget_per_block: Option<String>,
+    v_max: Option<String>,
+    cost_k: Option<String>,
+    cost_b: Option<String>,
+    treasury_ratio: Option<String>,
+    heartbeat_window: Option<u32>,
+}
+
+impl ParamsConfig {
+    fn to_override(&self) -> Result<gk::TokenomicParamsOverride> {
+        fn parse(name: &str, value: &Option<String>) -> Result<Option<gk::FixedPoint>> {
+            value
+                .as_ref()
+                .map(|v| {
+                    gk::FixedPoint::from_str(v).map_err(|err| anyhow!("Invalid {}: {}", name, err))
+                })
+                .transpose()
+        }
+        Ok(gk::TokenomicParamsOverride {
+            rho: parse("rho", &self.rho)?,
+            slash_rate: parse("slash_rate", &self.slash_rate)?,
+            budget_per_block: parse("budget_per_block", &self.budget_per_block)?,
+            v_max: parse("v_max", &self.v_max)?,
+            cost_k: parse("cost_k", &self.cost_k)?,
+            cost_b: parse("cost_b", &self.cost_b)?,
+            treasury_ratio: parse("treasury_ratio", &self.treasury_ratio)?,
+            heartbeat_window: self.heartbeat_window,
+        })
+    }
+}
+
+impl SimulationConfig {
+    pub fn load(filename: &str) -> Result<Self> {
+        let file = std::fs::File::open(filename)
+            .with_context(|| format!("Failed to open simulation config {}", filename))?;
+        let config: Self =
+            serde_json::from_reader(file).context("Failed to parse the simulation config")?;
+        config.params.to_override()?;
+        Ok(config)
+    }
+}
+
+struct Fork {
+    recv_mq: MessageDispatcher,
+    gk: MiningEconomics,
+    /// The worker stats when forking, so that the reports only count in the simulated period.
+    forked_stats: BTreeMap<WorkerPublicKey, WorkerSnapshot>,
+}
+
+pub(super) struct Simulation {
+    config: SimulationConfig,
+    fork: Option<Fork>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct WorkerSnapshot {
+    v: gk::FixedPoint,
+    payout: gk::FixedPoint,
+    slash: gk::FixedPoint,
+}
+
+impl WorkerSnapshot {
+    fn of(worker: &gk::WorkerInfo) -> Self {
+        let info = worker.tokenomic_info();
+        Self {
+            v: info.v,
+            payout: info.stat.total_payout,
+            slash: info.stat.total_slash,
+        }
+    }
+
+    fn since(&self, start: &WorkerSnapshot) -> Self {
+        Self {
+            v: self.v,
+            payout: self.payout.saturating_sub(start.payout),
+            slash: self.slash.saturating_sub(start.slash),
+        }
+    }
+}
+
+/// The state of a worker in the baseline and simulated runs.
+pub(super) struct WorkerDelta {
+    pub pubkey: WorkerPublicKey,
+    pub baseline_v: gk::FixedPoint,
+    pub simulated_v: gk::FixedPoint,
+    /// Payout since the simulation started.
+    pub baseline_payout: gk::FixedPoint,
+    pub simulated_payout: gk::FixedPoint,
+    /// Slashed V since the simulation started.
+    pub baseline_slash: gk::FixedPoint,
+    pub simulated_slash: gk::FixedPoint,
+}
+
+impl Simulation {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self { config, fork: None }
+    }
+
+    pub fn at_block(&self) -> BlockNumber {
+        self.config.at_block
+    }
+
+    pub fn started(&self) -> bool {
+        self.fork.is_some()
+    }
+
+    /// Fork the baseline if the simulation starts at the given block.
+    ///
+    /// Must be called before the baseline processes the block.
+    pub fn fork_if_due(&mut self, block_number: BlockNumber, baseline: &MiningEconomics) {
+        if self.fork.is_some() || block_number < self.config.at_block {
+            return;
+        }
+        log::info!(
+            "Starting the tokenomic simulation at block {}",
+            block_number
+        );
+        let state = serde_cbor::to_vec(baseline).expect("Failed to serialize the gatekeeper");
+        let mut recv_mq = MessageDispatcher::new();
+        let mut gk: MiningEconomics =
+            phala_mq::checkpoint_helper::using_dispatcher(&mut recv_mq, move || {
+                serde_cbor::from_slice(&state).expect("Failed to fork the gatekeeper")
+            });
+        let overrides = self
+            .config
+            .params
+            .to_override()
+            .expect("Config checked when loading");
+        gk.override_tokenomic_params(overrides);
+        let forked_stats = gk
+            .workers()
+            .map(|w| (*w.pubkey(), WorkerSnapshot::of(w)))
+            .collect();
+        self.fork = Some(Fork {
+            recv_mq,
+            gk,
+            forked_stats,
+        });
+    }
+
+    /// Feed the simulated gatekeeper with the messages of a block.
+    pub fn process_block(
+        &mut self,
+        storage: &Storage,
+        block_number: BlockNumber,
+        now_ms: u64,
+        messages: Vec<Message>,
+    ) {
+        let fork = match &mut self.fork {
+            Some(fork) => fork,
+            None => return,
+        };
+        let mut block = BlockInfo {
+            block_number,
+            now_ms,
+            storage,
+            recv_mq: &mut fork.recv_mq,
+            send_mq: &mut Default::default(),
+        };
+        block.recv_mq.reset_local_index();
+        fork.gk.will_process_block(&block);
+        for message in messages {
+            block.recv_mq.dispatch(message);
+            fork.gk.process_messages(&mut block, &mut ());
+        }
+        fork.gk.did_process_block(&block, &mut ());
+        fork.recv_mq.clear();
+    }
+
+    /// Compare the workers between the baseline and the simulation.
+    pub fn deltas(&self, baseline: &MiningEconomics) -> Vec<WorkerDelta> {
+        let fork = match &self.fork {
+            Some(fork) => fork,
+            None => return vec![],
+        };
+        let snapshot = |gk: &MiningEconomics| -> BTreeMap<WorkerPublicKey, WorkerSnapshot> {
+            gk.workers()
+                .map(|w| {
+                    let start = fork
+                        .forked_stats
+                        .get(w.pubkey())
+                        .cloned()
+                        .unwrap_or_default();
+                    (*w.pubkey(), WorkerSnapshot::of(w).since(&start))
+                })
+                .collect()
+        };
+        let baseline = snapshot(baseline);
+        let simulated = snapshot(&fork.gk);
+        let mut pubkeys: Vec<_> = baseline.keys().chain(simulated.keys()).collect();
+        pubkeys.sort();
+        pubkeys.dedup();
+        pubkeys
+            .into_iter()
+            .map(|pubkey| {
+                let b = baseline.get(pubkey).cloned().unwrap_or_default();
+                let s = simulated.get(pubkey).cloned().unwrap_or_default();
+                WorkerDelta {
+                    pubkey: *pubkey,
+                    baseline_v: b.v,
+                    simulated_v: s.v,
+                    baseline_payout: b.payout,
+                    simulated_payout: s.payout,
+                    baseline_slash: b.slash,
+                    simulated_slash: s.slash,
+                }
+            })
+            .collect()
+    }
+}
+
+/// `b - a` for the unsigned fixed points.
+fn signed_delta(a: gk::FixedPoint, b: gk::FixedPoint) -> String {
+    if b >= a {
+        (b - a).to_string()
+    } else {
+        format!("-{}", a - b)
+    }
+}
+
+pub(super) fn write_csv(deltas: &[WorkerDelta], mut writer: impl Write) -> Result<()> {
+    writeln!(
+        writer,
+        "pubkey,baseline_v,simulated_v,delta_v,baseline_payout,simulated_payout,delta_payout,baseline_slash,simulated_slash,delta_slash"
+    )?;
+    for d in deltas {
+        writeln!(
+            writer,
+            "0x{},{},{},{},{},{},{},{},{},{}",
+            hex::encode(d.pubkey.0),
+            d.baseline_v,
+            d.simulated_v,
+            signed_delta(d.baseline_v, d.simulated_v),
+            d.baseline_payout,
+            d.simulated_payout,
+            signed_delta(d.baseline_payout, d.simulated_payout),
+            d.baseline_slash,
+            d.simulated_slash,
+            signed_delta(d.baseline_slash, d.simulated_slash),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,244 @@
+//! Offline block sources, so that the replay can run without a substrate node.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufReader;
+
+use anyhow::{anyhow, bail, Context, Result};
+use parity_scale_codec::Decode;
+use pherry::headers_cache::{self, BlockHeaderWithChanges, Record};
+use pherry::types::{BlockNumber, Header};
+
+/// Number of blocks requested from the headers-cache server at once.
+const CACHE_BATCH_SIZE: BlockNumber = 100;
+/// Number of attempts to request the headers-cache server before giving up.
+const CACHE_MAX_ATTEMPTS: u32 = 5;
+
+/// Sequentially decodes the records from a list of files grabbed by `headers-cache grab`.
+struct RecordFiles {
+    files: VecDeque<String>,
+    reader: Option<BufReader<File>>,
+    buffer: Vec<u8>,
+}
+
+impl RecordFiles {
+    fn new(files: Vec<String>) -> Self {
+        Self {
+            files: files.into(),
+            reader: None,
+            buffer: vec![],
+        }
+    }
+
+    fn next<T: Decode>(&mut self) -> Result<Option<T>> {
+        loop {
+            if self.reader.is_none() {
+                let filename = match self.files.pop_front() {
+                    Some(filename) => filename,
+                    None => return Ok(None),
+                };
+                log::info!("Reading records from {}", filename);
+                let file = File::open(&filename)
+                    .with_context(|| format!("Failed to open {}", filename))?;
+                self.reader = Some(BufReader::new(file));
+            }
+            let input = self.reader.as_mut().expect("Reader opened above");
+            match Record::read(input, &mut self.buffer)? {
+                Some(record) => {
+                    let item = T::decode(&mut record.payload()).context("Invalid record")?;
+                    return Ok(Some(item));
+                }
+                None => self.reader = None,
+            }
+        }
+    }
+}
+
+pub(super) enum OfflineSource {
+    /// A headers-cache server, e.g. `headers-cache serve` running on the local machine.
+    Cache {
+        client: headers_cache::Client,
+        buffer: VecDeque<BlockHeaderWithChanges>,
+    },
+    /// The files produced by `headers-cache grab storage-changes` and optionally
+    /// `headers-cache grab para-headers`.
+    Files {
+        storage_changes: RecordFiles,
+        para_headers: Option<RecordFiles>,
+    },
+}
+
+impl OfflineSource {
+    pub(super) fn cache(uri: &str) -> Self {
+        Self::Cache {
+            client: headers_cache::Client::new(uri),
+            buffer: Default::default(),
+        }
+    }
+
+    pub(super) fn files(storage_changes: Vec<String>, para_headers: Vec<String>) -> Self {
+        if para_headers.is_empty() {
+            log::warn!("No parachain headers given, the state roots will not be verified");
+        }
+        Self::Files {
+            storage_changes: RecordFiles::new(storage_changes),
+            para_headers: if para_headers.is_empty() {
+                None
+            } else {
+                Some(RecordFiles::new(para_headers))
+            },
+        }
+    }
+
+    /// Whether the blocks come with the real headers to verify the state roots against.
+    pub(super) fn has_headers(&self) -> bool {
+        match self {
+            Self::Cache { .. } => true,
+            Self::Files { para_headers, .. } => para_headers.is_some(),
+        }
+    }
+
+    /// Get the storage changes of the given block. Returns None if the source has no more blocks.
+    ///
+    /// Blocks must be requested in ascending order. Blocks earlier than the requested one are
+    /// skipped.
+    pub(super) async fn next_block(
+        &mut self,
+        block_number: BlockNumber,
+    ) -> Result<Option<BlockHeaderWithChanges>> {
+        loop {
+            let block = match self.next_buffered(block_number).await? {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+            let number = block.block_header.number;
+            if number < block_number {
+                continue;
+            }
+            if number > block_number {
+                bail!("Missing storage changes of block {block_number}, got {number}");
+            }
+            return Ok(Some(block));
+        }
+    }
+
+    async fn next_buffered(
+        &mut self,
+        block_number: BlockNumber,
+    ) -> Result<Option<BlockHeaderWithChanges>> {
+        match self {
+            Self::Cache { client, buffer } => {
+                if buffer.is_empty() {
+                    let blocks = match fetch_batch(client, block_number).await? {
+                        Some(blocks) => blocks,
+                        None => return Ok(None),
+                    };
+                    let headers = client
+                        .get_parachain_headers(block_number, blocks.len() as _)
+                        .await
+                        .context("Failed to get parachain headers from the cache")?;
+                    for (mut block, header) in blocks.into_iter().zip(headers) {
+                        attach_header(&mut block, header)?;
+                        buffer.push_back(block);
+                    }
+                }
+                Ok(buffer.pop_front())
+            }
+            Self::Files {
+                storage_changes,
+                para_headers,
+            } => {
+                let mut block: BlockHeaderWithChanges = match storage_changes.next()? {
+                    Some(block) => block,
+                    None => return Ok(None),
+                };
+                if let Some(para_headers) = para_headers {
+                    loop {
+                        let header: Header = para_headers.next()?.ok_or_else(|| {
+                            anyhow!("Missing header of block {}", block.block_header.number)
+                        })?;
+                        if header.number >= block.block_header.number {
+                            attach_header(&mut block, header)?;
+                            break;
+                        }
+                    }
+                }
+                Ok(Some(block))
+            }
+        }
+    }
+}
+
+/// Fetch the storage changes of the blocks starting from `from` from the headers-cache server.
+/// Returns None if `from` is past the end of the cached blocks.
+async fn fetch_batch(
+    client: &headers_cache::Client,
+    from: BlockNumber,
+) -> Result<Option<Vec<BlockHeaderWithChanges>>> {
+    // The server rejects the whole range if any block in it is missing, so fall back to a single
+    // block when approaching the end of the cached data.
+    match get_storage_changes(client, from, CACHE_BATCH_SIZE).await {
+        Ok(blocks) => return Ok(Some(blocks)),
+        Err(err) if headers_cache::is_not_found(&err) => {}
+        Err(err) => return Err(err),
+    }
+    let err = match get_storage_changes(client, from, 1).await {
+        Ok(blocks) => return Ok(Some(blocks)),
+        Err(err) if headers_cache::is_not_found(&err) => err,
+        Err(err) => return Err(err),
+    };
+    let highest = client
+        .get_highest_storage_changes()
+        .await
+        .context("Failed to get the status of the cache")?;
+    if highest.map_or(true, |highest| from > highest) {
+        log::info!("No more blocks in the cache");
+        return Ok(None);
+    }
+    Err(err.context(format!(
+        "Storage changes of block {from} are missing in the cache"
+    )))
+}
+
+/// Get the storage changes from the headers-cache server, retrying on the errors other than a
+/// missing block, e.g. the server is restarting.
+async fn get_storage_changes(
+    client: &headers_cache::Client,
+    from: BlockNumber,
+    count: BlockNumber,
+) -> Result<Vec<BlockHeaderWithChanges>> {
+    let mut attempt = 1;
+    loop {
+        match client.get_storage_changes(from, count).await {
+            Ok(blocks) => return Ok(blocks),
+            Err(err) if attempt < CACHE_MAX_ATTEMPTS && !headers_cache::is_not_found(&err) => {
+                log::warn!("Failed to get storage changes from the cache, retrying: {err}");
+                tokio::time::sleep(std::time::Duration::from_secs(attempt as _)).await;
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(err.context(format!(
+                    "Failed to get storage changes of blocks {from}+{count} from the cache"
+                )))
+            }
+        }
+    }
+}
+
+fn attach_header(block: &mut BlockHeaderWithChanges, header: Header) -> Result<()> {
+    if header.number != block.block_header.number {
+        bail!(
+            "Header {} does not match the storage changes of block {}",
+            header.number,
+            block.block_header.number
+        );
+    }
+    block.block_header = header;
+    Ok(())
+}
+
+/// Load the genesis storage saved by `--save-genesis-state`.
+pub(super) fn load_genesis_state(filename: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let data = std::fs::read(filename).with_context(|| format!("Failed to read {}", filename))?;
+    Decode::decode(&mut &data[..]).context("Failed to decode the genesis state")
+}
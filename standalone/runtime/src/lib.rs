@@ -335,6 +335,7 @@ impl InstanceFilter<RuntimeCall> for ProxyType {
                     | RuntimeCall::PhalaStakePool(pallet_stakepool::Call::create { .. })
                     | RuntimeCall::PhalaRegistry(pallet_registry::Call::register_worker { .. })
                     | RuntimeCall::PhalaMq(pallet_mq::Call::sync_offchain_message { .. })
+                    | RuntimeCall::PhalaMq(pallet_mq::Call::sync_offchain_messages { .. })
             ),
 		}
 	}
@@ -1272,6 +1273,7 @@ parameter_types! {
 	pub const MinMiningStaking: Balance = 1 * DOLLARS;
 	pub const MinContribution: Balance = 1 * CENTS;
 	pub const MiningGracePeriod: u64 = 7 * 24 * 3600;
+	pub const CommissionIncreaseDelay: u64 = 7 * 24 * 3600;
 	pub const MinInitP: u32 = 50;
 	pub const MiningEnabledByDefault: bool = false;
 	pub const MaxPoolWorkers: u32 = 200;
@@ -1289,6 +1291,7 @@ impl pallet_registry::Config for Runtime {
 	type GovernanceOrigin = EnsureRootOrHalfCouncil;
 }
 impl pallet_mq::Config for Runtime {
+	type RuntimeEvent = RuntimeEvent;
 	type QueueNotifyConfig = msg_routing::MessageRouteConfig;
 	type CallMatcher = MqCallMatcher;
 }
@@ -1309,6 +1312,7 @@ impl pallet_stakepool::Config for Runtime {
 	type Currency = Balances;
 	type MinContribution = MinContribution;
 	type GracePeriod = MiningGracePeriod;
+	type CommissionIncreaseDelay = CommissionIncreaseDelay;
 	type MiningEnabledByDefault = MiningEnabledByDefault;
 	type MaxPoolWorkers = MaxPoolWorkers;
 	type OnSlashed = Treasury;
@@ -1319,6 +1323,9 @@ impl pallet_fat::Config for Runtime {
 	type RuntimeEvent = RuntimeEvent;
 	type InkCodeSizeLimit = ConstU32<{1024*1024*2}>;
 	type SidevmCodeSizeLimit = ConstU32<{1024*1024*8}>;
+	type Currency = Balances;
+	type MaxClusterWorkers = ConstU32<100>;
+	type MaxDeployerCodeHashes = ConstU32<100>;
 }
 
 impl pallet_fat_tokenomic::Config for Runtime {
@@ -1629,6 +1636,26 @@ impl_runtime_apis! {
 		fn sender_sequence(sender: &phala_types::messaging::MessageOrigin) -> Option<u64> {
 			PhalaMq::offchain_ingress(sender)
 		}
+		fn dead_letters(
+			start: u64,
+			limit: u32,
+		) -> Vec<(u64, pallet_mq_runtime_api::DeadLetter<BlockNumber>)> {
+			PhalaMq::dead_letters(start, limit)
+		}
+	}
+
+	impl pallet_stakepool_runtime_api::StakePoolApi<Block, AccountId, Balance> for Runtime {
+		fn staker_projection(
+			pid: u64,
+			user: AccountId,
+		) -> Option<pallet_stakepool_runtime_api::StakerProjection<Balance>> {
+			PhalaStakePool::staker_projection(pid, user)
+		}
+		fn releasing_projection(
+			pid: u64,
+		) -> Option<pallet_stakepool_runtime_api::ReleasingProjection<Balance>> {
+			PhalaStakePool::releasing_projection(pid)
+		}
 	}
 
 	impl sp_session::SessionKeys<Block> for Runtime {
@@ -34,6 +34,7 @@ impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
             PhalaRegistry::on_gk_message_received,
             PhalaMining::on_gk_message_received,
             PhalaMining::on_mining_message_received,
+            PhalaMining::on_reliability_message_received,
             PhalaFatContracts::on_worker_cluster_message_received,
             PhalaFatContracts::on_cluster_message_received,
             PhalaFatContracts::on_contract_message_received,